@@ -11,18 +11,26 @@ use std::cmp::min;
 use std::env;
 use std::error::Error;
 use std::num::NonZeroUsize;
-use std::sync::atomic::{AtomicI64, AtomicU64};
+use std::process;
+use std::sync::atomic::{AtomicI64, AtomicU64, AtomicUsize};
 use std::sync::Arc;
+use std::thread;
 use std::time::Duration;
 use string_error::into_err;
+use tokio::signal::unix::{signal, SignalKind};
 use undermoon::common::config::ClusterConfig;
 use undermoon::common::track::TrackedFutureRegistry;
 use undermoon::protocol::SimpleRedisClientFactory;
+use undermoon::proxy::audit::AuditLogger;
 use undermoon::proxy::backend::DefaultConnFactory;
+use undermoon::proxy::client::ClientRegistry;
+use undermoon::proxy::drain::DrainController;
 use undermoon::proxy::executor::SharedForwardHandler;
 use undermoon::proxy::manager::MetaMap;
+use undermoon::proxy::metrics::{run_metrics_server, MetricsService, ProxyMetrics};
 use undermoon::proxy::service::{ServerProxyConfig, ServerProxyService};
 use undermoon::proxy::slowlog::SlowRequestLogger;
+use undermoon::proxy::timeout::CmdTimeouts;
 use undermoon::MAX_REDIRECTIONS;
 
 fn gen_conf() -> Result<(ServerProxyConfig, ClusterConfig), &'static str> {
@@ -60,7 +68,32 @@ fn gen_conf() -> Result<(ServerProxyConfig, ClusterConfig), &'static str> {
     if max_redirections != 0 {
         max_redirections = min(MAX_REDIRECTIONS, max_redirections);
     }
-    let max_redirections = NonZeroUsize::new(max_redirections);
+
+    // Empty path is the same as not set.
+    let get_path = |field: &str| s.get::<String>(field).ok().filter(|p| !p.is_empty());
+    let tls_cert_path = get_path("tls_cert_path");
+    let tls_key_path = get_path("tls_key_path");
+    let tls_client_ca_path = get_path("tls_client_ca_path");
+    if tls_cert_path.is_some() != tls_key_path.is_some() {
+        return Err("tls_cert_path and tls_key_path");
+    }
+    let backend_tls_ca_path = get_path("backend_tls_ca_path");
+    let backend_tls_cert_path = get_path("backend_tls_cert_path");
+    let backend_tls_key_path = get_path("backend_tls_key_path");
+    let backend_tls_server_name = get_path("backend_tls_server_name");
+    if backend_tls_cert_path.is_some() != backend_tls_key_path.is_some() {
+        return Err("backend_tls_cert_path and backend_tls_key_path");
+    }
+    if backend_tls_ca_path.is_some() && backend_tls_server_name.is_none() {
+        return Err("backend_tls_server_name");
+    }
+    let metrics_address = get_path("metrics_address");
+    let audit_log_path = get_path("audit_log_path");
+    let cmd_timeouts = CmdTimeouts::new(
+        s.get::<u64>("cmd_timeout").unwrap_or_else(|_| 0),
+        &s.get::<String>("cmd_class_timeouts").unwrap_or_default(),
+    )
+    .map_err(|_| "cmd_class_timeouts")?;
 
     let config = ServerProxyConfig {
         address: address.clone(),
@@ -70,7 +103,7 @@ fn gen_conf() -> Result<(ServerProxyConfig, ClusterConfig), &'static str> {
         auto_select_cluster: s
             .get::<bool>("auto_select_cluster")
             .unwrap_or_else(|_| true),
-        slowlog_len,
+        slowlog_len: AtomicUsize::new(slowlog_len.get()),
         slowlog_log_slower_than: AtomicI64::new(
             s.get::<i64>("slowlog_log_slower_than")
                 .unwrap_or_else(|_| 50000),
@@ -78,6 +111,11 @@ fn gen_conf() -> Result<(ServerProxyConfig, ClusterConfig), &'static str> {
         slowlog_sample_rate: AtomicU64::new(
             s.get::<u64>("slowlog_sample_rate").unwrap_or_else(|_| 1000),
         ),
+        hotkey_sample_rate: AtomicU64::new(
+            s.get::<u64>("hotkey_sample_rate").unwrap_or_else(|_| 100),
+        ),
+        maxclients: AtomicU64::new(s.get::<u64>("maxclients").unwrap_or_else(|_| 0)),
+        idle_timeout: AtomicU64::new(s.get::<u64>("idle_timeout").unwrap_or_else(|_| 0)),
         thread_number,
         session_channel_size: s
             .get::<usize>("session_channel_size")
@@ -85,25 +123,51 @@ fn gen_conf() -> Result<(ServerProxyConfig, ClusterConfig), &'static str> {
         backend_channel_size: s
             .get::<usize>("backend_channel_size")
             .unwrap_or_else(|_| 4096),
-        backend_conn_num,
-        backend_batch_min_time: s
-            .get::<usize>("backend_batch_min_time")
-            .unwrap_or_else(|_| 20000),
-        backend_batch_max_time: s
-            .get::<usize>("backend_batch_max_time")
-            .unwrap_or_else(|_| 400_000),
-        backend_batch_buf,
-        session_batch_min_time: s
-            .get::<usize>("session_batch_min_time")
-            .unwrap_or_else(|_| 20000),
-        session_batch_max_time: s
-            .get::<usize>("session_batch_max_time")
-            .unwrap_or_else(|_| 400_000),
-        session_batch_buf,
+        backend_conn_num: AtomicUsize::new(backend_conn_num.get()),
+        backend_batch_min_time: AtomicUsize::new(
+            s.get::<usize>("backend_batch_min_time")
+                .unwrap_or_else(|_| 20000),
+        ),
+        backend_batch_max_time: AtomicUsize::new(
+            s.get::<usize>("backend_batch_max_time")
+                .unwrap_or_else(|_| 400_000),
+        ),
+        backend_batch_buf: AtomicUsize::new(backend_batch_buf.get()),
+        session_batch_min_time: AtomicUsize::new(
+            s.get::<usize>("session_batch_min_time")
+                .unwrap_or_else(|_| 20000),
+        ),
+        session_batch_max_time: AtomicUsize::new(
+            s.get::<usize>("session_batch_max_time")
+                .unwrap_or_else(|_| 400_000),
+        ),
+        session_batch_buf: AtomicUsize::new(session_batch_buf.get()),
         active_redirection: s
             .get::<bool>("active_redirection")
             .unwrap_or_else(|_| false),
-        max_redirections,
+        max_redirections: AtomicUsize::new(max_redirections),
+        tls_cert_path,
+        tls_key_path,
+        tls_client_ca_path,
+        backend_tls_ca_path,
+        backend_tls_cert_path,
+        backend_tls_key_path,
+        backend_tls_server_name,
+        metrics_address,
+        audit_log_path,
+        audit_log_max_size: s
+            .get::<u64>("audit_log_max_size")
+            .unwrap_or_else(|_| 100 * 1024 * 1024),
+        audit_log_max_files: s.get::<usize>("audit_log_max_files").unwrap_or_else(|_| 10),
+        backend_reply_timeout: s.get::<u64>("backend_reply_timeout").unwrap_or_else(|_| 0),
+        breaker_error_rate: s.get::<u64>("breaker_error_rate").unwrap_or_else(|_| 50),
+        breaker_min_requests: s.get::<u64>("breaker_min_requests").unwrap_or_else(|_| 20),
+        breaker_consecutive_timeouts: s
+            .get::<u64>("breaker_consecutive_timeouts")
+            .unwrap_or_else(|_| 3),
+        breaker_open_time: s.get::<u64>("breaker_open_time").unwrap_or_else(|_| 5000),
+        cmd_timeouts,
+        drain_timeout: s.get::<u64>("drain_timeout").unwrap_or_else(|_| 30),
     };
 
     let mut cluster_config = ClusterConfig::default();
@@ -113,6 +177,21 @@ fn gen_conf() -> Result<(ServerProxyConfig, ClusterConfig), &'static str> {
         "migration_max_blocking_time",
         "migration_scan_interval",
         "migration_scan_count",
+        "user",
+        "password",
+        "enable_flushall",
+        "read_preference",
+        "max_ops_per_sec",
+        "max_bytes_per_sec",
+        "maxclients",
+        "idle_timeout",
+        "denied_commands",
+        "allowed_commands",
+        "renamed_commands",
+        "key_prefix",
+        "mirror_to",
+        "mirror_sample_rate",
+        "audit_log",
     ];
     for field in cluster_fields.iter() {
         if let Ok(value) = s.get::<String>(*field) {
@@ -125,6 +204,62 @@ fn gen_conf() -> Result<(ServerProxyConfig, ClusterConfig), &'static str> {
     Ok((config, cluster_config))
 }
 
+// The first SIGTERM starts draining and the second one exits immediately.
+async fn handle_sigterm(drain_controller: Arc<DrainController>) {
+    let mut sigterm = match signal(SignalKind::terminate()) {
+        Ok(sigterm) => sigterm,
+        Err(err) => {
+            error!("failed to listen to SIGTERM: {}", err);
+            return;
+        }
+    };
+    while sigterm.recv().await.is_some() {
+        if !drain_controller.start_drain() {
+            warn!("received SIGTERM again while draining, exit now");
+            process::exit(1);
+        }
+        info!("received SIGTERM, start draining");
+    }
+}
+
+// Re-reads the config file and applies the fields which can be changed at runtime.
+async fn handle_sighup(config: Arc<ServerProxyConfig>) {
+    let mut sighup = match signal(SignalKind::hangup()) {
+        Ok(sighup) => sighup,
+        Err(err) => {
+            error!("failed to listen to SIGHUP: {}", err);
+            return;
+        }
+    };
+    while sighup.recv().await.is_some() {
+        info!("received SIGHUP, reload config");
+        let new_config = match gen_conf() {
+            Ok((new_config, _)) => new_config,
+            Err(field) => {
+                error!("failed to reload config: invalid field {}", field);
+                continue;
+            }
+        };
+        let result = config.reload(&new_config);
+        info!("config reloaded, changed fields: {:?}", result.changed);
+        if !result.new_connections_only.is_empty() {
+            info!(
+                "config fields only applied to new connections: {:?}",
+                result.new_connections_only
+            );
+        }
+        if !result.restart_required.is_empty() {
+            warn!(
+                "config fields require restart to take effect: {:?}",
+                result.restart_required
+            );
+        }
+        for (field, err) in result.failed.iter() {
+            error!("failed to reload config field {}: {:?}", field, err);
+        }
+    }
+}
+
 fn main() -> Result<(), Box<dyn Error>> {
     env_logger::init();
     let (config, cluster_config) = gen_conf().map_err(|field| {
@@ -137,27 +272,60 @@ fn main() -> Result<(), Box<dyn Error>> {
 
     let config = Arc::new(config);
 
+    let backend_tls_connector = config.create_backend_tls_connector().map_err(|err| {
+        error!("failed to load backend TLS config: {}", err);
+        err
+    })?;
+
     let timeout = Duration::new(1, 0);
-    let client_factory = SimpleRedisClientFactory::new(timeout);
+    let client_factory =
+        SimpleRedisClientFactory::new_with_tls(timeout, backend_tls_connector.clone());
 
     let slow_request_logger = Arc::new(SlowRequestLogger::new(config.clone()));
     let meta_map = Arc::new(ArcSwap::new(Arc::new(MetaMap::empty())));
     let future_registry = Arc::new(TrackedFutureRegistry::default());
+    let proxy_metrics = Arc::new(ProxyMetrics::default());
+    let client_registry = Arc::new(ClientRegistry::default());
+    let audit_logger = Arc::new(AuditLogger::new(&config).map_err(|err| {
+        error!("failed to open audit log: {}", err);
+        err
+    })?);
+    let drain_controller = Arc::new(DrainController::default());
 
     let forward_handler = SharedForwardHandler::new(
         config.clone(),
         cluster_config,
         Arc::new(client_factory),
         slow_request_logger.clone(),
+        client_registry.clone(),
+        audit_logger,
+        drain_controller.clone(),
         meta_map,
-        Arc::new(DefaultConnFactory::default()),
+        Arc::new(DefaultConnFactory::new(backend_tls_connector)),
         future_registry.clone(),
     );
+
+    if let Some(metrics_address) = config.metrics_address.clone() {
+        let metrics_service = Arc::new(MetricsService::new(
+            proxy_metrics.clone(),
+            forward_handler.clone(),
+        ));
+        info!("serving metrics at {}", metrics_address);
+        thread::spawn(move || {
+            if let Err(err) = run_metrics_server(metrics_address, metrics_service) {
+                error!("metrics server failed: {}", err);
+            }
+        });
+    }
+
     let server = ServerProxyService::new(
         config.clone(),
         forward_handler,
         slow_request_logger,
+        proxy_metrics,
+        client_registry,
         future_registry,
+        drain_controller.clone(),
     );
 
     let mut runtime = tokio::runtime::Builder::new()
@@ -166,6 +334,9 @@ fn main() -> Result<(), Box<dyn Error>> {
         .enable_all()
         .build()?;
 
+    runtime.spawn(handle_sigterm(drain_controller));
+    runtime.spawn(handle_sighup(config.clone()));
+
     if let Err(err) = runtime.block_on(server.run()) {
         error!("tokio runtime failed: {}", err);
         return Err(err);
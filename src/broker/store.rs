@@ -1691,6 +1691,22 @@ mod tests {
             cluster_config.compression_strategy,
             CompressionStrategy::SetGetOnly
         );
+        assert!(!cluster_config.requires_auth());
+
+        let mut config = HashMap::new();
+        config.insert("password".to_string(), "pwd".to_string());
+        store.change_config(cluster_name.clone(), config).unwrap();
+
+        let cluster_config = store
+            .get_cluster_by_name(&cluster_name, migration_limit)
+            .unwrap()
+            .get_config();
+        assert!(cluster_config.requires_auth());
+        assert!(cluster_config.check_password(None, b"pwd"));
+        assert_eq!(
+            cluster_config.compression_strategy,
+            CompressionStrategy::SetGetOnly
+        );
     }
 
     #[test]
@@ -1,26 +1,164 @@
+use super::cluster::ClusterName;
 use serde::de::Error;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::convert::TryFrom;
+use std::fmt;
 use std::str::FromStr;
 use std::sync::atomic::{AtomicU64, Ordering};
 
-#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[derive(Clone, PartialEq, Deserialize, Serialize)]
 pub struct ClusterConfig {
     #[serde(default)]
     pub compression_strategy: CompressionStrategy,
     #[serde(default)]
+    pub compression_codec: CompressionCodec,
+    // In bytes. The values smaller than this are stored without compression.
+    #[serde(default)]
+    pub compression_min_size: usize,
+    #[serde(default)]
     pub migration_config: MigrationConfig,
+    // Authentication is disabled when the password is empty.
+    #[serde(default = "default_auth_user")]
+    pub user: String,
+    #[serde(default)]
+    pub password: String,
+    // FLUSHALL and FLUSHDB will remove the data of the whole cluster.
+    #[serde(default)]
+    pub enable_flushall: bool,
+    #[serde(default)]
+    pub read_preference: ReadPreference,
+    // Token bucket limits of the whole cluster on each proxy. 0 means unlimited.
+    #[serde(default)]
+    pub max_ops_per_sec: u64,
+    #[serde(default)]
+    pub max_bytes_per_sec: u64,
+    // The client limits of the cluster on each proxy.
+    // 0 means unlimited for `maxclients`, and using the proxy config for `idle_timeout`.
+    #[serde(default)]
+    pub maxclients: u64,
+    // In seconds
+    #[serde(default)]
+    pub idle_timeout: u64,
+    // All the commands are allowed when `allowed_commands` is empty.
+    #[serde(default)]
+    pub denied_commands: CommandList,
+    #[serde(default)]
+    pub allowed_commands: CommandList,
+    #[serde(default)]
+    pub renamed_commands: CommandRenames,
+    // Prepended to all the keys of the cluster. See `valid_key_prefix`.
+    #[serde(default, deserialize_with = "deserialize_key_prefix")]
+    pub key_prefix: String,
+    // A cluster name or a `host:port` address. Mirroring is disabled when it's empty.
+    // The keyless commands and transactions are not mirrored.
+    #[serde(default)]
+    pub mirror_to: String,
+    // Mirror one out of `mirror_sample_rate` commands. 0 disables the mirroring.
+    #[serde(default = "default_mirror_sample_rate")]
+    pub mirror_sample_rate: u64,
+    // Record the write commands to the audit log of the server proxy.
+    #[serde(default)]
+    pub audit_log: bool,
+}
+
+fn default_auth_user() -> String {
+    DEFAULT_AUTH_USER.to_string()
+}
+
+pub const DEFAULT_AUTH_USER: &str = "default";
+
+fn default_mirror_sample_rate() -> u64 {
+    1
+}
+
+// Compares all the bytes so that the time taken doesn't reveal
+// how many leading bytes of the password are correct.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b.iter()).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+// The prefix can't contain `{` or `}` which would change the hash tag of the prefixed keys.
+pub fn valid_key_prefix(prefix: &str) -> bool {
+    prefix
+        .chars()
+        .all(|c| c != '{' && c != '}' && !c.is_whitespace() && !c.is_control())
+}
+
+fn deserialize_key_prefix<'de, D>(deserializer: D) -> Result<String, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let prefix = String::deserialize(deserializer)?;
+    if !valid_key_prefix(&prefix) {
+        return Err(D::Error::custom(format!("invalid key prefix: {}", prefix)));
+    }
+    Ok(prefix)
 }
 
 impl Default for ClusterConfig {
     fn default() -> Self {
         Self {
             compression_strategy: CompressionStrategy::default(),
+            compression_codec: CompressionCodec::default(),
+            compression_min_size: 0,
             migration_config: MigrationConfig::default(),
+            user: default_auth_user(),
+            password: String::new(),
+            enable_flushall: false,
+            read_preference: ReadPreference::default(),
+            max_ops_per_sec: 0,
+            max_bytes_per_sec: 0,
+            maxclients: 0,
+            idle_timeout: 0,
+            denied_commands: CommandList::default(),
+            allowed_commands: CommandList::default(),
+            renamed_commands: CommandRenames::default(),
+            key_prefix: String::new(),
+            mirror_to: String::new(),
+            mirror_sample_rate: default_mirror_sample_rate(),
+            audit_log: false,
         }
     }
 }
 
+// Hide the password from the logs.
+impl fmt::Debug for ClusterConfig {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let password = if self.password.is_empty() {
+            ""
+        } else {
+            HIDDEN_PASSWORD
+        };
+        f.debug_struct("ClusterConfig")
+            .field("compression_strategy", &self.compression_strategy)
+            .field("compression_codec", &self.compression_codec)
+            .field("compression_min_size", &self.compression_min_size)
+            .field("migration_config", &self.migration_config)
+            .field("user", &self.user)
+            .field("password", &password)
+            .field("enable_flushall", &self.enable_flushall)
+            .field("read_preference", &self.read_preference)
+            .field("max_ops_per_sec", &self.max_ops_per_sec)
+            .field("max_bytes_per_sec", &self.max_bytes_per_sec)
+            .field("maxclients", &self.maxclients)
+            .field("idle_timeout", &self.idle_timeout)
+            .field("denied_commands", &self.denied_commands)
+            .field("allowed_commands", &self.allowed_commands)
+            .field("renamed_commands", &self.renamed_commands)
+            .field("key_prefix", &self.key_prefix)
+            .field("mirror_to", &self.mirror_to)
+            .field("mirror_sample_rate", &self.mirror_sample_rate)
+            .field("audit_log", &self.audit_log)
+            .finish()
+    }
+}
+
+pub const HIDDEN_PASSWORD: &str = "******";
+
 impl ClusterConfig {
     pub fn set_field(&mut self, field: &str, value: &str) -> Result<(), ConfigError> {
         let field = field.to_lowercase();
@@ -30,6 +168,86 @@ impl ClusterConfig {
                     CompressionStrategy::from_str(&value).map_err(|_| ConfigError::InvalidValue)?;
                 self.compression_strategy = strategy;
             }
+            "compression_codec" => {
+                self.compression_codec =
+                    CompressionCodec::from_str(value).map_err(|_| ConfigError::InvalidValue)?;
+            }
+            "compression_min_size" => {
+                self.compression_min_size = value
+                    .parse::<usize>()
+                    .map_err(|_| ConfigError::InvalidValue)?;
+            }
+            "user" => {
+                if value.is_empty() {
+                    return Err(ConfigError::InvalidValue);
+                }
+                self.user = value.to_string();
+            }
+            "password" => self.password = value.to_string(),
+            "enable_flushall" => {
+                self.enable_flushall = value
+                    .parse::<bool>()
+                    .map_err(|_| ConfigError::InvalidValue)?;
+            }
+            "read_preference" => {
+                let preference =
+                    ReadPreference::from_str(value).map_err(|_| ConfigError::InvalidValue)?;
+                self.read_preference = preference;
+            }
+            "max_ops_per_sec" => {
+                self.max_ops_per_sec = value
+                    .parse::<u64>()
+                    .map_err(|_| ConfigError::InvalidValue)?;
+            }
+            "max_bytes_per_sec" => {
+                self.max_bytes_per_sec = value
+                    .parse::<u64>()
+                    .map_err(|_| ConfigError::InvalidValue)?;
+            }
+            "maxclients" => {
+                self.maxclients = value
+                    .parse::<u64>()
+                    .map_err(|_| ConfigError::InvalidValue)?;
+            }
+            "idle_timeout" => {
+                self.idle_timeout = value
+                    .parse::<u64>()
+                    .map_err(|_| ConfigError::InvalidValue)?;
+            }
+            "denied_commands" => {
+                self.denied_commands =
+                    CommandList::from_str(value).map_err(|_| ConfigError::InvalidValue)?;
+            }
+            "allowed_commands" => {
+                self.allowed_commands =
+                    CommandList::from_str(value).map_err(|_| ConfigError::InvalidValue)?;
+            }
+            "renamed_commands" => {
+                self.renamed_commands =
+                    CommandRenames::from_str(value).map_err(|_| ConfigError::InvalidValue)?;
+            }
+            "key_prefix" => {
+                if !valid_key_prefix(value) {
+                    return Err(ConfigError::InvalidValue);
+                }
+                self.key_prefix = value.to_string();
+            }
+            "mirror_to" => {
+                if !value.is_empty() && MirrorTarget::from_str(value).is_err() {
+                    return Err(ConfigError::InvalidValue);
+                }
+                self.mirror_to = value.to_string();
+            }
+            "audit_log" => {
+                self.audit_log = value
+                    .parse::<bool>()
+                    .map_err(|_| ConfigError::InvalidValue)?;
+            }
+            "mirror_sample_rate" => {
+                self.mirror_sample_rate = value
+                    .parse::<u64>()
+                    .map_err(|_| ConfigError::InvalidValue)?;
+            }
             _ => {
                 if field.starts_with("migration_") {
                     let f = field
@@ -45,7 +263,114 @@ impl ClusterConfig {
         Ok(())
     }
 
+    pub fn requires_auth(&self) -> bool {
+        !self.password.is_empty()
+    }
+
+    // `user` is None for the `AUTH <cluster> <password>` form.
+    pub fn check_password(&self, user: Option<&[u8]>, password: &[u8]) -> bool {
+        if !self.requires_auth() {
+            return true;
+        }
+        let user_matched = user
+            .map(|user| constant_time_eq(user, self.user.as_bytes()))
+            .unwrap_or(true);
+        let password_matched = constant_time_eq(password, self.password.as_bytes());
+        user_matched && password_matched
+    }
+
+    // The connections between proxies are not authenticated,
+    // so UMFORWARD and UMSYNC carry `AUTH <cluster> <password>` instead.
+    pub fn gen_forward_auth(&self, cluster_name: &ClusterName) -> Vec<Vec<u8>> {
+        if !self.requires_auth() {
+            return vec![];
+        }
+        vec![
+            b"AUTH".to_vec(),
+            cluster_name.as_bytes(),
+            self.password.as_bytes().to_vec(),
+        ]
+    }
+
+    // Returns the original command name if the command is renamed,
+    // or None if the command is not allowed.
+    pub fn filter_command<'a>(&'a self, cmd_name: &'a str) -> Option<&'a str> {
+        let cmd_name = match self.renamed_commands.get_original(cmd_name) {
+            Some(original) => original,
+            None if self.renamed_commands.is_renamed(cmd_name) => return None,
+            None => cmd_name,
+        };
+        self.check_command_lists(cmd_name)
+    }
+
+    // The commands forwarded by the peer proxies have already been renamed
+    // to the original names, so only the allowed and denied lists are checked.
+    pub fn filter_forwarded_command<'a>(&'a self, cmd_name: &'a str) -> Option<&'a str> {
+        let cmd_name = self
+            .renamed_commands
+            .get_original(cmd_name)
+            .unwrap_or(cmd_name);
+        self.check_command_lists(cmd_name)
+    }
+
+    fn check_command_lists<'a>(&'a self, cmd_name: &'a str) -> Option<&'a str> {
+        if !self.allowed_commands.is_empty() && !self.allowed_commands.contains(cmd_name) {
+            return None;
+        }
+        if self.denied_commands.contains(cmd_name) {
+            return None;
+        }
+        Some(cmd_name)
+    }
+
+    pub fn get_mirror_target(&self) -> Option<MirrorTarget> {
+        if self.mirror_to.is_empty() {
+            return None;
+        }
+        MirrorTarget::from_str(&self.mirror_to).ok()
+    }
+
+    pub fn has_command_filter(&self) -> bool {
+        !self.denied_commands.is_empty()
+            || !self.allowed_commands.is_empty()
+            || !self.renamed_commands.is_empty()
+    }
+
     pub fn to_str_map(&self) -> HashMap<String, String> {
+        // Empty arguments are not allowed in UMCTL SETCLUSTER
+        // so these fields are only sent when they are set.
+        let mut optional_fields = vec![];
+        if self.requires_auth() {
+            optional_fields.push(("password", self.password.clone()));
+        }
+        if !self.denied_commands.is_empty() {
+            optional_fields.push(("denied_commands", self.denied_commands.to_string()));
+        }
+        if !self.allowed_commands.is_empty() {
+            optional_fields.push(("allowed_commands", self.allowed_commands.to_string()));
+        }
+        if !self.renamed_commands.is_empty() {
+            optional_fields.push(("renamed_commands", self.renamed_commands.to_string()));
+        }
+        if !self.key_prefix.is_empty() {
+            optional_fields.push(("key_prefix", self.key_prefix.clone()));
+        }
+        if !self.mirror_to.is_empty() {
+            optional_fields.push(("mirror_to", self.mirror_to.clone()));
+            optional_fields.push(("mirror_sample_rate", self.mirror_sample_rate.to_string()));
+        }
+        if self.audit_log {
+            optional_fields.push(("audit_log", self.audit_log.to_string()));
+        }
+        if self.compression_codec != CompressionCodec::default() {
+            optional_fields.push(("compression_codec", self.compression_codec.to_string()));
+        }
+        if self.compression_min_size != 0 {
+            optional_fields.push((
+                "compression_min_size",
+                self.compression_min_size.to_string(),
+            ));
+        }
         vec![
             (
                 "compression_strategy",
@@ -67,13 +392,45 @@ impl ClusterConfig {
                 "migration_scan_count",
                 self.migration_config.scan_count.to_string(),
             ),
+            ("enable_flushall", self.enable_flushall.to_string()),
+            ("read_preference", self.read_preference.to_str().to_string()),
+            ("max_ops_per_sec", self.max_ops_per_sec.to_string()),
+            ("max_bytes_per_sec", self.max_bytes_per_sec.to_string()),
+            ("maxclients", self.maxclients.to_string()),
+            ("idle_timeout", self.idle_timeout.to_string()),
+            ("user", self.user.clone()),
         ]
         .into_iter()
+        .chain(optional_fields)
         .map(|(k, v)| (k.to_string(), v))
         .collect()
     }
 }
 
+#[derive(Debug, Clone, PartialEq)]
+pub enum MirrorTarget {
+    // Another cluster owned by this proxy.
+    Cluster(ClusterName),
+    // A standalone Redis or another proxy.
+    Address(String),
+}
+
+impl FromStr for MirrorTarget {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.is_empty() {
+            return Err(());
+        }
+        if s.contains(':') {
+            return Ok(MirrorTarget::Address(s.to_string()));
+        }
+        ClusterName::try_from(s)
+            .map(MirrorTarget::Cluster)
+            .map_err(|_| ())
+    }
+}
+
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub enum CompressionStrategy {
     Disabled = 0,
@@ -83,6 +440,10 @@ pub enum CompressionStrategy {
     // Allow all the String commands. User need to use lua script to
     // bypass the compression.
     AllowAll = 2,
+    // Only compress the field values of Hash and leave the String commands untouched.
+    // HINCRBY, HINCRBYFLOAT, HSTRLEN, HSCAN and HRANDFIELD are not allowed
+    // as they will get the wrong result.
+    HashOnly = 3,
 }
 
 impl Default for CompressionStrategy {
@@ -102,6 +463,7 @@ impl FromStr for CompressionStrategy {
             "disabled" => Ok(Self::Disabled),
             "set_get_only" => Ok(Self::SetGetOnly),
             "allow_all" => Ok(Self::AllowAll),
+            "hash_only" => Ok(Self::HashOnly),
             _ => Err(InvalidCompressionStr),
         }
     }
@@ -113,6 +475,7 @@ impl CompressionStrategy {
             Self::Disabled => "disabled",
             Self::SetGetOnly => "set_get_only",
             Self::AllowAll => "allow_all",
+            Self::HashOnly => "hash_only",
         }
     }
 }
@@ -137,6 +500,284 @@ impl<'de> Deserialize<'de> for CompressionStrategy {
     }
 }
 
+// The codec used to compress the values when compression is enabled.
+// The values compressed by any codec could be decompressed
+// no matter which codec is configured.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum CompressionCodec {
+    // The level ranges from 1 to 22.
+    Zstd(i32),
+    Lz4,
+    Snappy,
+}
+
+impl Default for CompressionCodec {
+    fn default() -> Self {
+        CompressionCodec::Zstd(1)
+    }
+}
+
+pub struct InvalidCompressionCodecStr;
+
+// "zstd", "zstd:<level>", "lz4" or "snappy".
+impl FromStr for CompressionCodec {
+    type Err = InvalidCompressionCodecStr;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let lowercase = s.to_lowercase();
+        let mut parts = lowercase.splitn(2, ':');
+        let codec = parts.next().unwrap_or_default();
+        match (codec, parts.next()) {
+            ("zstd", None) => Ok(Self::default()),
+            ("zstd", Some(level)) => match level.parse::<i32>() {
+                Ok(level) if (1..=22).contains(&level) => Ok(Self::Zstd(level)),
+                _ => Err(InvalidCompressionCodecStr),
+            },
+            ("lz4", None) => Ok(Self::Lz4),
+            ("snappy", None) => Ok(Self::Snappy),
+            _ => Err(InvalidCompressionCodecStr),
+        }
+    }
+}
+
+impl fmt::Display for CompressionCodec {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Zstd(level) => write!(f, "zstd:{}", level),
+            Self::Lz4 => write!(f, "lz4"),
+            Self::Snappy => write!(f, "snappy"),
+        }
+    }
+}
+
+impl Serialize for CompressionCodec {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for CompressionCodec {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        Self::from_str(&s).map_err(|_| D::Error::custom(format!("invalid compression codec {}", s)))
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ReadPreference {
+    Master,
+    // Fall back to the master when no replica is available.
+    PreferReplica,
+    // Reply an error when no replica is available.
+    ReplicaOnly,
+}
+
+impl Default for ReadPreference {
+    fn default() -> Self {
+        ReadPreference::Master
+    }
+}
+
+pub struct InvalidReadPreferenceStr;
+
+impl FromStr for ReadPreference {
+    type Err = InvalidReadPreferenceStr;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let lowercase = s.to_lowercase();
+        match lowercase.as_str() {
+            "master" => Ok(Self::Master),
+            "prefer_replica" => Ok(Self::PreferReplica),
+            "replica_only" => Ok(Self::ReplicaOnly),
+            _ => Err(InvalidReadPreferenceStr),
+        }
+    }
+}
+
+impl ReadPreference {
+    pub fn to_str(self) -> &'static str {
+        match self {
+            Self::Master => "master",
+            Self::PreferReplica => "prefer_replica",
+            Self::ReplicaOnly => "replica_only",
+        }
+    }
+}
+
+impl Serialize for ReadPreference {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(self.to_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for ReadPreference {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        Self::from_str(&s).map_err(|_| D::Error::custom(format!("invalid read preference {}", s)))
+    }
+}
+
+pub struct InvalidCommandListStr;
+
+// Comma-separated command names such as "flushall,keys,debug".
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct CommandList(BTreeSet<String>);
+
+impl CommandList {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    // `cmd_name` is case-insensitive.
+    pub fn contains(&self, cmd_name: &str) -> bool {
+        !self.0.is_empty() && self.0.contains(&cmd_name.to_lowercase())
+    }
+}
+
+impl FromStr for CommandList {
+    type Err = InvalidCommandListStr;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut commands = BTreeSet::new();
+        for cmd_name in s.split(',').map(str::trim).filter(|c| !c.is_empty()) {
+            if cmd_name.contains(char::is_whitespace) {
+                return Err(InvalidCommandListStr);
+            }
+            commands.insert(cmd_name.to_lowercase());
+        }
+        Ok(Self(commands))
+    }
+}
+
+impl fmt::Display for CommandList {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let commands: Vec<&str> = self.0.iter().map(|c| c.as_str()).collect();
+        write!(f, "{}", commands.join(","))
+    }
+}
+
+impl Serialize for CommandList {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for CommandList {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        Self::from_str(&s).map_err(|_| D::Error::custom(format!("invalid command list {}", s)))
+    }
+}
+
+// Comma-separated `<original>:<new name>` pairs such as "flushall:myflushall".
+// Like the `rename-command` of Redis, the original name could not be used anymore.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct CommandRenames {
+    // new name => original name
+    renames: BTreeMap<String, String>,
+    originals: BTreeSet<String>,
+}
+
+impl CommandRenames {
+    pub fn is_empty(&self) -> bool {
+        self.renames.is_empty()
+    }
+
+    pub fn get_original(&self, cmd_name: &str) -> Option<&str> {
+        if self.renames.is_empty() {
+            return None;
+        }
+        self.renames
+            .get(&cmd_name.to_lowercase())
+            .map(|original| original.as_str())
+    }
+
+    pub fn is_renamed(&self, cmd_name: &str) -> bool {
+        !self.originals.is_empty() && self.originals.contains(&cmd_name.to_lowercase())
+    }
+}
+
+impl FromStr for CommandRenames {
+    type Err = InvalidCommandListStr;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut renames = BTreeMap::new();
+        let mut originals = BTreeSet::new();
+        for pair in s.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            let mut parts = pair.splitn(2, ':');
+            let (original, new_name) = match (parts.next(), parts.next()) {
+                (Some(original), Some(new_name)) => (
+                    original.trim().to_lowercase(),
+                    new_name.trim().to_lowercase(),
+                ),
+                _ => return Err(InvalidCommandListStr),
+            };
+            if original.is_empty()
+                || new_name.is_empty()
+                || original.contains(char::is_whitespace)
+                || new_name.contains(char::is_whitespace)
+            {
+                return Err(InvalidCommandListStr);
+            }
+            if !originals.insert(original.clone()) {
+                return Err(InvalidCommandListStr);
+            }
+            if renames.insert(new_name, original).is_some() {
+                return Err(InvalidCommandListStr);
+            }
+        }
+        Ok(Self { renames, originals })
+    }
+}
+
+impl fmt::Display for CommandRenames {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let pairs: Vec<String> = self
+            .renames
+            .iter()
+            .map(|(new_name, original)| format!("{}:{}", original, new_name))
+            .collect();
+        write!(f, "{}", pairs.join(","))
+    }
+}
+
+impl Serialize for CommandRenames {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for CommandRenames {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        Self::from_str(&s).map_err(|_| D::Error::custom(format!("invalid renamed commands {}", s)))
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 pub struct MigrationConfig {
     pub max_migration_time: u64,
@@ -265,5 +906,225 @@ mod tests {
             .set_field("migration_scan_count", "666")
             .unwrap();
         assert_eq!(cluster_config.migration_config.scan_count, 666);
+
+        assert!(!cluster_config.enable_flushall);
+        cluster_config.set_field("enable_flushall", "true").unwrap();
+        assert!(cluster_config.enable_flushall);
+        assert!(cluster_config.set_field("enable_flushall", "yes").is_err());
+
+        assert_eq!(cluster_config.read_preference, ReadPreference::Master);
+        cluster_config
+            .set_field("read_preference", "prefer_replica")
+            .unwrap();
+        assert_eq!(
+            cluster_config.read_preference,
+            ReadPreference::PreferReplica
+        );
+        assert!(cluster_config
+            .set_field("read_preference", "replica")
+            .is_err());
+        assert_eq!(
+            cluster_config.to_str_map().get("read_preference").unwrap(),
+            "prefer_replica"
+        );
+
+        cluster_config.set_field("max_ops_per_sec", "1000").unwrap();
+        assert_eq!(cluster_config.max_ops_per_sec, 1000);
+        assert!(cluster_config.set_field("max_bytes_per_sec", "-1").is_err());
+    }
+
+    #[test]
+    fn test_config_check_password() {
+        let cluster_name = ClusterName::try_from("mycluster").unwrap();
+        let mut cluster_config = ClusterConfig::default();
+        assert!(!cluster_config.requires_auth());
+        assert!(cluster_config.check_password(None, b"any"));
+        assert!(!cluster_config.to_str_map().contains_key("password"));
+        assert!(cluster_config.gen_forward_auth(&cluster_name).is_empty());
+
+        cluster_config.set_field("password", "pwd").unwrap();
+        assert!(cluster_config.requires_auth());
+        assert_eq!(
+            cluster_config.gen_forward_auth(&cluster_name),
+            vec![b"AUTH".to_vec(), b"mycluster".to_vec(), b"pwd".to_vec()]
+        );
+        assert!(cluster_config.check_password(None, b"pwd"));
+        assert!(cluster_config.check_password(Some(b"default"), b"pwd"));
+        assert!(!cluster_config.check_password(Some(b"admin"), b"pwd"));
+        assert!(!cluster_config.check_password(None, b"wrong"));
+
+        cluster_config.set_field("user", "admin").unwrap();
+        assert!(cluster_config.check_password(Some(b"admin"), b"pwd"));
+        assert!(!cluster_config.check_password(Some(b"admin"), b"pwe"));
+        assert!(!cluster_config.check_password(Some(b"admin"), b"pwdd"));
+        assert!(cluster_config.set_field("user", "").is_err());
+
+        let str_map = cluster_config.to_str_map();
+        assert_eq!(str_map.get("user").unwrap(), "admin");
+        assert_eq!(str_map.get("password").unwrap(), "pwd");
+    }
+
+    #[test]
+    fn test_config_filter_command() {
+        let mut cluster_config = ClusterConfig::default();
+        assert!(!cluster_config.has_command_filter());
+        assert_eq!(cluster_config.filter_command("KEYS"), Some("KEYS"));
+        assert!(!cluster_config.to_str_map().contains_key("denied_commands"));
+
+        cluster_config
+            .set_field("denied_commands", "FLUSHALL, keys,debug")
+            .unwrap();
+        assert!(cluster_config.has_command_filter());
+        assert_eq!(cluster_config.filter_command("keys"), None);
+        assert_eq!(cluster_config.filter_command("Debug"), None);
+        assert_eq!(cluster_config.filter_command("get"), Some("get"));
+        assert_eq!(
+            cluster_config.to_str_map().get("denied_commands").unwrap(),
+            "debug,flushall,keys"
+        );
+
+        cluster_config
+            .set_field("allowed_commands", "get,set,flushall")
+            .unwrap();
+        assert_eq!(cluster_config.filter_command("get"), Some("get"));
+        assert_eq!(cluster_config.filter_command("del"), None);
+        assert_eq!(cluster_config.filter_command("flushall"), None);
+
+        cluster_config
+            .set_field("renamed_commands", "set:myset")
+            .unwrap();
+        assert_eq!(cluster_config.filter_command("MYSET"), Some("set"));
+        assert_eq!(cluster_config.filter_command("set"), None);
+        assert_eq!(cluster_config.filter_forwarded_command("set"), Some("set"));
+        assert_eq!(
+            cluster_config.filter_forwarded_command("MYSET"),
+            Some("set")
+        );
+        assert_eq!(cluster_config.filter_forwarded_command("flushall"), None);
+        assert_eq!(cluster_config.filter_forwarded_command("del"), None);
+        assert_eq!(
+            cluster_config.to_str_map().get("renamed_commands").unwrap(),
+            "set:myset"
+        );
+
+        assert!(cluster_config
+            .set_field("renamed_commands", "set:a,get:a")
+            .is_err());
+        assert!(cluster_config.set_field("renamed_commands", "set").is_err());
+        assert!(cluster_config
+            .set_field("denied_commands", "flush all")
+            .is_err());
+
+        cluster_config.set_field("allowed_commands", "").unwrap();
+        assert!(cluster_config.allowed_commands.is_empty());
+        assert_eq!(cluster_config.filter_command("del"), Some("del"));
+    }
+
+    #[test]
+    fn test_command_list_serde() {
+        let mut cluster_config = ClusterConfig::default();
+        cluster_config
+            .set_field("denied_commands", "keys,flushall")
+            .unwrap();
+        cluster_config
+            .set_field("renamed_commands", "config:myconfig")
+            .unwrap();
+        let json = serde_json::to_string(&cluster_config).unwrap();
+        let config: ClusterConfig = serde_json::from_str(&json).unwrap();
+        assert_eq!(config, cluster_config);
+    }
+
+    #[test]
+    fn test_config_key_prefix() {
+        let mut cluster_config = ClusterConfig::default();
+        assert!(!cluster_config.to_str_map().contains_key("key_prefix"));
+        cluster_config.set_field("key_prefix", "tenant1:").unwrap();
+        assert_eq!(
+            cluster_config.to_str_map().get("key_prefix").unwrap(),
+            "tenant1:"
+        );
+        for prefix in ["{tenant1}", "t}", "t 1"].iter() {
+            assert!(cluster_config.set_field("key_prefix", prefix).is_err());
+        }
+        assert_eq!(cluster_config.key_prefix, "tenant1:");
+
+        let json = serde_json::to_string(&cluster_config).unwrap();
+        let config: ClusterConfig = serde_json::from_str(&json).unwrap();
+        assert_eq!(config, cluster_config);
+        let json = json.replace("tenant1:", "{tenant1}");
+        assert!(serde_json::from_str::<ClusterConfig>(&json).is_err());
+    }
+
+    #[test]
+    fn test_config_mirror_to() {
+        let mut cluster_config = ClusterConfig::default();
+        assert_eq!(cluster_config.get_mirror_target(), None);
+        assert!(!cluster_config.to_str_map().contains_key("mirror_to"));
+
+        cluster_config.set_field("mirror_to", "shadow").unwrap();
+        assert_eq!(
+            cluster_config.get_mirror_target(),
+            Some(MirrorTarget::Cluster(
+                ClusterName::try_from("shadow").unwrap()
+            ))
+        );
+        cluster_config
+            .set_field("mirror_to", "127.0.0.1:6379")
+            .unwrap();
+        assert_eq!(
+            cluster_config.get_mirror_target(),
+            Some(MirrorTarget::Address("127.0.0.1:6379".to_string()))
+        );
+        cluster_config
+            .set_field("mirror_sample_rate", "10")
+            .unwrap();
+        let fields = cluster_config.to_str_map();
+        assert_eq!(fields.get("mirror_to").unwrap(), "127.0.0.1:6379");
+        assert_eq!(fields.get("mirror_sample_rate").unwrap(), "10");
+
+        let too_long = "a".repeat(1000);
+        assert!(cluster_config.set_field("mirror_to", &too_long).is_err());
+        assert!(cluster_config
+            .set_field("mirror_sample_rate", "-1")
+            .is_err());
+        cluster_config.set_field("mirror_to", "").unwrap();
+        assert_eq!(cluster_config.get_mirror_target(), None);
+    }
+
+    #[test]
+    fn test_config_compression_codec() {
+        let mut cluster_config = ClusterConfig::default();
+        assert_eq!(cluster_config.compression_codec, CompressionCodec::Zstd(1));
+        assert!(!cluster_config
+            .to_str_map()
+            .contains_key("compression_codec"));
+
+        cluster_config
+            .set_field("compression_codec", "zstd:3")
+            .unwrap();
+        assert_eq!(cluster_config.compression_codec, CompressionCodec::Zstd(3));
+        cluster_config
+            .set_field("compression_codec", "LZ4")
+            .unwrap();
+        assert_eq!(cluster_config.compression_codec, CompressionCodec::Lz4);
+        cluster_config
+            .set_field("compression_codec", "snappy")
+            .unwrap();
+        cluster_config
+            .set_field("compression_min_size", "64")
+            .unwrap();
+        let fields = cluster_config.to_str_map();
+        assert_eq!(fields.get("compression_codec").unwrap(), "snappy");
+        assert_eq!(fields.get("compression_min_size").unwrap(), "64");
+
+        for codec in ["zstd:0", "zstd:23", "lz4:1", "gzip"].iter() {
+            assert!(cluster_config
+                .set_field("compression_codec", codec)
+                .is_err());
+        }
+
+        let json = serde_json::to_string(&cluster_config).unwrap();
+        let config: ClusterConfig = serde_json::from_str(&json).unwrap();
+        assert_eq!(config, cluster_config);
     }
 }
@@ -5,6 +5,7 @@ pub mod future_group;
 pub mod proto;
 pub mod resp_execution;
 pub mod response;
+pub mod tls;
 pub mod track;
 pub mod try_chunks;
 pub mod utils;
@@ -1,7 +1,7 @@
 use super::cluster::SlotRange;
 use super::utils::{has_flags, CmdParseError};
 use crate::common::cluster::ClusterName;
-use crate::common::config::ClusterConfig;
+use crate::common::config::{ClusterConfig, HIDDEN_PASSWORD};
 use crate::protocol::{Array, BulkStr, Resp};
 use std::collections::HashMap;
 use std::convert::TryFrom;
@@ -350,6 +350,22 @@ impl ClusterConfigMap {
 #[derive(Debug)]
 pub struct ParseExtendedMetaError {}
 
+// Replaces the password in the arguments generated by `ProxyClusterMeta::to_args` for logging.
+pub fn hide_password_args(args: &[String]) -> Vec<&str> {
+    let mut hidden: Vec<&str> = args.iter().map(|arg| arg.as_str()).collect();
+    let config_start = match hidden.iter().position(|arg| *arg == CONFIG_PREFIX) {
+        Some(pos) => pos + 1,
+        None => return hidden,
+    };
+    // The config arguments are in the form of `<cluster> <field> <value>`.
+    for i in (config_start..hidden.len()).step_by(3) {
+        if hidden.get(i + 1) == Some(&"password") && i + 2 < hidden.len() {
+            hidden[i + 2] = HIDDEN_PASSWORD;
+        }
+    }
+    hidden
+}
+
 #[cfg(test)]
 mod tests {
     use super::super::config::CompressionStrategy;
@@ -549,6 +565,9 @@ mod tests {
             "mycluster",
             "migration_max_migration_time",
             "666",
+            "mycluster",
+            "password",
+            "pwd",
         ];
         let mut it = args.iter().map(|s| s.to_string()).peekable();
         let clusters_config = ClusterConfigMap::parse(&mut it).unwrap();
@@ -580,6 +599,10 @@ mod tests {
                 .max_blocking_time,
             66699
         );
+        assert_eq!(
+            clusters_config.config_map.get(&mycluster).unwrap().password,
+            "pwd"
+        );
 
         let mut result_args = clusters_config.to_args();
 
@@ -599,6 +622,30 @@ mod tests {
             "mycluster",
             "migration_scan_count",
             "16",
+            "mycluster",
+            "user",
+            "default",
+            "mycluster",
+            "enable_flushall",
+            "false",
+            "mycluster",
+            "read_preference",
+            "master",
+            "mycluster",
+            "max_ops_per_sec",
+            "0",
+            "mycluster",
+            "max_bytes_per_sec",
+            "0",
+            "mycluster",
+            "maxclients",
+            "0",
+            "mycluster",
+            "idle_timeout",
+            "0",
+            "mycluster",
+            "password",
+            "pwd",
             "othercluster",
             "compression_strategy",
             "disabled",
@@ -614,6 +661,27 @@ mod tests {
             "othercluster",
             "migration_scan_count",
             "16",
+            "othercluster",
+            "user",
+            "default",
+            "othercluster",
+            "enable_flushall",
+            "false",
+            "othercluster",
+            "read_preference",
+            "master",
+            "othercluster",
+            "max_ops_per_sec",
+            "0",
+            "othercluster",
+            "max_bytes_per_sec",
+            "0",
+            "othercluster",
+            "maxclients",
+            "0",
+            "othercluster",
+            "idle_timeout",
+            "0",
         ];
         result_args.sort();
         full_args.sort();
@@ -772,6 +840,27 @@ mod tests {
             "cluster_name",
             "migration_scan_count",
             "16",
+            "cluster_name",
+            "user",
+            "default",
+            "cluster_name",
+            "enable_flushall",
+            "false",
+            "cluster_name",
+            "read_preference",
+            "master",
+            "cluster_name",
+            "max_ops_per_sec",
+            "0",
+            "cluster_name",
+            "max_bytes_per_sec",
+            "0",
+            "cluster_name",
+            "maxclients",
+            "0",
+            "cluster_name",
+            "idle_timeout",
+            "0",
         ]
         .into_iter()
         .map(|s| s.to_string());
@@ -845,6 +934,33 @@ mod tests {
         assert!(cluster_meta.flags.force);
     }
 
+    #[test]
+    fn test_hide_password_args() {
+        let arguments = vec![
+            "233",
+            "NOFLAGS",
+            "cluster_name",
+            "127.0.0.1:7000",
+            "1",
+            "0-1000",
+            "CONFIG",
+            "cluster_name",
+            "user",
+            "password",
+            "cluster_name",
+            "password",
+            "pwd",
+        ];
+        let args: Vec<String> = arguments.iter().map(|s| s.to_string()).collect();
+        let mut expected = arguments.clone();
+        expected[12] = HIDDEN_PASSWORD;
+        assert_eq!(hide_password_args(&args), expected);
+
+        let mut config = ClusterConfig::default();
+        config.set_field("password", "pwd").unwrap();
+        assert!(!format!("{:?}", config).contains("pwd"));
+    }
+
     #[test]
     fn test_invalid_config_field() {
         let arguments = vec![
@@ -6,8 +6,32 @@ pub const TASK_NOT_FOUND: &str = "TASK_NOT_FOUND";
 pub const ERR_NOT_THE_SAME_SLOT: &str = "ERR_MULTI_SLOTS slots of the keys are not the same";
 pub const ERR_CLUSTER_NOT_FOUND: &str = "ERR_CLUSTER_NOT_FOUND";
 pub const ERR_BACKEND_CONNECTION: &str = "ERR_BACKEND_CONNECTION";
+pub const ERR_BACKEND_CIRCUIT_OPEN: &str = "ERR_BACKEND_CIRCUIT_OPEN";
+pub const ERR_BACKEND_TIMEOUT: &str = "ERR_BACKEND_TIMEOUT";
+pub const ERR_TIMEOUT: &str = "TIMEOUT command timed out in the proxy";
 pub const ERR_MOVED: &str = "MOVED";
 pub const CMD_NOT_SUPPORTED: &str = "ERR_COMMAND_NOT_SUPPORTED";
 pub const ERR_TOO_MANY_REDIRECTIONS: &str = "ERR_TOO_MANY_REDIRECTIONS";
 pub const MIGRATING_FINISHED: &str = "MIGRATING_FINISHED";
 pub const MIGRATION_TASK_NOT_FOUND: &str = "MIGRATION_TASK_NOT_FOUND";
+pub const QUEUED_REPLY: &str = "QUEUED";
+pub const ERR_CROSS_SLOT: &str = "CROSSSLOT Keys in request don't hash to the same slot";
+pub const ERR_EXEC_ABORT: &str = "EXECABORT Transaction discarded because of previous errors.";
+pub const ERR_NOAUTH: &str = "NOAUTH Authentication required.";
+pub const ERR_WRONG_PASS: &str = "WRONGPASS invalid username-password pair";
+pub const ERR_NOPROTO: &str = "NOPROTO unsupported protocol version";
+pub const ERR_FLUSHALL_DISABLED: &str = "ERR FLUSHALL is disabled for this cluster";
+pub const ERR_MAX_CLIENTS: &str = "ERR max number of clients reached";
+pub const ERR_RATE_LIMITED: &str = "ERR rate limited";
+pub const ERR_NOPERM: &str = "NOPERM this command is not allowed for this cluster";
+pub const ERR_KEY_PREFIX_UNSUPPORTED: &str =
+    "ERR this command is not supported when key_prefix is set";
+pub const ERR_KEY_PREFIX_UNSUPPORTED_KEY: &str =
+    "ERR keys containing '}' without hash tag are not supported when key_prefix is set";
+pub const ERR_NO_SUCH_CLIENT: &str = "ERR No such client";
+pub const ERR_INVALID_CLIENT_NAME: &str =
+    "ERR Client names cannot contain spaces, newlines or special characters.";
+pub const ERR_NO_AVAILABLE_REPLICA: &str = "ERR no available replica for this slot";
+pub const ERR_ATOMIC_CROSS_SLOT: &str =
+    "CROSSSLOT Keys of this atomic command must hash to the same slot";
+pub const ERR_PFCOUNT_CROSS_SLOT: &str = "CROSSSLOT Keys of PFCOUNT must hash to the same slot";
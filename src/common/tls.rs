@@ -0,0 +1,370 @@
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::io::BufReader;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpStream;
+use tokio_rustls::client::TlsStream;
+use tokio_rustls::rustls::internal::pemfile::{certs, pkcs8_private_keys, rsa_private_keys};
+use tokio_rustls::rustls::{
+    AllowAnyAuthenticatedClient, Certificate, ClientConfig, NoClientAuth, PrivateKey,
+    RootCertStore, ServerConfig, TLSError,
+};
+use tokio_rustls::webpki::DNSNameRef;
+use tokio_rustls::{TlsAcceptor, TlsConnector};
+
+pub fn load_certs(path: &str) -> Result<Vec<Certificate>, TlsError> {
+    let mut reader = BufReader::new(File::open(path).map_err(TlsError::Io)?);
+    let certs = certs(&mut reader).map_err(|()| TlsError::InvalidCert)?;
+    if certs.is_empty() {
+        return Err(TlsError::InvalidCert);
+    }
+    Ok(certs)
+}
+
+// Both PKCS8 and RSA private keys are supported.
+pub fn load_private_key(path: &str) -> Result<PrivateKey, TlsError> {
+    let mut reader = BufReader::new(File::open(path).map_err(TlsError::Io)?);
+    let keys = pkcs8_private_keys(&mut reader).map_err(|()| TlsError::InvalidKey)?;
+    if let Some(key) = keys.into_iter().next() {
+        return Ok(key);
+    }
+
+    let mut reader = BufReader::new(File::open(path).map_err(TlsError::Io)?);
+    let keys = rsa_private_keys(&mut reader).map_err(|()| TlsError::InvalidKey)?;
+    keys.into_iter().next().ok_or(TlsError::InvalidKey)
+}
+
+pub fn load_root_store(path: &str) -> Result<RootCertStore, TlsError> {
+    let mut reader = BufReader::new(File::open(path).map_err(TlsError::Io)?);
+    let mut root_store = RootCertStore::empty();
+    let (valid, _invalid) = root_store
+        .add_pem_file(&mut reader)
+        .map_err(|()| TlsError::InvalidCA)?;
+    if valid == 0 {
+        return Err(TlsError::InvalidCA);
+    }
+    Ok(root_store)
+}
+
+// When `client_ca_path` is set, clients need to provide the certificates signed by it.
+pub fn create_tls_acceptor(
+    cert_path: &str,
+    key_path: &str,
+    client_ca_path: Option<&str>,
+) -> Result<TlsAcceptor, TlsError> {
+    let certs = load_certs(cert_path)?;
+    let key = load_private_key(key_path)?;
+
+    let mut config = match client_ca_path {
+        Some(path) => ServerConfig::new(AllowAnyAuthenticatedClient::new(load_root_store(path)?)),
+        None => ServerConfig::new(NoClientAuth::new()),
+    };
+    config
+        .set_single_cert(certs, key)
+        .map_err(TlsError::Rustls)?;
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
+
+// Used to connect to the TLS enabled Redis and other server proxies.
+#[derive(Clone)]
+pub struct TlsClientConnector {
+    connector: TlsConnector,
+    // The backend addresses are usually IPs,
+    // so the name in the certificates need to be specified.
+    server_name: String,
+}
+
+impl TlsClientConnector {
+    pub fn new(
+        ca_path: &str,
+        cert_key_path: Option<(&str, &str)>,
+        server_name: String,
+    ) -> Result<Self, TlsError> {
+        DNSNameRef::try_from_ascii_str(&server_name).map_err(|_| TlsError::InvalidServerName)?;
+
+        let mut config = ClientConfig::new();
+        config.root_store = load_root_store(ca_path)?;
+        // Redis requires client certificates by default.
+        if let Some((cert_path, key_path)) = cert_key_path {
+            config
+                .set_single_client_cert(load_certs(cert_path)?, load_private_key(key_path)?)
+                .map_err(TlsError::Rustls)?;
+        }
+        Ok(Self {
+            connector: TlsConnector::from(Arc::new(config)),
+            server_name,
+        })
+    }
+
+    pub async fn connect<S>(&self, sock: S) -> io::Result<TlsStream<S>>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        let domain = DNSNameRef::try_from_ascii_str(&self.server_name)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "invalid server name"))?;
+        self.connector.connect(domain, sock).await
+    }
+}
+
+#[derive(Debug)]
+pub enum MaybeTlsStream {
+    Plain(TcpStream),
+    Tls(Box<TlsStream<TcpStream>>),
+}
+
+impl MaybeTlsStream {
+    pub async fn connect(
+        sock: TcpStream,
+        tls_connector: Option<&TlsClientConnector>,
+    ) -> io::Result<Self> {
+        match tls_connector {
+            Some(connector) => {
+                let sock = connector.connect(sock).await?;
+                Ok(Self::Tls(Box::new(sock)))
+            }
+            None => Ok(Self::Plain(sock)),
+        }
+    }
+}
+
+impl AsyncRead for MaybeTlsStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            Self::Plain(sock) => Pin::new(sock).poll_read(cx, buf),
+            Self::Tls(sock) => Pin::new(sock).poll_read(cx, buf),
+        }
+    }
+}
+
+impl AsyncWrite for MaybeTlsStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            Self::Plain(sock) => Pin::new(sock).poll_write(cx, buf),
+            Self::Tls(sock) => Pin::new(sock).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Self::Plain(sock) => Pin::new(sock).poll_flush(cx),
+            Self::Tls(sock) => Pin::new(sock).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Self::Plain(sock) => Pin::new(sock).poll_shutdown(cx),
+            Self::Tls(sock) => Pin::new(sock).poll_shutdown(cx),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum TlsError {
+    Io(io::Error),
+    InvalidCert,
+    InvalidKey,
+    InvalidCA,
+    InvalidServerName,
+    Rustls(TLSError),
+}
+
+impl fmt::Display for TlsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl Error for TlsError {
+    fn description(&self) -> &str {
+        "tls error"
+    }
+
+    fn cause(&self) -> Option<&dyn Error> {
+        match self {
+            TlsError::Io(err) => Some(err),
+            TlsError::Rustls(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::protocol::{new_simple_packet_codec, Array, BulkStr, Resp, RespCodec, RespPacket};
+    use crate::proxy::command::Command;
+    use futures::{SinkExt, StreamExt};
+    use matches::assert_matches;
+    use rcgen::{BasicConstraints, Certificate as GenCert, CertificateParams, IsCa};
+    use std::env;
+    use std::fs;
+    use std::path::PathBuf;
+    use std::process;
+    use tokio::net::UnixStream;
+    use tokio_util::codec::Decoder;
+
+    // Self-signed CA with a server and a client certificates signed by it.
+    pub struct TestingCerts {
+        dir: PathBuf,
+    }
+
+    impl TestingCerts {
+        pub fn generate(name: &str) -> Self {
+            let dir = env::temp_dir().join(format!("undermoon-tls-{}-{}", name, process::id()));
+            fs::create_dir_all(&dir).unwrap();
+
+            let mut ca_params = CertificateParams::new(vec![]);
+            ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+            let ca = GenCert::from_params(ca_params).unwrap();
+            let server =
+                GenCert::from_params(CertificateParams::new(vec!["localhost".to_string()]))
+                    .unwrap();
+            let client =
+                GenCert::from_params(CertificateParams::new(vec!["client".to_string()])).unwrap();
+
+            let files = vec![
+                ("ca.pem", ca.serialize_pem().unwrap()),
+                ("server.pem", server.serialize_pem_with_signer(&ca).unwrap()),
+                ("server.key", server.serialize_private_key_pem()),
+                ("client.pem", client.serialize_pem_with_signer(&ca).unwrap()),
+                ("client.key", client.serialize_private_key_pem()),
+            ];
+            for (file, content) in files.into_iter() {
+                fs::write(dir.join(file), content).unwrap();
+            }
+            Self { dir }
+        }
+
+        pub fn path(&self, file: &str) -> String {
+            self.dir.join(file).to_str().unwrap().to_string()
+        }
+    }
+
+    impl Drop for TestingCerts {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    fn gen_connector(
+        certs: &TestingCerts,
+        with_client_cert: bool,
+        server_name: &str,
+    ) -> TlsClientConnector {
+        let cert_path = certs.path("client.pem");
+        let key_path = certs.path("client.key");
+        let cert_key_path = if with_client_cert {
+            Some((cert_path.as_str(), key_path.as_str()))
+        } else {
+            None
+        };
+        TlsClientConnector::new(
+            &certs.path("ca.pem"),
+            cert_key_path,
+            server_name.to_string(),
+        )
+        .unwrap()
+    }
+
+    fn gen_ping() -> Box<RespPacket> {
+        Box::new(RespPacket::Data(Resp::Arr(Array::Arr(vec![Resp::Bulk(
+            BulkStr::Str(b"PING".to_vec()),
+        )]))))
+    }
+
+    async fn send_ping(
+        acceptor: TlsAcceptor,
+        connector: TlsClientConnector,
+    ) -> Result<Box<RespPacket>, io::Error> {
+        let (server_sock, client_sock) = UnixStream::pair()?;
+
+        let server = tokio::spawn(async move {
+            let sock = acceptor.accept(server_sock).await?;
+            let (encoder, decoder) = new_simple_packet_codec::<Box<RespPacket>, Box<RespPacket>>();
+            let (_writer, mut reader) = RespCodec::new(encoder, decoder).framed(sock).split();
+            match reader.next().await {
+                Some(Ok(packet)) => Ok(packet),
+                _ => Err(io::Error::from(io::ErrorKind::InvalidData)),
+            }
+        });
+
+        let sock = connector.connect(client_sock).await?;
+        let (encoder, decoder) = new_simple_packet_codec::<Box<RespPacket>, Box<RespPacket>>();
+        let (mut writer, _reader) = RespCodec::new(encoder, decoder).framed(sock).split();
+        writer
+            .send(gen_ping())
+            .await
+            .map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))?;
+
+        server.await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_tls_resp_codec() {
+        let certs = TestingCerts::generate("codec");
+        let acceptor =
+            create_tls_acceptor(&certs.path("server.pem"), &certs.path("server.key"), None)
+                .unwrap();
+        let packet = send_ping(acceptor, gen_connector(&certs, false, "localhost"))
+            .await
+            .unwrap();
+        assert_eq!(Command::new(packet).get_command_name(), Some("PING"));
+    }
+
+    #[tokio::test]
+    async fn test_mutual_tls() {
+        let certs = TestingCerts::generate("mtls");
+        let client_ca = certs.path("ca.pem");
+        let acceptor = create_tls_acceptor(
+            &certs.path("server.pem"),
+            &certs.path("server.key"),
+            Some(client_ca.as_str()),
+        )
+        .unwrap();
+        let packet = send_ping(acceptor.clone(), gen_connector(&certs, true, "localhost"))
+            .await
+            .unwrap();
+        assert_eq!(Command::new(packet).get_command_name(), Some("PING"));
+
+        let res = send_ping(acceptor, gen_connector(&certs, false, "localhost")).await;
+        assert!(res.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_server_name_mismatch() {
+        let certs = TestingCerts::generate("server_name");
+        let acceptor =
+            create_tls_acceptor(&certs.path("server.pem"), &certs.path("server.key"), None)
+                .unwrap();
+        let res = send_ping(acceptor, gen_connector(&certs, false, "otherhost")).await;
+        assert!(res.is_err());
+
+        let res = TlsClientConnector::new(&certs.path("ca.pem"), None, "127.0.0.1".to_string());
+        assert_matches!(res.err(), Some(TlsError::InvalidServerName));
+    }
+
+    #[test]
+    fn test_invalid_cert_files() {
+        let certs = TestingCerts::generate("invalid");
+        let res = create_tls_acceptor(&certs.path("server.key"), &certs.path("server.key"), None);
+        assert_matches!(res.err(), Some(TlsError::InvalidCert));
+        let res = create_tls_acceptor(&certs.path("server.pem"), &certs.path("ca.pem"), None);
+        assert_matches!(res.err(), Some(TlsError::InvalidKey));
+        let res = create_tls_acceptor(&certs.path("not_exist"), &certs.path("server.key"), None);
+        assert_matches!(res.err(), Some(TlsError::Io(_)));
+    }
+}
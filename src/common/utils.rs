@@ -125,6 +125,20 @@ pub fn array_append_front(resp: &mut RespVec, preceding_elements: Vec<BinSafeStr
     }
 }
 
+pub fn array_append_back(resp: &mut RespVec, following_elements: Vec<BinSafeStr>) -> bool {
+    match resp {
+        Resp::Arr(Array::Arr(ref mut resps)) => {
+            resps.extend(
+                following_elements
+                    .into_iter()
+                    .map(|s| Resp::Bulk(BulkStr::Str(s))),
+            );
+            true
+        }
+        _ => false,
+    }
+}
+
 pub fn change_bulk_str(resp: &mut RespVec, data: Vec<u8>) -> bool {
     match resp {
         Resp::Bulk(BulkStr::Str(s)) => {
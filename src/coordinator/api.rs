@@ -3,15 +3,18 @@ use super::service::CoordinatorConfig;
 use crate::common::response;
 use crate::common::track::TrackedFutureRegistry;
 use crate::common::utils::resolve_first_address;
-use crate::protocol::{Array, BulkStr, Resp, RespPacket, RespVec};
+use crate::protocol::{Array, BulkStr, Resp, RespPacket, RespVec, RespVersion};
+use crate::proxy::backend::BackendError;
 use crate::proxy::command::{new_command_pair, CmdType, Command, TaskReply};
-use crate::proxy::session::{handle_session, CmdHandler, CmdReplyFuture};
+use crate::proxy::drain::DrainSignal;
+use crate::proxy::session::{handle_session, CmdHandler, CmdReplyFuture, PubSubConnFuture};
 use crate::proxy::slowlog::Slowlog;
-use futures::{FutureExt, StreamExt};
+use futures::{future, FutureExt, StreamExt};
 use std::num::NonZeroUsize;
 use std::str;
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::net::TcpListener;
 
 const SESSION_CHANNEL_SIZE: usize = 1024;
@@ -82,6 +85,7 @@ impl ApiService {
                 SESSION_BATCH_MIN_TIME,
                 SESSION_BATCH_MAX_TIME,
                 session_batch_buf,
+                DrainSignal::never(),
             );
 
             let desc = format!("session: session_id={} peer={}", curr_session_id, peer);
@@ -230,4 +234,27 @@ impl CmdHandler for CoordCmdHandler {
     }
 
     fn handle_slowlog(&self, _request: Box<RespPacket>, _slowlog: Slowlog) {}
+
+    fn handle_metrics(&self, _request: &RespPacket, _reply: &RespPacket, _slowlog: &Slowlog) {}
+
+    fn get_idle_timeout(&self) -> Option<Duration> {
+        None
+    }
+
+    fn handle_idle_timeout(&self) {}
+
+    fn handle_reply_done(&self) {}
+
+    fn get_cmd_timeout(&self, _cmd: &Command) -> Option<Duration> {
+        None
+    }
+
+    // Pub/sub is not supported in coordinator.
+    fn create_pubsub_conn(&self) -> Result<PubSubConnFuture, RespVec> {
+        Ok(Box::pin(future::err(BackendError::NodeNotFound)))
+    }
+
+    fn get_resp_version(&self) -> RespVersion {
+        RespVersion::V2
+    }
 }
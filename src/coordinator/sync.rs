@@ -1,7 +1,9 @@
 use super::broker::MetaDataBroker;
 use super::core::{CoordinateError, ProxyMetaRetriever, ProxyMetaSender};
 use crate::common::cluster::{ClusterName, Proxy, Role, SlotRange};
-use crate::common::proto::{ClusterConfigMap, ClusterMapFlags, ProxyClusterMap, ProxyClusterMeta};
+use crate::common::proto::{
+    hide_password_args, ClusterConfigMap, ClusterMapFlags, ProxyClusterMap, ProxyClusterMeta,
+};
 use crate::common::response::{OK_REPLY, OLD_EPOCH_REPLY};
 use crate::protocol::{RedisClient, RedisClientFactory, Resp};
 use crate::replication::replicator::{encode_repl_meta, MasterMeta, ReplicaMeta, ReplicatorMeta};
@@ -125,7 +127,11 @@ async fn send_meta<C: RedisClient>(
     sub_command: String,
     args: Vec<String>,
 ) -> Result<(), CoordinateError> {
-    trace!("sending meta {} {:?}", sub_command, args);
+    trace!(
+        "sending meta {} {:?}",
+        sub_command,
+        hide_password_args(&args)
+    );
     let mut cmd = vec!["UMCTL".to_string(), sub_command.clone()];
     cmd.extend(args);
     let resp = client
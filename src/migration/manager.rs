@@ -12,6 +12,7 @@ use crate::proxy::backend::{CmdTask, CmdTaskFactory, ReqTask};
 use crate::proxy::blocking::{BlockingHintTask, TaskBlockingControllerFactory};
 use crate::proxy::cluster::{ClusterSendError, ClusterTag};
 use crate::proxy::command::CmdTypeTuple;
+use crate::proxy::metrics::MigrationMetrics;
 use crate::proxy::sender::{CmdTaskSender, CmdTaskSenderFactory};
 use crate::proxy::service::ServerProxyConfig;
 use crate::proxy::slowlog::TaskEvent;
@@ -297,6 +298,19 @@ where
         }
     }
 
+    pub fn is_migrating(&self, cluster_name: &ClusterName, slot: usize) -> bool {
+        if self.empty {
+            return false;
+        }
+        match self.task_map.get(cluster_name) {
+            Some(tasks) => tasks.values().any(|mgr_task| match &mgr_task.task {
+                Either::Left(migrating_task) => migrating_task.contains_slot(slot),
+                Either::Right(importing_task) => importing_task.contains_slot(slot),
+            }),
+            None => false,
+        }
+    }
+
     pub fn send_sync_task(
         &self,
         mut cmd_task: T,
@@ -475,6 +489,10 @@ where
                                 continue;
                             }
 
+                            let forward_auth = cluster_config_map
+                                .get(cluster_name)
+                                .map(|cluster_config| cluster_config.gen_forward_auth(cluster_name))
+                                .unwrap_or_default();
                             let task = Arc::new(RedisScanImportingTask::new(
                                 config.clone(),
                                 mgr_config.clone(),
@@ -484,6 +502,7 @@ where
                                 sender_factory.clone(),
                                 proxy_sender_factory.clone(),
                                 cmd_task_factory.clone(),
+                                forward_auth,
                             ));
                             new_tasks.push(NewTask {
                                 cluster_name: cluster_name.clone(),
@@ -555,6 +574,36 @@ where
         Err(SwitchError::TaskNotFound)
     }
 
+    pub fn get_migration_metrics(&self) -> Vec<MigrationMetrics> {
+        let mut metrics = vec![];
+        for (cluster_name, tasks) in self.task_map.iter() {
+            for (task_meta, mgr_task) in tasks.iter() {
+                let migration_meta = match task_meta.slot_range.tag.get_migration_meta() {
+                    Some(migration_meta) => migration_meta,
+                    None => continue,
+                };
+                let (migrating, state) = match &mgr_task.task {
+                    Either::Left(task) => (true, task.get_state()),
+                    Either::Right(task) => (false, task.get_state()),
+                };
+                metrics.push(MigrationMetrics {
+                    cluster_name: cluster_name.clone(),
+                    slot_range: task_meta
+                        .slot_range
+                        .range_list
+                        .clone()
+                        .to_strings()
+                        .join(" "),
+                    src_node: migration_meta.src_node_address.clone(),
+                    dst_node: migration_meta.dst_node_address.clone(),
+                    migrating,
+                    state,
+                });
+            }
+        }
+        metrics
+    }
+
     pub fn get_finished_tasks(&self) -> Vec<MigrationTaskMeta> {
         let mut metadata = vec![];
         {
@@ -573,6 +622,25 @@ where
         metadata
     }
 
+    // The commands are not blocked and the slots are not being switched.
+    pub fn is_at_safe_point(&self) -> bool {
+        self.task_map
+            .values()
+            .flat_map(|tasks| tasks.values())
+            .all(|mgr_task| {
+                let state = match &mgr_task.task {
+                    Either::Left(migrating_task) => migrating_task.get_state(),
+                    Either::Right(importing_task) => importing_task.get_state(),
+                };
+                matches!(
+                    state,
+                    MigrationState::PreCheck
+                        | MigrationState::Scanning
+                        | MigrationState::SwitchCommitted
+                )
+            })
+    }
+
     pub fn get_states(&self, cluster_name: &ClusterName) -> HashMap<RangeList, MigrationState> {
         let mut m = HashMap::new();
         if let Some(tasks) = self.task_map.get(cluster_name) {
@@ -12,7 +12,7 @@ use crate::common::response;
 use crate::common::utils::{gen_moved, pretty_print_bytes, ThreadSafe};
 use crate::common::version::UNDERMOON_MIGRATION_VERSION;
 use crate::protocol::{
-    PreCheckRedisClientFactory, RedisClientError, RedisClientFactory, Resp, RespVec,
+    BinSafeStr, PreCheckRedisClientFactory, RedisClientError, RedisClientFactory, Resp, RespVec,
 };
 use crate::proxy::backend::{CmdTask, CmdTaskFactory, ReqTask};
 use crate::proxy::blocking::{BlockingHandle, BlockingHintTask, TaskBlockingController};
@@ -512,6 +512,7 @@ where
         sender_factory: Arc<TSF>,
         proxy_sender_factory: Arc<PTSF>,
         cmd_task_factory: Arc<CTF>,
+        forward_auth: Vec<BinSafeStr>,
     ) -> Self {
         let src_sender = sender_factory.create(meta.src_node_address.clone());
         let dst_sender = sender_factory.create(meta.dst_node_address.clone());
@@ -521,6 +522,7 @@ where
             dst_sender,
             src_proxy_sender,
             cmd_task_factory.clone(),
+            forward_auth,
         );
         let (stop_signal_sender, stop_signal_receiver) = oneshot::channel();
         let range_map = RangeMap::from(slot_range.get_range_list());
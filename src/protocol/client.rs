@@ -1,4 +1,5 @@
 use super::resp::{BinSafeStr, RespVec};
+use crate::common::tls::{MaybeTlsStream, TlsClientConnector};
 use crate::common::utils::{resolve_first_address, ThreadSafe};
 use crate::protocol::{
     new_optional_multi_packet_codec, EncodeError, OptionalMulti, OptionalMultiPacketDecoder,
@@ -154,10 +155,10 @@ where
 
 #[derive(Debug)]
 struct RedisClientConnection {
-    sock: TcpStream,
+    sock: MaybeTlsStream,
 }
 
-impl From<RedisClientConnection> for TcpStream {
+impl From<RedisClientConnection> for MaybeTlsStream {
     fn from(conn: RedisClientConnection) -> Self {
         conn.sock
     }
@@ -204,7 +205,7 @@ type ClientCodec =
     RespCodec<OptionalMultiPacketEncoder<Vec<BinSafeStr>>, OptionalMultiPacketDecoder<RespVec>>;
 
 struct RedisClientConnectionHandle {
-    frame: Framed<TcpStream, ClientCodec>,
+    frame: Framed<MaybeTlsStream, ClientCodec>,
     reclaim_sender: Arc<crossbeam_channel::Sender<RedisClientConnection>>,
 }
 
@@ -412,12 +413,12 @@ impl RedisClientFactory for PooledRedisClientFactory {
 }
 
 pub struct SimpleRedisClient {
-    frame: Framed<TcpStream, ClientCodec>,
+    frame: Framed<MaybeTlsStream, ClientCodec>,
     timeout: Duration,
 }
 
 impl SimpleRedisClient {
-    pub fn new(frame: Framed<TcpStream, ClientCodec>, timeout: Duration) -> Self {
+    pub fn new(frame: Framed<MaybeTlsStream, ClientCodec>, timeout: Duration) -> Self {
         Self { frame, timeout }
     }
 
@@ -472,14 +473,22 @@ impl RedisClient for SimpleRedisClient {
 
 pub struct SimpleRedisClientFactory {
     timeout: Duration,
+    tls_connector: Option<TlsClientConnector>,
 }
 
 impl SimpleRedisClientFactory {
     pub fn new(timeout: Duration) -> Self {
-        Self { timeout }
+        Self::new_with_tls(timeout, None)
     }
 
-    async fn create_conn(&self, address: String) -> Result<TcpStream, RedisClientError> {
+    pub fn new_with_tls(timeout: Duration, tls_connector: Option<TlsClientConnector>) -> Self {
+        Self {
+            timeout,
+            tls_connector,
+        }
+    }
+
+    async fn create_conn(&self, address: String) -> Result<MaybeTlsStream, RedisClientError> {
         let sock_address = match resolve_first_address(&address) {
             Some(address) => address,
             None => return Err(RedisClientError::InvalidAddress),
@@ -488,7 +497,9 @@ impl SimpleRedisClientFactory {
             Ok(conn) => conn,
             Err(io_err) => return Err(RedisClientError::Io(io_err)),
         };
-        Ok(sock)
+        MaybeTlsStream::connect(sock, self.tls_connector.as_ref())
+            .await
+            .map_err(RedisClientError::Io)
     }
 
     async fn create_client_impl(
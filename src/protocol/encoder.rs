@@ -1,4 +1,4 @@
-use super::resp::{Array, BinSafeStr, BulkStr, Resp, RespVec};
+use super::resp::{Array, BinSafeStr, BulkStr, Resp, RespPairs, RespVec};
 use std::error::Error;
 use std::fmt;
 use std::io;
@@ -26,22 +26,58 @@ where
         Resp::Integer(s) => encode_simple_element(writer, b":", s),
         Resp::Bulk(bulk) => encode_bulk_str(writer, bulk),
         Resp::Arr(array) => encode_array(writer, array),
+        Resp::Map(pairs) => encode_pairs(writer, b"%", pairs),
+        Resp::Set(arr) => encode_aggregate(writer, b"~", arr),
+        Resp::Double(s) => encode_simple_element(writer, b",", s),
+        Resp::Boolean(s) => encode_simple_element(writer, b"#", s),
+        Resp::Null => writer.write(b"_\r\n"),
+        Resp::BigNumber(s) => encode_simple_element(writer, b"(", s),
+        Resp::Verbatim(s) => encode_blob(writer, b"=", s),
+        Resp::Push(arr) => encode_aggregate(writer, b">", arr),
+        Resp::Attribute(pairs, resp) => {
+            Ok(encode_pairs(writer, b"|", pairs)? + encode_resp(writer, resp)?)
+        }
     }
 }
 
+fn encode_aggregate<W, T: AsRef<[u8]>>(
+    writer: &mut W,
+    prefix: &[u8],
+    arr: &[Resp<T>],
+) -> io::Result<usize>
+where
+    W: io::Write,
+{
+    let mut l = encode_simple_element(writer, prefix, arr.len().to_string().into_bytes())?;
+    for element in arr {
+        l += encode_resp(writer, element)?;
+    }
+    Ok(l)
+}
+
+fn encode_pairs<W, T: AsRef<[u8]>>(
+    writer: &mut W,
+    prefix: &[u8],
+    pairs: &RespPairs<T>,
+) -> io::Result<usize>
+where
+    W: io::Write,
+{
+    let mut l = encode_simple_element(writer, prefix, pairs.len().to_string().into_bytes())?;
+    for (k, v) in pairs {
+        l += encode_resp(writer, k)?;
+        l += encode_resp(writer, v)?;
+    }
+    Ok(l)
+}
+
 fn encode_array<W, T: AsRef<[u8]>>(writer: &mut W, array: &Array<T>) -> io::Result<usize>
 where
     W: io::Write,
 {
     match *array {
         Array::Nil => writer.write(b"*-1\r\n"),
-        Array::Arr(ref arr) => {
-            let mut l = encode_simple_element(writer, b"*", &arr.len().to_string().into_bytes())?;
-            for element in arr {
-                l += encode_resp(writer, element)?;
-            }
-            Ok(l)
-        }
+        Array::Arr(ref arr) => encode_aggregate(writer, b"*", arr),
     }
 }
 
@@ -51,15 +87,21 @@ where
 {
     match *bulk_str {
         BulkStr::Nil => writer.write(b"$-1\r\n"),
-        BulkStr::Str(ref s) => Ok(encode_simple_element(
-            writer,
-            b"$",
-            &s.as_ref().len().to_string().into_bytes(),
-        )? + writer.write(s.as_ref())?
-            + writer.write(b"\r\n")?),
+        BulkStr::Str(ref s) => encode_blob(writer, b"$", s),
     }
 }
 
+fn encode_blob<W, T: AsRef<[u8]>>(writer: &mut W, prefix: &[u8], s: T) -> io::Result<usize>
+where
+    W: io::Write,
+{
+    Ok(
+        encode_simple_element(writer, prefix, &s.as_ref().len().to_string().into_bytes())?
+            + writer.write(s.as_ref())?
+            + writer.write(b"\r\n")?,
+    )
+}
+
 fn encode_simple_element<W, T: AsRef<[u8]>>(
     writer: &mut W,
     prefix: &[u8],
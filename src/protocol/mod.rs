@@ -23,6 +23,6 @@ pub use self::packet::{
 };
 pub use self::resp::{
     Array, ArrayBytes, ArrayIndex, ArraySlice, ArrayVec, BinSafeStr, BulkStr, BulkStrBytes,
-    BulkStrIndex, BulkStrSlice, BulkStrVec, IndexedResp, Resp, RespBytes, RespIndex, RespSlice,
-    RespVec,
+    BulkStrIndex, BulkStrSlice, BulkStrVec, IndexedResp, Resp, RespBytes, RespIndex, RespPairs,
+    RespSlice, RespVec, RespVersion,
 };
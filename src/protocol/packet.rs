@@ -4,8 +4,8 @@ use super::fp::{RFunctor, VFunctor};
 use super::resp::{BinSafeStr, IndexedResp, Resp, RespSlice, RespVec};
 use super::stateless::{parse_indexed_resp, ParseError};
 use crate::common::utils::{
-    array_append_front, change_bulk_array_element, change_bulk_str, get_command_element,
-    get_command_len, left_trim_array, ThreadSafe,
+    array_append_back, array_append_front, change_bulk_array_element, change_bulk_str,
+    get_command_element, get_command_len, left_trim_array, ThreadSafe,
 };
 use crate::protocol::EncodeError;
 use bytes::BytesMut;
@@ -184,6 +184,20 @@ impl RespPacket {
         }
     }
 
+    pub fn append_cmd(&mut self, following_elements: Vec<BinSafeStr>) -> bool {
+        match self {
+            Self::Indexed(index_resp) => {
+                let mut resp = index_resp.to_resp_vec();
+                if !array_append_back(&mut resp, following_elements) {
+                    return false;
+                }
+                *self = Self::Data(resp);
+                true
+            }
+            Self::Data(resp) => array_append_back(resp, following_elements),
+        }
+    }
+
     pub fn change_bulk_str(&mut self, data: Vec<u8>) -> bool {
         let mut resp = match self {
             Self::Indexed(indexed_resp) => indexed_resp.to_resp_vec(),
@@ -639,7 +653,7 @@ impl<D: DecodedPacket<Hint = ()>> PacketDecoder for OptionalMultiPacketDecoder<D
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::protocol::Array;
+    use crate::protocol::{Array, BulkStr};
     use matches::assert_matches;
 
     #[test]
@@ -707,4 +721,38 @@ mod tests {
         };
         assert_eq!(response.len(), 0);
     }
+
+    #[test]
+    fn test_resp3_packet() {
+        let (mut encoder, mut decoder) = new_simple_packet_codec::<RespVec, RespVec>();
+        let resp = Resp::Attribute(
+            vec![(Resp::Simple(b"key".to_vec()), Resp::Boolean(b"t".to_vec()))],
+            Box::new(Resp::Map(vec![
+                (
+                    Resp::Bulk(BulkStr::Str(b"proto".to_vec())),
+                    Resp::Integer(b"3".to_vec()),
+                ),
+                (
+                    Resp::Verbatim(b"txt:hello".to_vec()),
+                    Resp::Set(vec![
+                        Resp::Null,
+                        Resp::Double(b"1.5".to_vec()),
+                        Resp::BigNumber(b"12345678901234567890".to_vec()),
+                    ]),
+                ),
+                (
+                    Resp::Simple(b"push".to_vec()),
+                    Resp::Push(vec![Resp::Error(b"err".to_vec())]),
+                ),
+            ])),
+        );
+        let mut buf = BytesMut::new();
+        encoder
+            .encode(resp.clone(), |data| buf.extend_from_slice(data))
+            .unwrap();
+        assert!(buf.starts_with(b"|1\r\n+key\r\n#t\r\n%3\r\n"));
+        let decoded = decoder.decode(&mut buf).unwrap().unwrap();
+        assert!(buf.is_empty());
+        assert_eq!(decoded, resp);
+    }
 }
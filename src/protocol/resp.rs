@@ -85,6 +85,14 @@ pub enum Array<T> {
     Nil,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RespVersion {
+    V2,
+    V3,
+}
+
+pub type RespPairs<T> = Vec<(Resp<T>, Resp<T>)>;
+
 #[derive(Debug, PartialEq, Clone)]
 pub enum Resp<T> {
     Error(T),
@@ -92,6 +100,19 @@ pub enum Resp<T> {
     Bulk(BulkStr<T>),
     Integer(T),
     Arr(Array<T>),
+    // The types below are only in RESP3.
+    Map(RespPairs<T>),
+    Set(Vec<Resp<T>>),
+    Double(T),
+    // `t` or `f`
+    Boolean(T),
+    Null,
+    BigNumber(T),
+    // Including the format prefix like `txt:`.
+    Verbatim(T),
+    Push(Vec<Resp<T>>),
+    // Attribute is always followed by the actual reply.
+    Attribute(RespPairs<T>, Box<Resp<T>>),
 }
 
 impl<A, B> Plug<A> for BulkStr<B> {
@@ -221,6 +242,17 @@ impl<A> VFunctor for Resp<A> {
             Self::Bulk(bulk_str) => Resp::Bulk(bulk_str.map(f)),
             Self::Integer(t) => Resp::Integer(f(t)),
             Self::Arr(arr) => Resp::Arr(arr.map(f)),
+            Self::Map(pairs) => Resp::Map(map_pairs(pairs, f)),
+            Self::Set(arr) => Resp::Set(arr.into_iter().map(move |e| e.map(f)).collect()),
+            Self::Double(t) => Resp::Double(f(t)),
+            Self::Boolean(t) => Resp::Boolean(f(t)),
+            Self::Null => Resp::Null,
+            Self::BigNumber(t) => Resp::BigNumber(f(t)),
+            Self::Verbatim(t) => Resp::Verbatim(f(t)),
+            Self::Push(arr) => Resp::Push(arr.into_iter().map(move |e| e.map(f)).collect()),
+            Self::Attribute(pairs, resp) => {
+                Resp::Attribute(map_pairs(pairs, f), Box::new(resp.map(f)))
+            }
         }
     }
 }
@@ -233,6 +265,26 @@ impl<'a, A> RFunctor<'a> for Resp<A> {
             Self::Bulk(ref bulk_str) => Resp::Bulk(bulk_str.as_ref()),
             Self::Integer(ref t) => Resp::Integer(t),
             Self::Arr(ref arr) => Resp::Arr(arr.as_ref()),
+            Self::Map(ref pairs) => Resp::Map(
+                pairs
+                    .iter()
+                    .map(|(k, v)| (k.as_ref(), v.as_ref()))
+                    .collect(),
+            ),
+            Self::Set(ref arr) => Resp::Set(arr.iter().map(|e| e.as_ref()).collect()),
+            Self::Double(ref t) => Resp::Double(t),
+            Self::Boolean(ref t) => Resp::Boolean(t),
+            Self::Null => Resp::Null,
+            Self::BigNumber(ref t) => Resp::BigNumber(t),
+            Self::Verbatim(ref t) => Resp::Verbatim(t),
+            Self::Push(ref arr) => Resp::Push(arr.iter().map(|e| e.as_ref()).collect()),
+            Self::Attribute(ref pairs, ref resp) => Resp::Attribute(
+                pairs
+                    .iter()
+                    .map(|(k, v)| (k.as_ref(), v.as_ref()))
+                    .collect(),
+                Box::new(resp.as_ref().as_ref()),
+            ),
         }
     }
 
@@ -243,6 +295,26 @@ impl<'a, A> RFunctor<'a> for Resp<A> {
             Self::Bulk(ref mut bulk_str) => Resp::Bulk(bulk_str.as_mut()),
             Self::Integer(ref mut t) => Resp::Integer(t),
             Self::Arr(ref mut arr) => Resp::Arr(arr.as_mut()),
+            Self::Map(ref mut pairs) => Resp::Map(
+                pairs
+                    .iter_mut()
+                    .map(|(k, v)| (k.as_mut(), v.as_mut()))
+                    .collect(),
+            ),
+            Self::Set(ref mut arr) => Resp::Set(arr.iter_mut().map(|e| e.as_mut()).collect()),
+            Self::Double(ref mut t) => Resp::Double(t),
+            Self::Boolean(ref mut t) => Resp::Boolean(t),
+            Self::Null => Resp::Null,
+            Self::BigNumber(ref mut t) => Resp::BigNumber(t),
+            Self::Verbatim(ref mut t) => Resp::Verbatim(t),
+            Self::Push(ref mut arr) => Resp::Push(arr.iter_mut().map(|e| e.as_mut()).collect()),
+            Self::Attribute(ref mut pairs, ref mut resp) => Resp::Attribute(
+                pairs
+                    .iter_mut()
+                    .map(|(k, v)| (k.as_mut(), v.as_mut()))
+                    .collect(),
+                Box::new(resp.as_mut().as_mut()),
+            ),
         }
     }
 
@@ -256,10 +328,90 @@ impl<'a, A> RFunctor<'a> for Resp<A> {
             Self::Bulk(ref mut bulk_str) => bulk_str.map_in_place(f),
             Self::Integer(ref mut t) => f(t),
             Self::Arr(ref mut arr) => arr.map_in_place(f),
+            Self::Map(ref mut pairs) => {
+                for (k, v) in pairs.iter_mut() {
+                    k.map_in_place(f);
+                    v.map_in_place(f);
+                }
+            }
+            Self::Set(ref mut arr) | Self::Push(ref mut arr) => {
+                for resp in arr.iter_mut() {
+                    resp.map_in_place(f)
+                }
+            }
+            Self::Double(ref mut t)
+            | Self::Boolean(ref mut t)
+            | Self::BigNumber(ref mut t)
+            | Self::Verbatim(ref mut t) => f(t),
+            Self::Null => (),
+            Self::Attribute(ref mut pairs, ref mut resp) => {
+                for (k, v) in pairs.iter_mut() {
+                    k.map_in_place(f);
+                    v.map_in_place(f);
+                }
+                resp.map_in_place(f)
+            }
+        }
+    }
+}
+
+fn map_pairs<A, B, F>(pairs: RespPairs<A>, f: F) -> RespPairs<B>
+where
+    F: Fn(A) -> B + Copy,
+{
+    pairs
+        .into_iter()
+        .map(move |(k, v)| (k.map(f), v.map(f)))
+        .collect()
+}
+
+impl<T> Resp<T> {
+    pub fn is_resp2(&self) -> bool {
+        match self {
+            Self::Error(_) | Self::Simple(_) | Self::Bulk(_) | Self::Integer(_) => true,
+            Self::Arr(Array::Nil) => true,
+            Self::Arr(Array::Arr(arr)) => arr.iter().all(|resp| resp.is_resp2()),
+            _ => false,
+        }
+    }
+}
+
+impl RespVec {
+    // Convert the RESP3 types for the RESP2 clients in the same way as Redis.
+    pub fn into_resp2(self) -> RespVec {
+        match self {
+            Self::Error(_) | Self::Simple(_) | Self::Bulk(_) | Self::Integer(_) => self,
+            Self::Arr(Array::Nil) => self,
+            Self::Arr(Array::Arr(arr)) => Resp::Arr(Array::Arr(
+                arr.into_iter().map(|resp| resp.into_resp2()).collect(),
+            )),
+            Self::Map(pairs) => Resp::Arr(Array::Arr(
+                pairs
+                    .into_iter()
+                    .flat_map(|(k, v)| vec![k.into_resp2(), v.into_resp2()])
+                    .collect(),
+            )),
+            Self::Set(arr) | Self::Push(arr) => Resp::Arr(Array::Arr(
+                arr.into_iter().map(|resp| resp.into_resp2()).collect(),
+            )),
+            Self::Double(t) | Self::BigNumber(t) => Resp::Bulk(BulkStr::Str(t)),
+            Self::Boolean(t) => {
+                let n = if t.as_slice() == b"t" { b"1" } else { b"0" };
+                Resp::Integer(n.to_vec())
+            }
+            Self::Null => Resp::Bulk(BulkStr::Nil),
+            Self::Verbatim(t) => {
+                // Skip the format prefix like `txt:`.
+                let s = t.get(VERBATIM_PREFIX_LEN..).unwrap_or(&[]).to_vec();
+                Resp::Bulk(BulkStr::Str(s))
+            }
+            Self::Attribute(_, resp) => resp.into_resp2(),
         }
     }
 }
 
+pub const VERBATIM_PREFIX_LEN: usize = 4;
+
 impl DataIndex {
     pub fn map<F: FnOnce(usize) -> usize + Copy>(self, f: F) -> Self {
         let DataIndex(s, e) = self;
@@ -311,3 +463,42 @@ where
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_into_resp2() {
+        let resp = Resp::Map(vec![
+            (
+                Resp::Simple(b"a".to_vec()),
+                Resp::Set(vec![Resp::Boolean(b"t".to_vec()), Resp::Null]),
+            ),
+            (
+                Resp::Simple(b"b".to_vec()),
+                Resp::Verbatim(b"txt:hello".to_vec()),
+            ),
+        ]);
+        assert!(!resp.is_resp2());
+        let expected = Resp::Arr(Array::Arr(vec![
+            Resp::Simple(b"a".to_vec()),
+            Resp::Arr(Array::Arr(vec![
+                Resp::Integer(b"1".to_vec()),
+                Resp::Bulk(BulkStr::Nil),
+            ])),
+            Resp::Simple(b"b".to_vec()),
+            Resp::Bulk(BulkStr::Str(b"hello".to_vec())),
+        ]));
+        let resp2 = resp.into_resp2();
+        assert!(resp2.is_resp2());
+        assert_eq!(resp2, expected);
+
+        let resp = Resp::Attribute(vec![], Box::new(Resp::Double(b"1.5".to_vec())));
+        assert_eq!(resp.into_resp2(), Resp::Bulk(BulkStr::Str(b"1.5".to_vec())));
+
+        let resp = Resp::Arr(Array::Arr(vec![Resp::Integer(b"1".to_vec())]));
+        assert!(resp.is_resp2());
+        assert_eq!(resp.clone().into_resp2(), resp);
+    }
+}
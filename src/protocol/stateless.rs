@@ -1,5 +1,8 @@
 use super::decoder::LF;
-use super::resp::{AdvanceIndex, ArrayIndex, BulkStrIndex, DataIndex, IndexedResp, RespIndex};
+use super::resp::{
+    AdvanceIndex, ArrayIndex, BulkStrIndex, DataIndex, IndexedResp, RespIndex, RespPairs,
+    VERBATIM_PREFIX_LEN,
+};
 use btoi::btoi;
 use bytes::BytesMut;
 use memchr::memchr;
@@ -69,6 +72,73 @@ pub fn parse_resp(buf: &[u8]) -> Result<(RespIndex, usize), ParseError> {
             v.advance(1);
             Ok((RespIndex::Arr(v), 1 + consumed))
         }
+        b'%' => {
+            let (pairs, consumed) = parse_pairs(next_buf)?;
+            let mut v = RespIndex::Map(pairs);
+            v.advance(1);
+            Ok((v, 1 + consumed))
+        }
+        b'~' => {
+            let (arr, consumed) = parse_aggregate(next_buf)?;
+            let mut v = RespIndex::Set(arr);
+            v.advance(1);
+            Ok((v, 1 + consumed))
+        }
+        b'>' => {
+            let (arr, consumed) = parse_aggregate(next_buf)?;
+            let mut v = RespIndex::Push(arr);
+            v.advance(1);
+            Ok((v, 1 + consumed))
+        }
+        b',' => {
+            let (mut v, consumed) = parse_line(next_buf)?;
+            v.advance(1);
+            Ok((RespIndex::Double(v), 1 + consumed))
+        }
+        b'#' => {
+            let (mut v, consumed) = parse_line(next_buf)?;
+            match next_buf.get(v.to_range()) {
+                Some(b"t") | Some(b"f") => (),
+                _ => return Err(ParseError::InvalidProtocol),
+            }
+            v.advance(1);
+            Ok((RespIndex::Boolean(v), 1 + consumed))
+        }
+        b'_' => {
+            let (v, consumed) = parse_line(next_buf)?;
+            if !v.to_range().is_empty() {
+                return Err(ParseError::InvalidProtocol);
+            }
+            Ok((RespIndex::Null, 1 + consumed))
+        }
+        b'(' => {
+            let (mut v, consumed) = parse_line(next_buf)?;
+            v.advance(1);
+            Ok((RespIndex::BigNumber(v), 1 + consumed))
+        }
+        b'=' => {
+            let (v, consumed) = parse_bulk_str(next_buf)?;
+            match v {
+                BulkStrIndex::Str(mut v) if v.to_range().len() >= VERBATIM_PREFIX_LEN => {
+                    v.advance(1);
+                    Ok((RespIndex::Verbatim(v), 1 + consumed))
+                }
+                _ => Err(ParseError::InvalidProtocol),
+            }
+        }
+        b'|' => {
+            // The attribute and the following reply are parsed as a whole.
+            let (pairs, mut consumed) = parse_pairs(next_buf)?;
+            let resp_buf = next_buf
+                .get(consumed..)
+                .ok_or(ParseError::InvalidProtocol)?;
+            let (mut resp, resp_consumed) = parse_resp(resp_buf)?;
+            resp.advance(consumed);
+            consumed += resp_consumed;
+            let mut v = RespIndex::Attribute(pairs, Box::new(resp));
+            v.advance(1);
+            Ok((v, 1 + consumed))
+        }
         prefix => {
             debug!("invalid prefix {:?}", prefix);
             Err(ParseError::InvalidProtocol)
@@ -77,15 +147,47 @@ pub fn parse_resp(buf: &[u8]) -> Result<(RespIndex, usize), ParseError> {
 }
 
 fn parse_array(buf: &[u8]) -> Result<(ArrayIndex, usize), ParseError> {
-    let (len, mut consumed) = parse_len(buf)?;
+    let (len, consumed) = parse_len(buf)?;
     if len < 0 {
         return Ok((ArrayIndex::Nil, consumed));
     }
 
-    let array_size = len as usize;
-    let mut array = Vec::with_capacity(array_size);
+    let (array, consumed) = parse_elements(buf, consumed, len as usize)?;
+    Ok((ArrayIndex::Arr(array), consumed))
+}
+
+// For the RESP3 aggregate types which could not be nil.
+fn parse_aggregate(buf: &[u8]) -> Result<(Vec<RespIndex>, usize), ParseError> {
+    let (len, consumed) = parse_len(buf)?;
+    if len < 0 {
+        return Err(ParseError::InvalidProtocol);
+    }
+    parse_elements(buf, consumed, len as usize)
+}
+
+fn parse_pairs(buf: &[u8]) -> Result<(RespPairs<DataIndex>, usize), ParseError> {
+    let (len, consumed) = parse_len(buf)?;
+    if len < 0 {
+        return Err(ParseError::InvalidProtocol);
+    }
+    let size = len.checked_mul(2).ok_or(ParseError::InvalidProtocol)?;
+    let (elements, consumed) = parse_elements(buf, consumed, size as usize)?;
+    let mut pairs = Vec::with_capacity(elements.len() / 2);
+    let mut it = elements.into_iter();
+    while let (Some(k), Some(v)) = (it.next(), it.next()) {
+        pairs.push((k, v));
+    }
+    Ok((pairs, consumed))
+}
 
-    for _ in 0..array_size {
+fn parse_elements(
+    buf: &[u8],
+    mut consumed: usize,
+    size: usize,
+) -> Result<(Vec<RespIndex>, usize), ParseError> {
+    let mut array = Vec::with_capacity(size);
+
+    for _ in 0..size {
         let next_buf = buf
             .get(consumed..)
             .ok_or_else(|| ParseError::InvalidProtocol)?;
@@ -95,7 +197,7 @@ fn parse_array(buf: &[u8]) -> Result<(ArrayIndex, usize), ParseError> {
         array.push(v);
     }
 
-    Ok((ArrayIndex::Arr(array), consumed))
+    Ok((array, consumed))
 }
 
 fn parse_bulk_str(buf: &[u8]) -> Result<(BulkStrIndex, usize), ParseError> {
@@ -300,4 +402,104 @@ mod tests {
             a.map_to_slice(data),
         );
     }
+
+    #[test]
+    fn test_parse_resp3_simple_types() {
+        let data = b"_\r\n";
+        let (a, s) = parse_resp(data).unwrap();
+        assert_eq!(s, 3);
+        assert_eq!(RespSlice::Null, a.map_to_slice(data));
+
+        let data = b"#t\r\n";
+        let (a, s) = parse_resp(data).unwrap();
+        assert_eq!(s, 4);
+        assert_eq!(RespSlice::Boolean(b"t"), a.map_to_slice(data));
+
+        let data = b",3.14\r\n";
+        let (a, s) = parse_resp(data).unwrap();
+        assert_eq!(s, 7);
+        assert_eq!(RespSlice::Double(b"3.14"), a.map_to_slice(data));
+
+        let data = b"(3492890328409238509324850943850943825024385\r\n";
+        let (a, _) = parse_resp(data).unwrap();
+        assert_eq!(
+            RespSlice::BigNumber(b"3492890328409238509324850943850943825024385"),
+            a.map_to_slice(data)
+        );
+
+        let data = b"=8\r\ntxt:abcd\r\n";
+        let (a, s) = parse_resp(data).unwrap();
+        assert_eq!(s, data.len());
+        assert_eq!(RespSlice::Verbatim(b"txt:abcd"), a.map_to_slice(data));
+
+        assert!(parse_resp(b"#x\r\n").is_err());
+        assert!(parse_resp(b"_abc\r\n").is_err());
+        assert!(parse_resp(b"=-1\r\n").is_err());
+        assert!(parse_resp(b"=2\r\nab\r\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_resp3_aggregate_types() {
+        let data = b"%2\r\n+a\r\n:1\r\n+b\r\n_\r\n";
+        let (a, s) = parse_resp(data).unwrap();
+        assert_eq!(s, data.len());
+        assert_eq!(
+            RespSlice::Map(vec![
+                (RespSlice::Simple(b"a"), RespSlice::Integer(b"1")),
+                (RespSlice::Simple(b"b"), RespSlice::Null),
+            ]),
+            a.map_to_slice(data)
+        );
+
+        let data = b"~2\r\n+a\r\n#f\r\n";
+        let (a, s) = parse_resp(data).unwrap();
+        assert_eq!(s, data.len());
+        assert_eq!(
+            RespSlice::Set(vec![RespSlice::Simple(b"a"), RespSlice::Boolean(b"f")]),
+            a.map_to_slice(data)
+        );
+
+        let data = b">2\r\n$7\r\nmessage\r\n:1\r\n";
+        let (a, s) = parse_resp(data).unwrap();
+        assert_eq!(s, data.len());
+        assert_eq!(
+            RespSlice::Push(vec![
+                RespSlice::Bulk(BulkStrSlice::Str(b"message")),
+                RespSlice::Integer(b"1"),
+            ]),
+            a.map_to_slice(data)
+        );
+
+        let data = b"|1\r\n+ttl\r\n:3\r\n*1\r\n:2\r\n";
+        let (a, s) = parse_resp(data).unwrap();
+        assert_eq!(s, data.len());
+        assert_eq!(
+            RespSlice::Attribute(
+                vec![(RespSlice::Simple(b"ttl"), RespSlice::Integer(b"3"))],
+                Box::new(RespSlice::Arr(ArraySlice::Arr(vec![RespSlice::Integer(
+                    b"2"
+                )]))),
+            ),
+            a.map_to_slice(data)
+        );
+
+        assert!(parse_resp(b"%-1\r\n").is_err());
+        assert!(parse_resp(b"~-1\r\n").is_err());
+        match parse_resp(b"%1\r\n+a\r\n") {
+            Err(ParseError::NotEnoughData) => (),
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn test_parse_map_len_overflow() {
+        match parse_resp(b"%4611686018427387904\r\n") {
+            Err(ParseError::InvalidProtocol) => (),
+            _ => panic!(),
+        }
+        match parse_resp(b"%9223372036854775807\r\n") {
+            Err(ParseError::InvalidProtocol) => (),
+            _ => panic!(),
+        }
+    }
 }
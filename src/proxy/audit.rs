@@ -0,0 +1,300 @@
+use super::cluster::ClusterTag;
+use super::command::{CmdType, Command, DataCmdType};
+use super::namespace::get_key_indices;
+use super::service::ServerProxyConfig;
+use super::session::CmdCtx;
+use chrono::{SecondsFormat, Utc};
+use crossbeam_channel::{Receiver, Sender, TrySendError};
+use serde::Serialize;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+// The records are dropped when the writer can't keep up with them.
+const AUDIT_CHANNEL_SIZE: usize = 10000;
+
+// The values are redacted. Only the keys are recorded.
+#[derive(Debug, Serialize)]
+pub struct AuditRecord {
+    timestamp: String,
+    session_id: usize,
+    client_addr: String,
+    cluster: String,
+    command: String,
+    keys: Vec<String>,
+    // The commands forwarded by UMFORWARD are also recorded by the proxy receiving them.
+    // Then `client_addr` is the address of the peer proxy.
+    forwarded: bool,
+}
+
+impl AuditRecord {
+    fn new(cmd_ctx: &CmdCtx, client_addr: &str, forwarded: bool) -> Self {
+        let cmd = cmd_ctx.get_cmd();
+        let keys = match get_key_indices(cmd) {
+            Ok(indices) => indices
+                .into_iter()
+                .filter_map(|i| cmd.get_command_element(i))
+                .map(|key| String::from_utf8_lossy(key).into_owned())
+                .collect(),
+            Err(_) => cmd
+                .get_key()
+                .map(|key| vec![String::from_utf8_lossy(key).into_owned()])
+                .unwrap_or_else(Vec::new),
+        };
+        Self {
+            timestamp: Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+            session_id: cmd_ctx.get_session_id(),
+            client_addr: client_addr.to_string(),
+            cluster: cmd_ctx.get_cluster_name().to_string(),
+            command: cmd
+                .get_command_name()
+                .map(|name| name.to_lowercase())
+                .unwrap_or_default(),
+            keys,
+            forwarded,
+        }
+    }
+}
+
+// All the data commands except the read-only ones are regarded as write commands,
+// including the unknown commands and scripts.
+pub fn is_write_cmd(cmd: &Command) -> bool {
+    if cmd.get_type() != CmdType::Others || cmd.is_read_only() {
+        return false;
+    }
+    !matches!(
+        cmd.get_data_cmd_type(),
+        DataCmdType::MGET
+            | DataCmdType::EXISTS
+            | DataCmdType::TOUCH
+            | DataCmdType::SUNION
+            | DataCmdType::SINTER
+            | DataCmdType::SDIFF
+            | DataCmdType::PFCOUNT
+            | DataCmdType::SCAN
+            | DataCmdType::KEYS
+            | DataCmdType::DBSIZE
+            | DataCmdType::RANDOMKEY
+    )
+}
+
+pub struct AuditLogger {
+    sender: Option<Sender<AuditRecord>>,
+    dropped: Arc<AtomicU64>,
+}
+
+impl AuditLogger {
+    pub fn disabled() -> Self {
+        Self {
+            sender: None,
+            dropped: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    // The audit log is disabled when `audit_log_path` is not set.
+    pub fn new(config: &ServerProxyConfig) -> io::Result<Self> {
+        let path = match config.audit_log_path.as_ref() {
+            Some(path) => path.clone(),
+            None => return Ok(Self::disabled()),
+        };
+        let file = RotatingFile::open(path, config.audit_log_max_size, config.audit_log_max_files)?;
+        let (sender, receiver) = crossbeam_channel::bounded(AUDIT_CHANNEL_SIZE);
+        let dropped = Arc::new(AtomicU64::new(0));
+        let dropped_clone = dropped.clone();
+        thread::Builder::new()
+            .name("audit-log".to_string())
+            .spawn(move || write_records(receiver, file, dropped_clone))?;
+        Ok(Self {
+            sender: Some(sender),
+            dropped,
+        })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.sender.is_some()
+    }
+
+    // Never blocks the request path.
+    pub fn log(&self, cmd_ctx: &CmdCtx, client_addr: &str, forwarded: bool) {
+        let sender = match self.sender.as_ref() {
+            Some(sender) => sender,
+            None => return,
+        };
+        match sender.try_send(AuditRecord::new(cmd_ctx, client_addr, forwarded)) {
+            Ok(()) => (),
+            Err(TrySendError::Full(_)) => {
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+            }
+            Err(TrySendError::Disconnected(_)) => {
+                error!("audit log writer is down");
+            }
+        }
+    }
+}
+
+fn write_records(receiver: Receiver<AuditRecord>, mut file: RotatingFile, dropped: Arc<AtomicU64>) {
+    // Flush after the pending records are written.
+    while let Ok(record) = receiver.recv() {
+        for record in std::iter::once(record).chain(receiver.try_iter()) {
+            let line = match serde_json::to_string(&record) {
+                Ok(line) => line,
+                Err(err) => {
+                    error!("failed to serialize audit record: {:?}", err);
+                    continue;
+                }
+            };
+            if let Err(err) = file.write_line(&line) {
+                error!("failed to write audit log: {:?}", err);
+            }
+        }
+        if let Err(err) = file.flush() {
+            error!("failed to flush audit log: {:?}", err);
+        }
+        let dropped_num = dropped.swap(0, Ordering::Relaxed);
+        if dropped_num > 0 {
+            warn!("dropped {} audit records", dropped_num);
+        }
+    }
+    info!("audit log writer exited");
+}
+
+// `<path>` is renamed to `<path>.1` and `<path>.1` to `<path>.2` and so on
+// when it exceeds `max_size`. At most `max_files` rotated files are kept.
+struct RotatingFile {
+    path: String,
+    max_size: u64,
+    max_files: usize,
+    writer: BufWriter<File>,
+    size: u64,
+}
+
+impl RotatingFile {
+    fn open(path: String, max_size: u64, max_files: usize) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let size = file.metadata()?.len();
+        Ok(Self {
+            path,
+            max_size,
+            max_files,
+            writer: BufWriter::new(file),
+            size,
+        })
+    }
+
+    fn write_line(&mut self, line: &str) -> io::Result<()> {
+        // 0 disables the rotation.
+        let line_size = line.len() as u64 + 1;
+        if self.max_size != 0 && self.size > 0 && self.size + line_size > self.max_size {
+            self.rotate()?;
+        }
+        self.writer.write_all(line.as_bytes())?;
+        self.writer.write_all(b"\n")?;
+        self.size += line_size;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.writer.flush()?;
+        if self.max_files == 0 {
+            fs::remove_file(&self.path)?;
+        } else {
+            for i in (1..self.max_files).rev() {
+                let from = format!("{}.{}", self.path, i);
+                if fs::metadata(&from).is_ok() {
+                    fs::rename(&from, format!("{}.{}", self.path, i + 1))?;
+                }
+            }
+            fs::rename(&self.path, format!("{}.1", self.path))?;
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        self.writer = BufWriter::new(file);
+        self.size = 0;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::cluster::ClusterName;
+    use crate::protocol::{Array, BulkStr, Resp, RespPacket};
+    use crate::proxy::command::new_command_pair;
+    use std::convert::TryFrom;
+    use std::env;
+    use std::process;
+
+    fn gen_cmd(args: &[&str]) -> Command {
+        let resp = Resp::Arr(Array::Arr(
+            args.iter()
+                .map(|arg| Resp::Bulk(BulkStr::Str(arg.as_bytes().to_vec())))
+                .collect(),
+        ));
+        Command::new(Box::new(RespPacket::Data(resp)))
+    }
+
+    #[test]
+    fn test_is_write_cmd() {
+        assert!(is_write_cmd(&gen_cmd(&["SET", "a", "1"])));
+        assert!(is_write_cmd(&gen_cmd(&["del", "a", "b"])));
+        assert!(is_write_cmd(&gen_cmd(&["EVAL", "script", "0"])));
+        assert!(is_write_cmd(&gen_cmd(&["HSET", "h", "f", "v"])));
+        assert!(!is_write_cmd(&gen_cmd(&["GET", "a"])));
+        assert!(!is_write_cmd(&gen_cmd(&["MGET", "a", "b"])));
+        assert!(!is_write_cmd(&gen_cmd(&["SCAN", "0"])));
+        assert!(!is_write_cmd(&gen_cmd(&["PING"])));
+    }
+
+    #[test]
+    fn test_audit_record() {
+        let cmd = gen_cmd(&["MSET", "a", "secret1", "b", "secret2"]);
+        let (reply_sender, _) = new_command_pair(&cmd);
+        let cluster_name = ClusterName::try_from("mycluster").unwrap();
+        let cmd_ctx = CmdCtx::new(cluster_name, cmd, reply_sender, 7, false);
+        let record = AuditRecord::new(&cmd_ctx, "127.0.0.1:40000", false);
+        let line = serde_json::to_string(&record).unwrap();
+        assert!(!line.contains("secret"));
+
+        let value: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(value["session_id"], 7);
+        assert_eq!(value["client_addr"], "127.0.0.1:40000");
+        assert_eq!(value["cluster"], "mycluster");
+        assert_eq!(value["command"], "mset");
+        assert_eq!(value["keys"], serde_json::json!(["a", "b"]));
+        assert_eq!(value["forwarded"], false);
+    }
+
+    #[test]
+    fn test_rotating_file() {
+        let dir = env::temp_dir().join(format!("undermoon-audit-test-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("audit.log").to_str().unwrap().to_string();
+
+        let mut file = RotatingFile::open(path.clone(), 10, 2).unwrap();
+        for line in ["aaaa", "bbbb", "cccc", "dddd", "eeee"].iter() {
+            file.write_line(line).unwrap();
+        }
+        file.flush().unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "eeee\n");
+        assert_eq!(
+            fs::read_to_string(format!("{}.1", path)).unwrap(),
+            "cccc\ndddd\n"
+        );
+        assert_eq!(
+            fs::read_to_string(format!("{}.2", path)).unwrap(),
+            "aaaa\nbbbb\n"
+        );
+        assert!(fs::metadata(format!("{}.3", path)).is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
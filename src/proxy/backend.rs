@@ -1,7 +1,10 @@
+use super::breaker::CircuitBreaker;
 use super::command::{CommandError, CommandResult};
 use super::service::ServerProxyConfig;
 use super::slowlog::TaskEvent;
 use crate::common::batch::TryChunksTimeoutStreamExt;
+use crate::common::response::{ERR_BACKEND_CONNECTION, ERR_BACKEND_TIMEOUT};
+use crate::common::tls::{MaybeTlsStream, TlsClientConnector};
 use crate::common::utils::{resolve_first_address, ThreadSafe};
 use crate::protocol::{
     new_simple_packet_codec, DecodeError, EncodeError, EncodedPacket, FromResp, MonoPacket,
@@ -23,6 +26,7 @@ use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::net::TcpStream;
+use tokio::time;
 use tokio_util::codec::Decoder;
 
 pub type BackendResult<T> = Result<T, BackendError>;
@@ -61,6 +65,9 @@ pub trait CmdTask: ThreadSafe {
         Self: Sized;
 
     fn log_event(&mut self, event: TaskEvent);
+
+    // The blocking commands such as BLPOP are not limited by the backend reply timeout.
+    fn is_blocking(&self) -> bool;
 }
 
 pub trait IntoTask<T: CmdTask>: CmdTask {
@@ -218,6 +225,13 @@ impl<T: CmdTask> CmdTask for ReqTask<T> {
             }
         }
     }
+
+    fn is_blocking(&self) -> bool {
+        match self {
+            Self::Simple(t) => t.is_blocking(),
+            Self::Multi(v) => v.iter().any(|t| t.is_blocking()),
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -240,6 +254,7 @@ impl<H: CmdTaskResultHandler> BackendNode<H> {
         handler: Arc<H>,
         config: Arc<ServerProxyConfig>,
         conn_factory: Arc<CF>,
+        breaker: Arc<CircuitBreaker>,
     ) -> (
         BackendNode<H>,
         impl Future<Output = Result<(), BackendError>> + Send,
@@ -256,14 +271,37 @@ impl<H: CmdTaskResultHandler> BackendNode<H> {
             rx,
             conn_failed.clone(),
             address,
-            config.backend_batch_min_time,
-            config.backend_batch_max_time,
-            config.backend_batch_buf,
+            config.get_backend_batch_min_time(),
+            config.get_backend_batch_max_time(),
+            config.get_backend_batch_buf(),
             conn_factory,
+            breaker,
         );
         (Self { tx, conn_failed }, handle_backend_fut)
     }
 
+    // The dedicated node owns a single connection which will not be shared with other sessions.
+    // It's used by the commands relying on the connection states such as WATCH and MULTI.
+    pub fn new_dedicated<CF>(
+        address: String,
+        handler: Arc<H>,
+        conn_factory: Arc<CF>,
+    ) -> (
+        BackendNode<H>,
+        impl Future<Output = Result<(), BackendError>> + Send,
+    )
+    where
+        CF: ConnFactory + Send + Sync + 'static,
+        CF::Pkt: Send,
+        <H as CmdTaskResultHandler>::Task: CmdTask<Pkt = CF::Pkt>,
+    {
+        let (tx, rx) = mpsc::unbounded();
+        let conn_failed = Arc::new(AtomicBool::new(false));
+        let handle_backend_fut =
+            handle_dedicated_backend(handler, rx, conn_failed.clone(), address, conn_factory);
+        (Self { tx, conn_failed }, handle_backend_fut)
+    }
+
     pub fn send(&self, mut cmd_task: H::Task) -> Result<(), BackendSendError<H::Task>> {
         cmd_task.log_event(TaskEvent::SentToWritingQueue);
         if self.conn_failed.load(Ordering::SeqCst) {
@@ -278,6 +316,10 @@ impl<H: CmdTaskResultHandler> BackendNode<H> {
     pub fn is_closed(&self) -> bool {
         self.tx.is_closed()
     }
+
+    pub fn is_conn_failed(&self) -> bool {
+        self.conn_failed.load(Ordering::SeqCst)
+    }
 }
 
 pub type ConnSink<T> = Pin<Box<dyn Sink<T, Error = BackendError> + Send>>;
@@ -293,11 +335,24 @@ pub trait ConnFactory: ThreadSafe {
     ) -> Pin<Box<dyn Future<Output = CreateConnResult<Self::Pkt>> + Send>>;
 }
 
-pub struct DefaultConnFactory<P>(PhantomData<P>);
+pub struct DefaultConnFactory<P> {
+    // Connect to the TLS enabled Redis and server proxies when it's set.
+    tls_connector: Option<TlsClientConnector>,
+    phantom: PhantomData<P>,
+}
+
+impl<P> DefaultConnFactory<P> {
+    pub fn new(tls_connector: Option<TlsClientConnector>) -> Self {
+        Self {
+            tls_connector,
+            phantom: PhantomData,
+        }
+    }
+}
 
 impl<P> Default for DefaultConnFactory<P> {
     fn default() -> Self {
-        Self(PhantomData)
+        Self::new(None)
     }
 }
 
@@ -308,11 +363,14 @@ impl<P: MonoPacket> ConnFactory for DefaultConnFactory<P> {
         &self,
         addr: SocketAddr,
     ) -> Pin<Box<dyn Future<Output = CreateConnResult<Self::Pkt>> + Send>> {
-        Box::pin(create_conn(addr))
+        Box::pin(create_conn(addr, self.tls_connector.clone()))
     }
 }
 
-async fn create_conn<T>(address: SocketAddr) -> CreateConnResult<T>
+async fn create_conn<T>(
+    address: SocketAddr,
+    tls_connector: Option<TlsClientConnector>,
+) -> CreateConnResult<T>
 where
     T: MonoPacket,
 {
@@ -323,6 +381,13 @@ where
             return Err(BackendError::Io(err));
         }
     };
+    let socket = match MaybeTlsStream::connect(socket, tls_connector.as_ref()).await {
+        Ok(socket) => socket,
+        Err(err) => {
+            error!("failed to do TLS handshake: {} {:?}", address, err);
+            return Err(BackendError::Io(err));
+        }
+    };
 
     let (encoder, decoder) = new_simple_packet_codec::<T, T>();
 
@@ -363,6 +428,7 @@ pub async fn handle_backend<H, F>(
     backend_batch_max_time: usize,
     backend_batch_buf: NonZeroUsize,
     conn_factory: Arc<F>,
+    breaker: Arc<CircuitBreaker>,
 ) -> Result<(), BackendError>
 where
     H: CmdTaskResultHandler,
@@ -407,6 +473,7 @@ where
                             return Err(BackendError::Canceled);
                         }
                     };
+                    breaker.record_error(tasks.len() as u64);
                     for task in tasks.into_iter() {
                         task.set_resp_result(Ok(Resp::Error(
                             format!("failed to connect to {}", address).into_bytes(),
@@ -425,6 +492,7 @@ where
             handler.clone(),
             backend_batch_buf,
             retry_state.take(),
+            Some(&breaker),
         )
         .await;
         match res {
@@ -441,6 +509,72 @@ where
     }
 }
 
+// Unlike `handle_backend`, this will not reconnect or retry
+// since the states of the connection would be lost.
+pub async fn handle_dedicated_backend<H, F>(
+    handler: Arc<H>,
+    mut task_receiver: mpsc::UnboundedReceiver<H::Task>,
+    conn_failed: Arc<AtomicBool>,
+    address: String,
+    conn_factory: Arc<F>,
+) -> Result<(), BackendError>
+where
+    H: CmdTaskResultHandler,
+    F: ConnFactory<Pkt = <H::Task as CmdTask>::Pkt> + Send + Sync + 'static,
+{
+    let fail_tasks = |task_receiver: &mut mpsc::UnboundedReceiver<H::Task>, tasks: Vec<H::Task>| {
+        task_receiver.close();
+        let pending_tasks = std::iter::from_fn(|| task_receiver.try_next().ok().flatten());
+        for task in tasks.into_iter().chain(pending_tasks) {
+            task.set_resp_result(Ok(Resp::Error(
+                format!("{}: {}", ERR_BACKEND_CONNECTION, address).into_bytes(),
+            )));
+        }
+    };
+
+    let sock_address = match resolve_first_address(&address) {
+        Some(addr) => addr,
+        None => {
+            error!("invalid address: {:?}", address);
+            conn_failed.store(true, Ordering::SeqCst);
+            fail_tasks(&mut task_receiver, vec![]);
+            return Err(BackendError::InvalidAddress);
+        }
+    };
+
+    let (writer, reader) = match conn_factory.create_conn(sock_address).await {
+        Ok(conn) => conn,
+        Err(err) => {
+            error!("failed to connect: {} {:?}", address, err);
+            conn_failed.store(true, Ordering::SeqCst);
+            fail_tasks(&mut task_receiver, vec![]);
+            return Err(err);
+        }
+    };
+
+    let mut tasks_receiver = (&mut task_receiver).map(|task| vec![task]);
+    let res = handle_conn(
+        writer,
+        reader,
+        &mut tasks_receiver,
+        handler,
+        NonZeroUsize::new(1).expect("handle_dedicated_backend"),
+        None,
+        None,
+    )
+    .await;
+    match res {
+        Ok(()) => Ok(()),
+        Err((err, retry_state)) => {
+            error!("dedicated connection is closed: {} {:?}", address, err);
+            conn_failed.store(true, Ordering::SeqCst);
+            let tasks = retry_state.map(|state| state.tasks).unwrap_or_default();
+            fail_tasks(&mut task_receiver, tasks);
+            Err(err)
+        }
+    }
+}
+
 async fn handle_conn<H, S>(
     mut writer: ConnSink<<<H as CmdTaskResultHandler>::Task as CmdTask>::Pkt>,
     mut reader: ConnStream<<<H as CmdTaskResultHandler>::Task as CmdTask>::Pkt>,
@@ -448,12 +582,21 @@ async fn handle_conn<H, S>(
     handler: Arc<H>,
     backend_batch_buf: NonZeroUsize,
     mut retry_state_opt: Option<RetryState<H::Task>>,
+    breaker: Option<&CircuitBreaker>,
 ) -> Result<(), (BackendError, Option<RetryState<H::Task>>)>
 where
     H: CmdTaskResultHandler,
     S: Stream<Item = Vec<H::Task>> + Unpin,
 {
     let mut packets = Vec::with_capacity(backend_batch_buf.get());
+    let reply_timeout = breaker.and_then(|breaker| breaker.get_reply_timeout());
+    // Only the tasks that will not be retried anymore are counted as errors.
+    let record_replies = |succeeded: usize, failed: usize| {
+        if let Some(breaker) = breaker {
+            breaker.record_success(succeeded as u64);
+            breaker.record_error(failed as u64);
+        }
+    };
 
     loop {
         let (retry_times_opt, mut tasks) = match retry_state_opt.take() {
@@ -481,11 +624,17 @@ where
 
         if let Err(err) = res {
             error!("backend write error: {}", err);
+            let tasks_num = tasks.len();
             let retry_state = handle_conn_err(retry_times_opt, tasks, &err);
+            if retry_state.is_none() {
+                record_replies(0, tasks_num);
+            }
             return Err((err, retry_state));
         }
 
         let mut tasks_iter = tasks.into_iter();
+        let mut succeeded = 0;
+        let mut failed = 0;
         // `while let` will consume ownership.
         #[allow(clippy::while_let_loop)]
         loop {
@@ -493,21 +642,59 @@ where
                 Some(task) => task,
                 None => break,
             };
-            let packet_res = match reader.next().await {
+            let reply_timeout = if task.is_blocking() {
+                None
+            } else {
+                reply_timeout
+            };
+            let packet_opt = match reply_timeout {
+                None => reader.next().await,
+                Some(reply_timeout) => match time::timeout(reply_timeout, reader.next()).await {
+                    Ok(packet_opt) => packet_opt,
+                    Err(_) => {
+                        // The connection can't be reused since the reply might come later.
+                        // The tasks are not retried since they might have been executed.
+                        error!("backend reply timed out. Close the connection.");
+                        record_replies(succeeded, failed + tasks_iter.len());
+                        if let Some(breaker) = breaker {
+                            breaker.record_timeout(1);
+                        }
+                        for task in std::iter::once(task).chain(tasks_iter) {
+                            task.set_resp_result(Ok(Resp::Error(
+                                ERR_BACKEND_TIMEOUT.to_string().into_bytes(),
+                            )));
+                        }
+                        let err = BackendError::Io(io::Error::from(io::ErrorKind::TimedOut));
+                        return Err((err, None));
+                    }
+                },
+            };
+            let packet_res = match packet_opt {
                 Some(pkt) => pkt,
                 None => {
                     error!("Failed to read packet. Connection is closed.");
                     let mut failed_tasks = vec![task];
                     failed_tasks.extend(tasks_iter);
+                    let failed_num = failed_tasks.len();
                     let err = BackendError::Io(io::Error::from(io::ErrorKind::BrokenPipe));
                     let retry_state = handle_conn_err(retry_times_opt, failed_tasks, &err);
+                    if retry_state.is_none() {
+                        failed += failed_num;
+                    }
+                    record_replies(succeeded, failed);
                     return Err((err, retry_state));
                 }
             };
 
+            if packet_res.is_ok() {
+                succeeded += 1;
+            } else {
+                failed += 1;
+            }
             task.log_event(TaskEvent::ReceivedFromBackend);
             handler.handle_task(task, packet_res);
         }
+        record_replies(succeeded, failed);
     }
 }
 
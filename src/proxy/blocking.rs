@@ -1,10 +1,11 @@
 use super::backend::{
     BackendError, CmdTask, CmdTaskResultHandler, CmdTaskResultHandlerFactory, ConnFactory, IntoTask,
 };
+use super::breaker::BreakerMap;
 use super::cluster::ClusterTag;
 use super::command::{CommandError, CommandResult};
 use super::sender::{
-    CachedSenderFactory, CmdTaskSender, CmdTaskSenderFactory, RRSenderGroupFactory,
+    CachedSenderFactory, CmdTaskSender, CmdTaskSenderFactory, ConnStates, RRSenderGroupFactory,
     RecoverableBackendNodeFactory,
 };
 use super::service::ServerProxyConfig;
@@ -45,18 +46,20 @@ pub fn gen_basic_blocking_sender_factory<F: CmdTaskResultHandlerFactory, CF: Con
     reply_handler_factory: Arc<F>,
     conn_factory: Arc<CF>,
     future_registry: Arc<TrackedFutureRegistry>,
+    breaker_map: Arc<BreakerMap>,
 ) -> BasicBlockingSenderFactory<F, CF>
 where
     <F::Handler as CmdTaskResultHandler>::Task: CmdTask<Pkt = CF::Pkt>,
     CF::Pkt: Send,
 {
     RRSenderGroupFactory::new(
-        config.backend_conn_num,
+        config.clone(),
         RecoverableBackendNodeFactory::new(
             config.clone(),
             reply_handler_factory,
             conn_factory,
             future_registry,
+            breaker_map,
         ),
     )
 }
@@ -330,6 +333,10 @@ where
     fn send(&self, cmd_task: Self::Task) -> Result<(), BackendError> {
         self.queue.send(cmd_task)
     }
+
+    fn get_conn_states(&self) -> ConnStates {
+        self.queue.inner_sender.get_conn_states()
+    }
 }
 
 pub struct TaskBlockingQueueSenderFactory<F, BS>
@@ -472,6 +479,10 @@ impl<T: CmdTask> CmdTask for CounterTask<T> {
     fn log_event(&mut self, event: TaskEvent) {
         self.inner.log_event(event)
     }
+
+    fn is_blocking(&self) -> bool {
+        self.inner.is_blocking()
+    }
 }
 
 pub struct BlockingHintTask<T: CmdTask> {
@@ -535,6 +546,10 @@ impl<T: CmdTask> CmdTask for BlockingHintTask<T> {
     fn log_event(&mut self, event: TaskEvent) {
         self.inner.log_event(event)
     }
+
+    fn is_blocking(&self) -> bool {
+        self.inner.is_blocking()
+    }
 }
 
 impl<T: CmdTask + ClusterTag> ClusterTag for BlockingHintTask<T> {
@@ -0,0 +1,402 @@
+use super::service::ServerProxyConfig;
+use crate::protocol::{Array, BulkStr, Resp, RespVec};
+use dashmap::DashMap;
+use std::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, Instant};
+
+// The error rate is calculated inside this window.
+const BREAKER_WINDOW: Duration = Duration::from_secs(10);
+
+const STATE_CLOSED: u8 = 0;
+const STATE_OPEN: u8 = 1;
+const STATE_HALF_OPEN: u8 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BreakerState {
+    Closed,
+    Open,
+    HalfOpen,
+}
+
+impl BreakerState {
+    fn from_u8(state: u8) -> Self {
+        match state {
+            STATE_OPEN => Self::Open,
+            STATE_HALF_OPEN => Self::HalfOpen,
+            _ => Self::Closed,
+        }
+    }
+
+    fn as_u8(self) -> u8 {
+        match self {
+            Self::Closed => STATE_CLOSED,
+            Self::Open => STATE_OPEN,
+            Self::HalfOpen => STATE_HALF_OPEN,
+        }
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Closed => "closed",
+            Self::Open => "open",
+            Self::HalfOpen => "half_open",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BreakerConfig {
+    // In percentage. 0 disables it.
+    pub error_rate: u64,
+    // The error rate is only checked after this number of requests in the window.
+    pub min_requests: u64,
+    // 0 disables it.
+    pub consecutive_timeouts: u64,
+    pub open_time: Duration,
+    // None disables the reply timeout.
+    pub reply_timeout: Option<Duration>,
+}
+
+impl BreakerConfig {
+    pub fn from_config(config: &ServerProxyConfig) -> Self {
+        let reply_timeout = if config.backend_reply_timeout == 0 {
+            None
+        } else {
+            Some(Duration::from_millis(config.backend_reply_timeout))
+        };
+        Self {
+            error_rate: config.breaker_error_rate,
+            min_requests: config.breaker_min_requests,
+            consecutive_timeouts: config.breaker_consecutive_timeouts,
+            open_time: Duration::from_millis(config.breaker_open_time),
+            reply_timeout,
+        }
+    }
+}
+
+struct BreakerCounters {
+    window_start: Instant,
+    requests: u64,
+    errors: u64,
+    consecutive_timeouts: u64,
+    opened_at: Instant,
+    // Only one probe request is allowed in the half-open state.
+    probe_sent_at: Option<Instant>,
+}
+
+// Closed: all the requests are sent to the backend.
+// Open: all the requests fail immediately until `open_time` passes.
+// HalfOpen: a single probe request is sent. The breaker is closed again
+// if it succeeds, or is opened again if it fails.
+pub struct CircuitBreaker {
+    address: String,
+    config: BreakerConfig,
+    state: AtomicU8,
+    counters: Mutex<BreakerCounters>,
+    rejected: AtomicU64,
+    opened_times: AtomicU64,
+}
+
+impl CircuitBreaker {
+    pub fn new(address: String, config: BreakerConfig) -> Self {
+        let now = Instant::now();
+        Self {
+            address,
+            config,
+            state: AtomicU8::new(STATE_CLOSED),
+            counters: Mutex::new(BreakerCounters {
+                window_start: now,
+                requests: 0,
+                errors: 0,
+                consecutive_timeouts: 0,
+                opened_at: now,
+                probe_sent_at: None,
+            }),
+            rejected: AtomicU64::new(0),
+            opened_times: AtomicU64::new(0),
+        }
+    }
+
+    pub fn get_state(&self) -> BreakerState {
+        BreakerState::from_u8(self.state.load(Ordering::Relaxed))
+    }
+
+    pub fn get_reply_timeout(&self) -> Option<Duration> {
+        self.config.reply_timeout
+    }
+
+    pub fn allow_request(&self) -> bool {
+        if self.get_state() == BreakerState::Closed {
+            return true;
+        }
+
+        let now = Instant::now();
+        let mut counters = self.counters.lock().expect("CircuitBreaker::allow_request");
+        let allowed = match self.get_state() {
+            BreakerState::Closed => true,
+            BreakerState::Open => {
+                if now.duration_since(counters.opened_at) >= self.config.open_time {
+                    self.set_state(BreakerState::HalfOpen);
+                    counters.probe_sent_at = Some(now);
+                    true
+                } else {
+                    false
+                }
+            }
+            // Send another probe if the result of the last one is lost.
+            BreakerState::HalfOpen => match counters.probe_sent_at {
+                Some(sent_at) if now.duration_since(sent_at) < self.config.open_time => false,
+                _ => {
+                    counters.probe_sent_at = Some(now);
+                    true
+                }
+            },
+        };
+        if !allowed {
+            self.rejected.fetch_add(1, Ordering::Relaxed);
+        }
+        allowed
+    }
+
+    pub fn record_success(&self, count: u64) {
+        if count == 0 {
+            return;
+        }
+        let now = Instant::now();
+        let mut counters = self
+            .counters
+            .lock()
+            .expect("CircuitBreaker::record_success");
+        Self::roll_window(&mut counters, now);
+        counters.requests += count;
+        counters.consecutive_timeouts = 0;
+        if self.get_state() == BreakerState::HalfOpen {
+            info!("circuit breaker of {} is closed", self.address);
+            self.set_state(BreakerState::Closed);
+            Self::reset_window(&mut counters, now);
+            counters.probe_sent_at = None;
+        }
+    }
+
+    pub fn record_error(&self, count: u64) {
+        self.record_failure(count, false)
+    }
+
+    pub fn record_timeout(&self, count: u64) {
+        self.record_failure(count, true)
+    }
+
+    fn record_failure(&self, count: u64, timeout: bool) {
+        if count == 0 {
+            return;
+        }
+        let now = Instant::now();
+        let mut counters = self
+            .counters
+            .lock()
+            .expect("CircuitBreaker::record_failure");
+        Self::roll_window(&mut counters, now);
+        counters.requests += count;
+        counters.errors += count;
+        if timeout {
+            counters.consecutive_timeouts += count;
+        }
+
+        let should_open = match self.get_state() {
+            BreakerState::Open => false,
+            BreakerState::HalfOpen => true,
+            BreakerState::Closed => self.exceed_thresholds(&counters),
+        };
+        if should_open {
+            warn!(
+                "circuit breaker of {} is open: requests {} errors {} consecutive timeouts {}",
+                self.address, counters.requests, counters.errors, counters.consecutive_timeouts
+            );
+            self.set_state(BreakerState::Open);
+            self.opened_times.fetch_add(1, Ordering::Relaxed);
+            counters.opened_at = now;
+            counters.probe_sent_at = None;
+            counters.consecutive_timeouts = 0;
+            Self::reset_window(&mut counters, now);
+        }
+    }
+
+    fn exceed_thresholds(&self, counters: &BreakerCounters) -> bool {
+        let timeouts = self.config.consecutive_timeouts;
+        if timeouts != 0 && counters.consecutive_timeouts >= timeouts {
+            return true;
+        }
+        let error_rate = self.config.error_rate;
+        error_rate != 0
+            && counters.requests >= self.config.min_requests
+            && counters.errors * 100 >= counters.requests * error_rate
+    }
+
+    fn roll_window(counters: &mut BreakerCounters, now: Instant) {
+        if now.duration_since(counters.window_start) >= BREAKER_WINDOW {
+            Self::reset_window(counters, now);
+        }
+    }
+
+    fn reset_window(counters: &mut BreakerCounters, now: Instant) {
+        counters.window_start = now;
+        counters.requests = 0;
+        counters.errors = 0;
+    }
+
+    fn set_state(&self, state: BreakerState) {
+        self.state.store(state.as_u8(), Ordering::Relaxed)
+    }
+
+    fn info_lines(&self) -> Vec<String> {
+        let (requests, errors) = {
+            let counters = self.counters.lock().expect("CircuitBreaker::info_lines");
+            (counters.requests, counters.errors)
+        };
+        vec![
+            format!("address: {}", self.address),
+            format!("state: {}", self.get_state().as_str()),
+            format!("requests: {}", requests),
+            format!("errors: {}", errors),
+            format!("rejected: {}", self.rejected.load(Ordering::Relaxed)),
+            format!(
+                "opened_times: {}",
+                self.opened_times.load(Ordering::Relaxed)
+            ),
+        ]
+    }
+}
+
+// All the connections to the same backend share the same breaker.
+pub struct BreakerMap {
+    config: BreakerConfig,
+    breakers: DashMap<String, Weak<CircuitBreaker>>,
+}
+
+impl BreakerMap {
+    pub fn new(config: BreakerConfig) -> Self {
+        Self {
+            config,
+            breakers: DashMap::new(),
+        }
+    }
+
+    pub fn get_or_create(&self, address: &str) -> Arc<CircuitBreaker> {
+        if let Some(breaker) = self.breakers.get(address).and_then(|b| b.upgrade()) {
+            return breaker;
+        }
+        let mut entry = self.breakers.entry(address.to_string()).or_default();
+        if let Some(breaker) = entry.upgrade() {
+            return breaker;
+        }
+        let breaker = Arc::new(CircuitBreaker::new(
+            address.to_string(),
+            self.config.clone(),
+        ));
+        *entry = Arc::downgrade(&breaker);
+        breaker
+    }
+
+    pub fn info(&self) -> RespVec {
+        self.breakers
+            .retain(|_, breaker| breaker.upgrade().is_some());
+        let breakers = self
+            .breakers
+            .iter()
+            .filter_map(|item| {
+                let breaker = item.value().upgrade()?;
+                let lines = breaker.info_lines();
+                Some(Resp::Arr(Array::Arr(
+                    lines
+                        .into_iter()
+                        .map(|s| Resp::Bulk(BulkStr::Str(s.into_bytes())))
+                        .collect(),
+                )))
+            })
+            .collect();
+        Resp::Arr(Array::Arr(breakers))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    fn gen_config() -> BreakerConfig {
+        BreakerConfig {
+            error_rate: 50,
+            min_requests: 10,
+            consecutive_timeouts: 3,
+            open_time: Duration::from_millis(50),
+            reply_timeout: None,
+        }
+    }
+
+    #[test]
+    fn test_breaker_error_rate() {
+        let breaker = CircuitBreaker::new("127.0.0.1:6379".to_string(), gen_config());
+        breaker.record_error(4);
+        assert_eq!(breaker.get_state(), BreakerState::Closed);
+        breaker.record_success(5);
+        assert_eq!(breaker.get_state(), BreakerState::Closed);
+        // 5 errors out of 10 requests.
+        breaker.record_error(1);
+        assert_eq!(breaker.get_state(), BreakerState::Open);
+        assert!(!breaker.allow_request());
+    }
+
+    #[test]
+    fn test_breaker_consecutive_timeouts() {
+        let breaker = CircuitBreaker::new("127.0.0.1:6379".to_string(), gen_config());
+        breaker.record_timeout(2);
+        breaker.record_success(1);
+        breaker.record_timeout(2);
+        assert_eq!(breaker.get_state(), BreakerState::Closed);
+        breaker.record_timeout(1);
+        assert_eq!(breaker.get_state(), BreakerState::Open);
+    }
+
+    #[test]
+    fn test_breaker_half_open() {
+        let breaker = CircuitBreaker::new("127.0.0.1:6379".to_string(), gen_config());
+        breaker.record_timeout(3);
+        assert!(!breaker.allow_request());
+
+        thread::sleep(Duration::from_millis(60));
+        assert!(breaker.allow_request());
+        assert_eq!(breaker.get_state(), BreakerState::HalfOpen);
+        // Only the probe is allowed.
+        assert!(!breaker.allow_request());
+        breaker.record_error(1);
+        assert_eq!(breaker.get_state(), BreakerState::Open);
+
+        thread::sleep(Duration::from_millis(60));
+        assert!(breaker.allow_request());
+        breaker.record_success(1);
+        assert_eq!(breaker.get_state(), BreakerState::Closed);
+        assert!(breaker.allow_request());
+        assert_eq!(breaker.rejected.load(Ordering::Relaxed), 2);
+        assert_eq!(breaker.opened_times.load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn test_breaker_map() {
+        let breaker_map = BreakerMap::new(gen_config());
+        let breaker1 = breaker_map.get_or_create("127.0.0.1:6379");
+        let breaker2 = breaker_map.get_or_create("127.0.0.1:6379");
+        assert!(Arc::ptr_eq(&breaker1, &breaker2));
+        match breaker_map.info() {
+            Resp::Arr(Array::Arr(breakers)) => assert_eq!(breakers.len(), 1),
+            _ => panic!(),
+        }
+
+        drop(breaker1);
+        drop(breaker2);
+        match breaker_map.info() {
+            Resp::Arr(Array::Arr(breakers)) => assert!(breakers.is_empty()),
+            _ => panic!(),
+        }
+    }
+}
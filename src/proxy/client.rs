@@ -0,0 +1,439 @@
+use crate::common::cluster::ClusterName;
+use crate::protocol::{Array, BulkStr, Resp, RespVec};
+use dashmap::DashMap;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Instant;
+use tokio::sync::Notify;
+
+#[derive(Default)]
+struct ClientCounter {
+    connected: AtomicUsize,
+    rejected: AtomicU64,
+    reaped: AtomicU64,
+}
+
+impl ClientCounter {
+    // 0 means unlimited.
+    fn try_increase(&self, maxclients: u64) -> bool {
+        let prev = self.connected.fetch_add(1, Ordering::SeqCst);
+        if maxclients != 0 && prev as u64 >= maxclients {
+            self.connected.fetch_sub(1, Ordering::SeqCst);
+            self.rejected.fetch_add(1, Ordering::Relaxed);
+            return false;
+        }
+        true
+    }
+
+    fn to_lines(&self) -> Vec<String> {
+        vec![
+            format!("connected: {}", self.connected.load(Ordering::SeqCst)),
+            format!("rejected: {}", self.rejected.load(Ordering::Relaxed)),
+            format!("reaped: {}", self.reaped.load(Ordering::Relaxed)),
+        ]
+    }
+}
+
+// Decreases the connected count when the session is closed.
+pub struct ClientGuard {
+    counter: Arc<ClientCounter>,
+}
+
+impl Drop for ClientGuard {
+    fn drop(&mut self) {
+        self.counter.connected.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+// A session is counted in the cluster it's sending commands to.
+pub struct ClusterClientGuard {
+    cluster_name: ClusterName,
+    _guard: ClientGuard,
+}
+
+impl ClusterClientGuard {
+    pub fn get_cluster_name(&self) -> &ClusterName {
+        &self.cluster_name
+    }
+}
+
+// The states of a session shown in CLIENT LIST.
+pub struct ClientInfo {
+    id: usize,
+    addr: String,
+    created: Instant,
+    cluster_name: RwLock<Option<ClusterName>>,
+    name: RwLock<String>,
+    last_cmd: Mutex<String>,
+    // In milliseconds since `created`.
+    last_active: AtomicU64,
+    // The commands which have been received but not replied yet.
+    pending: AtomicUsize,
+    killed: Notify,
+}
+
+impl ClientInfo {
+    pub fn new(id: usize, addr: String) -> Self {
+        Self {
+            id,
+            addr,
+            created: Instant::now(),
+            cluster_name: RwLock::new(None),
+            name: RwLock::new(String::new()),
+            last_cmd: Mutex::new(String::new()),
+            last_active: AtomicU64::new(0),
+            pending: AtomicUsize::new(0),
+            killed: Notify::new(),
+        }
+    }
+
+    pub fn get_id(&self) -> usize {
+        self.id
+    }
+
+    pub fn get_addr(&self) -> &str {
+        &self.addr
+    }
+
+    pub fn set_cluster_name(&self, cluster_name: ClusterName) {
+        *self
+            .cluster_name
+            .write()
+            .expect("ClientInfo::set_cluster_name") = Some(cluster_name);
+    }
+
+    fn in_cluster(&self, cluster_name: &ClusterName) -> bool {
+        self.cluster_name
+            .read()
+            .expect("ClientInfo::in_cluster")
+            .as_ref()
+            == Some(cluster_name)
+    }
+
+    // An empty name means the client has no name.
+    pub fn get_name(&self) -> String {
+        self.name.read().expect("ClientInfo::get_name").clone()
+    }
+
+    pub fn set_name(&self, name: String) {
+        *self.name.write().expect("ClientInfo::set_name") = name;
+    }
+
+    pub fn record_cmd(&self, cmd_name: Option<&str>) {
+        self.pending.fetch_add(1, Ordering::Relaxed);
+        let now = self.created.elapsed().as_millis() as u64;
+        self.last_active.store(now, Ordering::Relaxed);
+        // Reuse the buffer to avoid allocation for every command.
+        let mut last_cmd = self.last_cmd.lock().expect("ClientInfo::record_cmd");
+        last_cmd.clear();
+        last_cmd.push_str(cmd_name.unwrap_or("NULL"));
+        last_cmd.make_ascii_lowercase();
+    }
+
+    pub fn record_reply(&self) {
+        self.pending.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    // The session will be closed.
+    pub fn kill(&self) {
+        self.killed.notify();
+    }
+
+    pub async fn wait_killed(&self) {
+        self.killed.notified().await
+    }
+
+    pub fn to_line(&self) -> String {
+        let cluster_name = self
+            .cluster_name
+            .read()
+            .expect("ClientInfo::to_line")
+            .as_ref()
+            .map(|cluster_name| cluster_name.to_string())
+            .unwrap_or_else(String::new);
+        let age = self.created.elapsed();
+        let idle = age
+            .as_millis()
+            .saturating_sub(u128::from(self.last_active.load(Ordering::Relaxed)));
+        format!(
+            "id={} addr={} name={} cluster={} age={} idle={} pending={} cmd={}",
+            self.id,
+            self.addr,
+            self.get_name(),
+            cluster_name,
+            age.as_secs(),
+            idle / 1000,
+            self.pending.load(Ordering::Relaxed),
+            self.last_cmd.lock().expect("ClientInfo::to_line"),
+        )
+    }
+}
+
+// Removes the session from the registry when the session is closed.
+pub struct SessionGuard {
+    sessions: Arc<DashMap<usize, Arc<ClientInfo>>>,
+    client_info: Arc<ClientInfo>,
+}
+
+impl SessionGuard {
+    pub fn get_client_info(&self) -> &Arc<ClientInfo> {
+        &self.client_info
+    }
+}
+
+impl Drop for SessionGuard {
+    fn drop(&mut self) {
+        self.sessions.remove(&self.client_info.get_id());
+    }
+}
+
+// The filters of `CLIENT KILL`. The session matching all of them will be killed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClientKillFilter {
+    pub id: Option<usize>,
+    pub addr: Option<String>,
+    // Skip the session sending this command.
+    pub skip_me: bool,
+}
+
+impl ClientKillFilter {
+    fn matches(&self, client_info: &ClientInfo, self_id: usize) -> bool {
+        if self.skip_me && client_info.get_id() == self_id {
+            return false;
+        }
+        if let Some(id) = self.id {
+            if client_info.get_id() != id {
+                return false;
+            }
+        }
+        if let Some(addr) = self.addr.as_ref() {
+            if client_info.get_addr() != addr {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+pub struct ClientRegistry {
+    proxy: Arc<ClientCounter>,
+    clusters: DashMap<ClusterName, Arc<ClientCounter>>,
+    sessions: Arc<DashMap<usize, Arc<ClientInfo>>>,
+}
+
+impl Default for ClientRegistry {
+    fn default() -> Self {
+        Self {
+            proxy: Arc::new(ClientCounter::default()),
+            clusters: DashMap::new(),
+            sessions: Arc::new(DashMap::new()),
+        }
+    }
+}
+
+impl ClientRegistry {
+    pub fn try_connect(&self, maxclients: u64) -> Option<ClientGuard> {
+        if !self.proxy.try_increase(maxclients) {
+            return None;
+        }
+        Some(ClientGuard {
+            counter: self.proxy.clone(),
+        })
+    }
+
+    pub fn try_join_cluster(
+        &self,
+        cluster_name: &ClusterName,
+        maxclients: u64,
+    ) -> Option<ClusterClientGuard> {
+        let counter = self.get_cluster_counter(cluster_name);
+        if !counter.try_increase(maxclients) {
+            return None;
+        }
+        Some(ClusterClientGuard {
+            cluster_name: cluster_name.clone(),
+            _guard: ClientGuard { counter },
+        })
+    }
+
+    pub fn record_reaped(&self, cluster_name: Option<&ClusterName>) {
+        self.proxy.reaped.fetch_add(1, Ordering::Relaxed);
+        if let Some(cluster_name) = cluster_name {
+            self.get_cluster_counter(cluster_name)
+                .reaped
+                .fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn get_cluster_counter(&self, cluster_name: &ClusterName) -> Arc<ClientCounter> {
+        if let Some(counter) = self.clusters.get(cluster_name) {
+            return counter.clone();
+        }
+        self.clusters
+            .entry(cluster_name.clone())
+            .or_insert_with(|| Arc::new(ClientCounter::default()))
+            .clone()
+    }
+
+    pub fn register_session(&self, client_info: Arc<ClientInfo>) -> SessionGuard {
+        self.sessions
+            .insert(client_info.get_id(), client_info.clone());
+        SessionGuard {
+            sessions: self.sessions.clone(),
+            client_info,
+        }
+    }
+
+    // Clients could only see the other clients of the same cluster.
+    pub fn get_session_num(&self) -> usize {
+        self.sessions.len()
+    }
+
+    pub fn list_clients(&self, cluster_name: &ClusterName) -> Vec<Arc<ClientInfo>> {
+        let mut clients: Vec<_> = self
+            .sessions
+            .iter()
+            .filter(|item| item.value().in_cluster(cluster_name))
+            .map(|item| item.value().clone())
+            .collect();
+        clients.sort_unstable_by_key(|client_info| client_info.get_id());
+        clients
+    }
+
+    // Returns the number of the killed clients.
+    pub fn kill_clients(
+        &self,
+        cluster_name: &ClusterName,
+        filter: &ClientKillFilter,
+        self_id: usize,
+    ) -> usize {
+        let mut killed = 0;
+        for client_info in self.list_clients(cluster_name).into_iter() {
+            if filter.matches(&client_info, self_id) {
+                client_info.kill();
+                killed += 1;
+            }
+        }
+        killed
+    }
+
+    pub fn info(&self) -> RespVec {
+        let to_resp = |lines: Vec<String>| {
+            Resp::Arr(Array::Arr(
+                lines
+                    .into_iter()
+                    .map(|s| Resp::Bulk(BulkStr::Str(s.into_bytes())))
+                    .collect(),
+            ))
+        };
+        let clusters = self
+            .clusters
+            .iter()
+            .map(|item| {
+                let mut lines = vec![format!("name: {}", item.key())];
+                lines.extend(item.value().to_lines());
+                to_resp(lines)
+            })
+            .collect();
+        Resp::Arr(Array::Arr(vec![
+            Resp::Bulk(BulkStr::Str(b"proxy".to_vec())),
+            to_resp(self.proxy.to_lines()),
+            Resp::Bulk(BulkStr::Str(b"clusters".to_vec())),
+            Resp::Arr(Array::Arr(clusters)),
+        ]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryFrom;
+
+    #[test]
+    fn test_proxy_maxclients() {
+        let registry = ClientRegistry::default();
+        let guard1 = registry.try_connect(2).unwrap();
+        let _guard2 = registry.try_connect(2).unwrap();
+        assert!(registry.try_connect(2).is_none());
+        assert_eq!(registry.proxy.rejected.load(Ordering::Relaxed), 1);
+        drop(guard1);
+        assert!(registry.try_connect(2).is_some());
+        assert_eq!(registry.proxy.connected.load(Ordering::SeqCst), 1);
+
+        let guards: Vec<_> = (0..10).filter_map(|_| registry.try_connect(0)).collect();
+        assert_eq!(guards.len(), 10);
+    }
+
+    #[test]
+    fn test_cluster_maxclients() {
+        let registry = ClientRegistry::default();
+        let cluster1 = ClusterName::try_from("cluster1").unwrap();
+        let cluster2 = ClusterName::try_from("cluster2").unwrap();
+
+        let guard = registry.try_join_cluster(&cluster1, 1).unwrap();
+        assert_eq!(guard.get_cluster_name(), &cluster1);
+        assert!(registry.try_join_cluster(&cluster1, 1).is_none());
+        assert!(registry.try_join_cluster(&cluster2, 1).is_some());
+        drop(guard);
+        assert!(registry.try_join_cluster(&cluster1, 1).is_some());
+
+        registry.record_reaped(Some(&cluster1));
+        let counter = registry.get_cluster_counter(&cluster1);
+        assert_eq!(counter.reaped.load(Ordering::Relaxed), 1);
+        assert_eq!(counter.rejected.load(Ordering::Relaxed), 1);
+        assert_eq!(registry.proxy.reaped.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn test_client_info() {
+        let client_info = ClientInfo::new(7, "127.0.0.1:6000".to_string());
+        client_info.set_cluster_name(ClusterName::try_from("mycluster").unwrap());
+        client_info.set_name("myclient".to_string());
+        client_info.record_cmd(Some("GET"));
+        client_info.record_cmd(Some("SET"));
+        client_info.record_reply();
+        assert_eq!(
+            client_info.to_line(),
+            "id=7 addr=127.0.0.1:6000 name=myclient cluster=mycluster age=0 idle=0 pending=1 cmd=set"
+        );
+    }
+
+    #[test]
+    fn test_list_and_kill_clients() {
+        let registry = ClientRegistry::default();
+        let cluster1 = ClusterName::try_from("cluster1").unwrap();
+        let cluster2 = ClusterName::try_from("cluster2").unwrap();
+        let guards: Vec<_> = (0..3)
+            .map(|id| {
+                let client_info = Arc::new(ClientInfo::new(id, format!("127.0.0.1:{}", id)));
+                let cluster_name = if id == 2 { &cluster2 } else { &cluster1 };
+                client_info.set_cluster_name(cluster_name.clone());
+                registry.register_session(client_info)
+            })
+            .collect();
+
+        let ids: Vec<_> = registry
+            .list_clients(&cluster1)
+            .iter()
+            .map(|client_info| client_info.get_id())
+            .collect();
+        assert_eq!(ids, vec![0, 1]);
+
+        let filter = ClientKillFilter {
+            id: None,
+            addr: None,
+            skip_me: true,
+        };
+        assert_eq!(registry.kill_clients(&cluster1, &filter, 0), 1);
+        let filter = ClientKillFilter {
+            id: None,
+            addr: Some("127.0.0.1:2".to_string()),
+            skip_me: false,
+        };
+        assert_eq!(registry.kill_clients(&cluster1, &filter, 0), 0);
+        assert_eq!(registry.kill_clients(&cluster2, &filter, 0), 1);
+
+        drop(guards);
+        assert!(registry.list_clients(&cluster1).is_empty());
+    }
+}
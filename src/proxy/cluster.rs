@@ -1,4 +1,5 @@
 use super::backend::{BackendError, CmdTask, IntoTask};
+use super::metrics::BackendMetrics;
 use super::sender::{CmdTaskSender, CmdTaskSenderFactory};
 use super::slot::SlotMap;
 use crate::common::cluster::{ClusterName, RangeList, SlotRange, SlotRangeTag};
@@ -105,6 +106,7 @@ where
                 epoch,
                 slot_ranges.clone(),
                 active_redirection,
+                cluster_meta.get_configs().get(cluster_name),
             );
             remote_clusters.insert(cluster_name.clone(), remote_cluster);
         }
@@ -272,6 +274,142 @@ where
             .map(|local_cluster| &local_cluster.config)
     }
 
+    // The clusters without local slots could still have the config
+    // so that their clients could authenticate before being redirected.
+    pub fn get_auth_config(&self, cluster_name: &ClusterName) -> Option<&ClusterConfig> {
+        self.get_config(cluster_name).or_else(|| {
+            self.remote_clusters
+                .get(cluster_name)
+                .and_then(|remote_cluster| remote_cluster.config.as_ref())
+        })
+    }
+
+    pub fn is_local_slot(&self, cluster_name: &ClusterName, slot: usize) -> bool {
+        self.local_clusters
+            .get(cluster_name)
+            .map(|local_cluster| local_cluster.local_backend.slot_map.get(slot).is_some())
+            .unwrap_or(false)
+    }
+
+    pub fn get_local_nodes(&self, cluster_name: &ClusterName) -> Vec<String> {
+        let mut nodes: Vec<String> = self
+            .local_clusters
+            .get(cluster_name)
+            .map(|local_cluster| local_cluster.local_backend.nodes.keys().cloned().collect())
+            .unwrap_or_default();
+        nodes.sort();
+        nodes
+    }
+
+    pub fn get_peer_proxies(&self, cluster_name: &ClusterName) -> Vec<String> {
+        self.remote_clusters
+            .get(cluster_name)
+            .map(|remote_cluster| {
+                remote_cluster
+                    .remote_backend
+                    .nodes
+                    .keys()
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    pub fn get_backend_metrics(&self) -> Vec<BackendMetrics> {
+        let mut metrics = vec![];
+        for (cluster_name, local_cluster) in self.local_clusters.iter() {
+            for (address, sender) in local_cluster.local_backend.nodes.iter() {
+                metrics.push(BackendMetrics {
+                    cluster_name: cluster_name.clone(),
+                    address: address.clone(),
+                    peer: false,
+                    conn_states: sender.get_conn_states(),
+                });
+            }
+        }
+        for (cluster_name, remote_cluster) in self.remote_clusters.iter() {
+            for (address, sender) in remote_cluster.remote_backend.nodes.iter() {
+                metrics.push(BackendMetrics {
+                    cluster_name: cluster_name.clone(),
+                    address: address.clone(),
+                    peer: true,
+                    conn_states: sender.get_conn_states(),
+                });
+            }
+        }
+        metrics
+    }
+
+    pub fn send_to_local_node(
+        &self,
+        cmd_task: <S as CmdTaskSender>::Task,
+        address: &str,
+    ) -> Result<(), ClusterSendError<<S as CmdTaskSender>::Task>> {
+        let sender = self
+            .local_clusters
+            .get(cmd_task.get_cluster_name())
+            .and_then(|local_cluster| local_cluster.local_backend.nodes.get(address));
+        match sender {
+            Some(sender) => sender.send(cmd_task).map_err(ClusterSendError::Backend),
+            None => {
+                let resp = Resp::Error(format!("node not found: {}", address).into_bytes());
+                cmd_task.set_resp_result(Ok(resp));
+                Err(ClusterSendError::SlotNotCovered)
+            }
+        }
+    }
+
+    pub fn send_to_peer(
+        &self,
+        cmd_task: <P as CmdTaskSender>::Task,
+        address: &str,
+    ) -> Result<(), ClusterSendError<<P as CmdTaskSender>::Task>> {
+        match self.remote_clusters.get(cmd_task.get_cluster_name()) {
+            Some(remote_cluster) => match remote_cluster.send_to_peer(cmd_task, address) {
+                Err(ClusterSendError::SlotNotFound(cmd_task)) => {
+                    let resp = Resp::Error(format!("peer not found: {}", address).into_bytes());
+                    cmd_task.set_resp_result(Ok(resp));
+                    Err(ClusterSendError::SlotNotCovered)
+                }
+                others => others,
+            },
+            None => {
+                let resp = Resp::Error(format!("peer not found: {}", address).into_bytes());
+                cmd_task.set_resp_result(Ok(resp));
+                Err(ClusterSendError::SlotNotCovered)
+            }
+        }
+    }
+
+    // Used by the commands which can't go through the shared backend connections.
+    // Returns the error reply if the slot is not owned by the local cluster.
+    pub fn get_local_node_address(
+        &self,
+        cluster_name: &ClusterName,
+        slot: usize,
+    ) -> Result<String, RespVec> {
+        if let Some(local_cluster) = self.local_clusters.get(cluster_name) {
+            if let Some(addr) = local_cluster.local_backend.slot_map.get(slot) {
+                return Ok(addr.to_string());
+            }
+        }
+
+        match self.remote_clusters.get(cluster_name) {
+            Some(remote_cluster) => match remote_cluster.slot_map.get(slot) {
+                Some(addr) => Err(Resp::Error(gen_moved(slot, addr.to_string()).into_bytes())),
+                None => Err(Resp::Error(
+                    format!("slot not covered {}", slot).into_bytes(),
+                )),
+            },
+            None if self.local_clusters.contains_key(cluster_name) => Err(Resp::Error(
+                format!("slot not found: {}", cluster_name).into_bytes(),
+            )),
+            None => Err(Resp::Error(
+                format!("{}: {}", ERR_CLUSTER_NOT_FOUND, cluster_name).into_bytes(),
+            )),
+        }
+    }
+
     pub fn cluster_exists(&self, cluster_name: &ClusterName) -> bool {
         self.local_clusters.contains_key(cluster_name)
             || self.remote_clusters.contains_key(cluster_name)
@@ -402,7 +540,11 @@ pub struct RemoteCluster<P: CmdTaskSender> {
     epoch: u64,
     slot_map: SlotMap,
     slot_ranges: HashMap<String, Vec<SlotRange>>,
-    remote_backend: Option<SenderMap<P>>,
+    // Always created since PUBLISH and the keyless commands also use the peer proxies
+    // even without active redirection.
+    remote_backend: SenderMap<P>,
+    active_redirection: bool,
+    config: Option<ClusterConfig>,
 }
 
 impl<P: CmdTaskSender> RemoteCluster<P> {
@@ -412,18 +554,17 @@ impl<P: CmdTaskSender> RemoteCluster<P> {
         epoch: u64,
         slot_map: HashMap<String, Vec<SlotRange>>,
         active_redirection: bool,
+        config: Option<ClusterConfig>,
     ) -> Self {
-        let remote_backend = if active_redirection {
-            Some(SenderMap::from_slot_map(sender_factory, &slot_map))
-        } else {
-            None
-        };
+        let remote_backend = SenderMap::from_slot_map(sender_factory, &slot_map);
         Self {
             name,
             epoch,
             slot_map: SlotMap::from_ranges(slot_map.clone()),
             slot_ranges: slot_map,
             remote_backend,
+            active_redirection,
+            config,
         }
     }
 
@@ -449,7 +590,7 @@ impl<P: CmdTaskSender> RemoteCluster<P> {
 
         match self.slot_map.get(slot) {
             Some(addr) => {
-                if self.remote_backend.is_some() {
+                if self.active_redirection {
                     Err(ClusterSendError::ActiveRedirection {
                         task: cmd_task,
                         slot,
@@ -475,14 +616,8 @@ impl<P: CmdTaskSender> RemoteCluster<P> {
         slot: usize,
         address: &str,
     ) -> Result<(), ClusterSendError<<P as CmdTaskSender>::Task>> {
-        if let Some(remote_backend) = self.remote_backend.as_ref() {
-            match remote_backend.nodes.get(address) {
-                Some(sender) => sender.send(cmd_task).map_err(ClusterSendError::Backend),
-                None => {
-                    warn!("failed to get node");
-                    Err(ClusterSendError::SlotNotFound(cmd_task))
-                }
-            }
+        if self.active_redirection {
+            self.send_to_peer(cmd_task, address)
         } else {
             let resp = Resp::Error(gen_moved(slot, address.to_string()).into_bytes());
             cmd_task.set_resp_result(Ok(resp));
@@ -490,6 +625,20 @@ impl<P: CmdTaskSender> RemoteCluster<P> {
         }
     }
 
+    pub fn send_to_peer(
+        &self,
+        cmd_task: <P as CmdTaskSender>::Task,
+        address: &str,
+    ) -> Result<(), ClusterSendError<<P as CmdTaskSender>::Task>> {
+        match self.remote_backend.nodes.get(address) {
+            Some(sender) => sender.send(cmd_task).map_err(ClusterSendError::Backend),
+            None => {
+                warn!("failed to get node");
+                Err(ClusterSendError::SlotNotFound(cmd_task))
+            }
+        }
+    }
+
     pub fn gen_remote_cluster_nodes(
         &self,
         migration_states: &HashMap<RangeList, MigrationState>,
@@ -34,6 +34,20 @@ pub enum CmdType {
     Config,
     Command,
     Asking,
+    Multi,
+    Exec,
+    Discard,
+    Watch,
+    Unwatch,
+    Subscribe,
+    Psubscribe,
+    Unsubscribe,
+    Punsubscribe,
+    Publish,
+    Hello,
+    ReadOnly,
+    ReadWrite,
+    Client,
 }
 
 impl CmdType {
@@ -62,6 +76,20 @@ impl CmdType {
             b"CONFIG" => CmdType::Config,
             b"COMMAND" => CmdType::Command,
             b"ASKING" => CmdType::Asking,
+            b"MULTI" => CmdType::Multi,
+            b"EXEC" => CmdType::Exec,
+            b"DISCARD" => CmdType::Discard,
+            b"WATCH" => CmdType::Watch,
+            b"UNWATCH" => CmdType::Unwatch,
+            b"SUBSCRIBE" => CmdType::Subscribe,
+            b"PSUBSCRIBE" => CmdType::Psubscribe,
+            b"UNSUBSCRIBE" => CmdType::Unsubscribe,
+            b"PUNSUBSCRIBE" => CmdType::Punsubscribe,
+            b"PUBLISH" => CmdType::Publish,
+            b"HELLO" => CmdType::Hello,
+            b"READONLY" => CmdType::ReadOnly,
+            b"READWRITE" => CmdType::ReadWrite,
+            b"CLIENT" => CmdType::Client,
             _ => CmdType::Others,
         }
     }
@@ -116,12 +144,38 @@ pub enum DataCmdType {
     RPOPLPUSH,
     LREM,
     LTRIM,
+    LPUSH,
+    LPUSHX,
+    RPUSH,
+    RPUSHX,
+    LSET,
+    LINSERT,
     // Hash commands
     HDEL,
+    HGET,
+    HGETALL,
+    HINCRBY,
+    HINCRBYFLOAT,
+    HMGET,
+    HMSET,
+    HRANDFIELD,
+    HSCAN,
+    HSET,
+    HSETNX,
+    HSTRLEN,
+    HVALS,
     // Set commands
     SMOVE,
     SPOP,
     SREM,
+    SUNION,
+    SINTER,
+    SDIFF,
+    SADD,
+    // HyperLogLog commands
+    PFCOUNT,
+    PFMERGE,
+    PFADD,
     // Sorted Set commands
     ZPOPMAX,
     ZPOPMIN,
@@ -129,15 +183,26 @@ pub enum DataCmdType {
     ZREMRANGEBYLEX,
     ZREMRANGEBYRANK,
     ZREMRANGEBYSCORE,
+    ZADD,
+    ZINCRBY,
     // Key commands
     EXPIRE,
     EXPIREAT,
     PEXPIRE,
     PEXPIREAT,
+    PERSIST,
     MOVE,
     RENAME,
     RENAMENX,
     UNLINK,
+    TOUCH,
+    SCAN,
+    KEYS,
+    // Server commands
+    DBSIZE,
+    RANDOMKEY,
+    FLUSHALL,
+    FLUSHDB,
     Others,
 }
 
@@ -192,18 +257,51 @@ impl DataCmdType {
             b"EXPIREAT" => DataCmdType::EXPIREAT,
             b"PEXPIRE" => DataCmdType::PEXPIRE,
             b"PEXPIREAT" => DataCmdType::PEXPIREAT,
+            b"PERSIST" => DataCmdType::PERSIST,
             b"HDEL" => DataCmdType::HDEL,
+            b"HGET" => DataCmdType::HGET,
+            b"HGETALL" => DataCmdType::HGETALL,
+            b"HINCRBY" => DataCmdType::HINCRBY,
+            b"HINCRBYFLOAT" => DataCmdType::HINCRBYFLOAT,
+            b"HMGET" => DataCmdType::HMGET,
+            b"HMSET" => DataCmdType::HMSET,
+            b"HRANDFIELD" => DataCmdType::HRANDFIELD,
+            b"HSCAN" => DataCmdType::HSCAN,
+            b"HSET" => DataCmdType::HSET,
+            b"HSETNX" => DataCmdType::HSETNX,
+            b"HSTRLEN" => DataCmdType::HSTRLEN,
+            b"HVALS" => DataCmdType::HVALS,
             b"LPOP" => DataCmdType::LPOP,
             b"RPOP" => DataCmdType::RPOP,
             b"RPOPLPUSH" => DataCmdType::RPOPLPUSH,
             b"LREM" => DataCmdType::LREM,
             b"LTRIM" => DataCmdType::LTRIM,
+            b"LPUSH" => DataCmdType::LPUSH,
+            b"LPUSHX" => DataCmdType::LPUSHX,
+            b"RPUSH" => DataCmdType::RPUSH,
+            b"RPUSHX" => DataCmdType::RPUSHX,
+            b"LSET" => DataCmdType::LSET,
+            b"LINSERT" => DataCmdType::LINSERT,
             b"MOVE" => DataCmdType::MOVE,
             b"RENAME" => DataCmdType::RENAME,
             b"RENAMENX" => DataCmdType::RENAMENX,
             b"SMOVE" => DataCmdType::SMOVE,
             b"SPOP" => DataCmdType::SPOP,
             b"SREM" => DataCmdType::SREM,
+            b"SUNION" => DataCmdType::SUNION,
+            b"SINTER" => DataCmdType::SINTER,
+            b"SDIFF" => DataCmdType::SDIFF,
+            b"SADD" => DataCmdType::SADD,
+            b"PFCOUNT" => DataCmdType::PFCOUNT,
+            b"PFMERGE" => DataCmdType::PFMERGE,
+            b"PFADD" => DataCmdType::PFADD,
+            b"TOUCH" => DataCmdType::TOUCH,
+            b"SCAN" => DataCmdType::SCAN,
+            b"KEYS" => DataCmdType::KEYS,
+            b"DBSIZE" => DataCmdType::DBSIZE,
+            b"RANDOMKEY" => DataCmdType::RANDOMKEY,
+            b"FLUSHALL" => DataCmdType::FLUSHALL,
+            b"FLUSHDB" => DataCmdType::FLUSHDB,
             b"UNLINK" => DataCmdType::UNLINK,
             b"ZPOPMAX" => DataCmdType::ZPOPMAX,
             b"ZPOPMIN" => DataCmdType::ZPOPMIN,
@@ -211,6 +309,8 @@ impl DataCmdType {
             b"ZREMRANGEBYLEX" => DataCmdType::ZREMRANGEBYLEX,
             b"ZREMRANGEBYRANK" => DataCmdType::ZREMRANGEBYRANK,
             b"ZREMRANGEBYSCORE" => DataCmdType::ZREMRANGEBYSCORE,
+            b"ZADD" => DataCmdType::ZADD,
+            b"ZINCRBY" => DataCmdType::ZINCRBY,
             _ => DataCmdType::Others,
         }
     }
@@ -260,6 +360,80 @@ pub fn requires_blocking_migration(data_cmd_type: DataCmdType) -> bool {
     }
 }
 
+// The commands that modify the data.
+// The unknown commands in `DataCmdType::Others` are not included.
+pub fn is_write_cmd_type(data_cmd_type: DataCmdType) -> bool {
+    matches!(
+        data_cmd_type,
+        // String
+        DataCmdType::APPEND | DataCmdType::BITFIELD | DataCmdType::BITOP | DataCmdType::DECR
+        | DataCmdType::DECRBY | DataCmdType::GETSET | DataCmdType::INCR | DataCmdType::INCRBY
+        | DataCmdType::INCRBYFLOAT | DataCmdType::MSET | DataCmdType::MSETNX
+        | DataCmdType::PSETEX | DataCmdType::SET | DataCmdType::SETBIT | DataCmdType::SETEX
+        | DataCmdType::SETNX | DataCmdType::SETRANGE | DataCmdType::EVAL
+        | DataCmdType::EVALSHA | DataCmdType::DEL
+        // List
+        | DataCmdType::BLPOP | DataCmdType::BRPOP | DataCmdType::BRPOPLPUSH
+        | DataCmdType::LPOP | DataCmdType::RPOP | DataCmdType::RPOPLPUSH | DataCmdType::LREM
+        | DataCmdType::LTRIM | DataCmdType::LPUSH | DataCmdType::LPUSHX | DataCmdType::RPUSH
+        | DataCmdType::RPUSHX | DataCmdType::LSET | DataCmdType::LINSERT
+        // Hash
+        | DataCmdType::HDEL | DataCmdType::HINCRBY | DataCmdType::HINCRBYFLOAT
+        | DataCmdType::HMSET | DataCmdType::HSET | DataCmdType::HSETNX
+        // Set
+        | DataCmdType::SMOVE | DataCmdType::SPOP | DataCmdType::SREM | DataCmdType::SADD
+        // HyperLogLog
+        | DataCmdType::PFMERGE | DataCmdType::PFADD
+        // Sorted Set
+        | DataCmdType::ZPOPMAX | DataCmdType::ZPOPMIN | DataCmdType::ZREM
+        | DataCmdType::ZREMRANGEBYLEX | DataCmdType::ZREMRANGEBYRANK
+        | DataCmdType::ZREMRANGEBYSCORE | DataCmdType::ZADD | DataCmdType::ZINCRBY
+        // Key
+        | DataCmdType::EXPIRE | DataCmdType::EXPIREAT | DataCmdType::PEXPIRE
+        | DataCmdType::PEXPIREAT | DataCmdType::PERSIST | DataCmdType::MOVE
+        | DataCmdType::RENAME | DataCmdType::RENAMENX | DataCmdType::UNLINK
+        // Server
+        | DataCmdType::FLUSHALL | DataCmdType::FLUSHDB
+    )
+}
+
+// Only the single key commands which never modify the data
+// could be sent to the replicas.
+pub fn is_read_only_cmd(cmd_name: &[u8]) -> bool {
+    let mut stack_cmd_name = ArrayVec::<[u8; MAX_COMMAND_NAME_LENGTH]>::new();
+    for b in cmd_name {
+        if stack_cmd_name.try_push(byte_to_uppercase(*b)).is_err() {
+            return false;
+        }
+    }
+    let cmd_name: &[u8] = &stack_cmd_name;
+
+    matches!(
+        cmd_name,
+        // String
+        b"GET" | b"GETRANGE" | b"SUBSTR" | b"STRLEN" | b"GETBIT" | b"BITCOUNT" | b"BITPOS"
+        // Key
+        | b"EXISTS" | b"TYPE" | b"TTL" | b"PTTL" | b"DUMP"
+        // Hash
+        | b"HGET" | b"HMGET" | b"HGETALL" | b"HKEYS" | b"HVALS" | b"HLEN" | b"HEXISTS"
+        | b"HSTRLEN" | b"HSCAN"
+        // List
+        | b"LRANGE" | b"LLEN" | b"LINDEX"
+        // Set
+        | b"SMEMBERS" | b"SISMEMBER" | b"SCARD" | b"SRANDMEMBER" | b"SSCAN"
+        // Sorted Set
+        | b"ZRANGE" | b"ZRANGEBYSCORE" | b"ZRANGEBYLEX" | b"ZREVRANGE" | b"ZREVRANGEBYSCORE"
+        | b"ZREVRANGEBYLEX" | b"ZRANK" | b"ZREVRANK" | b"ZSCORE" | b"ZCARD" | b"ZCOUNT"
+        | b"ZLEXCOUNT" | b"ZSCAN"
+        // Geo
+        | b"GEOPOS" | b"GEODIST" | b"GEOHASH" | b"GEORADIUS_RO" | b"GEORADIUSBYMEMBER_RO"
+        // Stream
+        | b"XRANGE" | b"XREVRANGE" | b"XLEN"
+        // HyperLogLog
+        | b"PFCOUNT"
+    )
+}
+
 #[derive(Debug)]
 struct CommandInfo {
     cmd_type: CmdType,
@@ -280,9 +454,13 @@ impl CommandInfo {
     }
 
     fn get_key(data_cmd_type: DataCmdType, packet: &RespPacket) -> Option<&[u8]> {
+        packet.get_array_element(Self::get_key_index(data_cmd_type))
+    }
+
+    fn get_key_index(data_cmd_type: DataCmdType) -> usize {
         match data_cmd_type {
-            DataCmdType::EVAL | DataCmdType::EVALSHA => packet.get_array_element(3),
-            _ => packet.get_array_element(1),
+            DataCmdType::EVAL | DataCmdType::EVALSHA => 3,
+            _ => 1,
         }
     }
 }
@@ -328,7 +506,28 @@ impl Command {
     }
 
     pub fn change_element(&mut self, index: usize, data: Vec<u8>) -> bool {
-        self.request.change_bulk_array_element(index, data)
+        if !self.request.change_bulk_array_element(index, data) {
+            return false;
+        }
+        // The slot needs to be consistent with the changed key.
+        let data_cmd_type = self.info.data_cmd_type;
+        if index == CommandInfo::get_key_index(data_cmd_type) {
+            self.info.slot = CommandInfo::get_key(data_cmd_type, &self.request).map(generate_slot);
+        }
+        true
+    }
+
+    pub fn append_elements(&mut self, following_elements: Vec<BinSafeStr>) -> bool {
+        self.request.append_cmd(following_elements)
+    }
+
+    // The command type will also be changed.
+    pub fn change_command_name(&mut self, cmd_name: Vec<u8>) -> bool {
+        if !self.request.change_bulk_array_element(0, cmd_name) {
+            return false;
+        }
+        self.info = CommandInfo::new(&self.request);
+        true
     }
 
     pub fn extract_inner_cmd(&mut self, removed_num: usize) -> Option<usize> {
@@ -360,6 +559,23 @@ impl Command {
     pub fn get_slot(&self) -> Option<usize> {
         self.info.slot
     }
+
+    // The total size of the command arguments.
+    pub fn get_data_size(&self) -> usize {
+        let len = self.get_command_len().unwrap_or(0);
+        (0..len)
+            .filter_map(|i| self.get_command_element(i))
+            .map(|element| element.len())
+            .sum()
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.info.cmd_type == CmdType::Others
+            && self
+                .get_command_element(0)
+                .map(is_read_only_cmd)
+                .unwrap_or(false)
+    }
 }
 
 pub struct TaskReply {
@@ -386,6 +602,10 @@ impl TaskReply {
         (request, packet, slowlog)
     }
 
+    pub fn get_packet(&self) -> &RespPacket {
+        &self.packet
+    }
+
     pub fn into_resp_vec(self) -> RespVec {
         let (_, packet, _) = self.into_inner();
         packet.into_resp_vec()
@@ -524,6 +744,49 @@ mod tests {
     fn test_parse_cmd_type() {
         assert_eq!(CmdType::from_cmd_name(b"pInG"), CmdType::Ping);
         assert_eq!(CmdType::from_cmd_name(b"get"), CmdType::Others);
+        assert_eq!(CmdType::from_cmd_name(b"multi"), CmdType::Multi);
+        assert_eq!(CmdType::from_cmd_name(b"Exec"), CmdType::Exec);
+        assert_eq!(CmdType::from_cmd_name(b"WATCH"), CmdType::Watch);
+        assert_eq!(CmdType::from_cmd_name(b"subscribe"), CmdType::Subscribe);
+        assert_eq!(CmdType::from_cmd_name(b"PUBLISH"), CmdType::Publish);
+        assert_eq!(CmdType::from_cmd_name(b"hello"), CmdType::Hello);
+        assert_eq!(CmdType::from_cmd_name(b"readonly"), CmdType::ReadOnly);
+        assert_eq!(CmdType::from_cmd_name(b"READWRITE"), CmdType::ReadWrite);
+        assert_eq!(CmdType::from_cmd_name(b"Client"), CmdType::Client);
+    }
+
+    #[test]
+    fn test_write_cmd_type() {
+        assert!(is_write_cmd_type(DataCmdType::from_cmd_name(b"set")));
+        assert!(is_write_cmd_type(DataCmdType::from_cmd_name(b"LPUSH")));
+        assert!(is_write_cmd_type(DataCmdType::from_cmd_name(b"zadd")));
+        assert!(is_write_cmd_type(DataCmdType::from_cmd_name(b"EVAL")));
+        assert!(!is_write_cmd_type(DataCmdType::from_cmd_name(b"GET")));
+        assert!(!is_write_cmd_type(DataCmdType::from_cmd_name(b"HGETALL")));
+        assert!(!is_write_cmd_type(DataCmdType::from_cmd_name(b"PFCOUNT")));
+        assert!(!is_write_cmd_type(DataCmdType::from_cmd_name(b"UNKNOWN")));
+    }
+
+    #[test]
+    fn test_read_only_cmd() {
+        assert!(is_read_only_cmd(b"get"));
+        assert!(is_read_only_cmd(b"HGETALL"));
+        assert!(is_read_only_cmd(b"zRangeByScore"));
+        assert!(!is_read_only_cmd(b"SET"));
+        assert!(!is_read_only_cmd(b"GEORADIUS"));
+        assert!(!is_read_only_cmd(b"EVAL"));
+        assert!(!is_read_only_cmd(b"UNKNOWN"));
+
+        let request = RespPacket::Data(Resp::Arr(Array::Arr(vec![
+            Resp::Bulk(BulkStr::Str(b"get".to_vec())),
+            Resp::Bulk(BulkStr::Str(b"somekey".to_vec())),
+        ])));
+        assert!(Command::new(Box::new(request)).is_read_only());
+        let request = RespPacket::Data(Resp::Arr(Array::Arr(vec![
+            Resp::Bulk(BulkStr::Str(b"del".to_vec())),
+            Resp::Bulk(BulkStr::Str(b"somekey".to_vec())),
+        ])));
+        assert!(!Command::new(Box::new(request)).is_read_only());
     }
 
     #[test]
@@ -531,7 +794,12 @@ mod tests {
         assert_eq!(DataCmdType::from_cmd_name(b"aPPend"), DataCmdType::APPEND);
         assert_eq!(DataCmdType::from_cmd_name(b"get"), DataCmdType::GET);
         assert_eq!(DataCmdType::from_cmd_name(b"eVaL"), DataCmdType::EVAL);
-        assert_eq!(DataCmdType::from_cmd_name(b"HMGET"), DataCmdType::Others);
+        assert_eq!(DataCmdType::from_cmd_name(b"HMGET"), DataCmdType::HMGET);
+        assert_eq!(DataCmdType::from_cmd_name(b"HLEN"), DataCmdType::Others);
+        assert_eq!(DataCmdType::from_cmd_name(b"sunion"), DataCmdType::SUNION);
+        assert_eq!(DataCmdType::from_cmd_name(b"PFCount"), DataCmdType::PFCOUNT);
+        assert_eq!(DataCmdType::from_cmd_name(b"TOUCH"), DataCmdType::TOUCH);
+        assert_eq!(DataCmdType::from_cmd_name(b"scan"), DataCmdType::SCAN);
     }
 
     #[test]
@@ -4,14 +4,41 @@ use super::command::DataCmdType;
 use super::manager::SharedMetaMap;
 use super::session::CmdCtx;
 use crate::common::cluster::ClusterName;
-use crate::common::config::{ClusterConfig, CompressionStrategy};
+use crate::common::config::{ClusterConfig, CompressionCodec, CompressionStrategy};
 use crate::protocol::{Array, BulkStr, OptionalMulti, Resp, RespPacket};
 use std::error::Error;
 use std::fmt;
 use std::io;
 
+// The compressed values start with `VALUE_HEADER_MAGIC` and the codec id.
+// The values without this header are compressed by zstd in the older versions.
+const VALUE_HEADER_MAGIC: &[u8] = b"\xffUMC";
+const CODEC_NONE: u8 = 0;
+const CODEC_ZSTD: u8 = 1;
+const CODEC_LZ4: u8 = 2;
+const CODEC_SNAPPY: u8 = 3;
+// Redis limits a string value to 512MB.
+const MAX_DECOMPRESSED_SIZE: usize = 512 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct CompressionConfig {
+    pub strategy: CompressionStrategy,
+    pub codec: CompressionCodec,
+    pub min_size: usize,
+}
+
+impl CompressionConfig {
+    pub fn from_cluster_config(config: &ClusterConfig) -> Self {
+        Self {
+            strategy: config.compression_strategy,
+            codec: config.compression_codec,
+            min_size: config.compression_min_size,
+        }
+    }
+}
+
 pub trait CompressionStrategyConfig {
-    fn get_config(&self, cluster_name: &ClusterName) -> CompressionStrategy;
+    fn get_config(&self, cluster_name: &ClusterName) -> CompressionConfig;
 }
 
 pub struct CompressionStrategyMetaMapConfig<C: ConnFactory<Pkt = RespPacket>> {
@@ -27,11 +54,11 @@ impl<C: ConnFactory<Pkt = RespPacket>> CompressionStrategyMetaMapConfig<C> {
 impl<C: ConnFactory<Pkt = RespPacket>> CompressionStrategyConfig
     for CompressionStrategyMetaMapConfig<C>
 {
-    fn get_config(&self, cluster_name: &ClusterName) -> CompressionStrategy {
+    fn get_config(&self, cluster_name: &ClusterName) -> CompressionConfig {
         let meta_map = self.meta_map.lease();
         match meta_map.get_cluster_map().get_config(&cluster_name) {
-            Some(config) => config.compression_strategy,
-            None => ClusterConfig::default().compression_strategy,
+            Some(config) => CompressionConfig::from_cluster_config(config),
+            None => CompressionConfig::from_cluster_config(&ClusterConfig::default()),
         }
     }
 }
@@ -45,12 +72,20 @@ impl<C: CompressionStrategyConfig> CmdCompressor<C> {
         Self { config }
     }
 
+    pub fn is_enabled(&self, cluster_name: &ClusterName) -> bool {
+        self.config.get_config(cluster_name).strategy != CompressionStrategy::Disabled
+    }
+
     pub fn try_compressing_cmd_ctx(&self, cmd_ctx: &mut CmdCtx) -> Result<(), CompressionError> {
-        let strategy = self.config.get_config(cmd_ctx.get_cluster_name());
+        let config = self.config.get_config(cmd_ctx.get_cluster_name());
+        let strategy = config.strategy;
 
         if strategy == CompressionStrategy::Disabled {
             return Err(CompressionError::Disabled);
         }
+        if strategy == CompressionStrategy::HashOnly {
+            return Self::try_compressing_hash_cmd(cmd_ctx, &config);
+        }
 
         let index = match cmd_ctx.get_data_cmd_type() {
             DataCmdType::GETSET | DataCmdType::SET | DataCmdType::SETNX => OptionalMulti::Single(2),
@@ -86,23 +121,55 @@ impl<C: CompressionStrategyConfig> CmdCompressor<C> {
         };
 
         match index {
-            OptionalMulti::Single(index) => Self::compress_one_element(cmd_ctx, index),
+            OptionalMulti::Single(index) => Self::compress_one_element(cmd_ctx, index, &config),
             OptionalMulti::Multi(indices) => {
                 for index in indices.into_iter() {
-                    Self::compress_one_element(cmd_ctx, index)?;
+                    Self::compress_one_element(cmd_ctx, index, &config)?;
                 }
                 Ok(())
             }
         }
     }
 
-    fn compress_one_element(cmd_ctx: &mut CmdCtx, index: usize) -> Result<(), CompressionError> {
+    fn try_compressing_hash_cmd(
+        cmd_ctx: &mut CmdCtx,
+        config: &CompressionConfig,
+    ) -> Result<(), CompressionError> {
+        let indices = match cmd_ctx.get_data_cmd_type() {
+            // HSET key field value [field value ...]
+            DataCmdType::HSET | DataCmdType::HMSET => {
+                let l = match cmd_ctx.get_cmd().get_command_len() {
+                    None => return Err(CompressionError::InvalidRequest),
+                    Some(l) => l,
+                };
+                (3..l).step_by(2).collect()
+            }
+            DataCmdType::HSETNX => vec![3],
+            DataCmdType::HINCRBY
+            | DataCmdType::HINCRBYFLOAT
+            | DataCmdType::HSTRLEN
+            | DataCmdType::HSCAN
+            | DataCmdType::HRANDFIELD => return Err(CompressionError::RestrictedCmd),
+            _ => return Ok(()),
+        };
+
+        for index in indices.into_iter() {
+            Self::compress_one_element(cmd_ctx, index, config)?;
+        }
+        Ok(())
+    }
+
+    fn compress_one_element(
+        cmd_ctx: &mut CmdCtx,
+        index: usize,
+        config: &CompressionConfig,
+    ) -> Result<(), CompressionError> {
         let value = match cmd_ctx.get_cmd().get_command_element(index) {
             Some(e) => e,
             None => return Err(CompressionError::InvalidRequest),
         };
 
-        let compressed = match zstd::encode_all(value, 1) {
+        let compressed = match compress_value(config.codec, config.min_size, value) {
             Ok(c) => c,
             Err(err) => {
                 return Err(CompressionError::Io(err));
@@ -131,68 +198,154 @@ impl<C: CompressionStrategyConfig> CmdReplyDecompressor<C> {
         cmd_ctx: &CmdCtx,
         packet: &mut RespPacket,
     ) -> Result<(), CompressionError> {
-        let strategy = self.config.get_config(cmd_ctx.get_cluster_name());
+        let strategy = self.config.get_config(cmd_ctx.get_cluster_name()).strategy;
 
         if strategy == CompressionStrategy::Disabled {
             return Err(CompressionError::Disabled);
         }
 
-        let data_cmd_type = cmd_ctx.get_data_cmd_type();
-        match data_cmd_type {
-            DataCmdType::GET | DataCmdType::GETSET => {
-                let compressed = if let Resp::Bulk(BulkStr::Str(s)) = packet.to_resp_slice() {
-                    let compressed = match zstd::decode_all(s) {
-                        Ok(c) => c,
-                        Err(err) => {
-                            return Err(CompressionError::Io(err));
-                        }
-                    };
-                    Some(compressed)
-                } else {
-                    None
-                };
-                if let Some(c) = compressed {
-                    if !packet.change_bulk_str(c) {
-                        return Err(CompressionError::InvalidResp);
-                    }
+        let is_hash = strategy == CompressionStrategy::HashOnly;
+        match cmd_ctx.get_data_cmd_type() {
+            DataCmdType::GET | DataCmdType::GETSET if !is_hash => Self::decompress_bulk_str(packet),
+            DataCmdType::HGET if is_hash => Self::decompress_bulk_str(packet),
+            DataCmdType::MGET if !is_hash => Self::decompress_array(packet, |_| true),
+            DataCmdType::HMGET | DataCmdType::HVALS if is_hash => {
+                Self::decompress_array(packet, |_| true)
+            }
+            // The reply of HGETALL is `field value [field value ...]`.
+            DataCmdType::HGETALL if is_hash => Self::decompress_array(packet, |i| i % 2 == 1),
+            _ => Err(CompressionError::UnsupportedCmdType),
+        }
+    }
+
+    fn decompress_bulk_str(packet: &mut RespPacket) -> Result<(), CompressionError> {
+        let compressed = if let Resp::Bulk(BulkStr::Str(s)) = packet.to_resp_slice() {
+            let compressed = match decompress_value(s) {
+                Ok(c) => c,
+                Err(err) => {
+                    return Err(CompressionError::Io(err));
                 }
-                Ok(())
+            };
+            Some(compressed)
+        } else {
+            None
+        };
+        if let Some(c) = compressed {
+            if !packet.change_bulk_str(c) {
+                return Err(CompressionError::InvalidResp);
             }
-            DataCmdType::MGET => {
-                let compressed_arr = if let Resp::Arr(Array::Arr(arr)) = packet.to_resp_slice() {
-                    let mut compressed_arr = vec![];
-                    for bulk_str in arr.iter() {
-                        let element = match bulk_str {
-                            Resp::Bulk(BulkStr::Str(s)) => {
-                                let compressed = match zstd::decode_all(*s) {
-                                    Ok(c) => c,
-                                    Err(err) => {
-                                        return Err(CompressionError::Io(err));
-                                    }
-                                };
-                                Some(compressed)
+        }
+        Ok(())
+    }
+
+    // Only decompress the elements of which `is_value(index)` is true.
+    fn decompress_array<F>(packet: &mut RespPacket, is_value: F) -> Result<(), CompressionError>
+    where
+        F: Fn(usize) -> bool,
+    {
+        let compressed_arr = if let Resp::Arr(Array::Arr(arr)) = packet.to_resp_slice() {
+            let mut compressed_arr = vec![];
+            for (i, bulk_str) in arr.iter().enumerate() {
+                let element = match bulk_str {
+                    Resp::Bulk(BulkStr::Str(s)) if is_value(i) => {
+                        let compressed = match decompress_value(s) {
+                            Ok(c) => c,
+                            Err(err) => {
+                                return Err(CompressionError::Io(err));
                             }
-                            _ => None,
                         };
-                        compressed_arr.push(element);
+                        Some(compressed)
                     }
-                    compressed_arr
-                } else {
-                    vec![]
+                    _ => None,
                 };
+                compressed_arr.push(element);
+            }
+            compressed_arr
+        } else {
+            vec![]
+        };
 
-                for (i, compressed) in compressed_arr.into_iter().enumerate() {
-                    if let Some(c) = compressed {
-                        if !packet.change_bulk_array_element(i, c) {
-                            return Err(CompressionError::InvalidResp);
-                        }
-                    }
+        for (i, compressed) in compressed_arr.into_iter().enumerate() {
+            if let Some(c) = compressed {
+                if !packet.change_bulk_array_element(i, c) {
+                    return Err(CompressionError::InvalidResp);
                 }
-                Ok(())
             }
-            _ => Err(CompressionError::UnsupportedCmdType),
         }
+        Ok(())
+    }
+}
+
+// The values smaller than `min_size` are only prefixed with the header.
+pub fn compress_value(
+    codec: CompressionCodec,
+    min_size: usize,
+    value: &[u8],
+) -> Result<Vec<u8>, io::Error> {
+    let (codec_id, body) = if value.len() < min_size {
+        (CODEC_NONE, None)
+    } else {
+        match codec {
+            CompressionCodec::Zstd(level) => (CODEC_ZSTD, Some(zstd::encode_all(value, level)?)),
+            CompressionCodec::Lz4 => (CODEC_LZ4, Some(lz4_flex::compress_prepend_size(value))),
+            CompressionCodec::Snappy => (
+                CODEC_SNAPPY,
+                Some(snap::raw::Encoder::new().compress_vec(value)?),
+            ),
+        }
+    };
+    let body = body.as_deref().unwrap_or(value);
+    let mut compressed = Vec::with_capacity(VALUE_HEADER_MAGIC.len() + 1 + body.len());
+    compressed.extend_from_slice(VALUE_HEADER_MAGIC);
+    compressed.push(codec_id);
+    compressed.extend_from_slice(body);
+    Ok(compressed)
+}
+
+pub fn decompress_value(value: &[u8]) -> Result<Vec<u8>, io::Error> {
+    if !value.starts_with(VALUE_HEADER_MAGIC) || value.len() == VALUE_HEADER_MAGIC.len() {
+        return zstd::decode_all(value);
+    }
+    let codec_id = value[VALUE_HEADER_MAGIC.len()];
+    let body = &value[VALUE_HEADER_MAGIC.len() + 1..];
+    match codec_id {
+        CODEC_NONE => Ok(body.to_vec()),
+        CODEC_ZSTD => zstd::decode_all(body),
+        CODEC_LZ4 => decompress_lz4(body),
+        CODEC_SNAPPY => snap::raw::Decoder::new()
+            .decompress_vec(body)
+            .map_err(io::Error::from),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("unknown compression codec {}", codec_id),
+        )),
+    }
+}
+
+// The size header is allocated up front, so it needs to be checked
+// before decompressing the corrupted or forged values.
+fn decompress_lz4(body: &[u8]) -> Result<Vec<u8>, io::Error> {
+    let size = match body.get(..4) {
+        Some(header) => {
+            let mut size_bytes = [0; 4];
+            size_bytes.copy_from_slice(header);
+            u32::from_le_bytes(size_bytes) as usize
+        }
+        None => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "lz4: missing size header",
+            ))
+        }
+    };
+    if size > MAX_DECOMPRESSED_SIZE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("lz4: decompressed size {} exceeds the limit", size),
+        ));
     }
+    lz4_flex::decompress_size_prepended(body)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, format!("lz4: {:?}", err)))
 }
 
 #[derive(Debug)]
@@ -258,8 +411,11 @@ mod tests {
     }
 
     impl CompressionStrategyConfig for DummyConfig {
-        fn get_config(&self, _cluster_name: &ClusterName) -> CompressionStrategy {
-            self.strategy
+        fn get_config(&self, _cluster_name: &ClusterName) -> CompressionConfig {
+            CompressionConfig {
+                strategy: self.strategy,
+                ..CompressionConfig::default()
+            }
         }
     }
 
@@ -381,4 +537,203 @@ mod tests {
         assert_eq!(reply_one, b"value_one");
         assert_eq!(reply_two, b"value_two");
     }
+
+    #[test]
+    fn test_compression_codecs() {
+        let value = "value".repeat(100).into_bytes();
+        let codecs = [
+            CompressionCodec::Zstd(1),
+            CompressionCodec::Zstd(19),
+            CompressionCodec::Lz4,
+            CompressionCodec::Snappy,
+        ];
+        for codec in codecs.iter() {
+            let compressed = compress_value(*codec, 0, &value).unwrap();
+            assert!(compressed.starts_with(VALUE_HEADER_MAGIC));
+            assert!(compressed.len() < value.len());
+            assert_eq!(decompress_value(&compressed).unwrap(), value);
+        }
+
+        let compressed = compress_value(CompressionCodec::Lz4, 64, b"small").unwrap();
+        assert_eq!(compressed.len(), VALUE_HEADER_MAGIC.len() + 1 + 5);
+        assert_eq!(decompress_value(&compressed).unwrap(), b"small");
+
+        // Written by the older versions without the header.
+        let legacy = zstd::encode_all(value.as_slice(), 1).unwrap();
+        assert_eq!(decompress_value(&legacy).unwrap(), value);
+
+        let mut unknown = VALUE_HEADER_MAGIC.to_vec();
+        unknown.push(233);
+        assert!(decompress_value(&unknown).is_err());
+    }
+
+    #[test]
+    fn test_lz4_size_limit() {
+        let mut value = VALUE_HEADER_MAGIC.to_vec();
+        value.push(CODEC_LZ4);
+        value.extend_from_slice(&u32::MAX.to_le_bytes());
+        value.extend_from_slice(b"whatever");
+        let err = decompress_value(&value).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        let mut truncated = VALUE_HEADER_MAGIC.to_vec();
+        truncated.push(CODEC_LZ4);
+        truncated.push(1);
+        assert!(decompress_value(&truncated).is_err());
+    }
+
+    #[test]
+    fn test_set_get_with_codec() {
+        struct CodecConfig(CompressionConfig);
+
+        impl CompressionStrategyConfig for CodecConfig {
+            fn get_config(&self, _cluster_name: &ClusterName) -> CompressionConfig {
+                self.0
+            }
+        }
+
+        let config = CompressionConfig {
+            strategy: CompressionStrategy::SetGetOnly,
+            codec: CompressionCodec::Snappy,
+            min_size: 0,
+        };
+        let mut cmd_ctx = gen_cmd_ctx(vec![
+            "SET".to_string(),
+            "key".to_string(),
+            "value".to_string(),
+        ]);
+        let compressor = CmdCompressor::new(CodecConfig(config));
+        compressor.try_compressing_cmd_ctx(&mut cmd_ctx).unwrap();
+        let compressed_value = cmd_ctx.get_cmd().get_command_element(2).unwrap().to_vec();
+
+        // The codec is changed after the value is written.
+        let config = CompressionConfig {
+            codec: CompressionCodec::Lz4,
+            ..config
+        };
+        let cmd_ctx = gen_cmd_ctx(vec!["GET".to_string(), "key".to_string()]);
+        let decompressor = CmdReplyDecompressor::new(CodecConfig(config));
+        let mut reply_packet = gen_bulk_str_packet(compressed_value);
+        decompressor
+            .decompress(&cmd_ctx, &mut reply_packet)
+            .unwrap();
+        match reply_packet {
+            RespPacket::Data(Resp::Bulk(BulkStr::Str(v))) => assert_eq!(v, b"value"),
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn test_hash_only() {
+        let config = || DummyConfig {
+            strategy: CompressionStrategy::HashOnly,
+        };
+        let compressor = CmdCompressor::new(config());
+        let decompressor = CmdReplyDecompressor::new(config());
+
+        let mut cmd_ctx = gen_cmd_ctx(
+            vec![
+                "HSET",
+                "key",
+                "field_one",
+                "value_one",
+                "field_two",
+                "value_two",
+            ]
+            .into_iter()
+            .map(|s| s.to_string())
+            .collect(),
+        );
+        compressor.try_compressing_cmd_ctx(&mut cmd_ctx).unwrap();
+        let cmd = cmd_ctx.get_cmd();
+        assert_eq!(cmd.get_command_element(2).unwrap(), b"field_one");
+        assert_eq!(cmd.get_command_element(4).unwrap(), b"field_two");
+        let compressed_value_one = cmd.get_command_element(3).unwrap().to_vec();
+        let compressed_value_two = cmd.get_command_element(5).unwrap().to_vec();
+        assert_ne!(compressed_value_one, b"value_one");
+        assert_ne!(compressed_value_two, b"value_two");
+
+        // The String commands are not compressed.
+        let mut cmd_ctx = gen_cmd_ctx(vec![
+            "SET".to_string(),
+            "key".to_string(),
+            "value".to_string(),
+        ]);
+        compressor.try_compressing_cmd_ctx(&mut cmd_ctx).unwrap();
+        assert_eq!(cmd_ctx.get_cmd().get_command_element(2).unwrap(), b"value");
+
+        let mut cmd_ctx = gen_cmd_ctx(vec![
+            "HINCRBY".to_string(),
+            "key".to_string(),
+            "field".to_string(),
+            "1".to_string(),
+        ]);
+        let err = compressor
+            .try_compressing_cmd_ctx(&mut cmd_ctx)
+            .unwrap_err();
+        assert!(matches!(err, CompressionError::RestrictedCmd));
+
+        let cmd_ctx = gen_cmd_ctx(vec!["HGETALL".to_string(), "key".to_string()]);
+        let mut reply_packet = RespPacket::Data(Resp::Arr(Array::Arr(vec![
+            Resp::Bulk(BulkStr::Str(b"field_one".to_vec())),
+            Resp::Bulk(BulkStr::Str(compressed_value_one.clone())),
+            Resp::Bulk(BulkStr::Str(b"field_two".to_vec())),
+            Resp::Bulk(BulkStr::Str(compressed_value_two.clone())),
+        ])));
+        decompressor
+            .decompress(&cmd_ctx, &mut reply_packet)
+            .unwrap();
+        let expected: Vec<&[u8]> = vec![b"field_one", b"value_one", b"field_two", b"value_two"];
+        match reply_packet {
+            RespPacket::Data(Resp::Arr(Array::Arr(arr))) => {
+                let arr: Vec<&[u8]> = arr
+                    .iter()
+                    .map(|e| match e {
+                        Resp::Bulk(BulkStr::Str(s)) => s.as_slice(),
+                        _ => panic!(),
+                    })
+                    .collect();
+                assert_eq!(arr, expected);
+            }
+            _ => panic!(),
+        }
+
+        let cmd_ctx = gen_cmd_ctx(vec![
+            "HMGET".to_string(),
+            "key".to_string(),
+            "field_two".to_string(),
+            "field_three".to_string(),
+        ]);
+        let mut reply_packet = RespPacket::Data(Resp::Arr(Array::Arr(vec![
+            Resp::Bulk(BulkStr::Str(compressed_value_two)),
+            Resp::Bulk(BulkStr::Nil),
+        ])));
+        decompressor
+            .decompress(&cmd_ctx, &mut reply_packet)
+            .unwrap();
+        match reply_packet {
+            RespPacket::Data(Resp::Arr(Array::Arr(arr))) => {
+                assert_eq!(arr[0], Resp::Bulk(BulkStr::Str(b"value_two".to_vec())));
+                assert_eq!(arr[1], Resp::Bulk(BulkStr::Nil));
+            }
+            _ => panic!(),
+        }
+
+        let cmd_ctx = gen_cmd_ctx(vec!["HGET".to_string(), "key".to_string(), "f".to_string()]);
+        let mut reply_packet = gen_bulk_str_packet(compressed_value_one.clone());
+        decompressor
+            .decompress(&cmd_ctx, &mut reply_packet)
+            .unwrap();
+        match reply_packet {
+            RespPacket::Data(Resp::Bulk(BulkStr::Str(v))) => assert_eq!(v, b"value_one"),
+            _ => panic!(),
+        }
+
+        let cmd_ctx = gen_cmd_ctx(vec!["GET".to_string(), "key".to_string()]);
+        let mut reply_packet = gen_bulk_str_packet(compressed_value_one);
+        let err = decompressor
+            .decompress(&cmd_ctx, &mut reply_packet)
+            .unwrap_err();
+        assert!(matches!(err, CompressionError::UnsupportedCmdType));
+    }
 }
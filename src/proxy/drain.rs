@@ -0,0 +1,109 @@
+use crate::common::utils::ThreadSafe;
+use futures::{future, Future};
+use tokio::sync::watch;
+
+pub trait DrainSource: ThreadSafe {
+    // The proxy should not exit while the migration tasks are blocking
+    // the commands or switching the slots with the peer proxies.
+    fn is_migration_at_safe_point(&self) -> bool;
+}
+
+// In the drain mode, the server proxy stops accepting new connections
+// and closes the sessions once they become idle.
+pub struct DrainController {
+    sender: watch::Sender<bool>,
+    receiver: watch::Receiver<bool>,
+}
+
+impl Default for DrainController {
+    fn default() -> Self {
+        let (sender, receiver) = watch::channel(false);
+        Self { sender, receiver }
+    }
+}
+
+impl DrainController {
+    // Returns false if it's already draining.
+    pub fn start_drain(&self) -> bool {
+        if self.is_draining() {
+            return false;
+        }
+        if let Err(err) = self.sender.broadcast(true) {
+            error!("failed to start draining: {:?}", err);
+            return false;
+        }
+        info!("start draining");
+        true
+    }
+
+    pub fn is_draining(&self) -> bool {
+        *self.receiver.borrow()
+    }
+
+    pub fn signal(&self) -> DrainSignal {
+        DrainSignal(Some(self.receiver.clone()))
+    }
+}
+
+pub struct DrainSignal(Option<watch::Receiver<bool>>);
+
+impl DrainSignal {
+    // For the services which never drain.
+    pub fn never() -> Self {
+        Self(None)
+    }
+
+    pub async fn wait(&mut self) {
+        let receiver = match self.0.as_mut() {
+            Some(receiver) => receiver,
+            None => return future::pending().await,
+        };
+        loop {
+            if *receiver.borrow() {
+                return;
+            }
+            // The controller is dropped.
+            if receiver.recv().await.is_none() {
+                return future::pending().await;
+            }
+        }
+    }
+
+    // Returns None if the proxy starts draining before `fut` is done.
+    pub async fn or_drain<F: Future + Unpin>(&mut self, fut: F) -> Option<F::Output> {
+        let drained = self.wait();
+        futures::pin_mut!(drained);
+        match future::select(fut, drained).await {
+            future::Either::Left((output, _)) => Some(output),
+            future::Either::Right(_) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tokio::time;
+
+    #[tokio::test]
+    async fn test_drain_signal() {
+        let controller = DrainController::default();
+        let mut signal = controller.signal();
+        assert!(!controller.is_draining());
+        let res = signal
+            .or_drain(Box::pin(time::delay_for(Duration::from_millis(1))))
+            .await;
+        assert!(res.is_some());
+
+        assert!(controller.start_drain());
+        assert!(!controller.start_drain());
+        assert!(controller.is_draining());
+        let res = signal.or_drain(future::pending::<()>()).await;
+        assert!(res.is_none());
+
+        let mut never = DrainSignal::never();
+        let res = never.or_drain(future::ready(1)).await;
+        assert_eq!(res, Some(1));
+    }
+}
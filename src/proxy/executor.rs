@@ -1,13 +1,26 @@
-use super::backend::{CmdTask, CmdTaskFactory, ConnFactory};
+use super::audit::{is_write_cmd, AuditLogger};
+use super::backend::{CmdTask, CmdTaskFactory, CmdTaskResult, ConnFactory};
+use super::client::{ClientKillFilter, ClientRegistry};
 use super::cluster::{ClusterMetaError, ClusterTag};
-use super::command::{CmdReplyReceiver, CmdType, DataCmdType, TaskResult};
+use super::command::{
+    is_write_cmd_type, new_command_pair, CmdReplyReceiver, CmdType, Command, CommandError,
+    DataCmdType, TaskReply, TaskResult,
+};
 use super::compress::{CmdCompressor, CompressionError, CompressionStrategyMetaMapConfig};
+use super::drain::{DrainController, DrainSource};
+use super::hotkey::{hot_keys_to_resp, HotKeyTracker, KeyAccess, DEFAULT_HOTKEY_COUNT};
 use super::manager::{MetaManager, SharedMetaMap};
+use super::metrics::{BackendMetrics, MetricsSource, MigrationMetrics};
+use super::mirror::forward_and_compare;
+use super::namespace::{self, NamespaceError};
 use super::service::ServerProxyConfig;
-use super::session::{CmdCtx, CmdCtxFactory, CmdCtxHandler, CmdReplyFuture};
+use super::session::{
+    CmdCtx, CmdCtxFactory, CmdCtxHandler, CmdReplyFuture, PubSubConnFuture, ReadMode, SessionState,
+};
 use super::slowlog::{slowlogs_to_resp, SlowRequestLogger};
+use super::transaction::get_command_keys;
 use crate::common::cluster::ClusterName;
-use crate::common::config::ClusterConfig;
+use crate::common::config::{ClusterConfig, MirrorTarget};
 use crate::common::proto::ProxyClusterMeta;
 use crate::common::response;
 use crate::common::track::TrackedFutureRegistry;
@@ -18,13 +31,20 @@ use crate::common::version::UNDERMOON_VERSION;
 use crate::migration::manager::SwitchError;
 use crate::migration::task::parse_switch_command;
 use crate::migration::task::MgrSubCmd;
-use crate::protocol::{Array, BulkStr, RedisClientFactory, Resp, RespPacket, RespVec, VFunctor};
+use crate::protocol::{
+    Array, BinSafeStr, BulkStr, RedisClientFactory, Resp, RespPacket, RespVec, RespVersion,
+    VFunctor,
+};
 use crate::replication::replicator::ReplicatorMeta;
 use atoi::atoi;
 use btoi::btou;
-use futures::future;
+use futures::{future, Future};
 use futures_timer::Delay;
+use rand::Rng;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::convert::TryFrom;
+use std::iter;
+use std::pin::Pin;
 use std::str;
 use std::sync::{self, Arc};
 use std::time::Duration;
@@ -50,11 +70,15 @@ where
     F: RedisClientFactory,
     C: ConnFactory<Pkt = RespPacket>,
 {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         config: Arc<ServerProxyConfig>,
         cluster_config: ClusterConfig,
         client_factory: Arc<F>,
         slow_request_logger: Arc<SlowRequestLogger>,
+        client_registry: Arc<ClientRegistry>,
+        audit_logger: Arc<AuditLogger>,
+        drain_controller: Arc<DrainController>,
         meta_map: SharedMetaMap<C>,
         conn_factory: Arc<C>,
         future_registry: Arc<TrackedFutureRegistry>,
@@ -65,6 +89,9 @@ where
                 cluster_config,
                 client_factory,
                 slow_request_logger,
+                client_registry,
+                audit_logger,
+                drain_controller,
                 meta_map,
                 conn_factory,
                 future_registry,
@@ -73,6 +100,34 @@ where
     }
 }
 
+impl<F, C> MetricsSource for SharedForwardHandler<F, C>
+where
+    F: RedisClientFactory,
+    C: ConnFactory<Pkt = RespPacket>,
+{
+    fn get_backend_metrics(&self) -> Vec<BackendMetrics> {
+        self.handler.manager.get_backend_metrics()
+    }
+
+    fn get_migration_metrics(&self) -> Vec<MigrationMetrics> {
+        self.handler.manager.get_migration_metrics()
+    }
+
+    fn get_clusters(&self) -> Vec<ClusterName> {
+        self.handler.manager.get_clusters()
+    }
+}
+
+impl<F, C> DrainSource for SharedForwardHandler<F, C>
+where
+    F: RedisClientFactory,
+    C: ConnFactory<Pkt = RespPacket>,
+{
+    fn is_migration_at_safe_point(&self) -> bool {
+        self.handler.manager.is_migration_at_safe_point()
+    }
+}
+
 impl<F, C> CmdCtxHandler for SharedForwardHandler<F, C>
 where
     F: RedisClientFactory,
@@ -82,10 +137,25 @@ where
         &self,
         cmd_ctx: CmdCtx,
         reply_receiver: CmdReplyReceiver,
-        session_cluster_name: &sync::RwLock<ClusterName>,
-    ) -> CmdReplyFuture {
+        session_state: &SessionState,
+    ) -> CmdReplyFuture<'_> {
         self.handler
-            .handle_cmd_ctx(cmd_ctx, reply_receiver, session_cluster_name)
+            .handle_cmd_ctx(cmd_ctx, reply_receiver, session_state)
+    }
+
+    fn create_pubsub_conn(
+        &self,
+        session_state: &SessionState,
+    ) -> Result<PubSubConnFuture, RespVec> {
+        self.handler.create_pubsub_conn(session_state)
+    }
+
+    fn get_idle_timeout(&self, session_state: &SessionState) -> Option<Duration> {
+        self.handler.get_idle_timeout(session_state)
+    }
+
+    fn handle_idle_timeout(&self, session_state: &SessionState) {
+        self.handler.handle_idle_timeout(session_state)
     }
 }
 
@@ -95,6 +165,10 @@ pub struct ForwardHandler<F: RedisClientFactory, C: ConnFactory<Pkt = RespPacket
     slow_request_logger: Arc<SlowRequestLogger>,
     compressor: CmdCompressor<CompressionStrategyMetaMapConfig<C>>,
     future_registry: Arc<TrackedFutureRegistry>,
+    hot_key_tracker: HotKeyTracker,
+    client_registry: Arc<ClientRegistry>,
+    audit_logger: Arc<AuditLogger>,
+    drain_controller: Arc<DrainController>,
 }
 
 impl<F, C> ForwardHandler<F, C>
@@ -102,11 +176,15 @@ where
     F: RedisClientFactory,
     C: ConnFactory<Pkt = RespPacket>,
 {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         config: Arc<ServerProxyConfig>,
         cluster_config: ClusterConfig,
         client_factory: Arc<F>,
         slow_request_logger: Arc<SlowRequestLogger>,
+        client_registry: Arc<ClientRegistry>,
+        audit_logger: Arc<AuditLogger>,
+        drain_controller: Arc<DrainController>,
         meta_map: SharedMetaMap<C>,
         conn_factory: Arc<C>,
         future_registry: Arc<TrackedFutureRegistry>,
@@ -124,6 +202,10 @@ where
             slow_request_logger,
             compressor: CmdCompressor::new(CompressionStrategyMetaMapConfig::new(meta_map)),
             future_registry,
+            hot_key_tracker: HotKeyTracker::default(),
+            client_registry,
+            audit_logger,
+            drain_controller,
         }
     }
 }
@@ -133,37 +215,253 @@ where
     F: RedisClientFactory,
     C: ConnFactory<Pkt = RespPacket>,
 {
-    fn handle_auth(&self, mut cmd_ctx: CmdCtx, session_cluster_name: &sync::RwLock<ClusterName>) {
-        let key = cmd_ctx.get_key();
-        let cluster = match key {
+    fn handle_auth(&self, cmd_ctx: CmdCtx, session_state: &SessionState) {
+        match cmd_ctx.get_cmd().get_command_len() {
+            Some(2) => self.handle_auth_cluster(cmd_ctx, session_state),
+            Some(3) => self.handle_auth_password(cmd_ctx, session_state),
+            _ => cmd_ctx.set_resp_result(Ok(Resp::Error(
+                String::from("Missing cluster name").into_bytes(),
+            ))),
+        }
+    }
+
+    // AUTH <cluster> only selects the cluster.
+    fn handle_auth_cluster(&self, mut cmd_ctx: CmdCtx, session_state: &SessionState) {
+        let cluster_name = match cmd_ctx.get_key().map(|key| parse_cluster_name(key)) {
+            Some(Ok(cluster_name)) => cluster_name,
+            Some(Err(err)) => {
+                return cmd_ctx.set_resp_result(Ok(Resp::Error(err.to_string().into_bytes())))
+            }
             None => {
                 return cmd_ctx.set_resp_result(Ok(Resp::Error(
                     String::from("Missing cluster name").into_bytes(),
                 )))
             }
-            Some(cluster_name) => match str::from_utf8(&cluster_name) {
-                Ok(cluster) => cluster.to_string(),
-                Err(_) => {
+        };
+
+        session_state.set_cluster_name(cluster_name.clone());
+        cmd_ctx.set_cluster_name(cluster_name);
+        cmd_ctx.set_resp_result(Ok(Resp::Simple(String::from("OK").into_bytes())));
+    }
+
+    // Supports both AUTH <cluster> <password> and AUTH <user> <password>.
+    // If the first argument is an existing cluster, it will be treated as the cluster name.
+    // Otherwise it's the user of the current cluster.
+    fn handle_auth_password(&self, mut cmd_ctx: CmdCtx, session_state: &SessionState) {
+        let (name, password) = {
+            let cmd = cmd_ctx.get_cmd();
+            match (cmd.get_command_element(1), cmd.get_command_element(2)) {
+                (Some(name), Some(password)) => (name.to_vec(), password.to_vec()),
+                _ => {
                     return cmd_ctx.set_resp_result(Ok(Resp::Error(
-                        String::from("Invalid cluster name").into_bytes(),
+                        String::from("Missing password").into_bytes(),
                     )))
                 }
-            },
+            }
+        };
+
+        if !self.authenticate(&mut cmd_ctx, session_state, &name, &password) {
+            return cmd_ctx.set_resp_result(Ok(Resp::Error(
+                response::ERR_WRONG_PASS.to_string().into_bytes(),
+            )));
+        }
+        cmd_ctx.set_resp_result(Ok(Resp::Simple(String::from("OK").into_bytes())));
+    }
+
+    // Returns false if the password is wrong.
+    fn authenticate(
+        &self,
+        cmd_ctx: &mut CmdCtx,
+        session_state: &SessionState,
+        name: &[u8],
+        password: &[u8],
+    ) -> bool {
+        let cluster_name = match parse_cluster_name(name) {
+            Ok(cluster_name) if self.manager.cluster_exists(&cluster_name) => Some(cluster_name),
+            _ => None,
+        };
+
+        match cluster_name {
+            Some(cluster_name) => {
+                if !self.manager.check_password(&cluster_name, None, password) {
+                    return false;
+                }
+                session_state.set_cluster_name(cluster_name.clone());
+                session_state.set_authenticated(cluster_name.clone());
+                cmd_ctx.set_cluster_name(cluster_name);
+            }
+            None => {
+                let cluster_name = cmd_ctx.get_cluster_name().clone();
+                if !self
+                    .manager
+                    .check_password(&cluster_name, Some(name), password)
+                {
+                    return false;
+                }
+                session_state.set_authenticated(cluster_name);
+            }
+        }
+        true
+    }
+
+    // HELLO [protover [AUTH username password] [SETNAME clientname]]
+    fn handle_hello(&self, mut cmd_ctx: CmdCtx, session_state: &SessionState) {
+        let (version, auth, client_name) = match parse_hello_args(cmd_ctx.get_cmd()) {
+            Ok(args) => args,
+            Err(err) => return cmd_ctx.set_resp_result(Ok(Resp::Error(err.into_bytes()))),
         };
-        let cluster_name = match ClusterName::try_from(cluster.as_str()) {
-            Ok(cluster_name) => cluster_name,
-            _err => {
+
+        if let Some((name, password)) = auth {
+            if !self.authenticate(&mut cmd_ctx, session_state, &name, &password) {
                 return cmd_ctx.set_resp_result(Ok(Resp::Error(
-                    String::from("Cluster name is too long").into_bytes(),
-                )))
+                    response::ERR_WRONG_PASS.to_string().into_bytes(),
+                )));
             }
+        } else if !self.is_authenticated(cmd_ctx.get_cluster_name(), session_state) {
+            return cmd_ctx.set_resp_result(Ok(Resp::Error(
+                response::ERR_NOAUTH.to_string().into_bytes(),
+            )));
+        }
+
+        if let Some(client_name) = client_name {
+            session_state.get_client_info().set_name(client_name);
+        }
+
+        let version = version.unwrap_or_else(|| session_state.get_resp_version());
+        session_state.set_resp_version(version);
+        let proto = match version {
+            RespVersion::V2 => 2,
+            RespVersion::V3 => 3,
         };
 
-        *session_cluster_name
-            .write()
-            .expect("ForwardHandler::handle_auth") = cluster_name.clone();
-        cmd_ctx.set_cluster_name(cluster_name);
-        cmd_ctx.set_resp_result(Ok(Resp::Simple(String::from("OK").into_bytes())));
+        let bulk = |s: &str| Resp::Bulk(BulkStr::Str(s.as_bytes().to_vec()));
+        let info = vec![
+            (bulk("server"), bulk("undermoon")),
+            (bulk("version"), bulk(UNDERMOON_VERSION)),
+            (bulk("proto"), Resp::Integer(proto.to_string().into_bytes())),
+            (
+                bulk("id"),
+                Resp::Integer(cmd_ctx.get_session_id().to_string().into_bytes()),
+            ),
+            (bulk("mode"), bulk("cluster")),
+            (bulk("role"), bulk("master")),
+            (bulk("modules"), Resp::Arr(Array::Arr(vec![]))),
+        ];
+        // It will be converted to a flat array for RESP2 clients by the session.
+        cmd_ctx.set_resp_result(Ok(Resp::Map(info)));
+    }
+
+    // CLIENT LIST | INFO | ID | GETNAME | SETNAME <name> | KILL <filters>
+    fn handle_client(&self, cmd_ctx: CmdCtx, session_state: &SessionState) {
+        let (cmd_ctx, sub_cmd) = match Self::get_sub_command(cmd_ctx, 1) {
+            Some((cmd_ctx, sub_cmd)) => (cmd_ctx, sub_cmd.to_uppercase()),
+            None => return,
+        };
+        let client_info = session_state.get_client_info();
+        let cmd_len = cmd_ctx.get_cmd().get_command_len().unwrap_or(0);
+
+        let resp = match (sub_cmd.as_str(), cmd_len) {
+            ("LIST", 2) => {
+                let cluster_name = session_state.get_cluster_name();
+                let clients: String = self
+                    .client_registry
+                    .list_clients(&cluster_name)
+                    .iter()
+                    .map(|client_info| format!("{}\n", client_info.to_line()))
+                    .collect();
+                Resp::Bulk(BulkStr::Str(clients.into_bytes()))
+            }
+            ("INFO", 2) => Resp::Bulk(BulkStr::Str(
+                format!("{}\n", client_info.to_line()).into_bytes(),
+            )),
+            ("ID", 2) => Resp::Integer(client_info.get_id().to_string().into_bytes()),
+            ("GETNAME", 2) => {
+                let name = client_info.get_name();
+                if name.is_empty() {
+                    Resp::Bulk(BulkStr::Nil)
+                } else {
+                    Resp::Bulk(BulkStr::Str(name.into_bytes()))
+                }
+            }
+            ("SETNAME", 3) => match cmd_ctx
+                .get_cmd()
+                .get_command_element(2)
+                .and_then(parse_client_name)
+            {
+                Some(name) => {
+                    client_info.set_name(name);
+                    Resp::Simple(response::OK_REPLY.to_string().into_bytes())
+                }
+                None => Resp::Error(response::ERR_INVALID_CLIENT_NAME.to_string().into_bytes()),
+            },
+            ("KILL", _) => match parse_client_kill_args(cmd_ctx.get_cmd()) {
+                Ok(filter) => {
+                    let cluster_name = session_state.get_cluster_name();
+                    let killed = self.client_registry.kill_clients(
+                        &cluster_name,
+                        &filter,
+                        client_info.get_id(),
+                    );
+                    // The old form `CLIENT KILL <addr>` replies OK or an error.
+                    if cmd_len != 3 {
+                        Resp::Integer(killed.to_string().into_bytes())
+                    } else if killed == 0 {
+                        Resp::Error(response::ERR_NO_SUCH_CLIENT.to_string().into_bytes())
+                    } else {
+                        Resp::Simple(response::OK_REPLY.to_string().into_bytes())
+                    }
+                }
+                Err(err) => Resp::Error(err.to_string().into_bytes()),
+            },
+            _ => Resp::Error(
+                format!(
+                    "ERR Unknown subcommand or wrong number of arguments for '{}'",
+                    sub_cmd
+                )
+                .into_bytes(),
+            ),
+        };
+        cmd_ctx.set_resp_result(Ok(resp))
+    }
+
+    fn is_authenticated(&self, cluster_name: &ClusterName, session_state: &SessionState) -> bool {
+        !self.manager.requires_auth(cluster_name) || session_state.is_authenticated(cluster_name)
+    }
+
+    // DRAIN stops the whole proxy. Once any cluster has a password,
+    // only the clients authenticated with a password could send it.
+    fn can_drain(&self, session_state: &SessionState) -> bool {
+        let protected_clusters: Vec<ClusterName> = self
+            .manager
+            .get_clusters()
+            .into_iter()
+            .filter(|cluster_name| self.manager.requires_auth(cluster_name))
+            .collect();
+        protected_clusters.is_empty()
+            || protected_clusters
+                .iter()
+                .any(|cluster_name| session_state.is_authenticated(cluster_name))
+    }
+
+    // Returns false if the cluster has reached its `maxclients`.
+    fn join_cluster(&self, cluster_name: &ClusterName, session_state: &SessionState) -> bool {
+        if session_state.get_client_cluster().as_ref() == Some(cluster_name) {
+            return true;
+        }
+        let maxclients = match self.manager.get_maxclients(cluster_name) {
+            Some(maxclients) => maxclients,
+            None => return true,
+        };
+        match self
+            .client_registry
+            .try_join_cluster(cluster_name, maxclients)
+        {
+            Some(guard) => {
+                session_state.set_cluster_client(guard);
+                true
+            }
+            None => false,
+        }
     }
 
     fn handle_cluster(&self, cmd_ctx: CmdCtx) {
@@ -224,7 +522,7 @@ where
         Some((cmd_ctx, sub_cmd))
     }
 
-    fn handle_umctl(&self, cmd_ctx: CmdCtx) {
+    fn handle_umctl(&self, cmd_ctx: CmdCtx, session_state: &SessionState) {
         let (cmd_ctx, sub_cmd) = match Self::get_sub_command(cmd_ctx, 1) {
             Some((cmd_ctx, sub_cmd)) => (cmd_ctx, sub_cmd),
             None => return,
@@ -244,7 +542,11 @@ where
         } else if sub_cmd.eq("SETREPL") {
             self.handle_umctl_setrepl(cmd_ctx);
         } else if sub_cmd.eq("INFO") {
-            let resp = self.manager.info();
+            let mut resp = self.manager.info();
+            if let Resp::Arr(Array::Arr(ref mut sections)) = resp {
+                sections.push(Resp::Bulk(BulkStr::Str(b"Clients".to_vec())));
+                sections.push(self.client_registry.info());
+            }
             cmd_ctx.set_resp_result(Ok(resp));
         } else if sub_cmd.eq("INFOREPL") {
             self.handle_umctl_info_repl(cmd_ctx);
@@ -258,10 +560,21 @@ where
             self.handle_umctl_mgr_cmd(cmd_ctx, MgrSubCmd::FinalSwitch);
         } else if sub_cmd.eq("SLOWLOG") {
             self.handle_umctl_slowlog(cmd_ctx);
+        } else if sub_cmd.eq("HOTKEYS") {
+            self.handle_umctl_hotkeys(cmd_ctx, session_state);
         } else if sub_cmd.eq("DEBUG") {
             self.handle_umctl_debug(cmd_ctx);
         } else if sub_cmd.eq("GETEPOCH") {
             self.handle_umctl_get_epoch(cmd_ctx);
+        } else if sub_cmd.eq("DRAIN") {
+            if !self.can_drain(session_state) {
+                cmd_ctx.set_resp_result(Ok(Resp::Error(
+                    response::ERR_NOAUTH.to_string().into_bytes(),
+                )));
+                return;
+            }
+            self.drain_controller.start_drain();
+            cmd_ctx.set_resp_result(Ok(Resp::Simple(response::OK_REPLY.into())));
         } else {
             cmd_ctx.set_resp_result(Ok(Resp::Error(
                 String::from("Invalid sub command").into_bytes(),
@@ -282,17 +595,22 @@ where
             };
 
         match self.manager.set_meta(cluster_meta) {
-            Ok(()) => match extended_res {
-                Ok(()) => {
-                    debug!("Successfully update local meta data");
-                    cmd_ctx.set_resp_result(Ok(Resp::Simple("OK".to_string().into_bytes())));
-                }
-                Err(_) => {
-                    cmd_ctx.set_resp_result(Ok(Resp::Simple(
-                        "WARNING: ignored invalid config".to_string().into_bytes(),
-                    )));
+            Ok(()) => {
+                // Drops the hot keys of the removed clusters.
+                let clusters = self.manager.get_clusters().into_iter().collect();
+                self.hot_key_tracker.retain_clusters(&clusters);
+                match extended_res {
+                    Ok(()) => {
+                        debug!("Successfully update local meta data");
+                        cmd_ctx.set_resp_result(Ok(Resp::Simple("OK".to_string().into_bytes())));
+                    }
+                    Err(_) => {
+                        cmd_ctx.set_resp_result(Ok(Resp::Simple(
+                            "WARNING: ignored invalid config".to_string().into_bytes(),
+                        )));
+                    }
                 }
-            },
+            }
             Err(err) => match err {
                 ClusterMetaError::OldEpoch => cmd_ctx.set_resp_result(Ok(Resp::Error(
                     response::OLD_EPOCH_REPLY.to_string().into_bytes(),
@@ -404,6 +722,50 @@ where
         }
     }
 
+    // UMCTL HOTKEYS [cluster] [count]
+    fn handle_umctl_hotkeys(&self, cmd_ctx: CmdCtx, session_state: &SessionState) {
+        let cluster_name = match cmd_ctx.get_cmd().get_command_element(2) {
+            Some(element) => match str::from_utf8(element)
+                .ok()
+                .and_then(|s| ClusterName::try_from(s).ok())
+            {
+                Some(cluster_name) => Some(cluster_name),
+                None => {
+                    cmd_ctx.set_resp_result(Ok(Resp::Error(b"invalid cluster name".to_vec())));
+                    return;
+                }
+            },
+            None => None,
+        };
+        let count = match cmd_ctx.get_cmd().get_command_element(3) {
+            Some(element) => match atoi::<usize>(element) {
+                Some(count) => count,
+                None => {
+                    cmd_ctx.set_resp_result(Ok(Resp::Error(b"invalid count".to_vec())));
+                    return;
+                }
+            },
+            None => DEFAULT_HOTKEY_COUNT,
+        };
+        // Only the clusters the client has access to are dumped.
+        if let Some(cluster_name) = cluster_name.as_ref() {
+            if !self.is_authenticated(cluster_name, session_state) {
+                cmd_ctx.set_resp_result(Ok(Resp::Error(
+                    response::ERR_NOAUTH.to_string().into_bytes(),
+                )));
+                return;
+            }
+        }
+        let hot_keys = self.hot_key_tracker.get_hot_keys(
+            |name| {
+                cluster_name.as_ref().map(|c| c == name).unwrap_or(true)
+                    && self.is_authenticated(name, session_state)
+            },
+            count,
+        );
+        cmd_ctx.set_resp_result(Ok(hot_keys_to_resp(hot_keys)));
+    }
+
     fn handle_umctl_debug(&self, cmd_ctx: CmdCtx) {
         let (cmd_ctx, sub_cmd) = match Self::get_sub_command(cmd_ctx, 2) {
             Some((cmd_ctx, sub_cmd)) => (cmd_ctx, sub_cmd),
@@ -479,7 +841,11 @@ where
         }
     }
 
-    fn handle_data_cmd(&self, cmd_ctx: CmdCtx, reply_receiver: CmdReplyReceiver) -> CmdReplyFuture {
+    fn handle_data_cmd(
+        &self,
+        cmd_ctx: CmdCtx,
+        reply_receiver: CmdReplyReceiver,
+    ) -> CmdReplyFuture<'_> {
         match cmd_ctx.get_data_cmd_type() {
             DataCmdType::MGET => {
                 CmdReplyFuture::Right(Box::pin(self.handle_mget(cmd_ctx, reply_receiver)))
@@ -487,20 +853,32 @@ where
             DataCmdType::MSET => {
                 CmdReplyFuture::Right(Box::pin(self.handle_mset(cmd_ctx, reply_receiver)))
             }
-            DataCmdType::DEL if cmd_ctx.get_cmd().get_command_element(2).is_some() => {
-                CmdReplyFuture::Right(Box::pin(self.handle_multi_int_cmd(
-                    cmd_ctx,
-                    reply_receiver,
-                    "DEL",
-                )))
+            DataCmdType::DEL | DataCmdType::EXISTS | DataCmdType::UNLINK | DataCmdType::TOUCH
+                if cmd_ctx.get_cmd().get_command_element(2).is_some() =>
+            {
+                CmdReplyFuture::Right(Box::pin(self.handle_multi_int_cmd(cmd_ctx, reply_receiver)))
             }
-            DataCmdType::EXISTS if cmd_ctx.get_cmd().get_command_element(2).is_some() => {
-                CmdReplyFuture::Right(Box::pin(self.handle_multi_int_cmd(
-                    cmd_ctx,
-                    reply_receiver,
-                    "EXISTS",
-                )))
+            DataCmdType::SUNION | DataCmdType::SINTER | DataCmdType::SDIFF
+                if cmd_ctx.get_cmd().get_command_element(2).is_some() =>
+            {
+                CmdReplyFuture::Right(Box::pin(self.handle_set_op_cmd(cmd_ctx, reply_receiver)))
+            }
+            DataCmdType::PFCOUNT | DataCmdType::PFMERGE
+                if cmd_ctx.get_cmd().get_command_element(2).is_some() =>
+            {
+                self.handle_hll_cmd(cmd_ctx);
+                CmdReplyFuture::Left(reply_receiver)
+            }
+            DataCmdType::MSETNX => {
+                self.handle_msetnx(cmd_ctx);
+                CmdReplyFuture::Left(reply_receiver)
             }
+            DataCmdType::SCAN
+            | DataCmdType::KEYS
+            | DataCmdType::DBSIZE
+            | DataCmdType::RANDOMKEY
+            | DataCmdType::FLUSHALL
+            | DataCmdType::FLUSHDB => self.handle_keyless_cmd(cmd_ctx, reply_receiver, true),
             DataCmdType::BLPOP | DataCmdType::BRPOP | DataCmdType::BRPOPLPUSH => {
                 CmdReplyFuture::Right(Box::pin(
                     self.handle_list_blocking_commands(cmd_ctx, reply_receiver),
@@ -516,15 +894,12 @@ where
     async fn handle_mget(&self, cmd_ctx: CmdCtx, reply_receiver: CmdReplyReceiver) -> TaskResult {
         let arg_len = cmd_ctx.get_cmd().get_command_len().unwrap_or(0);
 
-        if !self.config.active_redirection {
-            let in_same_slot =
-                same_slot((1..arg_len).filter_map(|i| cmd_ctx.get_cmd().get_command_element(i)));
-            if !in_same_slot {
-                cmd_ctx.set_resp_result(Ok(Resp::Error(
-                    response::ERR_NOT_THE_SAME_SLOT.to_string().into_bytes(),
-                )));
-                return reply_receiver.await;
-            }
+        let keys = (1..arg_len).filter_map(|i| cmd_ctx.get_cmd().get_command_element(i));
+        if !self.can_split_keys(cmd_ctx.get_cluster_name(), keys) {
+            cmd_ctx.set_resp_result(Ok(Resp::Error(
+                response::ERR_NOT_THE_SAME_SLOT.to_string().into_bytes(),
+            )));
+            return reply_receiver.await;
         }
 
         let factory = CmdCtxFactory::default();
@@ -572,16 +947,13 @@ where
     async fn handle_mset(&self, cmd_ctx: CmdCtx, reply_receiver: CmdReplyReceiver) -> TaskResult {
         let arg_len = cmd_ctx.get_cmd().get_command_len().unwrap_or(0);
 
-        if !self.config.active_redirection {
-            let in_same_slot = same_slot(
-                (0..(arg_len / 2)).filter_map(|i| cmd_ctx.get_cmd().get_command_element(2 * i + 1)),
-            );
-            if !in_same_slot {
-                cmd_ctx.set_resp_result(Ok(Resp::Error(
-                    response::ERR_NOT_THE_SAME_SLOT.to_string().into_bytes(),
-                )));
-                return reply_receiver.await;
-            }
+        let keys =
+            (0..(arg_len / 2)).filter_map(|i| cmd_ctx.get_cmd().get_command_element(2 * i + 1));
+        if !self.can_split_keys(cmd_ctx.get_cluster_name(), keys) {
+            cmd_ctx.set_resp_result(Ok(Resp::Error(
+                response::ERR_NOT_THE_SAME_SLOT.to_string().into_bytes(),
+            )));
+            return reply_receiver.await;
         }
 
         let factory = CmdCtxFactory::default();
@@ -635,41 +1007,34 @@ where
         reply_receiver.await
     }
 
-    // DEL and EXISTS
+    // DEL, EXISTS, UNLINK and TOUCH
     async fn handle_multi_int_cmd(
         &self,
         cmd_ctx: CmdCtx,
         reply_receiver: CmdReplyReceiver,
-        cmd_name: &'static str,
     ) -> TaskResult {
+        let cmd_name = match cmd_ctx.get_data_cmd_type() {
+            DataCmdType::DEL => "DEL",
+            DataCmdType::EXISTS => "EXISTS",
+            DataCmdType::UNLINK => "UNLINK",
+            _ => "TOUCH",
+        };
         let arg_len = cmd_ctx.get_cmd().get_command_len().unwrap_or(0);
 
-        if !self.config.active_redirection {
-            let in_same_slot =
-                same_slot((1..arg_len).filter_map(|i| cmd_ctx.get_cmd().get_command_element(i)));
-            if !in_same_slot {
-                cmd_ctx.set_resp_result(Ok(Resp::Error(
-                    response::ERR_NOT_THE_SAME_SLOT.to_string().into_bytes(),
-                )));
-                return reply_receiver.await;
-            }
+        let keys = (1..arg_len).filter_map(|i| cmd_ctx.get_cmd().get_command_element(i));
+        if !self.can_split_keys(cmd_ctx.get_cluster_name(), keys) {
+            cmd_ctx.set_resp_result(Ok(Resp::Error(
+                response::ERR_NOT_THE_SAME_SLOT.to_string().into_bytes(),
+            )));
+            return reply_receiver.await;
         }
 
-        let factory = CmdCtxFactory::default();
-        let mut futs = vec![];
-        for i in 1.. {
-            let key = match cmd_ctx.get_cmd().get_command_element(i) {
-                Some(key) => key,
-                None => break,
-            };
-            let resp = Resp::Arr(Array::Arr(vec![
-                Resp::Bulk(BulkStr::Str(cmd_name.to_string().into_bytes())),
-                Resp::Bulk(BulkStr::Str(key.to_vec())),
-            ]));
-            let (sub_cmd_ctx, fut) = factory.create_with_ctx(cmd_ctx.get_context(), resp);
-            futs.push(fut);
-            self.handle_single_key_data_cmd(sub_cmd_ctx);
-        }
+        // The keys are counted independently so the sub-commands could be grouped by slot.
+        let keys = (1..arg_len).filter_map(|i| cmd_ctx.get_cmd().get_command_element(i));
+        let futs: Vec<_> = group_keys_by_slot(keys)
+            .into_iter()
+            .map(|keys| self.send_sub_cmd(&cmd_ctx, cmd_name, keys))
+            .collect();
 
         if futs.is_empty() {
             cmd_ctx.set_resp_result(Ok(Resp::Error(
@@ -715,72 +1080,568 @@ where
         reply_receiver.await
     }
 
-    async fn handle_list_blocking_commands(
+    // SUNION, SINTER and SDIFF
+    async fn handle_set_op_cmd(
         &self,
         cmd_ctx: CmdCtx,
         reply_receiver: CmdReplyReceiver,
     ) -> TaskResult {
         let data_cmd_type = cmd_ctx.get_data_cmd_type();
+        let arg_len = cmd_ctx.get_cmd().get_command_len().unwrap_or(0);
 
-        let (non_blocking_cmd_name, lrpop) = match data_cmd_type {
-            DataCmdType::BLPOP => ("LPOP", true),
-            DataCmdType::BRPOP => ("RPOP", true),
-            DataCmdType::BRPOPLPUSH => ("RPOPLPUSH", false),
-            _ => {
-                let cmd_name = cmd_ctx
-                    .get_cmd()
-                    .get_command_name()
-                    .map(|s| s.to_string())
-                    .unwrap_or_else(String::new);
-                cmd_ctx.set_resp_result(Ok(Resp::Error(
-                    format!("ERR unexpected command name '{}'", cmd_name).into_bytes(),
-                )));
-                return reply_receiver.await;
-            }
-        };
+        let keys = (1..arg_len).filter_map(|i| cmd_ctx.get_cmd().get_command_element(i));
+        if !self.can_split_keys(cmd_ctx.get_cluster_name(), keys) {
+            cmd_ctx.set_resp_result(Ok(Resp::Error(
+                response::ERR_NOT_THE_SAME_SLOT.to_string().into_bytes(),
+            )));
+            return reply_receiver.await;
+        }
 
-        let arg_len = match (data_cmd_type, cmd_ctx.get_cmd().get_command_len()) {
-            (DataCmdType::BLPOP, Some(len)) if len > 2 => len,
-            (DataCmdType::BRPOP, Some(len)) if len > 2 => len,
-            (DataCmdType::BRPOPLPUSH, Some(len)) if len == 4 => len,
-            _ => {
-                let cmd_name = cmd_ctx
-                    .get_cmd()
-                    .get_command_name()
-                    .map(|s| s.to_string())
-                    .unwrap_or_else(String::new);
-                cmd_ctx.set_resp_result(Ok(Resp::Error(
-                    format!("ERR invalid argument number for {:?}", cmd_name).into_bytes(),
-                )));
-                return reply_receiver.await;
-            }
-        };
+        // The first group always contains the first key at its front.
+        // For SDIFF, the other groups only need to be unioned before being subtracted.
+        let keys = (1..arg_len).filter_map(|i| cmd_ctx.get_cmd().get_command_element(i));
+        let futs: Vec<_> = group_keys_by_slot(keys)
+            .into_iter()
+            .enumerate()
+            .map(|(i, keys)| {
+                let cmd_name = match data_cmd_type {
+                    DataCmdType::SINTER => "SINTER",
+                    DataCmdType::SDIFF if i == 0 => "SDIFF",
+                    _ => "SUNION",
+                };
+                self.send_sub_cmd(&cmd_ctx, cmd_name, keys)
+            })
+            .collect();
 
-        let timeout = match cmd_ctx.get_cmd().get_command_last_element() {
-            None => {
-                cmd_ctx.set_resp_result(Ok(Resp::Error(b"ERR wrong number of arguments".to_vec())));
-                return reply_receiver.await;
-            }
-            Some(last) => match btoi::btou::<u64>(last) {
-                Err(_) => {
-                    cmd_ctx
-                        .set_resp_result(Ok(Resp::Error(b"ERR invalid timeout argument".to_vec())));
+        let mut sets = vec![];
+        let res = future::join_all(futs).await;
+        for sub_result in res.into_iter() {
+            let reply = match sub_result {
+                Ok(reply) => reply,
+                Err(err) => return Err(err),
+            };
+            match reply {
+                Resp::Arr(Array::Arr(resps)) => {
+                    let members: Vec<BinSafeStr> = resps
+                        .into_iter()
+                        .filter_map(|resp| match resp {
+                            Resp::Bulk(BulkStr::Str(member)) => Some(member),
+                            _ => None,
+                        })
+                        .collect();
+                    sets.push(members);
+                }
+                Resp::Error(err) => {
+                    cmd_ctx.set_resp_result(Ok(Resp::Error(err)));
                     return reply_receiver.await;
                 }
-                Ok(timeout) => timeout,
-            },
-        };
+                others => {
+                    let err_str = format!("unexpected reply from set command: {:?}", others);
+                    cmd_ctx.set_resp_result(Ok(Resp::Error(err_str.into_bytes())));
+                    return reply_receiver.await;
+                }
+            }
+        }
 
-        if !self.config.active_redirection {
-            let in_same_slot = same_slot(
-                (1..(arg_len - 1)).filter_map(|i| cmd_ctx.get_cmd().get_command_element(i)),
-            );
-            if !in_same_slot {
-                cmd_ctx.set_resp_result(Ok(Resp::Error(
-                    response::ERR_NOT_THE_SAME_SLOT.to_string().into_bytes(),
+        let members = merge_sets(data_cmd_type, sets);
+        let resp = Resp::Arr(Array::Arr(
+            members
+                .into_iter()
+                .map(|member| Resp::Bulk(BulkStr::Str(member)))
+                .collect(),
+        ));
+        cmd_ctx.set_resp_result(Ok(resp));
+        reply_receiver.await
+    }
+
+    // PFMERGE can't be split since it needs to be atomic.
+    // Merging the HyperLogLog values of PFCOUNT across slots needs temporary keys,
+    // so it's not split either.
+    fn handle_hll_cmd(&self, cmd_ctx: CmdCtx) {
+        let arg_len = cmd_ctx.get_cmd().get_command_len().unwrap_or(0);
+        let keys = (1..arg_len).filter_map(|i| cmd_ctx.get_cmd().get_command_element(i));
+        if !same_slot(keys) {
+            let err_str = match cmd_ctx.get_data_cmd_type() {
+                DataCmdType::PFCOUNT => response::ERR_PFCOUNT_CROSS_SLOT,
+                _ => response::ERR_ATOMIC_CROSS_SLOT,
+            };
+            return cmd_ctx.set_resp_result(Ok(Resp::Error(err_str.to_string().into_bytes())));
+        }
+        self.handle_single_key_data_cmd(cmd_ctx)
+    }
+
+    // MSETNX needs to be atomic so it can't be split.
+    fn handle_msetnx(&self, cmd_ctx: CmdCtx) {
+        let arg_len = cmd_ctx.get_cmd().get_command_len().unwrap_or(0);
+        let keys =
+            (0..(arg_len / 2)).filter_map(|i| cmd_ctx.get_cmd().get_command_element(2 * i + 1));
+        if arg_len > 3 && !same_slot(keys) {
+            return cmd_ctx.set_resp_result(Ok(Resp::Error(
+                response::ERR_ATOMIC_CROSS_SLOT.to_string().into_bytes(),
+            )));
+        }
+        self.handle_single_key_data_cmd(cmd_ctx)
+    }
+
+    // Without active redirection, the keys in different slots
+    // could only be processed when all the slots are owned by this proxy.
+    fn can_split_keys<'a, It: Iterator<Item = &'a [u8]> + Clone>(
+        &self,
+        cluster_name: &ClusterName,
+        keys: It,
+    ) -> bool {
+        self.config.active_redirection
+            || same_slot(keys.clone())
+            || self.manager.all_slots_local(cluster_name, keys)
+    }
+
+    fn send_sub_cmd<'a, It: IntoIterator<Item = &'a [u8]>>(
+        &self,
+        cmd_ctx: &CmdCtx,
+        cmd_name: &str,
+        args: It,
+    ) -> Pin<Box<dyn Future<Output = CmdTaskResult> + Send + 'static>> {
+        let elements = iter::once(cmd_name.as_bytes().to_vec())
+            .chain(args.into_iter().map(|arg| arg.to_vec()))
+            .map(|arg| Resp::Bulk(BulkStr::Str(arg)))
+            .collect();
+        let resp = Resp::Arr(Array::Arr(elements));
+        let (sub_cmd_ctx, fut) =
+            CmdCtxFactory::default().create_with_ctx(cmd_ctx.get_context(), resp);
+        self.handle_single_key_data_cmd(sub_cmd_ctx);
+        fut
+    }
+
+    async fn handle_publish(
+        &self,
+        cmd_ctx: CmdCtx,
+        reply_receiver: CmdReplyReceiver,
+        forward_to_peers: bool,
+    ) -> TaskResult {
+        if cmd_ctx.get_cmd().get_command_len() != Some(3) {
+            cmd_ctx.set_resp_result(Ok(Resp::Error(
+                b"ERR wrong number of arguments for 'publish' command".to_vec(),
+            )));
+            return reply_receiver.await;
+        }
+
+        let futs = self.manager.publish(&cmd_ctx, forward_to_peers);
+
+        // PUBLISH is best effort. Failed nodes are only logged.
+        let mut count: usize = 0;
+        let res = future::join_all(futs).await;
+        for sub_result in res.into_iter() {
+            match sub_result {
+                Ok(Resp::Integer(data)) => match btou::<usize>(&data) {
+                    Ok(n) => count += n,
+                    Err(err) => warn!("unexpected reply from PUBLISH: {:?} {:?}", data, err),
+                },
+                others => warn!("failed to publish: {:?}", others),
+            }
+        }
+
+        let resp = Resp::Integer(count.to_string().into_bytes());
+        cmd_ctx.set_resp_result(Ok(resp));
+        reply_receiver.await
+    }
+
+    // SCAN cursor [MATCH pattern] [COUNT count] [TYPE type]
+    // The cursor returned to the client contains the index of the node being scanned.
+    // Each SCAN only goes to a single node so the result could be empty before the end.
+    async fn handle_scan(
+        &self,
+        cmd_ctx: CmdCtx,
+        reply_receiver: CmdReplyReceiver,
+        include_peers: bool,
+    ) -> TaskResult {
+        let cursor = match cmd_ctx.get_cmd().get_command_element(1).map(btou::<u64>) {
+            Some(Ok(cursor)) => cursor,
+            Some(Err(_)) => {
+                cmd_ctx.set_resp_result(Ok(Resp::Error(b"ERR invalid cursor".to_vec())));
+                return reply_receiver.await;
+            }
+            None => {
+                cmd_ctx.set_resp_result(Ok(Resp::Error(
+                    b"ERR wrong number of arguments for 'scan' command".to_vec(),
+                )));
+                return reply_receiver.await;
+            }
+        };
+
+        let nodes = self
+            .manager
+            .get_nodes(cmd_ctx.get_cluster_name(), include_peers);
+        let (node_index, node_cursor) = decode_scan_cursor(cursor);
+        let node = match nodes.get(node_index) {
+            Some(node) => node,
+            None if cursor == 0 => {
+                cmd_ctx.set_resp_result(Ok(gen_scan_reply(0, vec![])));
+                return reply_receiver.await;
+            }
+            None => {
+                cmd_ctx.set_resp_result(Ok(Resp::Error(b"ERR invalid cursor".to_vec())));
+                return reply_receiver.await;
+            }
+        };
+
+        let mut resp = cmd_ctx.get_cmd().get_resp_slice().map(|b| b.to_vec());
+        change_bulk_array_element(&mut resp, 1, node_cursor.to_string().into_bytes());
+        let reply = match self.manager.send_to_node(&cmd_ctx, resp, node).await {
+            Ok(reply) => reply,
+            Err(err) => {
+                cmd_ctx.set_result(Err(err));
+                return reply_receiver.await;
+            }
+        };
+
+        let (next_node_cursor, keys) = match reply {
+            Resp::Arr(Array::Arr(mut resps)) if resps.len() == 2 => {
+                let keys = resps.pop();
+                let next_cursor = match resps.pop() {
+                    Some(Resp::Bulk(BulkStr::Str(c))) => btou::<u64>(&c).ok(),
+                    _ => None,
+                };
+                match (next_cursor, keys) {
+                    (Some(c), Some(Resp::Arr(Array::Arr(keys)))) => (c, keys),
+                    _ => {
+                        cmd_ctx.set_resp_result(Ok(Resp::Error(
+                            b"ERR unexpected reply from SCAN".to_vec(),
+                        )));
+                        return reply_receiver.await;
+                    }
+                }
+            }
+            Resp::Error(err) => {
+                cmd_ctx.set_resp_result(Ok(Resp::Error(err)));
+                return reply_receiver.await;
+            }
+            others => {
+                let err_str = format!("ERR unexpected reply from SCAN: {:?}", others);
+                cmd_ctx.set_resp_result(Ok(Resp::Error(err_str.into_bytes())));
+                return reply_receiver.await;
+            }
+        };
+
+        let next_cursor = if next_node_cursor != 0 {
+            encode_scan_cursor(node_index, next_node_cursor)
+        } else if node_index + 1 < nodes.len() {
+            encode_scan_cursor(node_index + 1, 0)
+        } else {
+            Some(0)
+        };
+        match next_cursor {
+            Some(next_cursor) => cmd_ctx.set_resp_result(Ok(gen_scan_reply(next_cursor, keys))),
+            None => cmd_ctx.set_resp_result(Ok(Resp::Error(
+                b"ERR cursor overflow in the proxy".to_vec(),
+            ))),
+        }
+        reply_receiver.await
+    }
+
+    async fn handle_keys(
+        &self,
+        cmd_ctx: CmdCtx,
+        reply_receiver: CmdReplyReceiver,
+        include_peers: bool,
+    ) -> TaskResult {
+        if cmd_ctx.get_cmd().get_command_len() != Some(2) {
+            cmd_ctx.set_resp_result(Ok(Resp::Error(
+                b"ERR wrong number of arguments for 'keys' command".to_vec(),
+            )));
+            return reply_receiver.await;
+        }
+
+        let resp = cmd_ctx.get_cmd().get_resp_slice().map(|b| b.to_vec());
+        let replies = match self.fan_out(&cmd_ctx, resp, include_peers).await {
+            Ok(replies) => replies,
+            Err(err) => return set_fan_out_err(cmd_ctx, reply_receiver, err).await,
+        };
+
+        let mut keys = vec![];
+        for reply in replies.into_iter() {
+            match reply {
+                Resp::Arr(Array::Arr(resps)) => keys.extend(resps),
+                others => {
+                    let err_str = format!("ERR unexpected reply from KEYS: {:?}", others);
+                    cmd_ctx.set_resp_result(Ok(Resp::Error(err_str.into_bytes())));
+                    return reply_receiver.await;
+                }
+            }
+        }
+
+        cmd_ctx.set_resp_result(Ok(Resp::Arr(Array::Arr(keys))));
+        reply_receiver.await
+    }
+
+    // SCAN, KEYS, DBSIZE, RANDOMKEY, FLUSHALL and FLUSHDB
+    // need to go through all the masters of the cluster.
+    fn handle_keyless_cmd(
+        &self,
+        cmd_ctx: CmdCtx,
+        reply_receiver: CmdReplyReceiver,
+        include_peers: bool,
+    ) -> CmdReplyFuture<'_> {
+        match cmd_ctx.get_data_cmd_type() {
+            DataCmdType::SCAN => CmdReplyFuture::Right(Box::pin(self.handle_scan(
+                cmd_ctx,
+                reply_receiver,
+                include_peers,
+            ))),
+            DataCmdType::KEYS => CmdReplyFuture::Right(Box::pin(self.handle_keys(
+                cmd_ctx,
+                reply_receiver,
+                include_peers,
+            ))),
+            DataCmdType::DBSIZE => CmdReplyFuture::Right(Box::pin(self.handle_dbsize(
+                cmd_ctx,
+                reply_receiver,
+                include_peers,
+            ))),
+            DataCmdType::RANDOMKEY => CmdReplyFuture::Right(Box::pin(self.handle_randomkey(
+                cmd_ctx,
+                reply_receiver,
+                include_peers,
+            ))),
+            _ => CmdReplyFuture::Right(Box::pin(self.handle_flushall(
+                cmd_ctx,
+                reply_receiver,
+                include_peers,
+            ))),
+        }
+    }
+
+    async fn handle_dbsize(
+        &self,
+        cmd_ctx: CmdCtx,
+        reply_receiver: CmdReplyReceiver,
+        include_peers: bool,
+    ) -> TaskResult {
+        let resp = cmd_ctx.get_cmd().get_resp_slice().map(|b| b.to_vec());
+        let replies = match self.fan_out(&cmd_ctx, resp, include_peers).await {
+            Ok(replies) => replies,
+            Err(err) => return set_fan_out_err(cmd_ctx, reply_receiver, err).await,
+        };
+
+        let mut count: u64 = 0;
+        for reply in replies.into_iter() {
+            if let Resp::Integer(data) = &reply {
+                if let Ok(n) = btou::<u64>(data) {
+                    count += n;
+                    continue;
+                }
+            }
+            let err_str = format!("ERR unexpected reply from DBSIZE: {:?}", reply);
+            cmd_ctx.set_resp_result(Ok(Resp::Error(err_str.into_bytes())));
+            return reply_receiver.await;
+        }
+
+        cmd_ctx.set_resp_result(Ok(Resp::Integer(count.to_string().into_bytes())));
+        reply_receiver.await
+    }
+
+    // Starts from a random node and stops at the first non-empty one.
+    async fn handle_randomkey(
+        &self,
+        cmd_ctx: CmdCtx,
+        reply_receiver: CmdReplyReceiver,
+        include_peers: bool,
+    ) -> TaskResult {
+        let nodes = self
+            .manager
+            .get_nodes(cmd_ctx.get_cluster_name(), include_peers);
+        let start = if nodes.is_empty() {
+            0
+        } else {
+            rand::thread_rng().gen_range(0, nodes.len())
+        };
+
+        let resp = cmd_ctx.get_cmd().get_resp_slice().map(|b| b.to_vec());
+        for node in nodes.iter().cycle().skip(start).take(nodes.len()) {
+            let reply = match self
+                .manager
+                .send_to_node(&cmd_ctx, resp.clone(), node)
+                .await
+            {
+                Ok(reply) => reply,
+                Err(err) => {
+                    cmd_ctx.set_result(Err(err));
+                    return reply_receiver.await;
+                }
+            };
+            match reply {
+                Resp::Bulk(BulkStr::Nil) => continue,
+                reply => {
+                    cmd_ctx.set_resp_result(Ok(reply));
+                    return reply_receiver.await;
+                }
+            }
+        }
+
+        cmd_ctx.set_resp_result(Ok(Resp::Bulk(BulkStr::Nil)));
+        reply_receiver.await
+    }
+
+    // FLUSHALL and FLUSHDB
+    async fn handle_flushall(
+        &self,
+        cmd_ctx: CmdCtx,
+        reply_receiver: CmdReplyReceiver,
+        include_peers: bool,
+    ) -> TaskResult {
+        if !self.manager.is_flushall_enabled(cmd_ctx.get_cluster_name()) {
+            cmd_ctx.set_resp_result(Ok(Resp::Error(
+                response::ERR_FLUSHALL_DISABLED.to_string().into_bytes(),
+            )));
+            return reply_receiver.await;
+        }
+
+        let resp = cmd_ctx.get_cmd().get_resp_slice().map(|b| b.to_vec());
+        if let Err(err) = self.fan_out(&cmd_ctx, resp, include_peers).await {
+            return set_fan_out_err(cmd_ctx, reply_receiver, err).await;
+        }
+
+        cmd_ctx.set_resp_result(Ok(Resp::Simple(
+            response::OK_REPLY.to_string().into_bytes(),
+        )));
+        reply_receiver.await
+    }
+
+    // INFO only returns the version of the proxy.
+    // Only `INFO keyspace` is sent to all the masters to merge their keyspace sections.
+    async fn handle_info(
+        &self,
+        cmd_ctx: CmdCtx,
+        reply_receiver: CmdReplyReceiver,
+        include_peers: bool,
+    ) -> TaskResult {
+        let section = cmd_ctx
+            .get_cmd()
+            .get_command_element(1)
+            .map(|s| String::from_utf8_lossy(s).to_lowercase())
+            .unwrap_or_else(|| "default".to_string());
+
+        let mut info = String::new();
+        match section.as_str() {
+            "default" | "all" | "everything" | "server" => {
+                info.push_str(&format!("# Server\r\nversion:{}\r\n", UNDERMOON_VERSION));
+            }
+            "keyspace" => {
+                let resp = Resp::Arr(Array::Arr(vec![
+                    Resp::Bulk(BulkStr::Str(b"INFO".to_vec())),
+                    Resp::Bulk(BulkStr::Str(b"keyspace".to_vec())),
+                ]));
+                let replies = match self.fan_out(&cmd_ctx, resp, include_peers).await {
+                    Ok(replies) => replies,
+                    Err(err) => return set_fan_out_err(cmd_ctx, reply_receiver, err).await,
+                };
+                let mut sections = vec![];
+                for reply in replies.into_iter() {
+                    match reply {
+                        Resp::Bulk(BulkStr::Str(s)) => sections.push(s),
+                        others => {
+                            let err_str = format!("ERR unexpected reply from INFO: {:?}", others);
+                            cmd_ctx.set_resp_result(Ok(Resp::Error(err_str.into_bytes())));
+                            return reply_receiver.await;
+                        }
+                    }
+                }
+                info.push_str(&merge_keyspace(&sections));
+            }
+            _ => (),
+        }
+
+        cmd_ctx.set_resp_result(Ok(Resp::Bulk(BulkStr::Str(info.into_bytes()))));
+        reply_receiver.await
+    }
+
+    // Sends the keyless command to all the masters of the cluster.
+    // Returns the first error reply if any.
+    async fn fan_out(
+        &self,
+        cmd_ctx: &CmdCtx,
+        resp: RespVec,
+        include_peers: bool,
+    ) -> Result<Vec<RespVec>, FanOutError> {
+        let futs: Vec<_> = self
+            .manager
+            .get_nodes(cmd_ctx.get_cluster_name(), include_peers)
+            .iter()
+            .map(|node| self.manager.send_to_node(cmd_ctx, resp.clone(), node))
+            .collect();
+
+        let mut replies = vec![];
+        for sub_result in future::join_all(futs).await.into_iter() {
+            match sub_result {
+                Ok(Resp::Error(err)) => return Err(FanOutError::ErrReply(err)),
+                Ok(reply) => replies.push(reply),
+                Err(err) => return Err(FanOutError::Cmd(err)),
+            }
+        }
+        Ok(replies)
+    }
+
+    async fn handle_list_blocking_commands(
+        &self,
+        cmd_ctx: CmdCtx,
+        reply_receiver: CmdReplyReceiver,
+    ) -> TaskResult {
+        let data_cmd_type = cmd_ctx.get_data_cmd_type();
+
+        let (non_blocking_cmd_name, lrpop) = match data_cmd_type {
+            DataCmdType::BLPOP => ("LPOP", true),
+            DataCmdType::BRPOP => ("RPOP", true),
+            DataCmdType::BRPOPLPUSH => ("RPOPLPUSH", false),
+            _ => {
+                let cmd_name = cmd_ctx
+                    .get_cmd()
+                    .get_command_name()
+                    .map(|s| s.to_string())
+                    .unwrap_or_else(String::new);
+                cmd_ctx.set_resp_result(Ok(Resp::Error(
+                    format!("ERR unexpected command name '{}'", cmd_name).into_bytes(),
+                )));
+                return reply_receiver.await;
+            }
+        };
+
+        let arg_len = match (data_cmd_type, cmd_ctx.get_cmd().get_command_len()) {
+            (DataCmdType::BLPOP, Some(len)) if len > 2 => len,
+            (DataCmdType::BRPOP, Some(len)) if len > 2 => len,
+            (DataCmdType::BRPOPLPUSH, Some(len)) if len == 4 => len,
+            _ => {
+                let cmd_name = cmd_ctx
+                    .get_cmd()
+                    .get_command_name()
+                    .map(|s| s.to_string())
+                    .unwrap_or_else(String::new);
+                cmd_ctx.set_resp_result(Ok(Resp::Error(
+                    format!("ERR invalid argument number for {:?}", cmd_name).into_bytes(),
                 )));
                 return reply_receiver.await;
             }
+        };
+
+        let timeout = match cmd_ctx.get_cmd().get_command_last_element() {
+            None => {
+                cmd_ctx.set_resp_result(Ok(Resp::Error(b"ERR wrong number of arguments".to_vec())));
+                return reply_receiver.await;
+            }
+            Some(last) => match btoi::btou::<u64>(last) {
+                Err(_) => {
+                    cmd_ctx
+                        .set_resp_result(Ok(Resp::Error(b"ERR invalid timeout argument".to_vec())));
+                    return reply_receiver.await;
+                }
+                Ok(timeout) => timeout,
+            },
+        };
+
+        let keys = (1..(arg_len - 1)).filter_map(|i| cmd_ctx.get_cmd().get_command_element(i));
+        if !self.can_split_keys(cmd_ctx.get_cluster_name(), keys) {
+            cmd_ctx.set_resp_result(Ok(Resp::Error(
+                response::ERR_NOT_THE_SAME_SLOT.to_string().into_bytes(),
+            )));
+            return reply_receiver.await;
         }
 
         let factory = CmdCtxFactory::default();
@@ -862,6 +1723,11 @@ where
 
     fn handle_single_key_data_cmd(&self, cmd_ctx: CmdCtx) {
         let mut cmd_ctx = cmd_ctx;
+        // The forwarded commands have already been recorded and mirrored by the peer proxy.
+        if cmd_ctx.get_redirection_times().is_none() {
+            self.record_hot_key(&cmd_ctx);
+            cmd_ctx = self.mirror_cmd(cmd_ctx);
+        }
         match self.compressor.try_compressing_cmd_ctx(&mut cmd_ctx) {
             Ok(())
             | Err(CompressionError::UnsupportedCmdType)
@@ -871,7 +1737,7 @@ where
                     .set_resp_result(Ok(Resp::Error("invalid command".to_string().into_bytes())));
             }
             Err(CompressionError::RestrictedCmd) => {
-                let err_msg = "unsupported string command when compression is enabled";
+                let err_msg = "unsupported command when compression is enabled";
                 return cmd_ctx.set_resp_result(Ok(Resp::Error(err_msg.to_string().into_bytes())));
             }
             Err(CompressionError::Io(err)) => {
@@ -883,12 +1749,139 @@ where
         self.manager.send(cmd_ctx);
     }
 
-    fn handle_umforward(
-        &self,
+    // Sends a sampled copy of the command to the `mirror_to` of the cluster.
+    // The primary reply is sent back without waiting for the mirror reply.
+    fn mirror_cmd(&self, mut cmd_ctx: CmdCtx) -> CmdCtx {
+        let target = match self
+            .manager
+            .sample_mirror_target(cmd_ctx.get_cluster_name())
+        {
+            Some(target) => target,
+            None => return cmd_ctx,
+        };
+
+        let mirror_cmd = Command::new(Box::new(cmd_ctx.get_cmd().get_packet()));
+        let (mirror_sender, mirror_receiver) = new_command_pair(&mirror_cmd);
+        let session_id = cmd_ctx.get_session_id();
+        match target {
+            MirrorTarget::Cluster(cluster_name) => {
+                let mut mirror_ctx =
+                    CmdCtx::new(cluster_name, mirror_cmd, mirror_sender, session_id, false);
+                // Use the compression strategy of the mirror cluster.
+                match self.compressor.try_compressing_cmd_ctx(&mut mirror_ctx) {
+                    Ok(())
+                    | Err(CompressionError::UnsupportedCmdType)
+                    | Err(CompressionError::Disabled) => self.manager.send(mirror_ctx),
+                    Err(err) => mirror_ctx.set_resp_result(Ok(Resp::Error(
+                        format!("failed to compress data: {:?}", err).into_bytes(),
+                    ))),
+                }
+            }
+            MirrorTarget::Address(address) => {
+                let mirror_ctx = CmdCtx::new(
+                    cmd_ctx.get_cluster(),
+                    mirror_cmd,
+                    mirror_sender,
+                    session_id,
+                    false,
+                );
+                self.manager.send_to_mirror_address(mirror_ctx, address);
+            }
+        }
+
+        let (primary_sender, primary_receiver) = new_command_pair(cmd_ctx.get_cmd());
+        let reply_sender = cmd_ctx.replace_reply_sender(primary_sender);
+        let stats = self.manager.get_mirror_stats(cmd_ctx.get_cluster_name());
+        tokio::spawn(forward_and_compare(
+            primary_receiver,
+            reply_sender,
+            mirror_receiver,
+            stats,
+        ));
+        cmd_ctx
+    }
+
+    fn record_hot_key(&self, cmd_ctx: &CmdCtx) {
+        let key = match cmd_ctx.get_cmd().get_key() {
+            Some(key) => key,
+            None => return,
+        };
+        let access = if is_write_cmd_type(cmd_ctx.get_data_cmd_type()) {
+            KeyAccess::Write
+        } else {
+            KeyAccess::Read
+        };
+        self.hot_key_tracker.record(
+            cmd_ctx.get_cluster_name(),
+            key,
+            access,
+            self.config.get_hotkey_sample_rate(),
+        );
+    }
+
+    // UMFORWARD and UMSYNC could carry `AUTH <cluster> <password>` starting from `index`.
+    // Returns the number of these elements, or None if the command is rejected.
+    fn check_forward_auth(
+        &self,
+        mut cmd_ctx: CmdCtx,
+        session_state: &SessionState,
+        index: usize,
+    ) -> Option<(CmdCtx, usize)> {
+        let auth = {
+            let cmd = cmd_ctx.get_cmd();
+            match cmd.get_command_element(index) {
+                Some(arg) if arg.eq_ignore_ascii_case(b"AUTH") => Some((
+                    cmd.get_command_element(index + 1).map(|name| name.to_vec()),
+                    cmd.get_command_element(index + 2)
+                        .map(|password| password.to_vec()),
+                )),
+                _ => None,
+            }
+        };
+
+        match auth {
+            None if self.is_authenticated(cmd_ctx.get_cluster_name(), session_state) => {
+                Some((cmd_ctx, 0))
+            }
+            None => {
+                cmd_ctx.set_resp_result(Ok(Resp::Error(
+                    response::ERR_NOAUTH.to_string().into_bytes(),
+                )));
+                None
+            }
+            Some((Some(name), Some(password))) => {
+                let cluster_name = match parse_cluster_name(&name) {
+                    Ok(cluster_name) => cluster_name,
+                    Err(err) => {
+                        cmd_ctx.set_resp_result(Ok(Resp::Error(err.to_string().into_bytes())));
+                        return None;
+                    }
+                };
+                if !self.manager.check_password(&cluster_name, None, &password) {
+                    cmd_ctx.set_resp_result(Ok(Resp::Error(
+                        response::ERR_WRONG_PASS.to_string().into_bytes(),
+                    )));
+                    return None;
+                }
+                cmd_ctx.set_cluster_name(cluster_name);
+                Some((cmd_ctx, 3))
+            }
+            Some(_) => {
+                cmd_ctx.set_resp_result(Ok(Resp::Error(
+                    String::from("Missing password").into_bytes(),
+                )));
+                None
+            }
+        }
+    }
+
+    fn handle_umforward(
+        &self,
         cmd_ctx: CmdCtx,
         reply_receiver: CmdReplyReceiver,
-    ) -> CmdReplyFuture {
-        let (mut cmd_ctx, redirection_times) = match Self::get_sub_command(cmd_ctx, 1) {
+        session_state: &SessionState,
+    ) -> CmdReplyFuture<'_> {
+        let (cmd_ctx, redirection_times) = match Self::get_sub_command(cmd_ctx, 1) {
             Some((cmd_ctx, sub_cmd)) => (cmd_ctx, sub_cmd.to_uppercase()),
             None => return CmdReplyFuture::Left(reply_receiver),
         };
@@ -901,8 +1894,12 @@ where
             }
         };
 
-        // UMFORWARD <redirection times>
-        match cmd_ctx.extract_inner_cmd(2) {
+        // UMFORWARD <redirection times> [AUTH <cluster> <password>]
+        let (mut cmd_ctx, auth_len) = match self.check_forward_auth(cmd_ctx, session_state, 2) {
+            Some(r) => r,
+            None => return CmdReplyFuture::Left(reply_receiver),
+        };
+        match cmd_ctx.extract_inner_cmd(2 + auth_len) {
             Some(cmd_len) if cmd_len > 0 => (),
             _ => {
                 cmd_ctx.set_resp_result(Ok(Resp::Error(b"missing forwarded command".to_vec())));
@@ -911,11 +1908,278 @@ where
         }
 
         cmd_ctx.set_redirection_times(times);
-        self.handle_data_cmd(cmd_ctx, reply_receiver)
+
+        if !self.manager.filter_forwarded_cmd(&mut cmd_ctx) {
+            cmd_ctx.set_resp_result(Ok(Resp::Error(
+                response::ERR_NOPERM.to_string().into_bytes(),
+            )));
+            return CmdReplyFuture::Left(reply_receiver);
+        }
+
+        // The replies are not stripped here but by the peer proxy.
+        if cmd_ctx.get_cmd_type() == CmdType::Others {
+            if let Some(prefix) = self.manager.get_key_prefix(cmd_ctx.get_cluster_name()) {
+                if let Err(err) = namespace::ensure_key_prefix(&mut cmd_ctx, &prefix) {
+                    cmd_ctx.set_resp_result(Ok(gen_namespace_error(err)));
+                    return CmdReplyFuture::Left(reply_receiver);
+                }
+            }
+        }
+
+        if cmd_ctx.get_cmd_type() == CmdType::Others {
+            self.audit_cmd(&cmd_ctx, session_state, true);
+        }
+
+        // The quota has been charged by the peer proxy receiving the command from the client.
+
+        if cmd_ctx.get_cmd_type() == CmdType::Publish {
+            return CmdReplyFuture::Right(Box::pin(self.handle_publish(
+                cmd_ctx,
+                reply_receiver,
+                false,
+            )));
+        }
+        // The keyless commands from peers only need to go through the local masters.
+        if cmd_ctx.get_cmd_type() == CmdType::Info {
+            return CmdReplyFuture::Right(Box::pin(self.handle_info(
+                cmd_ctx,
+                reply_receiver,
+                false,
+            )));
+        }
+        match cmd_ctx.get_data_cmd_type() {
+            DataCmdType::SCAN
+            | DataCmdType::KEYS
+            | DataCmdType::DBSIZE
+            | DataCmdType::RANDOMKEY
+            | DataCmdType::FLUSHALL
+            | DataCmdType::FLUSHDB => self.handle_keyless_cmd(cmd_ctx, reply_receiver, false),
+            _ => self.handle_data_cmd(cmd_ctx, reply_receiver),
+        }
+    }
+
+    fn audit_cmd(&self, cmd_ctx: &CmdCtx, session_state: &SessionState, forwarded: bool) {
+        if self.audit_logger.is_enabled()
+            && is_write_cmd(cmd_ctx.get_cmd())
+            && self
+                .manager
+                .is_audit_log_enabled(cmd_ctx.get_cluster_name())
+        {
+            let client_info = session_state.get_client_info();
+            self.audit_logger
+                .log(cmd_ctx, client_info.get_addr(), forwarded);
+        }
+    }
+
+    fn handle_umsync(&self, cmd_ctx: CmdCtx, session_state: &SessionState) {
+        // UMSYNC <key> [AUTH <cluster> <password>]
+        if let Some((cmd_ctx, _)) = self.check_forward_auth(cmd_ctx, session_state, 2) {
+            self.manager.send_sync_task(cmd_ctx);
+        }
+    }
+
+    fn handle_multi(&self, cmd_ctx: CmdCtx, session_state: &SessionState) {
+        // The replies inside EXEC can't be decompressed.
+        if self.compressor.is_enabled(cmd_ctx.get_cluster_name()) {
+            return cmd_ctx.set_resp_result(Ok(Resp::Error(
+                b"ERR transaction is not supported when compression is enabled".to_vec(),
+            )));
+        }
+        if !session_state.get_transaction().start() {
+            return cmd_ctx.set_resp_result(Ok(Resp::Error(
+                b"ERR MULTI calls can not be nested".to_vec(),
+            )));
+        }
+        cmd_ctx.set_resp_result(Ok(Resp::Simple(
+            response::OK_REPLY.to_string().into_bytes(),
+        )))
+    }
+
+    fn handle_queued_cmd(&self, cmd_ctx: CmdCtx, session_state: &SessionState) {
+        let mut transaction = session_state.get_transaction();
+
+        let keys = match cmd_ctx.get_cmd_type() {
+            CmdType::Others | CmdType::Unwatch => get_command_keys(cmd_ctx.get_cmd()),
+            // The argument of PING and ECHO is not a key.
+            CmdType::Ping | CmdType::Echo => Ok(vec![]),
+            _ => Err(NamespaceError::UnsupportedCmd),
+        };
+        let keys = match keys {
+            Ok(keys) => keys,
+            Err(_) => {
+                transaction.abort();
+                let cmd_name = cmd_ctx
+                    .get_cmd()
+                    .get_command_name()
+                    .map(|s| s.to_string())
+                    .unwrap_or_else(String::new);
+                return cmd_ctx.set_resp_result(Ok(Resp::Error(
+                    format!("ERR command '{}' is not allowed in transaction", cmd_name)
+                        .into_bytes(),
+                )));
+            }
+        };
+
+        if !transaction.add_keys(keys.into_iter()) {
+            transaction.abort();
+            return cmd_ctx.set_resp_result(Ok(Resp::Error(
+                response::ERR_CROSS_SLOT.to_string().into_bytes(),
+            )));
+        }
+
+        // The queued commands are charged here instead of at EXEC.
+        if cmd_ctx.get_cmd_type() == CmdType::Others
+            && self.manager.acquire_quota(&cmd_ctx).is_err()
+        {
+            transaction.abort();
+            return cmd_ctx.set_resp_result(Ok(Resp::Error(
+                response::ERR_RATE_LIMITED.to_string().into_bytes(),
+            )));
+        }
+
+        transaction.queue(cmd_ctx.get_cmd().get_packet().into_resp_vec());
+        cmd_ctx.set_resp_result(Ok(Resp::Simple(
+            response::QUEUED_REPLY.to_string().into_bytes(),
+        )))
+    }
+
+    fn handle_watch(&self, cmd_ctx: CmdCtx, session_state: &SessionState) {
+        let mut transaction = session_state.get_transaction();
+        if transaction.is_queuing() {
+            return cmd_ctx.set_resp_result(Ok(Resp::Error(
+                b"ERR WATCH inside MULTI is not allowed".to_vec(),
+            )));
+        }
+
+        let arg_len = cmd_ctx.get_cmd().get_command_len().unwrap_or(0);
+        if arg_len < 2 {
+            return cmd_ctx.set_resp_result(Ok(Resp::Error(
+                b"ERR wrong number of arguments for 'watch' command".to_vec(),
+            )));
+        }
+
+        let keys = (1..arg_len).filter_map(|i| cmd_ctx.get_cmd().get_command_element(i));
+        let slot = match (transaction.add_keys(keys), transaction.get_slot()) {
+            (true, Some(slot)) => slot,
+            _ => {
+                return cmd_ctx.set_resp_result(Ok(Resp::Error(
+                    response::ERR_CROSS_SLOT.to_string().into_bytes(),
+                )));
+            }
+        };
+
+        if transaction.get_backend().is_none() {
+            match self
+                .manager
+                .create_dedicated_backend(cmd_ctx.get_cluster_name(), slot)
+            {
+                Ok(backend) => transaction.set_backend(backend),
+                Err(resp) => {
+                    transaction.unwatch();
+                    return cmd_ctx.set_resp_result(Ok(resp));
+                }
+            }
+        }
+
+        if let Some(backend) = transaction.get_backend() {
+            if let Err(err) = backend.send(cmd_ctx) {
+                err.into_inner().set_resp_result(Ok(Resp::Error(
+                    response::ERR_BACKEND_CONNECTION.to_string().into_bytes(),
+                )));
+                transaction.unwatch();
+            }
+        }
     }
 
-    fn handle_umsync(&self, cmd_ctx: CmdCtx) {
-        self.manager.send_sync_task(cmd_ctx);
+    fn handle_discard(&self, cmd_ctx: CmdCtx, session_state: &SessionState) {
+        let mut transaction = session_state.get_transaction();
+        if !transaction.is_queuing() {
+            return cmd_ctx.set_resp_result(Ok(Resp::Error(b"ERR DISCARD without MULTI".to_vec())));
+        }
+        transaction.take();
+        cmd_ctx.set_resp_result(Ok(Resp::Simple(
+            response::OK_REPLY.to_string().into_bytes(),
+        )))
+    }
+
+    fn handle_exec(
+        &self,
+        cmd_ctx: CmdCtx,
+        reply_receiver: CmdReplyReceiver,
+        session_state: &SessionState,
+    ) -> CmdReplyFuture<'_> {
+        let transaction = {
+            let mut transaction = session_state.get_transaction();
+            if !transaction.is_queuing() {
+                cmd_ctx.set_resp_result(Ok(Resp::Error(b"ERR EXEC without MULTI".to_vec())));
+                return CmdReplyFuture::Left(reply_receiver);
+            }
+            transaction.take()
+        };
+
+        if transaction.is_aborted() {
+            cmd_ctx.set_resp_result(Ok(Resp::Error(
+                response::ERR_EXEC_ABORT.to_string().into_bytes(),
+            )));
+            return CmdReplyFuture::Left(reply_receiver);
+        }
+
+        let (slot, queued_cmds, backend) = transaction.into_parts();
+        let slot = match slot {
+            Some(slot) => slot,
+            None if queued_cmds.is_empty() => {
+                cmd_ctx.set_resp_result(Ok(Resp::Arr(Array::Arr(vec![]))));
+                return CmdReplyFuture::Left(reply_receiver);
+            }
+            None => {
+                cmd_ctx
+                    .set_resp_result(Ok(Resp::Error(b"ERR missing key in transaction".to_vec())));
+                return CmdReplyFuture::Left(reply_receiver);
+            }
+        };
+
+        // The whole block should be sent before handling the following commands in the pipeline.
+        let multi = Resp::Arr(Array::Arr(vec![Resp::Bulk(BulkStr::Str(
+            b"MULTI".to_vec(),
+        ))]));
+        let exec = Resp::Arr(Array::Arr(vec![Resp::Bulk(BulkStr::Str(b"EXEC".to_vec()))]));
+        let factory = CmdCtxFactory::default();
+        let mut futs = vec![];
+        let mut sub_cmd_ctxs = vec![];
+        for resp in iter::once(multi).chain(queued_cmds).chain(iter::once(exec)) {
+            let (sub_cmd_ctx, fut) = factory.create_with_ctx(cmd_ctx.get_context(), resp);
+            futs.push(fut);
+            sub_cmd_ctxs.push(sub_cmd_ctx);
+        }
+
+        match backend {
+            // The watched keys are on the connection created by WATCH.
+            Some(backend) => {
+                for sub_cmd_ctx in sub_cmd_ctxs.into_iter() {
+                    if let Err(err) = backend.send(sub_cmd_ctx) {
+                        err.into_inner().set_resp_result(Ok(Resp::Error(
+                            response::ERR_BACKEND_CONNECTION.to_string().into_bytes(),
+                        )));
+                    }
+                }
+                // The queued commands will still be sent after the dedicated connection is dropped.
+                drop(backend);
+            }
+            None => self
+                .manager
+                .send_transaction(cmd_ctx.get_cluster_name(), slot, sub_cmd_ctxs),
+        }
+
+        let fut = async move {
+            let exec_result = future::join_all(futs).await.pop();
+            match exec_result {
+                Some(Ok(resp)) => cmd_ctx.set_resp_result(Ok(resp)),
+                Some(Err(err)) => cmd_ctx.set_result(Err(err)),
+                None => cmd_ctx.set_result(Err(CommandError::InnerError)),
+            }
+            reply_receiver.await
+        };
+        CmdReplyFuture::Right(Box::pin(fut))
     }
 }
 
@@ -928,22 +2192,89 @@ where
         &self,
         cmd_ctx: CmdCtx,
         reply_receiver: CmdReplyReceiver,
-        session_cluster_name: &sync::RwLock<ClusterName>,
-    ) -> CmdReplyFuture {
+        session_state: &SessionState,
+    ) -> CmdReplyFuture<'_> {
         let mut cmd_ctx = cmd_ctx;
         if self.config.auto_select_cluster {
             cmd_ctx = self.manager.try_select_cluster(cmd_ctx);
         }
 
         let cmd_type = cmd_ctx.get_cmd().get_type();
+        match cmd_type {
+            // HELLO checks the authentication itself since it could carry the password.
+            // UMCTL is sent by the coordinator which does not belong to any cluster.
+            // Its DRAIN and HOTKEYS check the authentication themselves.
+            CmdType::Auth | CmdType::Hello | CmdType::Quit | CmdType::UmCtl => (),
+            // The peer proxies don't authenticate their connections.
+            // UMFORWARD and UMSYNC are checked by `check_forward_auth` instead.
+            CmdType::UmForward | CmdType::UmSync => (),
+            _ if !self.is_authenticated(cmd_ctx.get_cluster_name(), session_state) => {
+                cmd_ctx.set_resp_result(Ok(Resp::Error(
+                    response::ERR_NOAUTH.to_string().into_bytes(),
+                )));
+                return CmdReplyFuture::Left(reply_receiver);
+            }
+            _ if !self.join_cluster(cmd_ctx.get_cluster_name(), session_state) => {
+                cmd_ctx.set_resp_result(Ok(Resp::Error(
+                    response::ERR_MAX_CLIENTS.to_string().into_bytes(),
+                )));
+                return CmdReplyFuture::Left(reply_receiver);
+            }
+            _ if !self.manager.filter_cmd(&mut cmd_ctx) => {
+                cmd_ctx.set_resp_result(Ok(Resp::Error(
+                    response::ERR_NOPERM.to_string().into_bytes(),
+                )));
+                return CmdReplyFuture::Left(reply_receiver);
+            }
+            _ => (),
+        }
+        // The command could be renamed.
+        let cmd_type = cmd_ctx.get_cmd().get_type();
+
+        // The queued commands inside transactions also need to be prefixed.
+        let key_prefix = match cmd_type {
+            CmdType::Others | CmdType::Watch => {
+                self.manager.get_key_prefix(cmd_ctx.get_cluster_name())
+            }
+            _ => None,
+        };
+        let key_pattern = key_prefix
+            .as_ref()
+            .and_then(|_| namespace::get_key_pattern(cmd_ctx.get_cmd()));
+        if let Some(prefix) = key_prefix.as_ref() {
+            if let Err(err) = namespace::add_key_prefix(&mut cmd_ctx, prefix) {
+                cmd_ctx.set_resp_result(Ok(gen_namespace_error(err)));
+                return CmdReplyFuture::Left(reply_receiver);
+            }
+        }
+
+        if cmd_type == CmdType::Others {
+            self.audit_cmd(&cmd_ctx, session_state, false);
+        }
+
+        match cmd_type {
+            CmdType::Exec | CmdType::Discard | CmdType::Multi | CmdType::Watch => (),
+            _ if session_state.get_transaction().is_queuing() => {
+                self.handle_queued_cmd(cmd_ctx, session_state);
+                return CmdReplyFuture::Left(reply_receiver);
+            }
+            _ => (),
+        }
+
         match cmd_type {
             CmdType::Ping => {
                 cmd_ctx.set_resp_result(Ok(Resp::Simple(String::from("OK").into_bytes())))
             }
-            CmdType::Info => cmd_ctx.set_resp_result(Ok(Resp::Bulk(BulkStr::Str(
-                format!("version:{}\r\n", UNDERMOON_VERSION,).into_bytes(),
-            )))),
-            CmdType::Auth => self.handle_auth(cmd_ctx, session_cluster_name),
+            CmdType::Info => {
+                return CmdReplyFuture::Right(Box::pin(self.handle_info(
+                    cmd_ctx,
+                    reply_receiver,
+                    true,
+                )))
+            }
+            CmdType::Auth => self.handle_auth(cmd_ctx, session_state),
+            CmdType::Hello => self.handle_hello(cmd_ctx, session_state),
+            CmdType::Client => self.handle_client(cmd_ctx, session_state),
             CmdType::Quit => {
                 cmd_ctx.set_resp_result(Ok(Resp::Simple(String::from("OK").into_bytes())))
             }
@@ -963,9 +2294,11 @@ where
             CmdType::Invalid => cmd_ctx.set_resp_result(Ok(Resp::Error(
                 String::from("Invalid command").into_bytes(),
             ))),
-            CmdType::UmCtl => self.handle_umctl(cmd_ctx),
-            CmdType::UmForward => return self.handle_umforward(cmd_ctx, reply_receiver),
-            CmdType::UmSync => self.handle_umsync(cmd_ctx),
+            CmdType::UmCtl => self.handle_umctl(cmd_ctx, session_state),
+            CmdType::UmForward => {
+                return self.handle_umforward(cmd_ctx, reply_receiver, session_state)
+            }
+            CmdType::UmSync => self.handle_umsync(cmd_ctx, session_state),
             CmdType::Cluster => self.handle_cluster(cmd_ctx),
             CmdType::Config => self.handle_config(cmd_ctx),
             CmdType::Command => {
@@ -974,8 +2307,527 @@ where
             CmdType::Asking => cmd_ctx.set_resp_result(Ok(Resp::Simple(
                 response::OK_REPLY.to_string().into_bytes(),
             ))),
-            CmdType::Others => return self.handle_data_cmd(cmd_ctx, reply_receiver),
+            CmdType::ReadOnly => {
+                session_state.set_read_mode(ReadMode::ReadOnly);
+                cmd_ctx.set_resp_result(Ok(Resp::Simple(
+                    response::OK_REPLY.to_string().into_bytes(),
+                )))
+            }
+            CmdType::ReadWrite => {
+                session_state.set_read_mode(ReadMode::ReadWrite);
+                cmd_ctx.set_resp_result(Ok(Resp::Simple(
+                    response::OK_REPLY.to_string().into_bytes(),
+                )))
+            }
+            CmdType::Multi => self.handle_multi(cmd_ctx, session_state),
+            CmdType::Exec => return self.handle_exec(cmd_ctx, reply_receiver, session_state),
+            CmdType::Discard => self.handle_discard(cmd_ctx, session_state),
+            CmdType::Watch => self.handle_watch(cmd_ctx, session_state),
+            CmdType::Unwatch => {
+                session_state.get_transaction().unwatch();
+                cmd_ctx.set_resp_result(Ok(Resp::Simple(
+                    response::OK_REPLY.to_string().into_bytes(),
+                )))
+            }
+            // The channels are not in the namespace of the key prefix.
+            CmdType::Publish
+                if self
+                    .manager
+                    .get_key_prefix(cmd_ctx.get_cluster_name())
+                    .is_some() =>
+            {
+                cmd_ctx.set_resp_result(Ok(Resp::Error(
+                    response::ERR_KEY_PREFIX_UNSUPPORTED
+                        .to_string()
+                        .into_bytes(),
+                )))
+            }
+            CmdType::Publish => {
+                return CmdReplyFuture::Right(Box::pin(self.handle_publish(
+                    cmd_ctx,
+                    reply_receiver,
+                    true,
+                )))
+            }
+            // These commands are handled by the session in the pub/sub mode.
+            CmdType::Subscribe
+            | CmdType::Psubscribe
+            | CmdType::Unsubscribe
+            | CmdType::Punsubscribe => {
+                cmd_ctx.set_resp_result(Ok(Resp::Error(b"ERR unexpected pub/sub command".to_vec())))
+            }
+            CmdType::Others => {
+                if self.manager.acquire_quota(&cmd_ctx).is_err() {
+                    cmd_ctx.set_resp_result(Ok(Resp::Error(
+                        response::ERR_RATE_LIMITED.to_string().into_bytes(),
+                    )));
+                    return CmdReplyFuture::Left(reply_receiver);
+                }
+                let data_cmd_type = cmd_ctx.get_data_cmd_type();
+                let reply_fut = self.handle_data_cmd(cmd_ctx, reply_receiver);
+                return match key_prefix {
+                    Some(prefix) if namespace::requires_stripping_prefix(data_cmd_type) => {
+                        strip_key_prefix(reply_fut, data_cmd_type, prefix, key_pattern)
+                    }
+                    _ => reply_fut,
+                };
+            }
         };
         CmdReplyFuture::Left(reply_receiver)
     }
+
+    fn create_pubsub_conn(
+        &self,
+        session_state: &SessionState,
+    ) -> Result<PubSubConnFuture, RespVec> {
+        let cluster_name = session_state.get_cluster_name();
+        if !self.is_authenticated(&cluster_name, session_state) {
+            return Err(Resp::Error(response::ERR_NOAUTH.to_string().into_bytes()));
+        }
+        // The channels are not in the namespace of the key prefix.
+        if self.manager.get_key_prefix(&cluster_name).is_some() {
+            return Err(Resp::Error(
+                response::ERR_KEY_PREFIX_UNSUPPORTED
+                    .to_string()
+                    .into_bytes(),
+            ));
+        }
+        Ok(self.manager.create_pubsub_conn(&cluster_name))
+    }
+
+    fn get_idle_timeout(&self, session_state: &SessionState) -> Option<Duration> {
+        let cluster_name = session_state
+            .get_client_cluster()
+            .unwrap_or_else(|| session_state.get_cluster_name());
+        let idle_timeout = match self.manager.get_idle_timeout(&cluster_name) {
+            0 => self.config.get_idle_timeout(),
+            idle_timeout => idle_timeout,
+        };
+        if idle_timeout == 0 {
+            return None;
+        }
+        Some(Duration::from_secs(idle_timeout))
+    }
+
+    fn handle_idle_timeout(&self, session_state: &SessionState) {
+        self.client_registry
+            .record_reaped(session_state.get_client_cluster().as_ref());
+    }
+}
+
+fn strip_key_prefix<'a>(
+    reply_fut: CmdReplyFuture<'a>,
+    data_cmd_type: DataCmdType,
+    prefix: Vec<u8>,
+    pattern: Option<BinSafeStr>,
+) -> CmdReplyFuture<'a> {
+    let fut = async move {
+        let (request, packet, slowlog) = reply_fut.await?.into_inner();
+        let resp = namespace::strip_key_prefix(
+            data_cmd_type,
+            packet.into_resp_vec(),
+            &prefix,
+            pattern.as_deref(),
+        );
+        let packet = Box::new(RespPacket::from_resp_vec(resp));
+        Ok(Box::new(TaskReply::new(request, packet, slowlog)))
+    };
+    CmdReplyFuture::Right(Box::pin(fut))
+}
+
+fn gen_namespace_error(err: NamespaceError) -> RespVec {
+    let err_msg = match err {
+        NamespaceError::UnsupportedCmd => response::ERR_KEY_PREFIX_UNSUPPORTED,
+        NamespaceError::UnsupportedKey => response::ERR_KEY_PREFIX_UNSUPPORTED_KEY,
+        NamespaceError::InvalidRequest => "ERR invalid request",
+    };
+    Resp::Error(err_msg.to_string().into_bytes())
+}
+
+fn parse_cluster_name(name: &[u8]) -> Result<ClusterName, &'static str> {
+    let name = str::from_utf8(name).map_err(|_| "Invalid cluster name")?;
+    ClusterName::try_from(name).map_err(|_| "Cluster name is too long")
+}
+
+type HelloAuth = Option<(Vec<u8>, Vec<u8>)>;
+type HelloArgs = (Option<RespVersion>, HelloAuth, Option<String>);
+
+fn parse_hello_args(cmd: &Command) -> Result<HelloArgs, String> {
+    let version = match cmd.get_command_element(1) {
+        None => return Ok((None, None, None)),
+        Some(b"2") => RespVersion::V2,
+        Some(b"3") => RespVersion::V3,
+        Some(_) => return Err(response::ERR_NOPROTO.to_string()),
+    };
+
+    let mut auth = None;
+    let mut client_name = None;
+    let mut index = 2;
+    while let Some(option) = cmd.get_command_element(index) {
+        if option.eq_ignore_ascii_case(b"AUTH") {
+            match (
+                cmd.get_command_element(index + 1),
+                cmd.get_command_element(index + 2),
+            ) {
+                (Some(user), Some(password)) => {
+                    auth = Some((user.to_vec(), password.to_vec()));
+                    index += 3;
+                }
+                _ => return Err("ERR Syntax error in HELLO option 'AUTH'".to_string()),
+            }
+        } else if option.eq_ignore_ascii_case(b"SETNAME") {
+            let name = match cmd.get_command_element(index + 1) {
+                Some(name) => name,
+                None => return Err("ERR Syntax error in HELLO option 'SETNAME'".to_string()),
+            };
+            match parse_client_name(name) {
+                Some(name) => client_name = Some(name),
+                None => return Err(response::ERR_INVALID_CLIENT_NAME.to_string()),
+            }
+            index += 2;
+        } else {
+            return Err(format!(
+                "ERR Syntax error in HELLO option '{}'",
+                String::from_utf8_lossy(option)
+            ));
+        }
+    }
+    Ok((Some(version), auth, client_name))
+}
+
+// Like Redis, the client name could not contain spaces, newlines or special characters.
+fn parse_client_name(name: &[u8]) -> Option<String> {
+    if !name.iter().all(|b| (b'!'..=b'~').contains(b)) {
+        return None;
+    }
+    str::from_utf8(name).ok().map(|name| name.to_string())
+}
+
+// Supports both the old form `CLIENT KILL <addr>`
+// and the new form `CLIENT KILL [ID id] [ADDR addr] [SKIPME yes/no]`.
+fn parse_client_kill_args(cmd: &Command) -> Result<ClientKillFilter, &'static str> {
+    let arg = |index| {
+        cmd.get_command_element(index)
+            .and_then(|arg| str::from_utf8(arg).ok())
+    };
+
+    if cmd.get_command_len() == Some(3) {
+        let addr = arg(2).ok_or("ERR syntax error")?;
+        return Ok(ClientKillFilter {
+            id: None,
+            addr: Some(addr.to_string()),
+            skip_me: false,
+        });
+    }
+
+    let mut filter = ClientKillFilter {
+        id: None,
+        addr: None,
+        skip_me: true,
+    };
+    let mut index = 2;
+    while let Some(option) = arg(index) {
+        let value = arg(index + 1).ok_or("ERR syntax error")?;
+        match option.to_uppercase().as_str() {
+            "ID" => {
+                let id = value
+                    .parse::<usize>()
+                    .map_err(|_| "ERR client-id should be greater than 0")?;
+                filter.id = Some(id);
+            }
+            "ADDR" => filter.addr = Some(value.to_string()),
+            "SKIPME" => match value.to_lowercase().as_str() {
+                "yes" => filter.skip_me = true,
+                "no" => filter.skip_me = false,
+                _ => return Err("ERR syntax error"),
+            },
+            _ => return Err("ERR syntax error"),
+        }
+        index += 2;
+    }
+    if index == 2 {
+        return Err("ERR syntax error");
+    }
+    Ok(filter)
+}
+
+enum FanOutError {
+    ErrReply(BinSafeStr),
+    Cmd(CommandError),
+}
+
+async fn set_fan_out_err(
+    cmd_ctx: CmdCtx,
+    reply_receiver: CmdReplyReceiver,
+    err: FanOutError,
+) -> TaskResult {
+    match err {
+        FanOutError::ErrReply(err) => cmd_ctx.set_resp_result(Ok(Resp::Error(err))),
+        FanOutError::Cmd(err) => cmd_ctx.set_result(Err(err)),
+    }
+    reply_receiver.await
+}
+
+// Merges the lines like `db0:keys=1,expires=0,avg_ttl=0` from all the masters.
+// The average ttl is weighted by the number of keys with expiration.
+fn merge_keyspace(sections: &[BinSafeStr]) -> String {
+    let mut dbs: BTreeMap<String, (u64, u64, u64)> = BTreeMap::new();
+    for section in sections.iter() {
+        for line in String::from_utf8_lossy(section).lines() {
+            let (db, stats) = match line.trim().split_once(':') {
+                Some((db, stats)) if db.starts_with("db") => (db, stats),
+                _ => continue,
+            };
+            let (mut keys, mut expires, mut avg_ttl) = (0, 0, 0);
+            for kv in stats.split(',') {
+                match kv.split_once('=') {
+                    Some(("keys", v)) => keys = v.parse::<u64>().unwrap_or(0),
+                    Some(("expires", v)) => expires = v.parse::<u64>().unwrap_or(0),
+                    Some(("avg_ttl", v)) => avg_ttl = v.parse::<u64>().unwrap_or(0),
+                    _ => (),
+                }
+            }
+            let entry = dbs.entry(db.to_string()).or_insert((0, 0, 0));
+            entry.0 += keys;
+            entry.1 += expires;
+            entry.2 += avg_ttl * expires;
+        }
+    }
+
+    let mut keyspace = "# Keyspace\r\n".to_string();
+    for (db, (keys, expires, ttl_sum)) in dbs.into_iter() {
+        let avg_ttl = ttl_sum.checked_div(expires).unwrap_or(0);
+        keyspace.push_str(&format!(
+            "{}:keys={},expires={},avg_ttl={}\r\n",
+            db, keys, expires, avg_ttl
+        ));
+    }
+    keyspace
+}
+
+// The lowest bits of the SCAN cursor are the node index.
+// The cursor from peers has already been encoded in the same way by themselves.
+const SCAN_NODE_INDEX_BITS: u32 = 10;
+
+fn encode_scan_cursor(node_index: usize, node_cursor: u64) -> Option<u64> {
+    if node_index >= (1 << SCAN_NODE_INDEX_BITS)
+        || node_cursor.leading_zeros() < SCAN_NODE_INDEX_BITS
+    {
+        return None;
+    }
+    Some((node_cursor << SCAN_NODE_INDEX_BITS) | node_index as u64)
+}
+
+fn decode_scan_cursor(cursor: u64) -> (usize, u64) {
+    let node_index = cursor & ((1 << SCAN_NODE_INDEX_BITS) - 1);
+    (node_index as usize, cursor >> SCAN_NODE_INDEX_BITS)
+}
+
+fn gen_scan_reply(cursor: u64, keys: Vec<RespVec>) -> RespVec {
+    Resp::Arr(Array::Arr(vec![
+        Resp::Bulk(BulkStr::Str(cursor.to_string().into_bytes())),
+        Resp::Arr(Array::Arr(keys)),
+    ]))
+}
+
+// Groups the keys by slot and keeps the order of their first appearance.
+fn group_keys_by_slot<'a, It: Iterator<Item = &'a [u8]>>(keys: It) -> Vec<Vec<&'a [u8]>> {
+    let mut groups: Vec<Vec<&'a [u8]>> = vec![];
+    let mut slot_indices: HashMap<usize, usize> = HashMap::new();
+    for key in keys {
+        let slot = generate_slot(key);
+        match slot_indices.get(&slot) {
+            Some(i) => groups[*i].push(key),
+            None => {
+                slot_indices.insert(slot, groups.len());
+                groups.push(vec![key]);
+            }
+        }
+    }
+    groups
+}
+
+fn merge_sets(data_cmd_type: DataCmdType, sets: Vec<Vec<BinSafeStr>>) -> Vec<BinSafeStr> {
+    let mut sets = sets.into_iter();
+    let first = sets.next().unwrap_or_default();
+    match data_cmd_type {
+        DataCmdType::SINTER => {
+            let others: Vec<HashSet<BinSafeStr>> =
+                sets.map(|set| set.into_iter().collect()).collect();
+            first
+                .into_iter()
+                .filter(|member| others.iter().all(|set| set.contains(member)))
+                .collect()
+        }
+        DataCmdType::SDIFF => {
+            let others: HashSet<BinSafeStr> = sets.flatten().collect();
+            first
+                .into_iter()
+                .filter(|member| !others.contains(member))
+                .collect()
+        }
+        _ => {
+            let mut existing = HashSet::new();
+            iter::once(first)
+                .chain(sets)
+                .flatten()
+                .filter(|member| existing.insert(member.clone()))
+                .collect()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gen_cmd(args: &[&str]) -> Command {
+        let resp = Resp::Arr(Array::Arr(
+            args.iter()
+                .map(|arg| Resp::Bulk(BulkStr::Str(arg.as_bytes().to_vec())))
+                .collect(),
+        ));
+        Command::new(Box::new(RespPacket::Data(resp)))
+    }
+
+    #[test]
+    fn test_parse_hello_args() {
+        assert_eq!(
+            parse_hello_args(&gen_cmd(&["HELLO"])),
+            Ok((None, None, None))
+        );
+        assert_eq!(
+            parse_hello_args(&gen_cmd(&["HELLO", "3"])),
+            Ok((Some(RespVersion::V3), None, None))
+        );
+        assert_eq!(
+            parse_hello_args(&gen_cmd(&[
+                "hello", "2", "setname", "myclient", "auth", "user", "pass"
+            ])),
+            Ok((
+                Some(RespVersion::V2),
+                Some((b"user".to_vec(), b"pass".to_vec())),
+                Some("myclient".to_string())
+            ))
+        );
+        assert_eq!(
+            parse_hello_args(&gen_cmd(&["HELLO", "4"])),
+            Err(response::ERR_NOPROTO.to_string())
+        );
+        assert!(parse_hello_args(&gen_cmd(&["HELLO", "3", "AUTH", "user"])).is_err());
+        assert!(parse_hello_args(&gen_cmd(&["HELLO", "3", "SETNAME"])).is_err());
+        assert!(parse_hello_args(&gen_cmd(&["HELLO", "3", "UNKNOWN"])).is_err());
+        assert!(parse_hello_args(&gen_cmd(&["HELLO", "3", "SETNAME", "my client"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_client_kill_args() {
+        assert_eq!(
+            parse_client_kill_args(&gen_cmd(&["CLIENT", "KILL", "127.0.0.1:6000"])),
+            Ok(ClientKillFilter {
+                id: None,
+                addr: Some("127.0.0.1:6000".to_string()),
+                skip_me: false,
+            })
+        );
+        assert_eq!(
+            parse_client_kill_args(&gen_cmd(&["client", "kill", "id", "3", "skipme", "no"])),
+            Ok(ClientKillFilter {
+                id: Some(3),
+                addr: None,
+                skip_me: false,
+            })
+        );
+        assert_eq!(
+            parse_client_kill_args(&gen_cmd(&["CLIENT", "KILL", "ADDR", "127.0.0.1:6000"])),
+            Ok(ClientKillFilter {
+                id: None,
+                addr: Some("127.0.0.1:6000".to_string()),
+                skip_me: true,
+            })
+        );
+        assert!(parse_client_kill_args(&gen_cmd(&["CLIENT", "KILL"])).is_err());
+        assert!(parse_client_kill_args(&gen_cmd(&["CLIENT", "KILL", "ID", "x"])).is_err());
+        assert!(parse_client_kill_args(&gen_cmd(&["CLIENT", "KILL", "ID", "1", "ADDR"])).is_err());
+        assert!(parse_client_kill_args(&gen_cmd(&["CLIENT", "KILL", "TYPE", "normal"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_client_name() {
+        assert_eq!(parse_client_name(b"myclient"), Some("myclient".to_string()));
+        assert_eq!(parse_client_name(b""), Some(String::new()));
+        assert_eq!(parse_client_name(b"my client"), None);
+        assert_eq!(parse_client_name(b"my\nclient"), None);
+    }
+
+    #[test]
+    fn test_group_keys_by_slot() {
+        let keys: Vec<&[u8]> = vec![b"{a}1", b"b", b"{a}2", b"c", b"b"];
+        let groups = group_keys_by_slot(keys.into_iter());
+        let expected: Vec<Vec<&[u8]>> = vec![vec![b"{a}1", b"{a}2"], vec![b"b", b"b"], vec![b"c"]];
+        assert_eq!(groups, expected);
+    }
+
+    #[test]
+    fn test_merge_sets() {
+        let gen_sets = || {
+            vec![
+                vec![b"a".to_vec(), b"b".to_vec(), b"c".to_vec()],
+                vec![b"b".to_vec(), b"c".to_vec(), b"d".to_vec()],
+                vec![b"c".to_vec(), b"e".to_vec()],
+            ]
+        };
+        assert_eq!(
+            merge_sets(DataCmdType::SUNION, gen_sets()),
+            vec![
+                b"a".to_vec(),
+                b"b".to_vec(),
+                b"c".to_vec(),
+                b"d".to_vec(),
+                b"e".to_vec()
+            ]
+        );
+        assert_eq!(
+            merge_sets(DataCmdType::SINTER, gen_sets()),
+            vec![b"c".to_vec()]
+        );
+        assert_eq!(
+            merge_sets(DataCmdType::SDIFF, gen_sets()),
+            vec![b"a".to_vec()]
+        );
+        assert!(merge_sets(DataCmdType::SINTER, vec![]).is_empty());
+    }
+
+    #[test]
+    fn test_merge_keyspace() {
+        let sections = vec![
+            b"# Keyspace\r\ndb0:keys=10,expires=2,avg_ttl=100\r\n".to_vec(),
+            b"# Keyspace\r\ndb0:keys=5,expires=2,avg_ttl=300\r\ndb1:keys=1,expires=0,avg_ttl=0\r\n"
+                .to_vec(),
+            b"# Keyspace\r\n".to_vec(),
+        ];
+        assert_eq!(
+            merge_keyspace(&sections),
+            "# Keyspace\r\ndb0:keys=15,expires=4,avg_ttl=200\r\ndb1:keys=1,expires=0,avg_ttl=0\r\n"
+        );
+        assert_eq!(merge_keyspace(&[]), "# Keyspace\r\n");
+    }
+
+    #[test]
+    fn test_scan_cursor() {
+        assert_eq!(decode_scan_cursor(0), (0, 0));
+        let cursor = encode_scan_cursor(3, 233).unwrap();
+        assert_eq!(decode_scan_cursor(cursor), (3, 233));
+        let cursor = encode_scan_cursor(1023, 0).unwrap();
+        assert_eq!(decode_scan_cursor(cursor), (1023, 0));
+        assert!(encode_scan_cursor(1024, 0).is_none());
+        assert!(encode_scan_cursor(0, u64::MAX).is_none());
+
+        // Nested cursor from peers
+        let peer_cursor = encode_scan_cursor(2, 666).unwrap();
+        let cursor = encode_scan_cursor(5, peer_cursor).unwrap();
+        let (index, c) = decode_scan_cursor(cursor);
+        assert_eq!(index, 5);
+        assert_eq!(decode_scan_cursor(c), (2, 666));
+    }
 }
@@ -0,0 +1,337 @@
+use crate::common::cluster::ClusterName;
+use crate::protocol::{Array, BinSafeStr, BulkStr, Resp, RespVec};
+use dashmap::DashMap;
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+// The number of keys tracked for each cluster.
+const HOTKEY_CAPACITY: usize = 1024;
+// Keys longer than this are truncated to bound the memory usage.
+const MAX_KEY_LENGTH: usize = 256;
+pub const DEFAULT_HOTKEY_COUNT: usize = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum KeyAccess {
+    Read,
+    Write,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct HotKey {
+    pub cluster_name: ClusterName,
+    pub key: BinSafeStr,
+    // Estimated sampled count which could be larger than `read + write`
+    // because it includes the count of the evicted key.
+    pub count: u64,
+    pub read: u64,
+    pub write: u64,
+}
+
+#[derive(Debug, Default)]
+struct KeyCounter {
+    count: u64,
+    read: u64,
+    write: u64,
+}
+
+// The Space-Saving algorithm.
+// When it's full, the key with the minimum count is replaced by the new key
+// which inherits the minimum count so that the count is never underestimated.
+struct SpaceSaving {
+    capacity: usize,
+    // Key to its position in `heap`.
+    indices: HashMap<BinSafeStr, usize>,
+    // A min-heap ordered by the count so that the key to be replaced is always the first one.
+    heap: Vec<(BinSafeStr, KeyCounter)>,
+}
+
+impl SpaceSaving {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            indices: HashMap::new(),
+            heap: Vec::new(),
+        }
+    }
+
+    fn add(&mut self, key: &[u8], access: KeyAccess) {
+        let pos = match self.indices.get(key) {
+            Some(pos) => *pos,
+            None if self.heap.len() < self.capacity => {
+                let pos = self.heap.len();
+                self.heap.push((key.to_vec(), KeyCounter::default()));
+                self.indices.insert(key.to_vec(), pos);
+                self.sift_up(pos)
+            }
+            None => {
+                let (min_key, min_counter) = match self.heap.first_mut() {
+                    Some(entry) => entry,
+                    None => return,
+                };
+                self.indices.remove(min_key.as_slice());
+                *min_key = key.to_vec();
+                *min_counter = KeyCounter {
+                    count: min_counter.count,
+                    ..Default::default()
+                };
+                self.indices.insert(key.to_vec(), 0);
+                0
+            }
+        };
+
+        let (_, counter) = &mut self.heap[pos];
+        counter.count += 1;
+        match access {
+            KeyAccess::Read => counter.read += 1,
+            KeyAccess::Write => counter.write += 1,
+        }
+        // The count only increases.
+        self.sift_down(pos);
+    }
+
+    fn sift_up(&mut self, mut pos: usize) -> usize {
+        while pos > 0 {
+            let parent = (pos - 1) / 2;
+            if self.heap[parent].1.count <= self.heap[pos].1.count {
+                break;
+            }
+            self.swap(parent, pos);
+            pos = parent;
+        }
+        pos
+    }
+
+    fn sift_down(&mut self, mut pos: usize) {
+        loop {
+            let mut min_pos = pos;
+            for child in [2 * pos + 1, 2 * pos + 2].iter() {
+                if let Some((_, counter)) = self.heap.get(*child) {
+                    if counter.count < self.heap[min_pos].1.count {
+                        min_pos = *child;
+                    }
+                }
+            }
+            if min_pos == pos {
+                return;
+            }
+            self.swap(pos, min_pos);
+            pos = min_pos;
+        }
+    }
+
+    fn swap(&mut self, i: usize, j: usize) {
+        self.heap.swap(i, j);
+        for pos in [i, j].iter() {
+            if let Some(index) = self.indices.get_mut(self.heap[*pos].0.as_slice()) {
+                *index = *pos;
+            }
+        }
+    }
+
+    fn top(&self, count: usize) -> Vec<(BinSafeStr, &KeyCounter)> {
+        let mut keys: Vec<_> = self
+            .heap
+            .iter()
+            .map(|(key, counter)| (key.clone(), counter))
+            .collect();
+        keys.sort_unstable_by(|(k1, c1), (k2, c2)| c2.count.cmp(&c1.count).then(k1.cmp(k2)));
+        keys.truncate(count);
+        keys
+    }
+}
+
+pub struct HotKeyTracker {
+    clusters: DashMap<ClusterName, Mutex<SpaceSaving>>,
+    sample_count: AtomicU64,
+}
+
+impl Default for HotKeyTracker {
+    fn default() -> Self {
+        Self {
+            clusters: DashMap::new(),
+            sample_count: AtomicU64::new(0),
+        }
+    }
+}
+
+impl HotKeyTracker {
+    // `sample_rate` 0 disables the tracking.
+    pub fn record(
+        &self,
+        cluster_name: &ClusterName,
+        key: &[u8],
+        access: KeyAccess,
+        sample_rate: u64,
+    ) {
+        if sample_rate == 0 {
+            return;
+        }
+        let count = self.sample_count.fetch_add(1, Ordering::Relaxed);
+        if count.checked_rem(sample_rate) != Some(0) {
+            return;
+        }
+
+        let key = key.get(..MAX_KEY_LENGTH).unwrap_or(key);
+        if let Some(tracker) = self.clusters.get(cluster_name) {
+            tracker
+                .lock()
+                .expect("HotKeyTracker::record")
+                .add(key, access);
+            return;
+        }
+        self.clusters
+            .entry(cluster_name.clone())
+            .or_insert_with(|| Mutex::new(SpaceSaving::new(HOTKEY_CAPACITY)))
+            .lock()
+            .expect("HotKeyTracker::record")
+            .add(key, access);
+    }
+
+    // Returns the top keys of the clusters accepted by `filter`.
+    pub fn get_hot_keys<F>(&self, filter: F, count: usize) -> Vec<HotKey>
+    where
+        F: Fn(&ClusterName) -> bool,
+    {
+        let mut hot_keys = vec![];
+        for item in self.clusters.iter() {
+            if !filter(item.key()) {
+                continue;
+            }
+            let tracker = item.value().lock().expect("HotKeyTracker::get_hot_keys");
+            hot_keys.extend(tracker.top(count).into_iter().map(|(key, counter)| HotKey {
+                cluster_name: item.key().clone(),
+                key,
+                count: counter.count,
+                read: counter.read,
+                write: counter.write,
+            }));
+        }
+        hot_keys.sort_unstable_by_key(|hot_key| Reverse(hot_key.count));
+        hot_keys.truncate(count);
+        hot_keys
+    }
+
+    pub fn retain_clusters(&self, clusters: &HashSet<ClusterName>) {
+        self.clusters
+            .retain(|cluster_name, _| clusters.contains(cluster_name));
+    }
+}
+
+pub fn hot_keys_to_resp(hot_keys: Vec<HotKey>) -> RespVec {
+    let elements = hot_keys
+        .into_iter()
+        .map(|hot_key| {
+            Resp::Arr(Array::Arr(vec![
+                Resp::Bulk(BulkStr::Str(hot_key.cluster_name.to_string().into_bytes())),
+                Resp::Bulk(BulkStr::Str(hot_key.key)),
+                Resp::Integer(hot_key.count.to_string().into_bytes()),
+                Resp::Integer(hot_key.read.to_string().into_bytes()),
+                Resp::Integer(hot_key.write.to_string().into_bytes()),
+            ]))
+        })
+        .collect();
+    Resp::Arr(Array::Arr(elements))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryFrom;
+
+    #[test]
+    fn test_space_saving() {
+        let mut s = SpaceSaving::new(2);
+        s.add(b"a", KeyAccess::Read);
+        s.add(b"a", KeyAccess::Write);
+        s.add(b"a", KeyAccess::Read);
+        s.add(b"b", KeyAccess::Read);
+        s.add(b"c", KeyAccess::Write);
+
+        let top = s.top(10);
+        assert_eq!(top.len(), 2);
+        assert_eq!(top[0].0, b"a".to_vec());
+        assert_eq!(top[0].1.count, 3);
+        assert_eq!(top[0].1.read, 2);
+        assert_eq!(top[0].1.write, 1);
+        // "c" replaces "b" and inherits its count.
+        assert_eq!(top[1].0, b"c".to_vec());
+        assert_eq!(top[1].1.count, 2);
+        assert_eq!(top[1].1.read, 0);
+        assert_eq!(top[1].1.write, 1);
+    }
+
+    #[test]
+    fn test_space_saving_heap() {
+        let mut s = SpaceSaving::new(4);
+        let counts: &[(&[u8], usize)] = &[(b"a", 5), (b"b", 2), (b"c", 7), (b"d", 3)];
+        for (key, n) in counts.iter() {
+            for _ in 0..*n {
+                s.add(key, KeyAccess::Read);
+            }
+        }
+        for (pos, (key, counter)) in s.heap.iter().enumerate() {
+            assert_eq!(s.indices.get(key), Some(&pos));
+            if pos > 0 {
+                assert!(s.heap[(pos - 1) / 2].1.count <= counter.count);
+            }
+        }
+        assert_eq!(s.heap[0].0, b"b".to_vec());
+
+        // "e" replaces "b" which has the minimum count.
+        s.add(b"e", KeyAccess::Write);
+        assert!(!s.indices.contains_key(b"b".as_ref()));
+        let top = s.top(10);
+        let keys: Vec<BinSafeStr> = top.iter().map(|(key, _)| key.clone()).collect();
+        assert_eq!(
+            keys,
+            vec![b"c".to_vec(), b"a".to_vec(), b"d".to_vec(), b"e".to_vec()]
+        );
+        assert_eq!(top[2].1.count, 3);
+        assert_eq!(top[3].1.count, 3);
+        assert_eq!(top[3].1.write, 1);
+        assert_eq!(s.heap[0].1.count, 3);
+    }
+
+    #[test]
+    fn test_hot_key_tracker() {
+        let tracker = HotKeyTracker::default();
+        let cluster1 = ClusterName::try_from("cluster1").unwrap();
+        let cluster2 = ClusterName::try_from("cluster2").unwrap();
+        for _ in 0..3 {
+            tracker.record(&cluster1, b"key1", KeyAccess::Read, 1);
+        }
+        tracker.record(&cluster2, b"key2", KeyAccess::Write, 1);
+        tracker.record(&cluster2, b"key3", KeyAccess::Write, 0);
+
+        let hot_keys = tracker.get_hot_keys(|_| true, 10);
+        assert_eq!(hot_keys.len(), 2);
+        assert_eq!(hot_keys[0].cluster_name, cluster1);
+        assert_eq!(hot_keys[0].count, 3);
+        assert_eq!(hot_keys[1].key, b"key2".to_vec());
+
+        let hot_keys = tracker.get_hot_keys(|cluster_name| cluster_name == &cluster2, 10);
+        assert_eq!(hot_keys.len(), 1);
+        assert_eq!(hot_keys[0].write, 1);
+
+        assert_eq!(tracker.get_hot_keys(|_| true, 1).len(), 1);
+
+        let clusters = vec![cluster2.clone()].into_iter().collect();
+        tracker.retain_clusters(&clusters);
+        let hot_keys = tracker.get_hot_keys(|_| true, 10);
+        assert_eq!(hot_keys.len(), 1);
+        assert_eq!(hot_keys[0].cluster_name, cluster2);
+    }
+
+    #[test]
+    fn test_hot_key_sample_rate() {
+        let tracker = HotKeyTracker::default();
+        let cluster_name = ClusterName::try_from("mycluster").unwrap();
+        for _ in 0..10 {
+            tracker.record(&cluster_name, b"key", KeyAccess::Read, 5);
+        }
+        let hot_keys = tracker.get_hot_keys(|_| true, 10);
+        assert_eq!(hot_keys[0].count, 2);
+    }
+}
@@ -1,30 +1,45 @@
-use super::backend::{BackendError, CmdTask, ConnFactory, IntoTask};
+use super::backend::{
+    BackendError, BackendNode, CmdTask, CmdTaskFactory, CmdTaskResult, ConnFactory, IntoTask,
+};
 use super::blocking::{
     gen_basic_blocking_sender_factory, gen_blocking_sender_factory, BasicBlockingSenderFactory,
-    BlockingBackendSenderFactory, BlockingCmdTaskSender, BlockingMap, CounterTask,
+    BlockingBackendSenderFactory, BlockingCmdTaskSender, BlockingHintTask, BlockingMap,
+    CounterTask,
 };
+use super::breaker::{BreakerConfig, BreakerMap};
 use super::cluster::{ClusterBackendMap, ClusterMetaError, ClusterSendError, ClusterTag};
-use super::reply::{DecompressCommitHandlerFactory, ReplyCommitHandlerFactory};
+use super::metrics::{BackendMetrics, MigrationMetrics};
+use super::mirror::{MirrorMap, MirrorStats};
+use super::quota::{QuotaLimit, QuotaMap};
+use super::replica::ReplicaReadMap;
+use super::reply::{DecompressCommitHandlerFactory, ReplyCommitHandler, ReplyCommitHandlerFactory};
 use super::sender::{
     gen_migration_sender_factory, gen_sender_factory, BackendSenderFactory, CmdTaskSender,
     CmdTaskSenderFactory, MigrationBackendSenderFactory,
 };
 use super::service::ServerProxyConfig;
-use super::session::{CmdCtx, CmdCtxFactory};
+use super::session::{CmdCtx, CmdCtxFactory, PubSubConnFuture, ReadMode};
 use super::slowlog::TaskEvent;
+use super::transaction::DedicatedBackend;
 use crate::common::cluster::{ClusterName, MigrationTaskMeta, SlotRangeTag};
-use crate::common::config::ClusterConfig;
+use crate::common::config::{ClusterConfig, MirrorTarget, ReadPreference};
 use crate::common::proto::ProxyClusterMeta;
 use crate::common::response;
 use crate::common::track::TrackedFutureRegistry;
+use crate::common::utils::{array_append_front, generate_slot, resolve_first_address};
 use crate::migration::manager::{MigrationManager, MigrationMap, SwitchError};
 use crate::migration::task::MgrSubCmd;
 use crate::migration::task::SwitchArg;
-use crate::protocol::{Array, BulkStr, RedisClientFactory, Resp, RespPacket, RespVec};
+use crate::protocol::{Array, BulkStr, RedisClientFactory, Resp, RespPacket, RespVec, VFunctor};
 use crate::replication::manager::ReplicatorManager;
 use crate::replication::replicator::ReplicatorMeta;
+use crate::MAX_REDIRECTIONS;
 use arc_swap::{ArcSwap, Lease};
+use dashmap::DashMap;
+use futures::{future, Future};
+use std::collections::{HashMap, HashSet};
 use std::num::NonZeroUsize;
+use std::pin::Pin;
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{Arc, Mutex};
 
@@ -69,11 +84,15 @@ type SenderFactory<C> = BlockingBackendSenderFactory<
 >;
 
 type PeerSenderFactory<C> = BackendSenderFactory<ReplyCommitHandlerFactory, C>;
+type PeerSender<C> = <PeerSenderFactory<C> as CmdTaskSenderFactory>::Sender;
 
 type MigrationSenderFactory<C> =
     MigrationBackendSenderFactory<DecompressCommitHandlerFactory<CmdCtx, C>, C>;
 type MigrationProxySenderFactory<C> = MigrationBackendSenderFactory<ReplyCommitHandlerFactory, C>;
 
+type ReplicaSenderFactory<C> = BackendSenderFactory<DecompressCommitHandlerFactory<CmdCtx, C>, C>;
+type ReplicaMap<C> = ReplicaReadMap<<ReplicaSenderFactory<C> as CmdTaskSenderFactory>::Sender>;
+
 type ProxyMetaMap<C> = MetaMap<
     <SenderFactory<C> as CmdTaskSenderFactory>::Sender,
     <PeerSenderFactory<C> as CmdTaskSenderFactory>::Sender,
@@ -88,8 +107,19 @@ pub struct MetaManager<F: RedisClientFactory, C: ConnFactory<Pkt = RespPacket>>
     // inside meta_map.
     meta_map: SharedMetaMap<C>,
     epoch: AtomicU64,
-    lock: Mutex<()>, // This is the write lock for `epoch`, `cluster`, and `task`.
+    lock: Mutex<()>, // This is the write lock for `epoch`, `cluster`, `task`, and `replica_map`.
     replicator_manager: ReplicatorManager<F>,
+    // The replicas of the local masters used by the read commands.
+    replica_map: ArcSwap<ReplicaMap<C>>,
+    replica_sender_factory: ReplicaSenderFactory<C>,
+    client_factory: Arc<F>,
+    quota_map: QuotaMap,
+    mirror_map: MirrorMap,
+    // The senders of the `mirror_to` addresses.
+    mirror_senders: DashMap<String, PeerSender<C>>,
+    // The connections to the local nodes shared by the transactions without WATCH.
+    exec_backends: DashMap<String, DedicatedBackend>,
+    breaker_map: Arc<BreakerMap>,
     migration_manager: MigrationManager<
         F,
         MigrationSenderFactory<C>,
@@ -100,6 +130,8 @@ pub struct MetaManager<F: RedisClientFactory, C: ConnFactory<Pkt = RespPacket>>
     peer_sender_factory: PeerSenderFactory<C>,
     blocking_map: Arc<BlockingMap<BasicSenderFactory<C>, BlockingTaskRetrySender<C>>>,
     cluster_config: ClusterConfig,
+    conn_factory: Arc<C>,
+    future_registry: Arc<TrackedFutureRegistry>,
 }
 
 impl<F: RedisClientFactory, C: ConnFactory<Pkt = RespPacket>> MetaManager<F, C> {
@@ -112,15 +144,17 @@ impl<F: RedisClientFactory, C: ConnFactory<Pkt = RespPacket>> MetaManager<F, C>
         future_registry: Arc<TrackedFutureRegistry>,
     ) -> Self {
         let reply_handler_factory = Arc::new(DecompressCommitHandlerFactory::new(meta_map.clone()));
+        let breaker_map = Arc::new(BreakerMap::new(BreakerConfig::from_config(&config)));
         let blocking_task_sender = Arc::new(BlockingTaskRetrySender::new(
             meta_map.clone(),
-            config.max_redirections,
+            config.clone(),
         ));
         let basic_sender_factory = gen_basic_blocking_sender_factory(
             config.clone(),
             reply_handler_factory,
             conn_factory.clone(),
             future_registry.clone(),
+            breaker_map.clone(),
         );
         let blocking_map = Arc::new(BlockingMap::new(basic_sender_factory, blocking_task_sender));
         let sender_factory = gen_blocking_sender_factory(blocking_map.clone());
@@ -130,19 +164,29 @@ impl<F: RedisClientFactory, C: ConnFactory<Pkt = RespPacket>> MetaManager<F, C>
             reply_commit_handler_factory,
             conn_factory.clone(),
             future_registry.clone(),
+            breaker_map.clone(),
         );
         let migration_sender_factory = Arc::new(gen_migration_sender_factory(
             config.clone(),
             Arc::new(DecompressCommitHandlerFactory::new(meta_map.clone())),
             conn_factory.clone(),
             future_registry.clone(),
+            breaker_map.clone(),
         ));
         let migration_proxy_sender_factory = Arc::new(gen_migration_sender_factory(
             config.clone(),
             Arc::new(ReplyCommitHandlerFactory::default()),
-            conn_factory,
+            conn_factory.clone(),
             future_registry.clone(),
+            breaker_map.clone(),
         ));
+        let replica_sender_factory = gen_sender_factory(
+            config.clone(),
+            Arc::new(DecompressCommitHandlerFactory::new(meta_map.clone())),
+            conn_factory.clone(),
+            future_registry.clone(),
+            breaker_map.clone(),
+        );
         let cmd_ctx_factory = Arc::new(CmdCtxFactory::default());
         let config_clone = config.clone();
         let cluster_config_clone = cluster_config.clone();
@@ -155,6 +199,14 @@ impl<F: RedisClientFactory, C: ConnFactory<Pkt = RespPacket>> MetaManager<F, C>
                 client_factory.clone(),
                 future_registry.clone(),
             ),
+            replica_map: ArcSwap::new(Arc::new(ReplicaReadMap::empty())),
+            replica_sender_factory,
+            client_factory: client_factory.clone(),
+            quota_map: QuotaMap::default(),
+            mirror_map: MirrorMap::default(),
+            mirror_senders: DashMap::new(),
+            exec_backends: DashMap::new(),
+            breaker_map,
             migration_manager: MigrationManager::new(
                 config_clone,
                 cluster_config_clone,
@@ -162,12 +214,14 @@ impl<F: RedisClientFactory, C: ConnFactory<Pkt = RespPacket>> MetaManager<F, C>
                 migration_sender_factory,
                 migration_proxy_sender_factory,
                 cmd_ctx_factory,
-                future_registry,
+                future_registry.clone(),
             ),
             sender_factory,
             peer_sender_factory,
             blocking_map,
             cluster_config,
+            conn_factory,
+            future_registry,
         }
     }
 
@@ -234,6 +288,43 @@ impl<F: RedisClientFactory, C: ConnFactory<Pkt = RespPacket>> MetaManager<F, C>
             // Should go after the meta_map.store above
             self.epoch.store(cluster_meta.get_epoch(), Ordering::SeqCst);
 
+            // Rebuild the mirror senders and stats so that they only cover the current `mirror_to`.
+            let mirror_targets: HashMap<ClusterName, MirrorTarget> = cluster_meta
+                .get_configs()
+                .get_map()
+                .iter()
+                .filter_map(|(cluster_name, config)| {
+                    config
+                        .get_mirror_target()
+                        .map(|target| (cluster_name.clone(), target))
+                })
+                .collect();
+            let mirror_addresses: HashSet<String> = mirror_targets
+                .values()
+                .filter_map(|target| match target {
+                    MirrorTarget::Address(address) => Some(address.clone()),
+                    MirrorTarget::Cluster(_) => None,
+                })
+                .collect();
+            self.mirror_senders
+                .retain(|address, _| mirror_addresses.contains(address));
+            for address in mirror_addresses.into_iter() {
+                self.mirror_senders
+                    .entry(address.clone())
+                    .or_insert_with(|| peer_sender_factory.create(address));
+            }
+            self.mirror_map
+                .retain_clusters(|cluster_name| mirror_targets.contains_key(cluster_name));
+
+            let local_addresses: HashSet<&String> = cluster_meta
+                .get_local()
+                .get_map()
+                .values()
+                .flat_map(|nodes| nodes.keys())
+                .collect();
+            self.exec_backends
+                .retain(|address, _| local_addresses.contains(address));
+
             self.migration_manager.run_tasks(new_tasks);
         };
 
@@ -241,18 +332,38 @@ impl<F: RedisClientFactory, C: ConnFactory<Pkt = RespPacket>> MetaManager<F, C>
     }
 
     pub fn update_replicators(&self, meta: ReplicatorMeta) -> Result<(), ClusterMetaError> {
-        self.replicator_manager.update_replicators(meta)
+        let _guard = self.lock.lock().expect("MetaManager::update_replicators");
+
+        self.replicator_manager.update_replicators(meta)?;
+
+        let (master_metadata, _) = self.replicator_manager.get_metadata();
+        let replica_map = ReplicaReadMap::from_master_metadata(
+            &self.replica_map.load(),
+            master_metadata,
+            &self.replica_sender_factory,
+            self.client_factory.clone(),
+            self.future_registry.clone(),
+        );
+        self.replica_map.store(Arc::new(replica_map));
+        Ok(())
     }
 
     pub fn get_replication_info(&self) -> RespVec {
         self.replicator_manager.get_metadata_report()
     }
 
+    pub fn is_migration_at_safe_point(&self) -> bool {
+        self.meta_map.load().migration_map.is_at_safe_point()
+    }
+
     pub fn info(&self) -> RespVec {
         let meta_map = self.meta_map.load();
         let cluster_info = meta_map.cluster_map.info();
         let mgr_info = meta_map.migration_map.info();
         let repl_info = self.replicator_manager.get_metadata_report();
+        let quota_info = self.quota_map.info();
+        let mirror_info = self.mirror_map.info();
+        let breaker_info = self.breaker_map.info();
         Resp::Arr(Array::Arr(vec![
             Resp::Bulk(BulkStr::Str(b"Cluster".to_vec())),
             cluster_info,
@@ -260,6 +371,12 @@ impl<F: RedisClientFactory, C: ConnFactory<Pkt = RespPacket>> MetaManager<F, C>
             repl_info,
             Resp::Bulk(BulkStr::Str(b"Migration".to_vec())),
             mgr_info,
+            Resp::Bulk(BulkStr::Str(b"Throttle".to_vec())),
+            quota_info,
+            Resp::Bulk(BulkStr::Str(b"Mirror".to_vec())),
+            mirror_info,
+            Resp::Bulk(BulkStr::Str(b"CircuitBreaker".to_vec())),
+            breaker_info,
         ]))
     }
 
@@ -295,15 +412,81 @@ impl<F: RedisClientFactory, C: ConnFactory<Pkt = RespPacket>> MetaManager<F, C>
         )
     }
 
+    pub fn get_backend_metrics(&self) -> Vec<BackendMetrics> {
+        self.meta_map.lease().cluster_map.get_backend_metrics()
+    }
+
+    pub fn get_migration_metrics(&self) -> Vec<MigrationMetrics> {
+        self.meta_map.lease().migration_map.get_migration_metrics()
+    }
+
     pub fn get_finished_migration_tasks(&self) -> Vec<MigrationTaskMeta> {
         self.meta_map.load().migration_map.get_finished_tasks()
     }
 
     pub fn send(&self, cmd_ctx: CmdCtx) {
-        let max_redirections = self.config.max_redirections;
+        let cmd_ctx = match self.send_to_replica(cmd_ctx) {
+            Some(cmd_ctx) => cmd_ctx,
+            None => return,
+        };
+        let max_redirections = self.config.get_max_redirections();
         send_cmd_ctx(&self.meta_map, cmd_ctx, max_redirections);
     }
 
+    // Returns the command back if it should be sent to the master.
+    fn send_to_replica(&self, cmd_ctx: CmdCtx) -> Option<CmdCtx> {
+        if !cmd_ctx.get_cmd().is_read_only() {
+            return Some(cmd_ctx);
+        }
+        let slot = match cmd_ctx.get_slot() {
+            Some(slot) => slot,
+            None => return Some(cmd_ctx),
+        };
+
+        let meta_map = self.meta_map.lease();
+        let cluster_name = cmd_ctx.get_cluster_name();
+        let cluster_preference = meta_map
+            .cluster_map
+            .get_config(cluster_name)
+            .map(|config| config.read_preference)
+            .unwrap_or_default();
+        let preference = match (cmd_ctx.get_read_mode(), cluster_preference) {
+            (ReadMode::ReadWrite, _) => ReadPreference::Master,
+            (ReadMode::ReadOnly, ReadPreference::Master) => ReadPreference::PreferReplica,
+            (_, preference) => preference,
+        };
+        if preference == ReadPreference::Master {
+            return Some(cmd_ctx);
+        }
+        // The data of the migrating slots are only consistent on the masters.
+        if meta_map.migration_map.is_migrating(cluster_name, slot) {
+            return Some(cmd_ctx);
+        }
+        let master_address = match meta_map
+            .cluster_map
+            .get_local_node_address(cluster_name, slot)
+        {
+            Ok(address) => address,
+            // Let the master path reply the redirection.
+            Err(_) => return Some(cmd_ctx),
+        };
+
+        let replica_map = self.replica_map.lease();
+        let cmd_ctx = match replica_map.send(cmd_ctx, &master_address) {
+            Ok(()) => return None,
+            Err(cmd_ctx) => cmd_ctx,
+        };
+        match preference {
+            ReadPreference::ReplicaOnly => {
+                cmd_ctx.set_resp_result(Ok(Resp::Error(
+                    response::ERR_NO_AVAILABLE_REPLICA.to_string().into_bytes(),
+                )));
+                None
+            }
+            _ => Some(cmd_ctx),
+        }
+    }
+
     pub fn send_sync_task(&self, cmd_ctx: CmdCtx) {
         let meta_map = self.meta_map.load();
         if let Err(err) = meta_map.migration_map.send_sync_task(cmd_ctx) {
@@ -344,9 +527,359 @@ impl<F: RedisClientFactory, C: ConnFactory<Pkt = RespPacket>> MetaManager<F, C>
         cmd_ctx
     }
 
+    pub fn cluster_exists(&self, cluster_name: &ClusterName) -> bool {
+        self.meta_map
+            .lease()
+            .cluster_map
+            .cluster_exists(cluster_name)
+    }
+
+    pub fn all_slots_local<'a, It: Iterator<Item = &'a [u8]>>(
+        &self,
+        cluster_name: &ClusterName,
+        mut keys: It,
+    ) -> bool {
+        let meta_map = self.meta_map.lease();
+        keys.all(|key| {
+            meta_map
+                .cluster_map
+                .is_local_slot(cluster_name, generate_slot(key))
+        })
+    }
+
+    // Fails closed for the clusters without the config
+    // since their passwords can't be checked.
+    // The clusters not existing at all will get the `cluster not found` error instead.
+    pub fn requires_auth(&self, cluster_name: &ClusterName) -> bool {
+        let meta_map = self.meta_map.lease();
+        match meta_map.cluster_map.get_auth_config(cluster_name) {
+            Some(config) => config.requires_auth(),
+            None => meta_map.cluster_map.cluster_exists(cluster_name),
+        }
+    }
+
+    pub fn check_password(
+        &self,
+        cluster_name: &ClusterName,
+        user: Option<&[u8]>,
+        password: &[u8],
+    ) -> bool {
+        self.meta_map
+            .lease()
+            .cluster_map
+            .get_auth_config(cluster_name)
+            .map(|config| config.check_password(user, password))
+            .unwrap_or(false)
+    }
+
+    pub fn acquire_quota(&self, cmd_ctx: &CmdCtx) -> Result<(), QuotaLimit> {
+        let cluster_name = cmd_ctx.get_cluster_name();
+        let (max_ops_per_sec, max_bytes_per_sec) =
+            match self.meta_map.lease().cluster_map.get_config(cluster_name) {
+                Some(config) => (config.max_ops_per_sec, config.max_bytes_per_sec),
+                None => return Ok(()),
+            };
+        self.quota_map
+            .acquire(cluster_name, max_ops_per_sec, max_bytes_per_sec, || {
+                cmd_ctx.get_cmd().get_data_size() as u64
+            })
+    }
+
+    // Returns false if the command is denied by the cluster config.
+    // The renamed command will be changed back to the original one.
+    pub fn filter_cmd(&self, cmd_ctx: &mut CmdCtx) -> bool {
+        self.filter_cmd_impl(cmd_ctx, false)
+    }
+
+    pub fn filter_forwarded_cmd(&self, cmd_ctx: &mut CmdCtx) -> bool {
+        self.filter_cmd_impl(cmd_ctx, true)
+    }
+
+    fn filter_cmd_impl(&self, cmd_ctx: &mut CmdCtx, forwarded: bool) -> bool {
+        let meta_map = self.meta_map.lease();
+        let config = match meta_map.cluster_map.get_config(cmd_ctx.get_cluster_name()) {
+            Some(config) if config.has_command_filter() => config,
+            _ => return true,
+        };
+        let original = {
+            let cmd_name = match cmd_ctx.get_cmd().get_command_name() {
+                Some(cmd_name) => cmd_name,
+                None => return config.allowed_commands.is_empty(),
+            };
+            let filtered = if forwarded {
+                config.filter_forwarded_command(cmd_name)
+            } else {
+                config.filter_command(cmd_name)
+            };
+            match filtered {
+                None => return false,
+                Some(original) if original == cmd_name => return true,
+                Some(original) => original.as_bytes().to_vec(),
+            }
+        };
+        cmd_ctx.change_cmd_name(original)
+    }
+
+    pub fn get_key_prefix(&self, cluster_name: &ClusterName) -> Option<Vec<u8>> {
+        self.meta_map
+            .lease()
+            .cluster_map
+            .get_config(cluster_name)
+            .filter(|config| !config.key_prefix.is_empty())
+            .map(|config| config.key_prefix.as_bytes().to_vec())
+    }
+
+    pub fn is_audit_log_enabled(&self, cluster_name: &ClusterName) -> bool {
+        self.meta_map
+            .lease()
+            .cluster_map
+            .get_config(cluster_name)
+            .map(|config| config.audit_log)
+            .unwrap_or(false)
+    }
+
+    // Returns the `mirror_to` of the cluster if the command is sampled.
+    pub fn sample_mirror_target(&self, cluster_name: &ClusterName) -> Option<MirrorTarget> {
+        let (target, sample_rate) = {
+            let meta_map = self.meta_map.lease();
+            let config = meta_map.cluster_map.get_config(cluster_name)?;
+            (config.get_mirror_target()?, config.mirror_sample_rate)
+        };
+        // Mirroring to itself will double the writes.
+        if target == MirrorTarget::Cluster(cluster_name.clone()) {
+            return None;
+        }
+        if !self.mirror_map.sample(sample_rate) {
+            return None;
+        }
+        Some(target)
+    }
+
+    pub fn get_mirror_stats(&self, cluster_name: &ClusterName) -> Arc<MirrorStats> {
+        self.mirror_map.get_stats(cluster_name)
+    }
+
+    pub fn send_to_mirror_address(&self, cmd_ctx: CmdCtx, address: String) {
+        let res = match self.mirror_senders.get(&address) {
+            Some(sender) => sender.send(cmd_ctx),
+            // The `mirror_to` has just been changed by `set_meta`.
+            None => {
+                cmd_ctx.set_resp_result(Ok(Resp::Error(
+                    format!("mirror address {} not found", address).into_bytes(),
+                )));
+                return;
+            }
+        };
+        if let Err(err) = res {
+            warn!("failed to send to mirror {}: {:?}", address, err);
+        }
+    }
+
+    // Returns None if the cluster is not owned by this proxy.
+    pub fn get_maxclients(&self, cluster_name: &ClusterName) -> Option<u64> {
+        self.meta_map
+            .lease()
+            .cluster_map
+            .get_config(cluster_name)
+            .map(|config| config.maxclients)
+    }
+
+    pub fn get_idle_timeout(&self, cluster_name: &ClusterName) -> u64 {
+        self.meta_map
+            .lease()
+            .cluster_map
+            .get_config(cluster_name)
+            .map(|config| config.idle_timeout)
+            .unwrap_or(0)
+    }
+
+    pub fn is_flushall_enabled(&self, cluster_name: &ClusterName) -> bool {
+        self.meta_map
+            .lease()
+            .cluster_map
+            .get_config(cluster_name)
+            .map(|config| config.enable_flushall)
+            .unwrap_or(false)
+    }
+
     pub fn get_epoch(&self) -> u64 {
         self.epoch.load(Ordering::SeqCst)
     }
+
+    // The subscribers of all the nodes will receive the message.
+    pub fn publish(
+        &self,
+        cmd_ctx: &CmdCtx,
+        forward_to_peers: bool,
+    ) -> Vec<Pin<Box<dyn Future<Output = CmdTaskResult> + Send + 'static>>> {
+        let resp = cmd_ctx.get_cmd().get_resp_slice().map(|b| b.to_vec());
+        self.get_nodes(cmd_ctx.get_cluster_name(), forward_to_peers)
+            .iter()
+            .map(|node| self.send_to_node(cmd_ctx, resp.clone(), node))
+            .collect()
+    }
+
+    // Returns the local masters and then the peer proxies.
+    // Both are sorted so that the index could be used to iterate the nodes.
+    pub fn get_nodes(&self, cluster_name: &ClusterName, include_peers: bool) -> Vec<NodeAddress> {
+        let meta_map = self.meta_map.lease();
+        let cluster_map = &meta_map.cluster_map;
+        let mut nodes: Vec<NodeAddress> = cluster_map
+            .get_local_nodes(cluster_name)
+            .into_iter()
+            .map(NodeAddress::Local)
+            .collect();
+        if include_peers {
+            let mut peers = cluster_map.get_peer_proxies(cluster_name);
+            peers.sort();
+            nodes.extend(peers.into_iter().map(NodeAddress::Peer));
+        }
+        nodes
+    }
+
+    // The peers will not forward the command again
+    // and only send it to their local masters.
+    pub fn send_to_node(
+        &self,
+        cmd_ctx: &CmdCtx,
+        mut resp: RespVec,
+        node: &NodeAddress,
+    ) -> Pin<Box<dyn Future<Output = CmdTaskResult> + Send + 'static>> {
+        let meta_map = self.meta_map.lease();
+        let cluster_map = &meta_map.cluster_map;
+        let factory = CmdCtxFactory::default();
+
+        match node {
+            NodeAddress::Local(address) => {
+                let (sub_cmd_ctx, fut) = factory.create_with_ctx(cmd_ctx.get_context(), resp);
+                let task = BlockingHintTask::new(sub_cmd_ctx, false);
+                if let Err(err) = cluster_map.send_to_local_node(task, address) {
+                    warn!("failed to send to {}: {:?}", address, err);
+                }
+                fut
+            }
+            NodeAddress::Peer(address) => {
+                let mut preceding_elements = vec![b"UMFORWARD".to_vec(), b"0".to_vec()];
+                if let Some(config) = cluster_map.get_auth_config(cmd_ctx.get_cluster_name()) {
+                    preceding_elements.extend(config.gen_forward_auth(cmd_ctx.get_cluster_name()));
+                }
+                array_append_front(&mut resp, preceding_elements);
+                let (sub_cmd_ctx, fut) = factory.create_with_ctx(cmd_ctx.get_context(), resp);
+                if let Err(err) = cluster_map.send_to_peer(sub_cmd_ctx, address) {
+                    warn!("failed to send to peer {}: {:?}", address, err);
+                }
+                fut
+            }
+        }
+    }
+
+    // Subscribing to any master is enough since PUBLISH will go to all the masters.
+    pub fn create_pubsub_conn(&self, cluster_name: &ClusterName) -> PubSubConnFuture {
+        let address = self
+            .meta_map
+            .lease()
+            .cluster_map
+            .get_local_nodes(cluster_name)
+            .into_iter()
+            .next();
+        let sock_address = match address {
+            Some(address) => match resolve_first_address(&address) {
+                Some(sock_address) => sock_address,
+                None => return Box::pin(future::err(BackendError::InvalidAddress)),
+            },
+            None => return Box::pin(future::err(BackendError::NodeNotFound)),
+        };
+        self.conn_factory.create_conn(sock_address)
+    }
+
+    pub fn create_dedicated_backend(
+        &self,
+        cluster_name: &ClusterName,
+        slot: usize,
+    ) -> Result<DedicatedBackend, RespVec> {
+        let address = self.get_transaction_address(cluster_name, slot)?;
+        Ok(self.spawn_dedicated_backend(address))
+    }
+
+    // The transactions without WATCH share the same connection of the node.
+    // The whole MULTI ... EXEC block is sent while holding the entry of `exec_backends`
+    // so that the blocks of different sessions won't interleave.
+    pub fn send_transaction(&self, cluster_name: &ClusterName, slot: usize, cmd_ctxs: Vec<CmdCtx>) {
+        let address = match self.get_transaction_address(cluster_name, slot) {
+            Ok(address) => address,
+            Err(resp) => {
+                for cmd_ctx in cmd_ctxs.into_iter() {
+                    cmd_ctx.set_resp_result(Ok(resp.clone()));
+                }
+                return;
+            }
+        };
+        let mut backend = self
+            .exec_backends
+            .entry(address.clone())
+            .or_insert_with(|| self.spawn_dedicated_backend(address.clone()));
+
+        let mut failed = false;
+        for (i, cmd_ctx) in cmd_ctxs.into_iter().enumerate() {
+            let mut res = if failed {
+                Err(cmd_ctx)
+            } else {
+                backend.send(cmd_ctx).map_err(|err| err.into_inner())
+            };
+            // The connection could have been closed since the last transaction.
+            if i == 0 {
+                if let Err(cmd_ctx) = res {
+                    *backend = self.spawn_dedicated_backend(address.clone());
+                    res = backend.send(cmd_ctx).map_err(|err| err.into_inner());
+                }
+            }
+            if let Err(cmd_ctx) = res {
+                failed = true;
+                cmd_ctx.set_resp_result(Ok(Resp::Error(
+                    response::ERR_BACKEND_CONNECTION.to_string().into_bytes(),
+                )));
+            }
+        }
+        drop(backend);
+
+        if failed {
+            self.exec_backends.remove(&address);
+        }
+    }
+
+    fn get_transaction_address(
+        &self,
+        cluster_name: &ClusterName,
+        slot: usize,
+    ) -> Result<String, RespVec> {
+        let meta_map = self.meta_map.lease();
+        if meta_map.migration_map.is_migrating(cluster_name, slot) {
+            return Err(Resp::Error(
+                format!("{} slot {} is migrating", response::TRY_AGAIN_REPLY, slot).into_bytes(),
+            ));
+        }
+        meta_map
+            .cluster_map
+            .get_local_node_address(cluster_name, slot)
+    }
+
+    fn spawn_dedicated_backend(&self, address: String) -> DedicatedBackend {
+        let (node, fut) = BackendNode::new_dedicated(
+            address.clone(),
+            Arc::new(ReplyCommitHandler),
+            self.conn_factory.clone(),
+        );
+        let desc = format!("backend::DedicatedBackend: address={}", address);
+        let fut = TrackedFutureRegistry::wrap(self.future_registry.clone(), fut, desc);
+        tokio::spawn(fut);
+        node
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum NodeAddress {
+    Local(String),
+    Peer(String),
 }
 
 pub fn send_cmd_ctx<C: ConnFactory<Pkt = RespPacket>>(
@@ -414,9 +947,23 @@ fn send_cmd_ctx_to_remote_directly<C: ConnFactory<Pkt = RespPacket>>(
     address: String,
     max_redirections: Option<NonZeroUsize>,
 ) {
+    let forward_auth = meta_map
+        .cluster_map
+        .get_auth_config(cmd_ctx.get_cluster_name())
+        .map(|config| config.gen_forward_auth(cmd_ctx.get_cluster_name()))
+        .unwrap_or_default();
+    // The password can only be carried by UMFORWARD,
+    // so the redirections are limited even if `max_redirections` is not set.
     let times = cmd_ctx
         .get_redirection_times()
-        .or_else(|| max_redirections.map(|n| n.get() - 1));
+        .or_else(|| max_redirections.map(|n| n.get() - 1))
+        .or_else(|| {
+            if forward_auth.is_empty() {
+                None
+            } else {
+                Some(MAX_REDIRECTIONS)
+            }
+        });
     if let Some(times) = times {
         let times = match times.checked_sub(1) {
             None => {
@@ -428,7 +975,9 @@ fn send_cmd_ctx_to_remote_directly<C: ConnFactory<Pkt = RespPacket>>(
             Some(times) => times,
         };
 
-        let res = cmd_ctx.wrap_cmd(vec![b"UMFORWARD".to_vec(), times.to_string().into_bytes()]);
+        let mut preceding_elements = vec![b"UMFORWARD".to_vec(), times.to_string().into_bytes()];
+        preceding_elements.extend(forward_auth);
+        let res = cmd_ctx.wrap_cmd(preceding_elements);
         if !res {
             cmd_ctx.set_resp_result(Ok(Resp::Error(
                 b"failed to wrap command for redirections".to_vec(),
@@ -450,15 +999,12 @@ fn send_cmd_ctx_to_remote_directly<C: ConnFactory<Pkt = RespPacket>>(
 
 pub struct BlockingTaskRetrySender<C: ConnFactory<Pkt = RespPacket>> {
     meta_map: SharedMetaMap<C>,
-    max_redirections: Option<NonZeroUsize>,
+    config: Arc<ServerProxyConfig>,
 }
 
 impl<C: ConnFactory<Pkt = RespPacket>> BlockingTaskRetrySender<C> {
-    fn new(meta_map: SharedMetaMap<C>, max_redirections: Option<NonZeroUsize>) -> Self {
-        Self {
-            meta_map,
-            max_redirections,
-        }
+    fn new(meta_map: SharedMetaMap<C>, config: Arc<ServerProxyConfig>) -> Self {
+        Self { meta_map, config }
     }
 }
 
@@ -466,7 +1012,7 @@ impl<C: ConnFactory<Pkt = RespPacket>> CmdTaskSender for BlockingTaskRetrySender
     type Task = CmdCtx;
 
     fn send(&self, cmd_task: Self::Task) -> Result<(), BackendError> {
-        send_cmd_ctx(&self.meta_map, cmd_task, self.max_redirections);
+        send_cmd_ctx(&self.meta_map, cmd_task, self.config.get_max_redirections());
         Ok(())
     }
 }
@@ -0,0 +1,558 @@
+use super::sender::ConnStates;
+use super::slowlog::Slowlog;
+use crate::common::cluster::ClusterName;
+use crate::common::utils::ThreadSafe;
+use crate::migration::task::MigrationState;
+use crate::protocol::{Resp, RespPacket};
+use actix_web::{web, App, HttpResponse, HttpServer};
+use arrayvec::ArrayString;
+use dashmap::DashMap;
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+use std::io;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+
+const MAX_COMMAND_LABEL_LENGTH: usize = 32;
+// Limit the cardinality of the command label for each cluster.
+const MAX_COMMAND_LABEL_NUM: usize = 256;
+// The clients could select the clusters not existing before they get rejected,
+// so the number of the clusters is also limited.
+const MAX_CLUSTER_LABEL_NUM: usize = 1024;
+const OTHER_COMMANDS: &str = "OTHERS";
+const UMFORWARD: &str = "UMFORWARD";
+
+// In microseconds.
+const LATENCY_BUCKETS: [u64; 10] = [
+    100, 250, 500, 1_000, 2_500, 5_000, 10_000, 50_000, 100_000, 1_000_000,
+];
+
+#[derive(Default)]
+struct CmdMetrics {
+    count: AtomicU64,
+    // Not cumulative. The last one is for +Inf.
+    latency_buckets: [AtomicU64; LATENCY_BUCKETS.len() + 1],
+    // In nanoseconds.
+    latency_sum: AtomicU64,
+    latency_count: AtomicU64,
+}
+
+impl CmdMetrics {
+    fn record(&self, latency: Option<i64>) {
+        self.count.fetch_add(1, Ordering::Relaxed);
+        let latency = match latency {
+            Some(latency) if latency >= 0 => latency as u64,
+            _ => return,
+        };
+        let latency_us = latency / 1000;
+        let index = LATENCY_BUCKETS
+            .iter()
+            .position(|bound| latency_us <= *bound)
+            .unwrap_or(LATENCY_BUCKETS.len());
+        if let Some(bucket) = self.latency_buckets.get(index) {
+            bucket.fetch_add(1, Ordering::Relaxed);
+        }
+        self.latency_sum.fetch_add(latency, Ordering::Relaxed);
+        self.latency_count.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+#[derive(Default)]
+struct ClusterMetrics {
+    commands: RwLock<HashMap<String, Arc<CmdMetrics>>>,
+    // MOVED and ASK replies sent to the clients.
+    moved: AtomicU64,
+    // Commands forwarded to peer proxies by active redirection.
+    forwarded: AtomicU64,
+}
+
+impl ClusterMetrics {
+    fn get_cmd_metrics(&self, command: &str) -> Arc<CmdMetrics> {
+        if let Some(cmd_metrics) = self
+            .commands
+            .read()
+            .expect("ClusterMetrics::get_cmd_metrics")
+            .get(command)
+        {
+            return cmd_metrics.clone();
+        }
+
+        let mut commands = self
+            .commands
+            .write()
+            .expect("ClusterMetrics::get_cmd_metrics");
+        let command = if commands.len() >= MAX_COMMAND_LABEL_NUM && !commands.contains_key(command)
+        {
+            OTHER_COMMANDS
+        } else {
+            command
+        };
+        commands
+            .entry(command.to_string())
+            .or_insert_with(|| Arc::new(CmdMetrics::default()))
+            .clone()
+    }
+}
+
+#[derive(Default)]
+pub struct ProxyMetrics {
+    clusters: DashMap<ClusterName, Arc<ClusterMetrics>>,
+}
+
+impl ProxyMetrics {
+    pub fn record_reply(
+        &self,
+        cluster_name: &ClusterName,
+        request: &RespPacket,
+        reply: &RespPacket,
+        slowlog: &Slowlog,
+    ) {
+        let cluster_metrics = match self.get_cluster_metrics(cluster_name) {
+            Some(cluster_metrics) => cluster_metrics,
+            None => return,
+        };
+
+        // The commands sent to peers by active redirection are wrapped by UMFORWARD.
+        let (command, forwarded) = get_command_label(request);
+        if forwarded {
+            cluster_metrics.forwarded.fetch_add(1, Ordering::Relaxed);
+        }
+        if is_redirection_reply(reply) {
+            cluster_metrics.moved.fetch_add(1, Ordering::Relaxed);
+        }
+
+        cluster_metrics
+            .get_cmd_metrics(command.as_str())
+            .record(slowlog.get_latency());
+    }
+
+    fn get_cluster_metrics(&self, cluster_name: &ClusterName) -> Option<Arc<ClusterMetrics>> {
+        if let Some(cluster_metrics) = self.clusters.get(cluster_name) {
+            return Some(cluster_metrics.value().clone());
+        }
+        if self.clusters.len() >= MAX_CLUSTER_LABEL_NUM {
+            return None;
+        }
+        let cluster_metrics = self
+            .clusters
+            .entry(cluster_name.clone())
+            .or_insert_with(|| Arc::new(ClusterMetrics::default()))
+            .value()
+            .clone();
+        Some(cluster_metrics)
+    }
+
+    // Removes the clusters not owned by this proxy anymore.
+    fn retain_clusters(&self, clusters: &HashSet<ClusterName>) {
+        self.clusters
+            .retain(|cluster_name, _| clusters.contains(cluster_name));
+    }
+
+    fn gen_metrics(&self, buf: &mut String) {
+        let mut clusters: Vec<(ClusterName, Arc<ClusterMetrics>)> = self
+            .clusters
+            .iter()
+            .map(|item| (item.key().clone(), item.value().clone()))
+            .collect();
+        clusters.sort_by(|(a, _), (b, _)| a.as_str().cmp(b.as_str()));
+
+        let mut commands = vec![];
+        for (cluster_name, cluster_metrics) in clusters.iter() {
+            let mut cmds: Vec<(String, Arc<CmdMetrics>)> = cluster_metrics
+                .commands
+                .read()
+                .expect("ProxyMetrics::gen_metrics")
+                .iter()
+                .map(|(command, cmd_metrics)| (command.clone(), cmd_metrics.clone()))
+                .collect();
+            cmds.sort_by(|(a, _), (b, _)| a.cmp(b));
+            for (command, cmd_metrics) in cmds.into_iter() {
+                let labels = format!(
+                    "cluster=\"{}\",command=\"{}\"",
+                    escape_label(cluster_name.as_str()),
+                    escape_label(&command)
+                );
+                commands.push((labels, cmd_metrics));
+            }
+        }
+
+        write_header(
+            buf,
+            "undermoon_commands_total",
+            "counter",
+            "Number of the commands processed.",
+        );
+        for (labels, cmd_metrics) in commands.iter() {
+            let count = cmd_metrics.count.load(Ordering::Relaxed);
+            let _ = writeln!(buf, "undermoon_commands_total{{{}}} {}", labels, count);
+        }
+
+        write_header(
+            buf,
+            "undermoon_command_latency_seconds",
+            "histogram",
+            "Latency of the commands sampled by slowlog_sample_rate.",
+        );
+        for (labels, cmd_metrics) in commands.iter() {
+            let mut cumulative = 0;
+            for (i, bucket) in cmd_metrics.latency_buckets.iter().enumerate() {
+                cumulative += bucket.load(Ordering::Relaxed);
+                let le = match LATENCY_BUCKETS.get(i) {
+                    Some(bound) => (*bound as f64 / 1_000_000.0).to_string(),
+                    None => "+Inf".to_string(),
+                };
+                let _ = writeln!(
+                    buf,
+                    "undermoon_command_latency_seconds_bucket{{{},le=\"{}\"}} {}",
+                    labels, le, cumulative
+                );
+            }
+            let sum = cmd_metrics.latency_sum.load(Ordering::Relaxed) as f64 / 1_000_000_000.0;
+            let count = cmd_metrics.latency_count.load(Ordering::Relaxed);
+            let _ = writeln!(
+                buf,
+                "undermoon_command_latency_seconds_sum{{{}}} {}",
+                labels, sum
+            );
+            let _ = writeln!(
+                buf,
+                "undermoon_command_latency_seconds_count{{{}}} {}",
+                labels, count
+            );
+        }
+
+        write_header(
+            buf,
+            "undermoon_redirections_total",
+            "counter",
+            "Number of the MOVED and ASK replies and the commands forwarded to peers.",
+        );
+        for (cluster_name, cluster_metrics) in clusters.iter() {
+            let cluster_name = escape_label(cluster_name.as_str());
+            let _ = writeln!(
+                buf,
+                "undermoon_redirections_total{{cluster=\"{}\",type=\"moved\"}} {}",
+                cluster_name,
+                cluster_metrics.moved.load(Ordering::Relaxed)
+            );
+            let _ = writeln!(
+                buf,
+                "undermoon_redirections_total{{cluster=\"{}\",type=\"forwarded\"}} {}",
+                cluster_name,
+                cluster_metrics.forwarded.load(Ordering::Relaxed)
+            );
+        }
+    }
+}
+
+pub struct BackendMetrics {
+    pub cluster_name: ClusterName,
+    pub address: String,
+    pub peer: bool,
+    pub conn_states: ConnStates,
+}
+
+pub struct MigrationMetrics {
+    pub cluster_name: ClusterName,
+    pub slot_range: String,
+    pub src_node: String,
+    pub dst_node: String,
+    pub migrating: bool,
+    pub state: MigrationState,
+}
+
+pub trait MetricsSource: ThreadSafe {
+    fn get_backend_metrics(&self) -> Vec<BackendMetrics>;
+    fn get_migration_metrics(&self) -> Vec<MigrationMetrics>;
+    fn get_clusters(&self) -> Vec<ClusterName>;
+}
+
+pub struct MetricsService<S: MetricsSource> {
+    proxy_metrics: Arc<ProxyMetrics>,
+    source: S,
+}
+
+impl<S: MetricsSource> MetricsService<S> {
+    pub fn new(proxy_metrics: Arc<ProxyMetrics>, source: S) -> Self {
+        Self {
+            proxy_metrics,
+            source,
+        }
+    }
+
+    pub fn gen_metrics(&self) -> String {
+        let clusters = self.source.get_clusters().into_iter().collect();
+        self.proxy_metrics.retain_clusters(&clusters);
+
+        let mut buf = String::new();
+        self.proxy_metrics.gen_metrics(&mut buf);
+        gen_backend_metrics(&mut buf, self.source.get_backend_metrics());
+        gen_migration_metrics(&mut buf, self.source.get_migration_metrics());
+        buf
+    }
+}
+
+// The actix runtime can't be shared with the tokio runtime of the proxy
+// so this should be run in a separate thread.
+pub fn run_metrics_server<S: MetricsSource>(
+    address: String,
+    service: Arc<MetricsService<S>>,
+) -> io::Result<()> {
+    let mut system = actix_rt::System::new("metrics");
+    let server = HttpServer::new(move || {
+        App::new()
+            .data(service.clone())
+            .route("/metrics", web::get().to(get_metrics::<S>))
+    })
+    .workers(1)
+    .bind(&address)?
+    .run();
+    system.block_on(server)
+}
+
+async fn get_metrics<S: MetricsSource>(service: web::Data<Arc<MetricsService<S>>>) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(service.gen_metrics())
+}
+
+fn gen_backend_metrics(buf: &mut String, mut backends: Vec<BackendMetrics>) {
+    backends.sort_by(|a, b| {
+        (a.cluster_name.as_str(), &a.address).cmp(&(b.cluster_name.as_str(), &b.address))
+    });
+    write_header(
+        buf,
+        "undermoon_backend_connections",
+        "gauge",
+        "Number of the connections to Redis and peer proxies.",
+    );
+    for backend in backends.iter() {
+        let labels = format!(
+            "cluster=\"{}\",node=\"{}\",peer=\"{}\"",
+            escape_label(backend.cluster_name.as_str()),
+            escape_label(&backend.address),
+            backend.peer,
+        );
+        let _ = writeln!(
+            buf,
+            "undermoon_backend_connections{{{},state=\"connected\"}} {}",
+            labels, backend.conn_states.connected
+        );
+        let _ = writeln!(
+            buf,
+            "undermoon_backend_connections{{{},state=\"failed\"}} {}",
+            labels, backend.conn_states.failed
+        );
+    }
+}
+
+fn gen_migration_metrics(buf: &mut String, mut migrations: Vec<MigrationMetrics>) {
+    migrations.sort_by(|a, b| {
+        (a.cluster_name.as_str(), &a.slot_range).cmp(&(b.cluster_name.as_str(), &b.slot_range))
+    });
+    write_header(
+        buf,
+        "undermoon_migration_state",
+        "gauge",
+        "State of the migration tasks from 0 PRE_CHECK to 5 SWITCH_COMMITTED.",
+    );
+    for migration in migrations.iter() {
+        let role = if migration.migrating {
+            "migrating"
+        } else {
+            "importing"
+        };
+        let _ = writeln!(
+            buf,
+            "undermoon_migration_state{{cluster=\"{}\",slot_range=\"{}\",src_node=\"{}\",dst_node=\"{}\",role=\"{}\"}} {}",
+            escape_label(migration.cluster_name.as_str()),
+            escape_label(&migration.slot_range),
+            escape_label(&migration.src_node),
+            escape_label(&migration.dst_node),
+            role,
+            migration.state as u8,
+        );
+    }
+}
+
+fn write_header(buf: &mut String, name: &str, metric_type: &str, help: &str) {
+    let _ = writeln!(buf, "# HELP {} {}", name, help);
+    let _ = writeln!(buf, "# TYPE {} {}", name, metric_type);
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+// Returns the uppercase command name and whether it's forwarded by UMFORWARD.
+fn get_command_label(request: &RespPacket) -> (ArrayString<[u8; MAX_COMMAND_LABEL_LENGTH]>, bool) {
+    let mut label = to_command_label(request.get_array_element(0));
+    let forwarded = label.as_str() == UMFORWARD;
+    if forwarded {
+        // UMFORWARD <redirection times> <command> ...
+        label = to_command_label(request.get_array_element(2));
+    }
+    (label, forwarded)
+}
+
+fn to_command_label(name: Option<&[u8]>) -> ArrayString<[u8; MAX_COMMAND_LABEL_LENGTH]> {
+    let mut label = ArrayString::new();
+    let name = match name {
+        Some(name) if !name.is_empty() && name.len() <= MAX_COMMAND_LABEL_LENGTH => name,
+        _ => {
+            label.push_str(OTHER_COMMANDS);
+            return label;
+        }
+    };
+    for b in name.iter() {
+        if !b.is_ascii_alphanumeric() && *b != b'_' && *b != b'-' {
+            label.clear();
+            label.push_str(OTHER_COMMANDS);
+            return label;
+        }
+        label.push(b.to_ascii_uppercase() as char);
+    }
+    label
+}
+
+fn is_redirection(err: &[u8]) -> bool {
+    err.starts_with(b"MOVED ") || err.starts_with(b"ASK ")
+}
+
+fn is_redirection_reply(reply: &RespPacket) -> bool {
+    match reply {
+        RespPacket::Data(Resp::Error(err)) => is_redirection(err),
+        RespPacket::Data(_) => false,
+        RespPacket::Indexed(indexed_resp) => match indexed_resp.get_data().split_first() {
+            Some((b'-', err)) => is_redirection(err),
+            _ => false,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::{Array, BulkStr, RespVec};
+    use std::convert::TryFrom;
+
+    fn gen_request(elements: &[&str]) -> RespPacket {
+        let resps = elements
+            .iter()
+            .map(|s| Resp::Bulk(BulkStr::Str(s.as_bytes().to_vec())))
+            .collect();
+        RespPacket::from_resp_vec(Resp::Arr(Array::Arr(resps)))
+    }
+
+    fn gen_reply(resp: RespVec) -> RespPacket {
+        RespPacket::from_resp_vec(resp)
+    }
+
+    #[test]
+    fn test_command_label() {
+        let (label, forwarded) = get_command_label(&gen_request(&["get", "key"]));
+        assert_eq!(label.as_str(), "GET");
+        assert!(!forwarded);
+
+        let (label, forwarded) = get_command_label(&gen_request(&["UMFORWARD", "0", "set"]));
+        assert_eq!(label.as_str(), "SET");
+        assert!(forwarded);
+
+        let (label, _) = get_command_label(&gen_request(&["get key"]));
+        assert_eq!(label.as_str(), OTHER_COMMANDS);
+        let long_name = "a".repeat(MAX_COMMAND_LABEL_LENGTH + 1);
+        let (label, _) = get_command_label(&gen_request(&[long_name.as_str()]));
+        assert_eq!(label.as_str(), OTHER_COMMANDS);
+    }
+
+    #[test]
+    fn test_redirection_reply() {
+        let moved = gen_reply(Resp::Error(b"MOVED 233 127.0.0.1:6379".to_vec()));
+        assert!(is_redirection_reply(&moved));
+        let ask = gen_reply(Resp::Error(b"ASK 233 127.0.0.1:6379".to_vec()));
+        assert!(is_redirection_reply(&ask));
+        let err = gen_reply(Resp::Error(b"ERR MOVED".to_vec()));
+        assert!(!is_redirection_reply(&err));
+        let ok = gen_reply(Resp::Simple(b"OK".to_vec()));
+        assert!(!is_redirection_reply(&ok));
+    }
+
+    #[test]
+    fn test_gen_metrics() {
+        let metrics = ProxyMetrics::default();
+        let cluster_name = ClusterName::try_from("mycluster").unwrap();
+        let slowlog = Slowlog::new(0, false);
+        let ok = gen_reply(Resp::Simple(b"OK".to_vec()));
+        let moved = gen_reply(Resp::Error(b"MOVED 233 127.0.0.1:6379".to_vec()));
+        metrics.record_reply(&cluster_name, &gen_request(&["get", "a"]), &moved, &slowlog);
+        metrics.record_reply(&cluster_name, &gen_request(&["GET", "b"]), &ok, &slowlog);
+        metrics.record_reply(
+            &cluster_name,
+            &gen_request(&["UMFORWARD", "0", "SET", "a", "1"]),
+            &ok,
+            &slowlog,
+        );
+
+        let cluster_metrics = metrics.get_cluster_metrics(&cluster_name).unwrap();
+        cluster_metrics.get_cmd_metrics("GET").record(Some(300_000));
+        cluster_metrics
+            .get_cmd_metrics("GET")
+            .record(Some(2_000_000_000));
+
+        let mut buf = String::new();
+        metrics.gen_metrics(&mut buf);
+        let lines: Vec<&str> = buf.lines().collect();
+        let expected = [
+            "undermoon_commands_total{cluster=\"mycluster\",command=\"GET\"} 4",
+            "undermoon_commands_total{cluster=\"mycluster\",command=\"SET\"} 1",
+            "undermoon_command_latency_seconds_bucket{cluster=\"mycluster\",command=\"GET\",le=\"0.00025\"} 0",
+            "undermoon_command_latency_seconds_bucket{cluster=\"mycluster\",command=\"GET\",le=\"0.0005\"} 1",
+            "undermoon_command_latency_seconds_bucket{cluster=\"mycluster\",command=\"GET\",le=\"1\"} 1",
+            "undermoon_command_latency_seconds_bucket{cluster=\"mycluster\",command=\"GET\",le=\"+Inf\"} 2",
+            "undermoon_command_latency_seconds_sum{cluster=\"mycluster\",command=\"GET\"} 2.0003",
+            "undermoon_command_latency_seconds_count{cluster=\"mycluster\",command=\"GET\"} 2",
+            "undermoon_redirections_total{cluster=\"mycluster\",type=\"moved\"} 1",
+            "undermoon_redirections_total{cluster=\"mycluster\",type=\"forwarded\"} 1",
+        ];
+        for line in expected.iter() {
+            assert!(lines.contains(line), "{} not found in {}", line, buf);
+        }
+    }
+
+    #[test]
+    fn test_command_label_limit() {
+        let cluster_metrics = ClusterMetrics::default();
+        for i in 0..MAX_COMMAND_LABEL_NUM + 10 {
+            cluster_metrics.get_cmd_metrics(&format!("CMD{}", i));
+        }
+        let commands = cluster_metrics.commands.read().unwrap();
+        assert_eq!(commands.len(), MAX_COMMAND_LABEL_NUM + 1);
+        assert!(commands.contains_key(OTHER_COMMANDS));
+    }
+
+    #[test]
+    fn test_cluster_label_limit() {
+        let metrics = ProxyMetrics::default();
+        for i in 0..MAX_CLUSTER_LABEL_NUM {
+            let cluster_name = ClusterName::try_from(format!("cluster{}", i).as_str()).unwrap();
+            assert!(metrics.get_cluster_metrics(&cluster_name).is_some());
+        }
+        let cluster_name = ClusterName::try_from("mycluster").unwrap();
+        assert!(metrics.get_cluster_metrics(&cluster_name).is_none());
+
+        let mut clusters = HashSet::new();
+        clusters.insert(ClusterName::try_from("cluster0").unwrap());
+        clusters.insert(cluster_name.clone());
+        metrics.retain_clusters(&clusters);
+        assert_eq!(metrics.clusters.len(), 1);
+        assert!(metrics.get_cluster_metrics(&cluster_name).is_some());
+    }
+
+    #[test]
+    fn test_escape_label() {
+        assert_eq!(escape_label("a\"b\\c\nd"), "a\\\"b\\\\c\\nd");
+    }
+}
@@ -185,13 +185,16 @@ impl<F: CmdTaskFactory> MgrCmdStateUmSync<F> {
         state: MgrCmdStateExists<F>,
         cmd_task_factory: &F,
         lock_guard: KeyLockGuard,
+        forward_auth: &[BinSafeStr],
     ) -> (Self, ReqTask<F::Task>, ReplyFuture) {
         let MgrCmdStateExists {
             inner_task, key, ..
         } = state;
 
-        let (umsync_cmd_task, umsync_reply_fut) =
-            cmd_task_factory.create_with_ctx(inner_task.get_context(), Self::gen_umsync_resp(&key));
+        let (umsync_cmd_task, umsync_reply_fut) = cmd_task_factory.create_with_ctx(
+            inner_task.get_context(),
+            Self::gen_umsync_resp(&key, forward_auth),
+        );
 
         let task = ReqTask::Simple(umsync_cmd_task);
         let state = Self {
@@ -203,11 +206,16 @@ impl<F: CmdTaskFactory> MgrCmdStateUmSync<F> {
         (state, task, sync_fut)
     }
 
-    fn gen_umsync_resp(key: &[u8]) -> RespVec {
-        let elements = vec![
+    fn gen_umsync_resp(key: &[u8], forward_auth: &[BinSafeStr]) -> RespVec {
+        let mut elements = vec![
             Resp::Bulk(BulkStr::Str("UMSYNC".to_string().into_bytes())),
             Resp::Bulk(BulkStr::Str(key.into())),
         ];
+        elements.extend(
+            forward_auth
+                .iter()
+                .map(|arg| Resp::Bulk(BulkStr::Str(arg.clone()))),
+        );
         Resp::Arr(Array::Arr(elements))
     }
 
@@ -325,6 +333,8 @@ where
     )>,
     cmd_task_factory: Arc<F>,
     key_lock: Arc<KeyLock>,
+    // `AUTH <cluster> <password>` for UMSYNC if the cluster requires auth.
+    forward_auth: Arc<Vec<BinSafeStr>>,
 }
 
 impl<F, S, PS> RestoreDataCmdTaskHandler<F, S, PS>
@@ -339,6 +349,7 @@ where
         dst_sender: S,
         src_proxy_sender: PS,
         cmd_task_factory: Arc<F>,
+        forward_auth: Vec<BinSafeStr>,
     ) -> Self {
         let src_sender = Arc::new(src_sender);
         let dst_sender = Arc::new(dst_sender);
@@ -368,6 +379,7 @@ where
             task_receivers,
             cmd_task_factory,
             key_lock,
+            forward_auth: Arc::new(forward_auth),
         }
     }
 
@@ -388,6 +400,7 @@ where
         let restore_task_sender = self.restore_task_sender.clone();
         let cmd_task_factory = self.cmd_task_factory.clone();
         let key_lock = self.key_lock.clone();
+        let forward_auth = self.forward_auth.clone();
 
         let receiver_opt = self.task_receivers.take(Ordering::SeqCst).map(|p| *p);
         let (
@@ -414,6 +427,7 @@ where
             src_proxy_sender,
             cmd_task_factory.clone(),
             key_lock.clone(),
+            forward_auth,
         );
 
         let dump_pttl_task_handler = Self::handle_dump_pttl_task(
@@ -492,6 +506,7 @@ where
         src_proxy_sender: Arc<PS>,
         cmd_task_factory: Arc<F>,
         key_lock: Arc<KeyLock>,
+        forward_auth: Arc<Vec<BinSafeStr>>,
     ) {
         while let Some((state, reply_receiver)) = exists_task_receiver.next().await {
             let res = reply_receiver.await;
@@ -566,8 +581,12 @@ where
 
             let (_, data_cmd_type) = state.inner_task.get_type();
             if requires_blocking_migration(data_cmd_type) {
-                let (state, req_task, reply_fut) =
-                    MgrCmdStateUmSync::from_state_exists(state, &(*cmd_task_factory), lock_guard);
+                let (state, req_task, reply_fut) = MgrCmdStateUmSync::from_state_exists(
+                    state,
+                    &(*cmd_task_factory),
+                    lock_guard,
+                    &forward_auth,
+                );
                 if let Err(err) = src_proxy_sender.send(req_task) {
                     debug!("failed to send umsync: {:?}", err);
                 }
@@ -1093,6 +1112,7 @@ mod tests {
             DummyCmdTaskSender::new(true, HashMap::new(), 666),
             DummyCmdTaskSender::new(false, HashMap::new(), 0),
             Arc::new(CmdCtxFactory::default()),
+            vec![],
         );
 
         let (cmd_ctx, reply_receiver) = gen_test_cmd_ctx(vec!["GET", "somekey"]);
@@ -1116,6 +1136,7 @@ mod tests {
             DummyCmdTaskSender::new(false, HashMap::new(), 1),
             DummyCmdTaskSender::new(false, HashMap::new(), 0),
             Arc::new(CmdCtxFactory::default()),
+            vec![],
         );
 
         let (cmd_ctx, reply_receiver) = gen_test_cmd_ctx(vec!["GET", "somekey"]);
@@ -1142,6 +1163,7 @@ mod tests {
             DummyCmdTaskSender::new(false, HashMap::new(), 233),
             DummyCmdTaskSender::new(false, HashMap::new(), 0),
             Arc::new(CmdCtxFactory::default()),
+            vec![],
         );
 
         let (cmd_ctx, reply_receiver) = gen_test_cmd_ctx(vec!["GET", "somekey"]);
@@ -1173,6 +1195,7 @@ mod tests {
                 DummyCmdTaskSender::new(true, err_set.clone(), 666),
                 DummyCmdTaskSender::new(false, HashMap::new(), 0),
                 Arc::new(CmdCtxFactory::default()),
+                vec![],
             );
 
             let (cmd_ctx, reply_receiver) = gen_test_cmd_ctx(vec!["GET", "somekey"]);
@@ -1205,6 +1228,7 @@ mod tests {
                 DummyCmdTaskSender::new(true, err_set.clone(), 666),
                 DummyCmdTaskSender::new(false, HashMap::new(), 0),
                 Arc::new(CmdCtxFactory::default()),
+                vec![],
             );
 
             let (cmd_ctx, reply_receiver) = gen_test_cmd_ctx(vec!["GET", "somekey"]);
@@ -1241,6 +1265,7 @@ mod tests {
                 DummyCmdTaskSender::new(false, HashMap::new(), 666),
                 DummyCmdTaskSender::new(false, HashMap::new(), 0),
                 Arc::new(CmdCtxFactory::default()),
+                vec![],
             );
 
             let (cmd_ctx, reply_receiver) = gen_test_cmd_ctx(vec!["GET", "somekey"]);
@@ -1273,6 +1298,7 @@ mod tests {
                 DummyCmdTaskSender::new(false, HashMap::new(), 666),
                 DummyCmdTaskSender::new(false, HashMap::new(), 0),
                 Arc::new(CmdCtxFactory::default()),
+                vec![],
             );
 
             let (cmd_ctx, reply_receiver) = gen_test_cmd_ctx(vec!["GET", "somekey"]);
@@ -1305,6 +1331,7 @@ mod tests {
                 DummyCmdTaskSender::new(false, err_set.clone(), 666),
                 DummyCmdTaskSender::new(false, HashMap::new(), 0),
                 Arc::new(CmdCtxFactory::default()),
+                vec![],
             );
 
             let (cmd_ctx, reply_receiver) = gen_test_cmd_ctx(vec!["GET", "somekey"]);
@@ -1337,6 +1364,7 @@ mod tests {
             DummyCmdTaskSender::new(false, HashMap::new(), -2),
             DummyCmdTaskSender::new(false, HashMap::new(), 0),
             Arc::new(CmdCtxFactory::default()),
+            vec![],
         );
 
         let (cmd_ctx, reply_receiver) = gen_test_cmd_ctx(vec!["GET", "somekey"]);
@@ -1363,6 +1391,7 @@ mod tests {
             DummyCmdTaskSender::new(false, HashMap::new(), 1),
             DummyCmdTaskSender::new(false, HashMap::new(), 0),
             Arc::new(CmdCtxFactory::default()),
+            vec![],
         );
 
         let (cmd_ctx, reply_receiver) = gen_test_cmd_ctx(vec!["DEL", "somekey"]);
@@ -0,0 +1,195 @@
+use super::command::{CmdReplyReceiver, CmdReplySender};
+use crate::common::cluster::ClusterName;
+use crate::protocol::{Array, BulkStr, Resp, RespVec};
+use dashmap::DashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time;
+
+// The mirror replies taking longer than this are counted as failures.
+const MIRROR_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Default)]
+pub struct MirrorStats {
+    mirrored: AtomicU64,
+    mismatched: AtomicU64,
+    failed: AtomicU64,
+}
+
+pub struct MirrorMap {
+    clusters: DashMap<ClusterName, Arc<MirrorStats>>,
+    sample_count: AtomicU64,
+}
+
+impl Default for MirrorMap {
+    fn default() -> Self {
+        Self {
+            clusters: DashMap::new(),
+            sample_count: AtomicU64::new(0),
+        }
+    }
+}
+
+impl MirrorMap {
+    // `sample_rate` 0 disables the mirroring.
+    pub fn sample(&self, sample_rate: u64) -> bool {
+        if sample_rate == 0 {
+            return false;
+        }
+        let count = self.sample_count.fetch_add(1, Ordering::Relaxed);
+        count.checked_rem(sample_rate) == Some(0)
+    }
+
+    pub fn get_stats(&self, cluster_name: &ClusterName) -> Arc<MirrorStats> {
+        if let Some(stats) = self.clusters.get(cluster_name) {
+            return stats.clone();
+        }
+        self.clusters
+            .entry(cluster_name.clone())
+            .or_insert_with(|| Arc::new(MirrorStats::default()))
+            .clone()
+    }
+
+    pub fn retain_clusters<F>(&self, f: F)
+    where
+        F: Fn(&ClusterName) -> bool,
+    {
+        self.clusters.retain(|cluster_name, _| f(cluster_name));
+    }
+
+    pub fn info(&self) -> RespVec {
+        let clusters = self
+            .clusters
+            .iter()
+            .map(|item| {
+                let stats = item.value();
+                let lines = vec![
+                    format!("name: {}", item.key()),
+                    format!("mirrored: {}", stats.mirrored.load(Ordering::Relaxed)),
+                    format!("mismatched: {}", stats.mismatched.load(Ordering::Relaxed)),
+                    format!("failed: {}", stats.failed.load(Ordering::Relaxed)),
+                ];
+                Resp::Arr(Array::Arr(
+                    lines
+                        .into_iter()
+                        .map(|s| Resp::Bulk(BulkStr::Str(s.into_bytes())))
+                        .collect(),
+                ))
+            })
+            .collect();
+        Resp::Arr(Array::Arr(clusters))
+    }
+}
+
+// The primary reply is sent back to the client before waiting for the mirror reply.
+pub async fn forward_and_compare(
+    primary_receiver: CmdReplyReceiver,
+    mut reply_sender: CmdReplySender,
+    mirror_receiver: CmdReplyReceiver,
+    stats: Arc<MirrorStats>,
+) {
+    let primary_result = primary_receiver.await;
+    let primary_resp = match &primary_result {
+        Ok(reply) => Some(reply.get_packet().to_resp_vec()),
+        Err(_) => None,
+    };
+    // The client could have been disconnected.
+    if let Err(err) = reply_sender.send(primary_result) {
+        debug!("failed to send the primary reply: {:?}", err);
+    }
+
+    stats.mirrored.fetch_add(1, Ordering::Relaxed);
+    let mirror_resp = match time::timeout(MIRROR_TIMEOUT, mirror_receiver).await {
+        Ok(Ok(reply)) => reply.into_resp_vec(),
+        Ok(Err(err)) => {
+            stats.failed.fetch_add(1, Ordering::Relaxed);
+            warn!("failed to mirror command: {:?}", err);
+            return;
+        }
+        Err(_) => {
+            stats.failed.fetch_add(1, Ordering::Relaxed);
+            warn!("mirror command timed out");
+            return;
+        }
+    };
+
+    if let Some(primary_resp) = primary_resp {
+        if primary_resp != mirror_resp {
+            stats.mismatched.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::RespPacket;
+    use crate::proxy::backend::CmdTask;
+    use crate::proxy::command::{new_command_pair, Command};
+    use crate::proxy::session::CmdCtx;
+    use std::convert::TryFrom;
+
+    #[test]
+    fn test_mirror_sample() {
+        let mirror_map = MirrorMap::default();
+        assert!(!mirror_map.sample(0));
+        let sampled = (0..10).filter(|_| mirror_map.sample(5)).count();
+        assert_eq!(sampled, 2);
+    }
+
+    #[test]
+    fn test_mirror_retain_clusters() {
+        let mirror_map = MirrorMap::default();
+        let cluster1 = ClusterName::try_from("cluster1").unwrap();
+        let cluster2 = ClusterName::try_from("cluster2").unwrap();
+        let stats = mirror_map.get_stats(&cluster1);
+        stats.mirrored.fetch_add(1, Ordering::Relaxed);
+        mirror_map.get_stats(&cluster2);
+
+        mirror_map.retain_clusters(|cluster_name| cluster_name == &cluster1);
+        assert_eq!(mirror_map.clusters.len(), 1);
+        let stats = mirror_map.get_stats(&cluster1);
+        assert_eq!(stats.mirrored.load(Ordering::Relaxed), 1);
+    }
+
+    fn gen_cmd_ctx() -> (CmdCtx, CmdReplyReceiver) {
+        let resp = Resp::Arr(Array::Arr(vec![
+            Resp::Bulk(BulkStr::Str(b"GET".to_vec())),
+            Resp::Bulk(BulkStr::Str(b"key".to_vec())),
+        ]));
+        let cmd = Command::new(Box::new(RespPacket::Data(resp)));
+        let (reply_sender, reply_receiver) = new_command_pair(&cmd);
+        let cluster_name = ClusterName::try_from("mycluster").unwrap();
+        let cmd_ctx = CmdCtx::new(cluster_name, cmd, reply_sender, 0, false);
+        (cmd_ctx, reply_receiver)
+    }
+
+    async fn compare(primary: RespVec, mirror: RespVec, stats: Arc<MirrorStats>) {
+        let (mut primary_ctx, client_receiver) = gen_cmd_ctx();
+        let (mirror_ctx, mirror_receiver) = gen_cmd_ctx();
+        let (primary_sender, primary_receiver) = new_command_pair(primary_ctx.get_cmd());
+        let reply_sender = primary_ctx.replace_reply_sender(primary_sender);
+
+        primary_ctx.set_resp_result(Ok(primary.clone()));
+        mirror_ctx.set_resp_result(Ok(mirror));
+        forward_and_compare(primary_receiver, reply_sender, mirror_receiver, stats).await;
+
+        let reply = client_receiver.await.unwrap();
+        assert_eq!(reply.into_resp_vec(), primary);
+    }
+
+    #[tokio::test]
+    async fn test_forward_and_compare() {
+        let stats = Arc::new(MirrorStats::default());
+        let value = Resp::Bulk(BulkStr::Str(b"value".to_vec()));
+        compare(value.clone(), value.clone(), stats.clone()).await;
+        assert_eq!(stats.mirrored.load(Ordering::Relaxed), 1);
+        assert_eq!(stats.mismatched.load(Ordering::Relaxed), 0);
+
+        compare(value, Resp::Bulk(BulkStr::Nil), stats.clone()).await;
+        assert_eq!(stats.mirrored.load(Ordering::Relaxed), 2);
+        assert_eq!(stats.mismatched.load(Ordering::Relaxed), 1);
+        assert_eq!(stats.failed.load(Ordering::Relaxed), 0);
+    }
+}
@@ -1,14 +1,26 @@
+pub mod audit;
 pub mod backend;
 pub mod blocking;
+pub mod breaker;
+pub mod client;
 pub mod cluster;
 pub mod command;
 mod compress;
+pub mod drain;
 pub mod executor;
+pub mod hotkey;
 pub mod manager;
+pub mod metrics;
 pub mod migration_backend;
+pub mod mirror;
+pub mod namespace;
+pub mod quota;
+pub mod replica;
 pub mod reply;
 pub mod sender;
 pub mod service;
 pub mod session;
 mod slot;
 pub mod slowlog;
+pub mod timeout;
+pub mod transaction;
@@ -0,0 +1,668 @@
+use super::command::{CmdType, Command, DataCmdType};
+use super::session::CmdCtx;
+use crate::common::utils::get_hash_tag;
+use crate::protocol::{Array, BinSafeStr, BulkStr, Resp, RespVec};
+use btoi::btou;
+use std::error::Error;
+use std::fmt;
+
+// The key prefix is prepended to all the key arguments so that the tenants
+// sharing the same cluster can't see the keys of each other.
+// The slots need to stay the same so that the cluster clients could still find the keys:
+// - A key without hash tag is wrapped as a hash tag: `a` => `prefix{a}`.
+// - A key with hash tag is marked by `}` which won't change the hash tag: `{a}b` => `prefix}{a}b`.
+// The marks keep `a` and `{a}` different after being prefixed.
+// The keys containing `}` but without hash tag are rejected since they can't be wrapped.
+
+#[derive(Debug, PartialEq)]
+pub enum NamespaceError {
+    UnsupportedCmd,
+    UnsupportedKey,
+    InvalidRequest,
+}
+
+impl fmt::Display for NamespaceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl Error for NamespaceError {
+    fn description(&self) -> &str {
+        "namespace error"
+    }
+
+    fn cause(&self) -> Option<&dyn Error> {
+        None
+    }
+}
+
+pub fn add_key_prefix(cmd_ctx: &mut CmdCtx, prefix: &[u8]) -> Result<(), NamespaceError> {
+    prefix_keys(cmd_ctx, prefix, false)
+}
+
+// The commands forwarded by the peer proxies have probably been prefixed,
+// so only the keys and patterns not starting with the prefix are prefixed.
+// This still keeps the forwarded commands inside the namespace.
+pub fn ensure_key_prefix(cmd_ctx: &mut CmdCtx, prefix: &[u8]) -> Result<(), NamespaceError> {
+    prefix_keys(cmd_ctx, prefix, true)
+}
+
+fn prefix_keys(
+    cmd_ctx: &mut CmdCtx,
+    prefix: &[u8],
+    skip_prefixed: bool,
+) -> Result<(), NamespaceError> {
+    match cmd_ctx.get_data_cmd_type() {
+        DataCmdType::KEYS => {
+            let pattern = cmd_ctx
+                .get_cmd()
+                .get_command_element(1)
+                .ok_or(NamespaceError::InvalidRequest)?;
+            if skip_prefixed && is_pattern_prefixed(prefix, pattern) {
+                return Ok(());
+            }
+            let pattern = prefix_pattern(prefix, pattern);
+            if !cmd_ctx.change_cmd_element(1, pattern) {
+                return Err(NamespaceError::InvalidRequest);
+            }
+            return Ok(());
+        }
+        DataCmdType::SCAN => return add_scan_prefix(cmd_ctx, prefix, skip_prefixed),
+        _ => (),
+    }
+
+    let indices = get_key_indices(cmd_ctx.get_cmd())?;
+    for index in indices.into_iter() {
+        let key = match cmd_ctx.get_cmd().get_command_element(index) {
+            Some(key) => key,
+            None => return Err(NamespaceError::InvalidRequest),
+        };
+        if skip_prefixed && decode_key(prefix, key).is_some() {
+            continue;
+        }
+        let prefixed_key = encode_key(prefix, key)?;
+        if !cmd_ctx.change_cmd_element(index, prefixed_key) {
+            return Err(NamespaceError::InvalidRequest);
+        }
+    }
+    Ok(())
+}
+
+// SCAN cursor [MATCH pattern] [COUNT count] [TYPE type]
+fn add_scan_prefix(
+    cmd_ctx: &mut CmdCtx,
+    prefix: &[u8],
+    skip_prefixed: bool,
+) -> Result<(), NamespaceError> {
+    let cmd = cmd_ctx.get_cmd();
+    let len = cmd.get_command_len().unwrap_or(0);
+    let match_index = (2..len)
+        .step_by(2)
+        .find(|i| {
+            cmd.get_command_element(*i)
+                .map(|option| option.eq_ignore_ascii_case(b"MATCH"))
+                .unwrap_or(false)
+        })
+        .map(|i| i + 1);
+
+    let success = match match_index {
+        Some(index) => {
+            let pattern = cmd
+                .get_command_element(index)
+                .ok_or(NamespaceError::InvalidRequest)?;
+            if skip_prefixed && is_pattern_prefixed(prefix, pattern) {
+                return Ok(());
+            }
+            let pattern = prefix_pattern(prefix, pattern);
+            cmd_ctx.change_cmd_element(index, pattern)
+        }
+        None => {
+            let pattern = prefix_pattern(prefix, b"");
+            cmd_ctx.append_cmd_elements(vec![b"MATCH".to_vec(), pattern])
+        }
+    };
+    if !success {
+        return Err(NamespaceError::InvalidRequest);
+    }
+    Ok(())
+}
+
+fn encode_key(prefix: &[u8], key: &[u8]) -> Result<BinSafeStr, NamespaceError> {
+    let mut prefixed = Vec::with_capacity(prefix.len() + key.len() + 2);
+    prefixed.extend_from_slice(prefix);
+    if get_hash_tag(key).len() != key.len() {
+        prefixed.push(b'}');
+        prefixed.extend_from_slice(key);
+    } else if key.is_empty() || key.contains(&b'}') {
+        return Err(NamespaceError::UnsupportedKey);
+    } else {
+        prefixed.push(b'{');
+        prefixed.extend_from_slice(key);
+        prefixed.push(b'}');
+    }
+    Ok(prefixed)
+}
+
+// Returns None if the key is not in the namespace of the prefix.
+fn decode_key<'a>(prefix: &[u8], prefixed_key: &'a [u8]) -> Option<&'a [u8]> {
+    if !prefixed_key.starts_with(prefix) {
+        return None;
+    }
+    let key = &prefixed_key[prefix.len()..];
+    match key.first() {
+        Some(b'}') => Some(&key[1..]),
+        Some(b'{') if key.len() > 2 && key.last() == Some(&b'}') => Some(&key[1..key.len() - 1]),
+        _ => None,
+    }
+}
+
+// The pattern can't be converted exactly for the two forms of the prefixed keys.
+// The converted one matches more keys and the replies are filtered again
+// by the original pattern in `strip_key_prefix`.
+// The glob-style special characters in the prefix need to be escaped.
+fn prefix_pattern(prefix: &[u8], pattern: &[u8]) -> BinSafeStr {
+    let mut prefixed = Vec::with_capacity(prefix.len() + pattern.len() + 5);
+    for b in prefix.iter() {
+        if let b'*' | b'?' | b'[' | b']' | b'\\' = b {
+            prefixed.push(b'\\');
+        }
+        prefixed.push(*b);
+    }
+    prefixed.extend_from_slice(b"[{}]");
+    prefixed.extend_from_slice(pattern);
+    prefixed.push(b'*');
+    prefixed
+}
+
+fn is_pattern_prefixed(prefix: &[u8], pattern: &[u8]) -> bool {
+    let prefixed = prefix_pattern(prefix, b"");
+    pattern.starts_with(&prefixed[..prefixed.len() - 1])
+}
+
+// Returns the pattern of KEYS and SCAN before it's prefixed.
+pub fn get_key_pattern(cmd: &Command) -> Option<BinSafeStr> {
+    match cmd.get_data_cmd_type() {
+        DataCmdType::KEYS => cmd.get_command_element(1).map(|p| p.to_vec()),
+        DataCmdType::SCAN => {
+            let len = cmd.get_command_len().unwrap_or(0);
+            let pattern = (2..len)
+                .step_by(2)
+                .find(|i| {
+                    cmd.get_command_element(*i)
+                        .map(|option| option.eq_ignore_ascii_case(b"MATCH"))
+                        .unwrap_or(false)
+                })
+                .and_then(|i| cmd.get_command_element(i + 1));
+            Some(pattern.unwrap_or(b"*").to_vec())
+        }
+        _ => None,
+    }
+}
+
+// The same glob-style matching as Redis.
+fn glob_match(pattern: &[u8], string: &[u8]) -> bool {
+    let (mut p, mut s) = (0, 0);
+    while p < pattern.len() && s < string.len() {
+        match pattern[p] {
+            b'*' => {
+                while pattern.get(p + 1) == Some(&b'*') {
+                    p += 1;
+                }
+                if p + 1 == pattern.len() {
+                    return true;
+                }
+                return (s..string.len()).any(|i| glob_match(&pattern[p + 1..], &string[i..]));
+            }
+            b'?' => s += 1,
+            b'[' => {
+                p += 1;
+                let not = pattern.get(p) == Some(&b'^');
+                if not {
+                    p += 1;
+                }
+                let c = string[s];
+                let mut matched = false;
+                loop {
+                    match pattern.get(p) {
+                        // Unclosed bracket.
+                        None => {
+                            p -= 1;
+                            break;
+                        }
+                        Some(b']') => break,
+                        Some(b'\\') if p + 1 < pattern.len() => {
+                            p += 1;
+                            matched |= pattern[p] == c;
+                        }
+                        Some(&start) if p + 2 < pattern.len() && pattern[p + 1] == b'-' => {
+                            let end = pattern[p + 2];
+                            let (low, high) = if start <= end {
+                                (start, end)
+                            } else {
+                                (end, start)
+                            };
+                            matched |= low <= c && c <= high;
+                            p += 2;
+                        }
+                        Some(&b) => matched |= b == c,
+                    }
+                    p += 1;
+                }
+                if matched == not {
+                    return false;
+                }
+                s += 1;
+            }
+            b'\\' if p + 1 < pattern.len() => {
+                p += 1;
+                if pattern[p] != string[s] {
+                    return false;
+                }
+                s += 1;
+            }
+            b => {
+                if b != string[s] {
+                    return false;
+                }
+                s += 1;
+            }
+        }
+        p += 1;
+    }
+    if s == string.len() {
+        while pattern.get(p) == Some(&b'*') {
+            p += 1;
+        }
+    }
+    p == pattern.len() && s == string.len()
+}
+
+pub fn get_key_indices(cmd: &Command) -> Result<Vec<usize>, NamespaceError> {
+    let len = cmd.get_command_len().unwrap_or(0);
+    let all_args = || (1..len).collect();
+    let num_keys = |index: usize| -> Result<usize, NamespaceError> {
+        cmd.get_command_element(index)
+            .and_then(|n| btou::<usize>(n).ok())
+            .ok_or(NamespaceError::InvalidRequest)
+    };
+
+    if cmd.get_type() == CmdType::Watch {
+        return Ok(all_args());
+    }
+
+    let indices = match cmd.get_data_cmd_type() {
+        DataCmdType::MGET
+        | DataCmdType::DEL
+        | DataCmdType::EXISTS
+        | DataCmdType::UNLINK
+        | DataCmdType::TOUCH
+        | DataCmdType::SUNION
+        | DataCmdType::SINTER
+        | DataCmdType::SDIFF
+        | DataCmdType::PFCOUNT
+        | DataCmdType::PFMERGE => all_args(),
+        DataCmdType::MSET | DataCmdType::MSETNX => (1..len).step_by(2).collect(),
+        DataCmdType::RENAME
+        | DataCmdType::RENAMENX
+        | DataCmdType::RPOPLPUSH
+        | DataCmdType::BRPOPLPUSH
+        | DataCmdType::SMOVE => vec![1, 2],
+        DataCmdType::BLPOP | DataCmdType::BRPOP => (1..len.saturating_sub(1)).collect(),
+        DataCmdType::BITOP => (2..len).collect(),
+        DataCmdType::EVAL | DataCmdType::EVALSHA => (3..3 + num_keys(2)?).collect(),
+        DataCmdType::DBSIZE
+        | DataCmdType::RANDOMKEY
+        | DataCmdType::FLUSHALL
+        | DataCmdType::FLUSHDB
+        | DataCmdType::KEYS
+        | DataCmdType::SCAN => return Err(NamespaceError::UnsupportedCmd),
+        DataCmdType::Others => return get_other_key_indices(cmd, len),
+        _ if len > 1 => vec![1],
+        _ => vec![],
+    };
+    Ok(indices)
+}
+
+fn get_other_key_indices(cmd: &Command, len: usize) -> Result<Vec<usize>, NamespaceError> {
+    let cmd_name = match cmd.get_command_name() {
+        Some(cmd_name) => cmd_name.to_uppercase(),
+        None => return Err(NamespaceError::InvalidRequest),
+    };
+    let num_keys = |index: usize| -> Result<usize, NamespaceError> {
+        cmd.get_command_element(index)
+            .and_then(|n| btou::<usize>(n).ok())
+            .ok_or(NamespaceError::InvalidRequest)
+    };
+    let has_option = |options: &[&[u8]]| {
+        (2..len).any(|i| {
+            cmd.get_command_element(i)
+                .map(|arg| options.iter().any(|o| arg.eq_ignore_ascii_case(o)))
+                .unwrap_or(false)
+        })
+    };
+
+    let indices = match cmd_name.as_str() {
+        "SUNIONSTORE" | "SINTERSTORE" | "SDIFFSTORE" => (1..len).collect(),
+        "ZUNIONSTORE" | "ZINTERSTORE" | "ZDIFFSTORE" => {
+            let mut indices = vec![1];
+            indices.extend(3..3 + num_keys(2)?);
+            indices
+        }
+        "ZUNION" | "ZINTER" | "ZDIFF" => (2..2 + num_keys(1)?).collect(),
+        "LMOVE" | "BLMOVE" | "COPY" | "GEOSEARCHSTORE" => vec![1, 2],
+        "OBJECT" | "MEMORY" => vec![2],
+        // XREAD [COUNT count] [BLOCK milliseconds] STREAMS key [key ...] id [id ...]
+        "XREAD" | "XREADGROUP" => {
+            let streams = (1..len)
+                .find(|i| {
+                    cmd.get_command_element(*i)
+                        .map(|arg| arg.eq_ignore_ascii_case(b"STREAMS"))
+                        .unwrap_or(false)
+                })
+                .ok_or(NamespaceError::InvalidRequest)?;
+            let key_num = (len - streams - 1) / 2;
+            (streams + 1..streams + 1 + key_num).collect()
+        }
+        // The keys in the patterns of BY and GET can't be prefixed.
+        "SORT" | "SORT_RO" if has_option(&[b"BY", b"GET", b"STORE"]) => {
+            return Err(NamespaceError::UnsupportedCmd)
+        }
+        "GEORADIUS" | "GEORADIUSBYMEMBER" if has_option(&[b"STORE", b"STOREDIST"]) => {
+            return Err(NamespaceError::UnsupportedCmd)
+        }
+        "MIGRATE" => return Err(NamespaceError::UnsupportedCmd),
+        // Keyless commands
+        "SCRIPT" | "WAIT" | "TIME" | "DEBUG" | "LATENCY" | "SLOWLOG" | "LASTSAVE" | "ROLE"
+        | "LOLWUT" => vec![],
+        // The channels are not in the namespace.
+        "PUBSUB" => return Err(NamespaceError::UnsupportedCmd),
+        _ if len > 1 => vec![1],
+        _ => vec![],
+    };
+    Ok(indices)
+}
+
+pub fn requires_stripping_prefix(data_cmd_type: DataCmdType) -> bool {
+    matches!(
+        data_cmd_type,
+        DataCmdType::KEYS | DataCmdType::SCAN | DataCmdType::BLPOP | DataCmdType::BRPOP
+    )
+}
+
+// Removes the prefix from the keys in the replies of KEYS, SCAN, BLPOP and BRPOP.
+// The keys of KEYS and SCAN are also filtered by the original `pattern`.
+pub fn strip_key_prefix(
+    data_cmd_type: DataCmdType,
+    resp: RespVec,
+    prefix: &[u8],
+    pattern: Option<&[u8]>,
+) -> RespVec {
+    let strip = |resp: RespVec| match resp {
+        Resp::Bulk(BulkStr::Str(key)) => match decode_key(prefix, &key) {
+            Some(key) => Resp::Bulk(BulkStr::Str(key.to_vec())),
+            None => Resp::Bulk(BulkStr::Str(key)),
+        },
+        others => others,
+    };
+    let filter = |keys: Vec<RespVec>| -> Vec<RespVec> {
+        keys.into_iter()
+            .filter_map(|resp| match resp {
+                Resp::Bulk(BulkStr::Str(key)) => {
+                    let key = decode_key(prefix, &key)?;
+                    if !glob_match(pattern.unwrap_or(b"*"), key) {
+                        return None;
+                    }
+                    Some(Resp::Bulk(BulkStr::Str(key.to_vec())))
+                }
+                _ => None,
+            })
+            .collect()
+    };
+
+    match (data_cmd_type, resp) {
+        (DataCmdType::KEYS, Resp::Arr(Array::Arr(keys))) => Resp::Arr(Array::Arr(filter(keys))),
+        (DataCmdType::SCAN, Resp::Arr(Array::Arr(mut resps))) if resps.len() == 2 => {
+            if let Some(Resp::Arr(Array::Arr(keys))) = resps.pop() {
+                resps.push(Resp::Arr(Array::Arr(filter(keys))));
+            }
+            Resp::Arr(Array::Arr(resps))
+        }
+        (DataCmdType::BLPOP, Resp::Arr(Array::Arr(mut resps)))
+        | (DataCmdType::BRPOP, Resp::Arr(Array::Arr(mut resps)))
+            if !resps.is_empty() =>
+        {
+            let key = strip(resps.remove(0));
+            resps.insert(0, key);
+            Resp::Arr(Array::Arr(resps))
+        }
+        (_, resp) => resp,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::cluster::ClusterName;
+    use crate::common::utils::generate_slot;
+    use crate::protocol::RespPacket;
+    use crate::proxy::command::new_command_pair;
+    use std::convert::TryFrom;
+
+    fn gen_cmd_ctx(args: &[&str]) -> CmdCtx {
+        let resp = Resp::Arr(Array::Arr(
+            args.iter()
+                .map(|arg| Resp::Bulk(BulkStr::Str(arg.as_bytes().to_vec())))
+                .collect(),
+        ));
+        let cmd = Command::new(Box::new(RespPacket::Data(resp)));
+        let (reply_sender, _) = new_command_pair(&cmd);
+        let cluster_name = ClusterName::try_from("mycluster").unwrap();
+        CmdCtx::new(cluster_name, cmd, reply_sender, 0, false)
+    }
+
+    fn get_args(cmd_ctx: &CmdCtx) -> Vec<String> {
+        let len = cmd_ctx.get_cmd().get_command_len().unwrap();
+        (0..len)
+            .map(|i| {
+                let arg = cmd_ctx.get_cmd().get_command_element(i).unwrap();
+                String::from_utf8(arg.to_vec()).unwrap()
+            })
+            .collect()
+    }
+
+    fn check_prefixed(args: &[&str], expected: &[&str]) {
+        let mut cmd_ctx = gen_cmd_ctx(args);
+        add_key_prefix(&mut cmd_ctx, b"t1:").unwrap();
+        assert_eq!(get_args(&cmd_ctx), expected);
+    }
+
+    #[test]
+    fn test_add_key_prefix() {
+        check_prefixed(&["GET", "a"], &["GET", "t1:{a}"]);
+        check_prefixed(&["GET", "{a}"], &["GET", "t1:}{a}"]);
+        check_prefixed(&["GET", "b{a}c"], &["GET", "t1:}b{a}c"]);
+        check_prefixed(&["GET", "{a"], &["GET", "t1:{{a}"]);
+        check_prefixed(&["SET", "a", "v"], &["SET", "t1:{a}", "v"]);
+        check_prefixed(
+            &["MSET", "a", "1", "b", "2"],
+            &["MSET", "t1:{a}", "1", "t1:{b}", "2"],
+        );
+        check_prefixed(&["DEL", "a", "b"], &["DEL", "t1:{a}", "t1:{b}"]);
+        check_prefixed(
+            &["BLPOP", "a", "b", "0"],
+            &["BLPOP", "t1:{a}", "t1:{b}", "0"],
+        );
+        check_prefixed(&["RENAME", "a", "b"], &["RENAME", "t1:{a}", "t1:{b}"]);
+        check_prefixed(
+            &["EVAL", "script", "2", "a", "b", "arg"],
+            &["EVAL", "script", "2", "t1:{a}", "t1:{b}", "arg"],
+        );
+        check_prefixed(
+            &["ZUNIONSTORE", "d", "2", "a", "b", "WEIGHTS", "1", "2"],
+            &[
+                "ZUNIONSTORE",
+                "t1:{d}",
+                "2",
+                "t1:{a}",
+                "t1:{b}",
+                "WEIGHTS",
+                "1",
+                "2",
+            ],
+        );
+        check_prefixed(
+            &["XREAD", "COUNT", "1", "STREAMS", "a", "b", "0", "0"],
+            &[
+                "XREAD", "COUNT", "1", "STREAMS", "t1:{a}", "t1:{b}", "0", "0",
+            ],
+        );
+        check_prefixed(&["WATCH", "a", "b"], &["WATCH", "t1:{a}", "t1:{b}"]);
+        check_prefixed(&["HSET", "h", "f", "v"], &["HSET", "t1:{h}", "f", "v"]);
+        check_prefixed(&["SCRIPT", "FLUSH"], &["SCRIPT", "FLUSH"]);
+        check_prefixed(&["KEYS", "a*"], &["KEYS", "t1:[{}]a**"]);
+        check_prefixed(&["SCAN", "0"], &["SCAN", "0", "MATCH", "t1:[{}]*"]);
+        check_prefixed(
+            &["SCAN", "0", "COUNT", "10", "match", "a*"],
+            &["SCAN", "0", "COUNT", "10", "match", "t1:[{}]a**"],
+        );
+    }
+
+    fn check_ensure_prefixed(args: &[&str], expected: &[&str]) {
+        let mut cmd_ctx = gen_cmd_ctx(args);
+        ensure_key_prefix(&mut cmd_ctx, b"t1:").unwrap();
+        assert_eq!(get_args(&cmd_ctx), expected);
+    }
+
+    #[test]
+    fn test_ensure_key_prefix() {
+        check_ensure_prefixed(&["GET", "t1:{a}"], &["GET", "t1:{a}"]);
+        check_ensure_prefixed(&["GET", "a"], &["GET", "t1:{a}"]);
+        check_ensure_prefixed(&["DEL", "t1:{a}", "b"], &["DEL", "t1:{a}", "t1:{b}"]);
+        check_ensure_prefixed(&["KEYS", "t1:[{}]a**"], &["KEYS", "t1:[{}]a**"]);
+        check_ensure_prefixed(&["KEYS", "*"], &["KEYS", "t1:[{}]**"]);
+        check_ensure_prefixed(
+            &["SCAN", "0", "MATCH", "t1:[{}]**"],
+            &["SCAN", "0", "MATCH", "t1:[{}]**"],
+        );
+        check_ensure_prefixed(&["SCAN", "0"], &["SCAN", "0", "MATCH", "t1:[{}]*"]);
+    }
+
+    #[test]
+    fn test_unsupported_cmd() {
+        for args in [
+            vec!["FLUSHALL"],
+            vec!["RANDOMKEY"],
+            vec!["DBSIZE"],
+            vec!["SORT", "a", "BY", "w_*"],
+        ]
+        .iter()
+        {
+            let mut cmd_ctx = gen_cmd_ctx(args);
+            assert_eq!(
+                add_key_prefix(&mut cmd_ctx, b"t1:"),
+                Err(NamespaceError::UnsupportedCmd)
+            );
+        }
+        for key in ["a}", "{}a", ""].iter() {
+            let mut cmd_ctx = gen_cmd_ctx(&["GET", key]);
+            assert_eq!(
+                add_key_prefix(&mut cmd_ctx, b"t1:"),
+                Err(NamespaceError::UnsupportedKey)
+            );
+        }
+        let mut cmd_ctx = gen_cmd_ctx(&["PUBSUB", "CHANNELS"]);
+        assert_eq!(
+            add_key_prefix(&mut cmd_ctx, b"t1:"),
+            Err(NamespaceError::UnsupportedCmd)
+        );
+        let mut cmd_ctx = gen_cmd_ctx(&["EVAL", "script", "x"]);
+        assert_eq!(
+            add_key_prefix(&mut cmd_ctx, b"t1:"),
+            Err(NamespaceError::InvalidRequest)
+        );
+    }
+
+    #[test]
+    fn test_prefixed_slot() {
+        for key in ["a", "{user}a", "b{a}c", "{a", "a{"].iter() {
+            let mut cmd_ctx = gen_cmd_ctx(&["GET", key]);
+            add_key_prefix(&mut cmd_ctx, b"t1:").unwrap();
+            assert_eq!(
+                cmd_ctx.get_cmd().get_slot(),
+                Some(generate_slot(key.as_bytes()))
+            );
+        }
+    }
+
+    #[test]
+    fn test_decode_key() {
+        for key in ["a", "{a}", "b{a}c", "{a", "}{a}"].iter() {
+            let prefixed = encode_key(b"t1:", key.as_bytes()).unwrap();
+            assert_eq!(decode_key(b"t1:", &prefixed), Some(key.as_bytes()));
+        }
+        assert_ne!(encode_key(b"t1:", b"a"), encode_key(b"t1:", b"{a}"));
+        assert_eq!(decode_key(b"t1:", b"t2:{a}"), None);
+        assert_eq!(decode_key(b"t1:", b"t1:x:{a}"), None);
+        assert_eq!(decode_key(b"t1:", b"t1:{}"), None);
+    }
+
+    #[test]
+    fn test_prefix_pattern() {
+        assert_eq!(prefix_pattern(b"t*1:", b"a*"), b"t\\*1:[{}]a**".to_vec());
+        assert!(is_pattern_prefixed(b"t*1:", &prefix_pattern(b"t*1:", b"a")));
+        assert!(!is_pattern_prefixed(b"t*1:", b"t\\*1:a"));
+    }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match(b"*", b"abc"));
+        assert!(glob_match(b"*", b""));
+        assert!(glob_match(b"a*c", b"abbc"));
+        assert!(!glob_match(b"a*c", b"abcd"));
+        assert!(glob_match(b"h?llo", b"hello"));
+        assert!(glob_match(b"h[ae]llo", b"hallo"));
+        assert!(!glob_match(b"h[^e]llo", b"hello"));
+        assert!(glob_match(b"h[a-b]llo", b"hbllo"));
+        assert!(glob_match(b"h[b-a]llo", b"hbllo"));
+        assert!(glob_match(b"a\\*", b"a*"));
+        assert!(!glob_match(b"a\\*", b"ab"));
+        assert!(glob_match(b"{a}*", b"{a}b"));
+        assert!(!glob_match(b"abc", b"ab"));
+    }
+
+    #[test]
+    fn test_strip_key_prefix() {
+        let bulk = |s: &str| Resp::Bulk(BulkStr::Str(s.as_bytes().to_vec()));
+        let keys = Resp::Arr(Array::Arr(vec![
+            bulk("t1:{a}"),
+            bulk("t1:}{b}c"),
+            bulk("t1:x:{a}"),
+        ]));
+        let stripped = Resp::Arr(Array::Arr(vec![bulk("a"), bulk("{b}c")]));
+        assert_eq!(
+            strip_key_prefix(DataCmdType::KEYS, keys.clone(), b"t1:", Some(b"*")),
+            stripped
+        );
+        assert_eq!(
+            strip_key_prefix(DataCmdType::KEYS, keys.clone(), b"t1:", Some(b"{*")),
+            Resp::Arr(Array::Arr(vec![bulk("{b}c")]))
+        );
+
+        let scan = Resp::Arr(Array::Arr(vec![bulk("0"), keys]));
+        assert_eq!(
+            strip_key_prefix(DataCmdType::SCAN, scan, b"t1:", None),
+            Resp::Arr(Array::Arr(vec![bulk("0"), stripped]))
+        );
+
+        let pop = Resp::Arr(Array::Arr(vec![bulk("t1:{a}"), bulk("t1:v")]));
+        assert_eq!(
+            strip_key_prefix(DataCmdType::BLPOP, pop, b"t1:", None),
+            Resp::Arr(Array::Arr(vec![bulk("a"), bulk("t1:v")]))
+        );
+        assert_eq!(
+            strip_key_prefix(DataCmdType::BLPOP, Resp::Bulk(BulkStr::Nil), b"t1:", None),
+            Resp::Bulk(BulkStr::Nil)
+        );
+    }
+}
@@ -0,0 +1,253 @@
+use crate::common::cluster::ClusterName;
+use crate::protocol::{Array, BulkStr, Resp, RespVec};
+use dashmap::DashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+// The bucket could hold the tokens of one second at most,
+// which is also the largest burst.
+struct TokenBucket {
+    rate: u64,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    fn new(rate: u64, now: Instant) -> Self {
+        Self {
+            rate,
+            tokens: rate as f64,
+            last_refill: now,
+        }
+    }
+
+    fn refill(&mut self, rate: u64, now: Instant) {
+        if rate != self.rate {
+            self.rate = rate;
+            self.tokens = self.tokens.min(rate as f64);
+        }
+        let elapsed = now.saturating_duration_since(self.last_refill);
+        self.last_refill = now;
+        self.tokens = (self.tokens + elapsed.as_secs_f64() * rate as f64).min(rate as f64);
+    }
+
+    // A request larger than the bucket is allowed when the bucket is full
+    // and the tokens become negative so that the following requests will wait.
+    fn has_enough(&self, n: u64) -> bool {
+        self.rate == 0 || self.tokens >= n.min(self.rate) as f64
+    }
+
+    fn consume(&mut self, n: u64) {
+        if self.rate != 0 {
+            self.tokens -= n as f64;
+        }
+    }
+}
+
+struct QuotaState {
+    ops: TokenBucket,
+    bytes: TokenBucket,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum QuotaLimit {
+    Ops,
+    Bytes,
+}
+
+pub struct ClusterQuota {
+    state: Mutex<QuotaState>,
+    throttled_ops: AtomicU64,
+    throttled_bytes: AtomicU64,
+}
+
+impl ClusterQuota {
+    fn new(max_ops_per_sec: u64, max_bytes_per_sec: u64, now: Instant) -> Self {
+        Self {
+            state: Mutex::new(QuotaState {
+                ops: TokenBucket::new(max_ops_per_sec, now),
+                bytes: TokenBucket::new(max_bytes_per_sec, now),
+            }),
+            throttled_ops: AtomicU64::new(0),
+            throttled_bytes: AtomicU64::new(0),
+        }
+    }
+
+    fn acquire(
+        &self,
+        max_ops_per_sec: u64,
+        max_bytes_per_sec: u64,
+        bytes: u64,
+        now: Instant,
+    ) -> Result<(), QuotaLimit> {
+        let mut state = self.state.lock().expect("ClusterQuota::acquire");
+        state.ops.refill(max_ops_per_sec, now);
+        state.bytes.refill(max_bytes_per_sec, now);
+
+        if !state.ops.has_enough(1) {
+            self.throttled_ops.fetch_add(1, Ordering::Relaxed);
+            return Err(QuotaLimit::Ops);
+        }
+        if !state.bytes.has_enough(bytes) {
+            self.throttled_bytes.fetch_add(1, Ordering::Relaxed);
+            return Err(QuotaLimit::Bytes);
+        }
+        state.ops.consume(1);
+        state.bytes.consume(bytes);
+        Ok(())
+    }
+}
+
+// The limits are read from the cluster config on every request
+// so that the changes of the config take effect immediately.
+pub struct QuotaMap {
+    clusters: DashMap<ClusterName, Arc<ClusterQuota>>,
+}
+
+impl Default for QuotaMap {
+    fn default() -> Self {
+        Self {
+            clusters: DashMap::new(),
+        }
+    }
+}
+
+impl QuotaMap {
+    // 0 means unlimited. `get_bytes` is only called when the bandwidth is limited.
+    pub fn acquire<F>(
+        &self,
+        cluster_name: &ClusterName,
+        max_ops_per_sec: u64,
+        max_bytes_per_sec: u64,
+        get_bytes: F,
+    ) -> Result<(), QuotaLimit>
+    where
+        F: FnOnce() -> u64,
+    {
+        if max_ops_per_sec == 0 && max_bytes_per_sec == 0 {
+            return Ok(());
+        }
+        let bytes = if max_bytes_per_sec == 0 {
+            0
+        } else {
+            get_bytes()
+        };
+        let now = Instant::now();
+
+        let quota = match self.clusters.get(cluster_name) {
+            Some(quota) => quota.clone(),
+            None => self
+                .clusters
+                .entry(cluster_name.clone())
+                .or_insert_with(|| {
+                    Arc::new(ClusterQuota::new(max_ops_per_sec, max_bytes_per_sec, now))
+                })
+                .clone(),
+        };
+        quota.acquire(max_ops_per_sec, max_bytes_per_sec, bytes, now)
+    }
+
+    pub fn info(&self) -> RespVec {
+        let clusters = self
+            .clusters
+            .iter()
+            .map(|item| {
+                let lines = vec![
+                    format!("name: {}", item.key()),
+                    format!(
+                        "throttled_ops: {}",
+                        item.value().throttled_ops.load(Ordering::Relaxed)
+                    ),
+                    format!(
+                        "throttled_bytes: {}",
+                        item.value().throttled_bytes.load(Ordering::Relaxed)
+                    ),
+                ];
+                Resp::Arr(Array::Arr(
+                    lines
+                        .into_iter()
+                        .map(|s| Resp::Bulk(BulkStr::Str(s.into_bytes())))
+                        .collect(),
+                ))
+            })
+            .collect();
+        Resp::Arr(Array::Arr(clusters))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryFrom;
+    use std::time::Duration;
+
+    #[test]
+    fn test_token_bucket() {
+        let now = Instant::now();
+        let mut bucket = TokenBucket::new(2, now);
+        assert!(bucket.has_enough(1));
+        bucket.consume(1);
+        assert!(bucket.has_enough(1));
+        bucket.consume(1);
+        assert!(!bucket.has_enough(1));
+
+        bucket.refill(2, now + Duration::from_millis(500));
+        assert!(bucket.has_enough(1));
+        bucket.consume(1);
+        assert!(!bucket.has_enough(1));
+
+        // Never exceeds the rate.
+        bucket.refill(2, now + Duration::from_secs(10));
+        assert!(bucket.has_enough(2));
+        assert!(bucket.tokens <= 2.0);
+    }
+
+    #[test]
+    fn test_large_request() {
+        let now = Instant::now();
+        let mut bucket = TokenBucket::new(10, now);
+        assert!(bucket.has_enough(100));
+        bucket.consume(100);
+        bucket.refill(10, now + Duration::from_secs(5));
+        assert!(!bucket.has_enough(1));
+        bucket.refill(10, now + Duration::from_secs(10));
+        assert!(bucket.has_enough(1));
+    }
+
+    #[test]
+    fn test_unlimited() {
+        let now = Instant::now();
+        let mut bucket = TokenBucket::new(0, now);
+        bucket.consume(1000);
+        assert!(bucket.has_enough(1000));
+    }
+
+    #[test]
+    fn test_quota_map() {
+        let quota_map = QuotaMap::default();
+        let cluster_name = ClusterName::try_from("mycluster").unwrap();
+
+        for _ in 0..100 {
+            assert!(quota_map.acquire(&cluster_name, 0, 0, || 0).is_ok());
+        }
+        assert!(quota_map.clusters.is_empty());
+
+        assert!(quota_map.acquire(&cluster_name, 1, 0, || 0).is_ok());
+        assert_eq!(
+            quota_map.acquire(&cluster_name, 1, 0, || 0),
+            Err(QuotaLimit::Ops)
+        );
+        // Bytes limit.
+        let other = ClusterName::try_from("other").unwrap();
+        assert!(quota_map.acquire(&other, 0, 10, || 10).is_ok());
+        assert_eq!(
+            quota_map.acquire(&other, 0, 10, || 10),
+            Err(QuotaLimit::Bytes)
+        );
+
+        let quota = quota_map.clusters.get(&cluster_name).unwrap().clone();
+        assert_eq!(quota.throttled_ops.load(Ordering::Relaxed), 1);
+        assert_eq!(quota.throttled_bytes.load(Ordering::Relaxed), 0);
+    }
+}
@@ -0,0 +1,207 @@
+use super::backend::CmdTask;
+use super::cluster::ClusterTag;
+use super::sender::{CmdTaskSender, CmdTaskSenderFactory};
+use crate::common::cluster::ClusterName;
+use crate::common::future_group::{new_auto_drop_future, FutureAutoStopHandle};
+use crate::common::resp_execution::keep_connecting_and_sending_cmd;
+use crate::common::track::TrackedFutureRegistry;
+use crate::protocol::{BulkStr, RedisClientError, RedisClientFactory, Resp, RespVec};
+use crate::replication::replicator::MasterMeta;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+const LINK_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+pub struct ReplicaNode<S: CmdTaskSender> {
+    address: String,
+    sender: S,
+    // Updated by `INFO replication` periodically.
+    link_up: Arc<AtomicBool>,
+    _handle: FutureAutoStopHandle,
+}
+
+impl<S: CmdTaskSender> ReplicaNode<S> {
+    fn is_available(&self) -> bool {
+        self.link_up.load(Ordering::Relaxed) && self.sender.get_conn_states().failed == 0
+    }
+}
+
+// Replicas of the local masters which are used to serve the read commands.
+pub struct ReplicaReadMap<S: CmdTaskSender> {
+    // (cluster name, master address) => replicas
+    replicas: HashMap<(ClusterName, String), Vec<Arc<ReplicaNode<S>>>>,
+    cursor: AtomicUsize,
+}
+
+impl<S: CmdTaskSender> ReplicaReadMap<S> {
+    pub fn empty() -> Self {
+        Self {
+            replicas: HashMap::new(),
+            cursor: AtomicUsize::new(0),
+        }
+    }
+
+    // The existing replica nodes are reused to keep the connections and link states.
+    pub fn from_master_metadata<F, CF>(
+        old_map: &Self,
+        masters: Vec<MasterMeta>,
+        sender_factory: &F,
+        client_factory: Arc<CF>,
+        future_registry: Arc<TrackedFutureRegistry>,
+    ) -> Self
+    where
+        F: CmdTaskSenderFactory<Sender = S>,
+        CF: RedisClientFactory,
+    {
+        let mut old_nodes = HashMap::new();
+        for ((cluster_name, _), nodes) in old_map.replicas.iter() {
+            for node in nodes.iter() {
+                old_nodes.insert((cluster_name.clone(), node.address.clone()), node.clone());
+            }
+        }
+
+        let mut replicas = HashMap::new();
+        for meta in masters.into_iter() {
+            let MasterMeta {
+                cluster_name,
+                master_node_address,
+                replicas: peers,
+            } = meta;
+            let nodes = peers
+                .into_iter()
+                .map(|peer| {
+                    let key = (cluster_name.clone(), peer.node_address);
+                    if let Some(node) = old_nodes.get(&key) {
+                        return node.clone();
+                    }
+                    Arc::new(Self::create_node(
+                        &cluster_name,
+                        key.1,
+                        sender_factory,
+                        client_factory.clone(),
+                        future_registry.clone(),
+                    ))
+                })
+                .collect();
+            replicas.insert((cluster_name, master_node_address), nodes);
+        }
+
+        Self {
+            replicas,
+            cursor: AtomicUsize::new(0),
+        }
+    }
+
+    fn create_node<F, CF>(
+        cluster_name: &ClusterName,
+        address: String,
+        sender_factory: &F,
+        client_factory: Arc<CF>,
+        future_registry: Arc<TrackedFutureRegistry>,
+    ) -> ReplicaNode<S>
+    where
+        F: CmdTaskSenderFactory<Sender = S>,
+        CF: RedisClientFactory,
+    {
+        let link_up = Arc::new(AtomicBool::new(false));
+        let link_up_clone = link_up.clone();
+        let handle_result = move |resp: RespVec| -> Result<(), RedisClientError> {
+            link_up_clone.store(is_master_link_up(&resp), Ordering::Relaxed);
+            Ok(())
+        };
+        let cmd = vec![b"INFO".to_vec(), b"replication".to_vec()];
+        let fut = keep_connecting_and_sending_cmd(
+            client_factory,
+            address.clone(),
+            cmd,
+            LINK_CHECK_INTERVAL,
+            handle_result,
+        );
+        let (fut, handle) = new_auto_drop_future(fut);
+        let desc = format!(
+            "replica_read: cluster_name={} replica_node_address={}",
+            cluster_name, address
+        );
+        let fut = TrackedFutureRegistry::wrap(future_registry, fut, desc);
+        tokio::spawn(fut);
+
+        ReplicaNode {
+            sender: sender_factory.create(address.clone()),
+            address,
+            link_up,
+            _handle: handle,
+        }
+    }
+
+    pub fn has_replicas(&self, cluster_name: &ClusterName, master_address: &str) -> bool {
+        self.replicas
+            .get(&(cluster_name.clone(), master_address.to_string()))
+            .map(|nodes| !nodes.is_empty())
+            .unwrap_or(false)
+    }
+
+    // Returns the task back when there's no available replica
+    // or it fails to send it to all the available replicas.
+    pub fn send(&self, cmd_task: S::Task, master_address: &str) -> Result<(), S::Task>
+    where
+        S::Task: CmdTask + ClusterTag,
+    {
+        let key = (
+            cmd_task.get_cluster_name().clone(),
+            master_address.to_string(),
+        );
+        let nodes = match self.replicas.get(&key) {
+            Some(nodes) if !nodes.is_empty() => nodes,
+            _ => return Err(cmd_task),
+        };
+
+        let start = self.cursor.fetch_add(1, Ordering::Relaxed);
+        let available_nodes = (0..nodes.len())
+            .filter_map(|i| nodes.get((start + i) % nodes.len()))
+            .filter(|node| node.is_available());
+        let mut cmd_task = cmd_task;
+        for node in available_nodes {
+            cmd_task = match node.sender.try_send(cmd_task) {
+                Ok(()) => return Ok(()),
+                Err(cmd_task) => cmd_task,
+            };
+            warn!("failed to send to replica {}", node.address);
+        }
+        Err(cmd_task)
+    }
+}
+
+fn is_master_link_up(resp: &RespVec) -> bool {
+    let info = match resp {
+        Resp::Bulk(BulkStr::Str(info)) => info,
+        _ => return false,
+    };
+    String::from_utf8_lossy(info)
+        .lines()
+        .any(|line| line.trim() == "master_link_status:up")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_master_link_up() {
+        let info =
+            "# Replication\r\nrole:slave\r\nmaster_host:127.0.0.1\r\nmaster_link_status:up\r\n";
+        assert!(is_master_link_up(&Resp::Bulk(BulkStr::Str(
+            info.as_bytes().to_vec()
+        ))));
+        let info = "# Replication\r\nrole:slave\r\nmaster_link_status:down\r\n";
+        assert!(!is_master_link_up(&Resp::Bulk(BulkStr::Str(
+            info.as_bytes().to_vec()
+        ))));
+        let info = "# Replication\r\nrole:master\r\nconnected_slaves:0\r\n";
+        assert!(!is_master_link_up(&Resp::Bulk(BulkStr::Str(
+            info.as_bytes().to_vec()
+        ))));
+        assert!(!is_master_link_up(&Resp::Error(b"ERR".to_vec())));
+    }
+}
@@ -2,12 +2,12 @@ use super::backend::{
     BackendError, BackendNode, CmdTask, CmdTaskResultHandler, CmdTaskResultHandlerFactory,
     ConnFactory, ReqTask,
 };
+use super::breaker::{BreakerMap, CircuitBreaker};
 use super::service::ServerProxyConfig;
-use crate::common::response::ERR_BACKEND_CONNECTION;
+use crate::common::response::{ERR_BACKEND_CIRCUIT_OPEN, ERR_BACKEND_CONNECTION};
 use crate::common::track::TrackedFutureRegistry;
 use crate::protocol::Resp;
 use std::collections::HashMap;
-use std::num::NonZeroUsize;
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::{Arc, RwLock, Weak};
 
@@ -15,6 +15,34 @@ pub trait CmdTaskSender {
     type Task: CmdTask;
 
     fn send(&self, cmd_task: Self::Task) -> Result<(), BackendError>;
+
+    // Returns the task back instead of replying the error to it
+    // so that the caller can send it to somewhere else.
+    // The senders not supporting it reply the error inside `send`.
+    fn try_send(&self, cmd_task: Self::Task) -> Result<(), Self::Task> {
+        let _ = self.send(cmd_task);
+        Ok(())
+    }
+
+    fn get_conn_states(&self) -> ConnStates {
+        ConnStates::default()
+    }
+}
+
+// Connection numbers of a sender, only used for metrics.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct ConnStates {
+    pub connected: usize,
+    pub failed: usize,
+}
+
+impl ConnStates {
+    pub fn merge(self, other: Self) -> Self {
+        Self {
+            connected: self.connected + other.connected,
+            failed: self.failed + other.failed,
+        }
+    }
 }
 
 pub trait CmdTaskSenderFactory {
@@ -26,12 +54,19 @@ pub trait CmdTaskSenderFactory {
 pub struct RecoverableBackendNode<F: CmdTaskResultHandlerFactory> {
     address: String,
     node: BackendNode<<F as CmdTaskResultHandlerFactory>::Handler>,
+    breaker: Arc<CircuitBreaker>,
 }
 
 impl<F: CmdTaskResultHandlerFactory> CmdTaskSender for RecoverableBackendNode<F> {
     type Task = <<F as CmdTaskResultHandlerFactory>::Handler as CmdTaskResultHandler>::Task;
 
     fn send(&self, cmd_task: Self::Task) -> Result<(), BackendError> {
+        if !self.breaker.allow_request() {
+            cmd_task.set_resp_result(Ok(Resp::Error(
+                format!("{}: {}", ERR_BACKEND_CIRCUIT_OPEN, self.address).into_bytes(),
+            )));
+            return Err(BackendError::Canceled);
+        }
         self.node.send(cmd_task).map_err(|e| {
             let cmd_task = e.into_inner();
             cmd_task.set_resp_result(Ok(Resp::Error(
@@ -41,6 +76,30 @@ impl<F: CmdTaskResultHandlerFactory> CmdTaskSender for RecoverableBackendNode<F>
             BackendError::Canceled
         })
     }
+
+    fn try_send(&self, cmd_task: Self::Task) -> Result<(), Self::Task> {
+        if !self.breaker.allow_request() {
+            return Err(cmd_task);
+        }
+        self.node.send(cmd_task).map_err(|e| {
+            error!("backend node is closed");
+            e.into_inner()
+        })
+    }
+
+    fn get_conn_states(&self) -> ConnStates {
+        if self.node.is_conn_failed() {
+            ConnStates {
+                connected: 0,
+                failed: 1,
+            }
+        } else {
+            ConnStates {
+                connected: 1,
+                failed: 0,
+            }
+        }
+    }
 }
 
 pub struct RecoverableBackendNodeFactory<F: CmdTaskResultHandlerFactory, CF: ConnFactory>
@@ -52,6 +111,7 @@ where
     handler_factory: Arc<F>,
     conn_factory: Arc<CF>,
     future_registry: Arc<TrackedFutureRegistry>,
+    breaker_map: Arc<BreakerMap>,
 }
 
 impl<F: CmdTaskResultHandlerFactory, CF: ConnFactory> RecoverableBackendNodeFactory<F, CF>
@@ -64,12 +124,14 @@ where
         handler_factory: Arc<F>,
         conn_factory: Arc<CF>,
         future_registry: Arc<TrackedFutureRegistry>,
+        breaker_map: Arc<BreakerMap>,
     ) -> Self {
         Self {
             config,
             handler_factory,
             conn_factory,
             future_registry,
+            breaker_map,
         }
     }
 }
@@ -83,16 +145,22 @@ where
     type Sender = RecoverableBackendNode<F>;
 
     fn create(&self, address: String) -> Self::Sender {
+        let breaker = self.breaker_map.get_or_create(&address);
         let (node, fut) = BackendNode::new(
             address.clone(),
             Arc::new(self.handler_factory.create()),
             self.config.clone(),
             self.conn_factory.clone(),
+            breaker.clone(),
         );
         let desc = format!("backend::RecoverableBackendNode: address={}", address);
         let fut = TrackedFutureRegistry::wrap(self.future_registry.clone(), fut, desc);
         tokio::spawn(fut);
-        Self::Sender { address, node }
+        Self::Sender {
+            address,
+            node,
+            breaker,
+        }
     }
 }
 
@@ -124,6 +192,10 @@ impl<S: CmdTaskSender> CmdTaskSender for ReqAdaptorSender<S> {
             }
         }
     }
+
+    fn get_conn_states(&self) -> ConnStates {
+        self.sender.get_conn_states()
+    }
 }
 
 pub struct ReqAdaptorSenderFactory<F: CmdTaskSenderFactory> {
@@ -162,17 +234,34 @@ impl<S: CmdTaskSender> CmdTaskSender for RRSenderGroup<S> {
         };
         sender.send(cmd_task)
     }
+
+    fn try_send(&self, cmd_task: Self::Task) -> Result<(), Self::Task> {
+        let index = self.cursor.fetch_add(1, Ordering::SeqCst);
+        match self.senders.get(index % self.senders.len()) {
+            Some(sender) => sender.try_send(cmd_task),
+            None => Err(cmd_task),
+        }
+    }
+
+    fn get_conn_states(&self) -> ConnStates {
+        self.senders
+            .iter()
+            .fold(ConnStates::default(), |states, sender| {
+                states.merge(sender.get_conn_states())
+            })
+    }
 }
 
+// The group size is `backend_conn_num` when the group is created.
 pub struct RRSenderGroupFactory<F: CmdTaskSenderFactory> {
-    group_size: NonZeroUsize,
+    config: Arc<ServerProxyConfig>,
     inner_factory: F,
 }
 
 impl<F: CmdTaskSenderFactory> RRSenderGroupFactory<F> {
-    pub fn new(group_size: NonZeroUsize, inner_factory: F) -> Self {
+    pub fn new(config: Arc<ServerProxyConfig>, inner_factory: F) -> Self {
         Self {
-            group_size,
+            config,
             inner_factory,
         }
     }
@@ -183,7 +272,7 @@ impl<F: CmdTaskSenderFactory> CmdTaskSenderFactory for RRSenderGroupFactory<F> {
 
     fn create(&self, address: String) -> Self::Sender {
         let mut senders = Vec::new();
-        for _ in 0..self.group_size.get() {
+        for _ in 0..self.config.get_backend_conn_num().get() {
             senders.push(self.inner_factory.create(address.clone()));
         }
         Self::Sender {
@@ -203,6 +292,14 @@ impl<S: CmdTaskSender> CmdTaskSender for CachedSender<S> {
     fn send(&self, cmd_task: Self::Task) -> Result<(), BackendError> {
         self.inner_sender.send(cmd_task)
     }
+
+    fn try_send(&self, cmd_task: Self::Task) -> Result<(), Self::Task> {
+        self.inner_sender.try_send(cmd_task)
+    }
+
+    fn get_conn_states(&self) -> ConnStates {
+        self.inner_sender.get_conn_states()
+    }
 }
 
 // TODO: support cleanup here to avoid memory leak.
@@ -262,18 +359,20 @@ pub fn gen_sender_factory<F: CmdTaskResultHandlerFactory, CF: ConnFactory>(
     reply_handler_factory: Arc<F>,
     conn_factory: Arc<CF>,
     future_registry: Arc<TrackedFutureRegistry>,
+    breaker_map: Arc<BreakerMap>,
 ) -> BackendSenderFactory<F, CF>
 where
     <F::Handler as CmdTaskResultHandler>::Task: CmdTask<Pkt = CF::Pkt>,
     CF::Pkt: Send,
 {
     CachedSenderFactory::new(RRSenderGroupFactory::new(
-        config.backend_conn_num,
+        config.clone(),
         RecoverableBackendNodeFactory::new(
             config.clone(),
             reply_handler_factory,
             conn_factory,
             future_registry,
+            breaker_map,
         ),
     ))
 }
@@ -286,18 +385,20 @@ pub fn gen_migration_sender_factory<F: CmdTaskResultHandlerFactory, CF: ConnFact
     reply_handler_factory: Arc<F>,
     conn_factory: Arc<CF>,
     future_registry: Arc<TrackedFutureRegistry>,
+    breaker_map: Arc<BreakerMap>,
 ) -> MigrationBackendSenderFactory<F, CF>
 where
     <F::Handler as CmdTaskResultHandler>::Task: CmdTask<Pkt = CF::Pkt>,
     CF::Pkt: Send,
 {
     RRSenderGroupFactory::new(
-        config.backend_conn_num,
+        config.clone(),
         ReqAdaptorSenderFactory::new(RecoverableBackendNodeFactory::new(
             config.clone(),
             reply_handler_factory,
             conn_factory,
             future_registry,
+            breaker_map,
         )),
     )
 }
@@ -1,40 +1,129 @@
+use super::client::{ClientInfo, ClientRegistry};
+use super::drain::{DrainController, DrainSource};
+use super::metrics::ProxyMetrics;
 use super::session::CmdCtxHandler;
-use super::session::{handle_session, Session};
+use super::session::{handle_session, Session, SessionError};
 use super::slowlog::SlowRequestLogger;
+use super::timeout::CmdTimeouts;
 use crate::common::config::ConfigError;
+use crate::common::response;
+use crate::common::tls::{create_tls_acceptor, TlsClientConnector, TlsError};
 use crate::common::track::TrackedFutureRegistry;
 use crate::common::utils::{resolve_first_address, ThreadSafe};
-use futures::{FutureExt, StreamExt};
+use crate::MAX_REDIRECTIONS;
+use futures::{future, FutureExt, StreamExt};
+use std::cmp::min;
 use std::error::Error;
 use std::num::NonZeroUsize;
 use std::sync::atomic::{AtomicI64, AtomicU64, AtomicUsize, Ordering};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use string_error::into_err;
-use tokio::net::TcpListener;
+use tokio::io::AsyncWriteExt;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time;
+use tokio_rustls::TlsAcceptor;
 
 #[derive(Debug)]
 pub struct ServerProxyConfig {
     pub address: String,
     pub announce_address: String,
     pub auto_select_cluster: bool,
-    pub slowlog_len: NonZeroUsize,
+    pub slowlog_len: AtomicUsize,
     pub slowlog_log_slower_than: AtomicI64,
     pub slowlog_sample_rate: AtomicU64,
+    // 0 disables the hot key tracking.
+    pub hotkey_sample_rate: AtomicU64,
+    // 0 means unlimited.
+    pub maxclients: AtomicU64,
+    // In seconds. 0 disables it.
+    pub idle_timeout: AtomicU64,
     pub thread_number: NonZeroUsize,
     pub session_channel_size: usize,
     pub backend_channel_size: usize,
-    pub backend_conn_num: NonZeroUsize,
-    pub backend_batch_min_time: usize,
-    pub backend_batch_max_time: usize,
-    pub backend_batch_buf: NonZeroUsize,
-    pub session_batch_min_time: usize,
-    pub session_batch_max_time: usize,
-    pub session_batch_buf: NonZeroUsize,
+    // The changes of the connection number and the batching parameters
+    // only apply to the new backend connections and the new sessions.
+    pub backend_conn_num: AtomicUsize,
+    pub backend_batch_min_time: AtomicUsize,
+    pub backend_batch_max_time: AtomicUsize,
+    pub backend_batch_buf: AtomicUsize,
+    pub session_batch_min_time: AtomicUsize,
+    pub session_batch_max_time: AtomicUsize,
+    pub session_batch_buf: AtomicUsize,
     pub active_redirection: bool,
-    pub max_redirections: Option<NonZeroUsize>,
+    // 0 means no limit.
+    pub max_redirections: AtomicUsize,
+    // TLS is enabled for the client connections when both cert and key are set.
+    pub tls_cert_path: Option<String>,
+    pub tls_key_path: Option<String>,
+    pub tls_client_ca_path: Option<String>,
+    // TLS is enabled for the connections to Redis and other server proxies
+    // when the CA is set.
+    pub backend_tls_ca_path: Option<String>,
+    pub backend_tls_cert_path: Option<String>,
+    pub backend_tls_key_path: Option<String>,
+    pub backend_tls_server_name: Option<String>,
+    // The Prometheus metrics are served at /metrics when this is set.
+    pub metrics_address: Option<String>,
+    // The write commands of the clusters with `audit_log` enabled
+    // are recorded to this file as JSON lines.
+    pub audit_log_path: Option<String>,
+    // In bytes. 0 disables the rotation.
+    pub audit_log_max_size: u64,
+    pub audit_log_max_files: usize,
+    // In milliseconds. The connection to the backend is closed when a reply
+    // takes longer than this. 0 disables it.
+    pub backend_reply_timeout: u64,
+    // The circuit breaker of a backend opens when the error rate in percentage
+    // or the consecutive reply timeouts reach these thresholds. 0 disables them.
+    pub breaker_error_rate: u64,
+    pub breaker_min_requests: u64,
+    pub breaker_consecutive_timeouts: u64,
+    // In milliseconds.
+    pub breaker_open_time: u64,
+    pub cmd_timeouts: CmdTimeouts,
+    // In seconds. The max time to wait for the sessions to be closed
+    // and the migration tasks to reach a safe point in the drain mode.
+    pub drain_timeout: u64,
 }
 
 impl ServerProxyConfig {
+    pub fn get_slowlog_len(&self) -> NonZeroUsize {
+        to_non_zero(self.slowlog_len.load(Ordering::Relaxed))
+    }
+
+    pub fn get_backend_conn_num(&self) -> NonZeroUsize {
+        to_non_zero(self.backend_conn_num.load(Ordering::Relaxed))
+    }
+
+    pub fn get_backend_batch_min_time(&self) -> usize {
+        self.backend_batch_min_time.load(Ordering::Relaxed)
+    }
+
+    pub fn get_backend_batch_max_time(&self) -> usize {
+        self.backend_batch_max_time.load(Ordering::Relaxed)
+    }
+
+    pub fn get_backend_batch_buf(&self) -> NonZeroUsize {
+        to_non_zero(self.backend_batch_buf.load(Ordering::Relaxed))
+    }
+
+    pub fn get_session_batch_min_time(&self) -> usize {
+        self.session_batch_min_time.load(Ordering::Relaxed)
+    }
+
+    pub fn get_session_batch_max_time(&self) -> usize {
+        self.session_batch_max_time.load(Ordering::Relaxed)
+    }
+
+    pub fn get_session_batch_buf(&self) -> NonZeroUsize {
+        to_non_zero(self.session_batch_buf.load(Ordering::Relaxed))
+    }
+
+    pub fn get_max_redirections(&self) -> Option<NonZeroUsize> {
+        NonZeroUsize::new(self.max_redirections.load(Ordering::Relaxed))
+    }
+
     pub fn get_slowlog_log_slower_than(&self) -> i64 {
         self.slowlog_log_slower_than.load(Ordering::Relaxed)
     }
@@ -51,6 +140,60 @@ impl ServerProxyConfig {
         self.slowlog_sample_rate
             .store(slowlog_sample_rate, Ordering::Relaxed)
     }
+
+    pub fn get_hotkey_sample_rate(&self) -> u64 {
+        self.hotkey_sample_rate.load(Ordering::Relaxed)
+    }
+
+    pub fn set_hotkey_sample_rate(&self, hotkey_sample_rate: u64) {
+        self.hotkey_sample_rate
+            .store(hotkey_sample_rate, Ordering::Relaxed)
+    }
+
+    pub fn get_maxclients(&self) -> u64 {
+        self.maxclients.load(Ordering::Relaxed)
+    }
+
+    pub fn set_maxclients(&self, maxclients: u64) {
+        self.maxclients.store(maxclients, Ordering::Relaxed)
+    }
+
+    pub fn get_idle_timeout(&self) -> u64 {
+        self.idle_timeout.load(Ordering::Relaxed)
+    }
+
+    pub fn set_idle_timeout(&self, idle_timeout: u64) {
+        self.idle_timeout.store(idle_timeout, Ordering::Relaxed)
+    }
+
+    pub fn create_tls_acceptor(&self) -> Result<Option<TlsAcceptor>, TlsError> {
+        match (self.tls_cert_path.as_ref(), self.tls_key_path.as_ref()) {
+            (Some(cert_path), Some(key_path)) => {
+                create_tls_acceptor(cert_path, key_path, self.tls_client_ca_path.as_deref())
+                    .map(Some)
+            }
+            _ => Ok(None),
+        }
+    }
+
+    pub fn create_backend_tls_connector(&self) -> Result<Option<TlsClientConnector>, TlsError> {
+        let ca_path = match self.backend_tls_ca_path.as_ref() {
+            Some(ca_path) => ca_path,
+            None => return Ok(None),
+        };
+        let cert_key_path = match (
+            self.backend_tls_cert_path.as_ref(),
+            self.backend_tls_key_path.as_ref(),
+        ) {
+            (Some(cert_path), Some(key_path)) => Some((cert_path.as_str(), key_path.as_str())),
+            _ => None,
+        };
+        let server_name = self
+            .backend_tls_server_name
+            .clone()
+            .ok_or(TlsError::InvalidServerName)?;
+        TlsClientConnector::new(ca_path, cert_key_path, server_name).map(Some)
+    }
 }
 
 impl ServerProxyConfig {
@@ -59,24 +202,46 @@ impl ServerProxyConfig {
             "address" => Ok(self.address.clone()),
             "announce_address" => Ok(self.announce_address.clone()),
             "auto_select_cluster" => Ok(self.auto_select_cluster.to_string()),
-            "slowlog_len" => Ok(self.slowlog_len.to_string()),
+            "slowlog_len" => Ok(self.get_slowlog_len().to_string()),
             "thread_number" => Ok(self.thread_number.to_string()),
             "session_channel_size" => Ok(self.session_channel_size.to_string()),
             "backend_channel_size" => Ok(self.backend_channel_size.to_string()),
-            "backend_conn_num" => Ok(self.backend_conn_num.to_string()),
+            "backend_conn_num" => Ok(self.get_backend_conn_num().to_string()),
             "slowlog_log_slower_than" => Ok(self.get_slowlog_log_slower_than().to_string()),
             "slowlog_sample_rate" => Ok(self.get_slowlog_sample_rate().to_string()),
-            "backend_batch_min_time" => Ok(self.backend_batch_min_time.to_string()),
-            "backend_batch_max_time" => Ok(self.backend_batch_max_time.to_string()),
-            "backend_batch_buf" => Ok(self.backend_batch_buf.to_string()),
-            "session_batch_min_time" => Ok(self.session_batch_min_time.to_string()),
-            "session_batch_max_time" => Ok(self.session_batch_max_time.to_string()),
-            "session_batch_buf" => Ok(self.session_batch_buf.to_string()),
+            "hotkey_sample_rate" => Ok(self.get_hotkey_sample_rate().to_string()),
+            "maxclients" => Ok(self.get_maxclients().to_string()),
+            "idle_timeout" => Ok(self.get_idle_timeout().to_string()),
+            "backend_batch_min_time" => Ok(self.get_backend_batch_min_time().to_string()),
+            "backend_batch_max_time" => Ok(self.get_backend_batch_max_time().to_string()),
+            "backend_batch_buf" => Ok(self.get_backend_batch_buf().to_string()),
+            "session_batch_min_time" => Ok(self.get_session_batch_min_time().to_string()),
+            "session_batch_max_time" => Ok(self.get_session_batch_max_time().to_string()),
+            "session_batch_buf" => Ok(self.get_session_batch_buf().to_string()),
             "active_redirection" => Ok(self.active_redirection.to_string()),
             "max_redirections" => Ok(self
-                .max_redirections
+                .get_max_redirections()
                 .map(|n| n.get().to_string())
                 .unwrap_or_else(|| "none".to_string())),
+            "tls_cert_path" => Ok(self.tls_cert_path.clone().unwrap_or_default()),
+            "tls_key_path" => Ok(self.tls_key_path.clone().unwrap_or_default()),
+            "tls_client_ca_path" => Ok(self.tls_client_ca_path.clone().unwrap_or_default()),
+            "backend_tls_ca_path" => Ok(self.backend_tls_ca_path.clone().unwrap_or_default()),
+            "backend_tls_cert_path" => Ok(self.backend_tls_cert_path.clone().unwrap_or_default()),
+            "backend_tls_key_path" => Ok(self.backend_tls_key_path.clone().unwrap_or_default()),
+            "backend_tls_server_name" => {
+                Ok(self.backend_tls_server_name.clone().unwrap_or_default())
+            }
+            "metrics_address" => Ok(self.metrics_address.clone().unwrap_or_default()),
+            "audit_log_path" => Ok(self.audit_log_path.clone().unwrap_or_default()),
+            "audit_log_max_size" => Ok(self.audit_log_max_size.to_string()),
+            "audit_log_max_files" => Ok(self.audit_log_max_files.to_string()),
+            "backend_reply_timeout" => Ok(self.backend_reply_timeout.to_string()),
+            "breaker_error_rate" => Ok(self.breaker_error_rate.to_string()),
+            "breaker_min_requests" => Ok(self.breaker_min_requests.to_string()),
+            "breaker_consecutive_timeouts" => Ok(self.breaker_consecutive_timeouts.to_string()),
+            "breaker_open_time" => Ok(self.breaker_open_time.to_string()),
+            "drain_timeout" => Ok(self.drain_timeout.to_string()),
             _ => Err(ConfigError::FieldNotFound),
         }
     }
@@ -86,11 +251,11 @@ impl ServerProxyConfig {
             "address" => Err(ConfigError::ReadonlyField),
             "announce_address" => Err(ConfigError::ReadonlyField),
             "auto_select_cluster" => Err(ConfigError::ReadonlyField),
-            "slowlog_len" => Err(ConfigError::ReadonlyField),
+            "slowlog_len" => set_non_zero(&self.slowlog_len, value),
             "thread_number" => Err(ConfigError::ReadonlyField),
             "session_channel_size" => Err(ConfigError::ReadonlyField),
             "backend_channel_size" => Err(ConfigError::ReadonlyField),
-            "backend_conn_num" => Err(ConfigError::ReadonlyField),
+            "backend_conn_num" => set_non_zero(&self.backend_conn_num, value),
             "slowlog_log_slower_than" => {
                 let int_value = value
                     .parse::<i64>()
@@ -105,43 +270,239 @@ impl ServerProxyConfig {
                 self.set_slowlog_sample_rate(int_value);
                 Ok(())
             }
-            "backend_batch_max_time" => Err(ConfigError::ReadonlyField),
-            "backend_batch_min_time" => Err(ConfigError::ReadonlyField),
-            "backend_batch_buf" => Err(ConfigError::ReadonlyField),
-            "session_batch_min_time" => Err(ConfigError::ReadonlyField),
-            "session_batch_max_time" => Err(ConfigError::ReadonlyField),
-            "session_batch_buf" => Err(ConfigError::ReadonlyField),
+            "hotkey_sample_rate" => {
+                let int_value = value
+                    .parse::<u64>()
+                    .map_err(|_| ConfigError::InvalidValue)?;
+                self.set_hotkey_sample_rate(int_value);
+                Ok(())
+            }
+            "maxclients" => {
+                let int_value = value
+                    .parse::<u64>()
+                    .map_err(|_| ConfigError::InvalidValue)?;
+                self.set_maxclients(int_value);
+                Ok(())
+            }
+            "idle_timeout" => {
+                let int_value = value
+                    .parse::<u64>()
+                    .map_err(|_| ConfigError::InvalidValue)?;
+                self.set_idle_timeout(int_value);
+                Ok(())
+            }
+            "backend_batch_max_time" => set_usize(&self.backend_batch_max_time, value),
+            "backend_batch_min_time" => set_usize(&self.backend_batch_min_time, value),
+            "backend_batch_buf" => set_non_zero(&self.backend_batch_buf, value),
+            "session_batch_min_time" => set_usize(&self.session_batch_min_time, value),
+            "session_batch_max_time" => set_usize(&self.session_batch_max_time, value),
+            "session_batch_buf" => set_non_zero(&self.session_batch_buf, value),
             "active_redirection" => Err(ConfigError::ReadonlyField),
-            "max_redirections" => Err(ConfigError::ReadonlyField),
+            "max_redirections" => {
+                // "none" is returned by `get_field` when it's not limited.
+                let int_value = if value.eq_ignore_ascii_case("none") {
+                    0
+                } else {
+                    value
+                        .parse::<usize>()
+                        .map_err(|_| ConfigError::InvalidValue)?
+                };
+                self.max_redirections
+                    .store(min(int_value, MAX_REDIRECTIONS), Ordering::Relaxed);
+                Ok(())
+            }
+            "tls_cert_path" => Err(ConfigError::ReadonlyField),
+            "tls_key_path" => Err(ConfigError::ReadonlyField),
+            "tls_client_ca_path" => Err(ConfigError::ReadonlyField),
+            "backend_tls_ca_path" => Err(ConfigError::ReadonlyField),
+            "backend_tls_cert_path" => Err(ConfigError::ReadonlyField),
+            "backend_tls_key_path" => Err(ConfigError::ReadonlyField),
+            "backend_tls_server_name" => Err(ConfigError::ReadonlyField),
+            "metrics_address" => Err(ConfigError::ReadonlyField),
+            "audit_log_path" => Err(ConfigError::ReadonlyField),
+            "audit_log_max_size" => Err(ConfigError::ReadonlyField),
+            "audit_log_max_files" => Err(ConfigError::ReadonlyField),
+            "backend_reply_timeout" => Err(ConfigError::ReadonlyField),
+            "breaker_error_rate" => Err(ConfigError::ReadonlyField),
+            "breaker_min_requests" => Err(ConfigError::ReadonlyField),
+            "breaker_consecutive_timeouts" => Err(ConfigError::ReadonlyField),
+            "breaker_open_time" => Err(ConfigError::ReadonlyField),
+            "drain_timeout" => Err(ConfigError::ReadonlyField),
             _ => Err(ConfigError::FieldNotFound),
         }
     }
+
+    // Applies the changed fields of `new_config` which can be set at runtime.
+    pub fn reload(&self, new_config: &ServerProxyConfig) -> ConfigReloadResult {
+        let mut result = ConfigReloadResult::default();
+        for field in CONFIG_FIELDS.iter() {
+            let (old_value, new_value) = match (self.get_field(field), new_config.get_field(field))
+            {
+                (Ok(old_value), Ok(new_value)) => (old_value, new_value),
+                _ => continue,
+            };
+            if old_value == new_value {
+                continue;
+            }
+            match self.set_value(field, &new_value) {
+                Ok(()) if NEW_CONNECTION_FIELDS.contains(field) => {
+                    result.new_connections_only.push(field.to_string())
+                }
+                Ok(()) => result.changed.push(field.to_string()),
+                Err(ConfigError::ReadonlyField) => result.restart_required.push(field.to_string()),
+                Err(err) => result.failed.push((field.to_string(), err)),
+            }
+        }
+        if self.cmd_timeouts != new_config.cmd_timeouts {
+            result.restart_required.push("cmd_timeouts".to_string());
+        }
+        result
+    }
+}
+
+// All the fields supported by `get_field`.
+const CONFIG_FIELDS: &[&str] = &[
+    "address",
+    "announce_address",
+    "auto_select_cluster",
+    "slowlog_len",
+    "thread_number",
+    "session_channel_size",
+    "backend_channel_size",
+    "backend_conn_num",
+    "slowlog_log_slower_than",
+    "slowlog_sample_rate",
+    "hotkey_sample_rate",
+    "maxclients",
+    "idle_timeout",
+    "backend_batch_min_time",
+    "backend_batch_max_time",
+    "backend_batch_buf",
+    "session_batch_min_time",
+    "session_batch_max_time",
+    "session_batch_buf",
+    "active_redirection",
+    "max_redirections",
+    "tls_cert_path",
+    "tls_key_path",
+    "tls_client_ca_path",
+    "backend_tls_ca_path",
+    "backend_tls_cert_path",
+    "backend_tls_key_path",
+    "backend_tls_server_name",
+    "metrics_address",
+    "audit_log_path",
+    "audit_log_max_size",
+    "audit_log_max_files",
+    "backend_reply_timeout",
+    "breaker_error_rate",
+    "breaker_min_requests",
+    "breaker_consecutive_timeouts",
+    "breaker_open_time",
+    "drain_timeout",
+];
+
+// These fields are only read when the connections or the backend senders are created.
+const NEW_CONNECTION_FIELDS: &[&str] = &[
+    "backend_conn_num",
+    "backend_batch_min_time",
+    "backend_batch_max_time",
+    "backend_batch_buf",
+    "session_batch_min_time",
+    "session_batch_max_time",
+    "session_batch_buf",
+];
+
+#[derive(Debug, Default)]
+pub struct ConfigReloadResult {
+    pub changed: Vec<String>,
+    // Changed but only applied to the new connections.
+    pub new_connections_only: Vec<String>,
+    pub restart_required: Vec<String>,
+    pub failed: Vec<(String, ConfigError)>,
+}
+
+fn to_non_zero(n: usize) -> NonZeroUsize {
+    NonZeroUsize::new(n).unwrap_or_else(|| NonZeroUsize::new(1).expect("to_non_zero"))
+}
+
+fn set_usize(field: &AtomicUsize, value: &str) -> Result<(), ConfigError> {
+    let int_value = value
+        .parse::<usize>()
+        .map_err(|_| ConfigError::InvalidValue)?;
+    field.store(int_value, Ordering::Relaxed);
+    Ok(())
+}
+
+fn set_non_zero(field: &AtomicUsize, value: &str) -> Result<(), ConfigError> {
+    let int_value = value
+        .parse::<NonZeroUsize>()
+        .map_err(|_| ConfigError::InvalidValue)?;
+    field.store(int_value.get(), Ordering::Relaxed);
+    Ok(())
 }
 
 #[derive(Clone)]
-pub struct ServerProxyService<H: CmdCtxHandler + ThreadSafe + Clone> {
+pub struct ServerProxyService<H: CmdCtxHandler + DrainSource + ThreadSafe + Clone> {
     config: Arc<ServerProxyConfig>,
     cmd_ctx_handler: H,
     slow_request_logger: Arc<SlowRequestLogger>,
+    proxy_metrics: Arc<ProxyMetrics>,
+    client_registry: Arc<ClientRegistry>,
     future_registry: Arc<TrackedFutureRegistry>,
+    drain_controller: Arc<DrainController>,
 }
 
-impl<H: CmdCtxHandler + ThreadSafe + Clone> ServerProxyService<H> {
+impl<H: CmdCtxHandler + DrainSource + ThreadSafe + Clone> ServerProxyService<H> {
     pub fn new(
         config: Arc<ServerProxyConfig>,
         cmd_ctx_handler: H,
         slow_request_logger: Arc<SlowRequestLogger>,
+        proxy_metrics: Arc<ProxyMetrics>,
+        client_registry: Arc<ClientRegistry>,
         future_registry: Arc<TrackedFutureRegistry>,
+        drain_controller: Arc<DrainController>,
     ) -> Self {
         Self {
             config,
             cmd_ctx_handler,
             slow_request_logger,
+            proxy_metrics,
+            client_registry,
             future_registry,
+            drain_controller,
         }
     }
 
+    // Returns after the drain mode is done.
     pub async fn run(&self) -> Result<(), Box<dyn Error>> {
+        self.serve().await?;
+        self.wait_drained().await;
+        Ok(())
+    }
+
+    async fn wait_drained(&self) {
+        let deadline = Instant::now() + Duration::from_secs(self.config.drain_timeout);
+        loop {
+            let session_num = self.client_registry.get_session_num();
+            let migration_safe = self.cmd_ctx_handler.is_migration_at_safe_point();
+            if session_num == 0 && migration_safe {
+                info!("draining is done");
+                return;
+            }
+            if Instant::now() >= deadline {
+                warn!(
+                    "drain timeout: {} sessions left, migration at safe point: {}",
+                    session_num, migration_safe
+                );
+                return;
+            }
+            time::delay_for(Duration::from_millis(100)).await;
+        }
+    }
+
+    // Returns when the drain mode starts.
+    async fn serve(&self) -> Result<(), Box<dyn Error>> {
         let address = self.config.address.clone();
         let address = resolve_first_address(&address).ok_or_else(|| {
             let err_str = format!("failed to resolve address: {}", address);
@@ -149,6 +510,14 @@ impl<H: CmdCtxHandler + ThreadSafe + Clone> ServerProxyService<H> {
             into_err(err_str)
         })?;
 
+        let tls_acceptor = self.config.create_tls_acceptor().map_err(|err| {
+            error!("failed to load TLS config: {}", err);
+            err
+        })?;
+        if tls_acceptor.is_some() {
+            info!("TLS is enabled for {}", address);
+        }
+
         let mut listener = TcpListener::bind(&address).await.map_err(|err| {
             error!("unable to bind address: {} {:?}", address, err);
             err
@@ -156,15 +525,20 @@ impl<H: CmdCtxHandler + ThreadSafe + Clone> ServerProxyService<H> {
 
         let forward_handler = self.cmd_ctx_handler.clone();
         let slow_request_logger = self.slow_request_logger.clone();
+        let proxy_metrics = self.proxy_metrics.clone();
 
         let session_id = AtomicUsize::new(0);
         let config = self.config.clone();
 
         let future_registry = self.future_registry.clone();
 
+        let mut drain_signal = self.drain_controller.signal();
         let mut s = listener.incoming();
-        while let Some(sock) = s.next().await {
-            let sock = sock?;
+        loop {
+            let sock = match drain_signal.or_drain(s.next()).await {
+                Some(Some(sock)) => sock?,
+                Some(None) | None => break,
+            };
 
             if let Err(err) = sock.set_nodelay(true) {
                 let err_str = format!("failed to set TCP_NODELAY: {:?}", err);
@@ -178,22 +552,81 @@ impl<H: CmdCtxHandler + ThreadSafe + Clone> ServerProxyService<H> {
             };
             info!("accept conn: {}", peer);
 
+            let client_guard = match self.client_registry.try_connect(config.get_maxclients()) {
+                Some(client_guard) => client_guard,
+                None => {
+                    warn!("reject conn for reaching maxclients: {}", peer);
+                    tokio::spawn(reject_conn(sock));
+                    continue;
+                }
+            };
+
             let curr_session_id = session_id.fetch_add(1, Ordering::SeqCst);
+            let client_info = Arc::new(ClientInfo::new(curr_session_id, peer.clone()));
+            let session_guard = self.client_registry.register_session(client_info.clone());
 
             let handle_clone = forward_handler.clone();
-            let session_handler = handle_session(
-                Arc::new(Session::new(
-                    curr_session_id,
-                    handle_clone,
-                    slow_request_logger.clone(),
-                    config.clone(),
-                )),
-                sock,
-                config.session_channel_size,
-                config.session_batch_min_time,
-                config.session_batch_max_time,
-                config.session_batch_buf,
-            );
+            let session = Arc::new(Session::new(
+                curr_session_id,
+                handle_clone,
+                slow_request_logger.clone(),
+                proxy_metrics.clone(),
+                config.clone(),
+                client_info,
+            ));
+            let session_config = config.clone();
+            let tls_acceptor = tls_acceptor.clone();
+            let drain_signal = self.drain_controller.signal();
+            // The TLS handshake should not block accepting other connections.
+            let session_handler = async move {
+                let _client_guard = client_guard;
+                let channel_size = session_config.session_channel_size;
+                let batch_min_time = session_config.get_session_batch_min_time();
+                let batch_max_time = session_config.get_session_batch_max_time();
+                let batch_buf = session_config.get_session_batch_buf();
+                match tls_acceptor {
+                    Some(tls_acceptor) => {
+                        let sock = tls_acceptor.accept(sock).await.map_err(SessionError::Io)?;
+                        handle_session(
+                            session,
+                            sock,
+                            channel_size,
+                            batch_min_time,
+                            batch_max_time,
+                            batch_buf,
+                            drain_signal,
+                        )
+                        .await
+                    }
+                    None => {
+                        handle_session(
+                            session,
+                            sock,
+                            channel_size,
+                            batch_min_time,
+                            batch_max_time,
+                            batch_buf,
+                            drain_signal,
+                        )
+                        .await
+                    }
+                }
+            };
+
+            // CLIENT KILL closes the session by dropping it.
+            let session_handler = async move {
+                let killed = session_guard.get_client_info().wait_killed();
+                match future::select(Box::pin(session_handler), Box::pin(killed)).await {
+                    future::Either::Left((res, _)) => res,
+                    future::Either::Right(_) => {
+                        info!(
+                            "session is killed {}",
+                            session_guard.get_client_info().get_addr()
+                        );
+                        Ok(())
+                    }
+                }
+            };
 
             let desc = format!("session: session_id={} peer={}", curr_session_id, peer);
             let fut = session_handler.map(move |res| match res {
@@ -203,6 +636,15 @@ impl<H: CmdCtxHandler + ThreadSafe + Clone> ServerProxyService<H> {
             let fut = TrackedFutureRegistry::wrap(future_registry.clone(), fut, desc);
             tokio::spawn(fut);
         }
+        // The listener is dropped to stop accepting new connections.
+        info!("stop listening on {}", address);
         Ok(())
     }
 }
+
+async fn reject_conn(mut sock: TcpStream) {
+    let reply = format!("-{}\r\n", response::ERR_MAX_CLIENTS);
+    if let Err(err) = sock.write_all(reply.as_bytes()).await {
+        debug!("failed to send reply to rejected conn: {:?}", err);
+    }
+}
@@ -1,18 +1,26 @@
-use super::backend::{CmdTask, CmdTaskFactory, CmdTaskResult};
+use super::backend::{
+    CmdTask, CmdTaskFactory, CmdTaskResult, ConnSink, ConnStream, CreateConnResult,
+};
+use super::client::{ClientInfo, ClusterClientGuard};
 use super::cluster::{ClusterTag, DEFAULT_CLUSTER};
 use super::command::{
     new_command_pair, CmdReplyReceiver, CmdReplySender, CmdType, Command, CommandError,
     CommandResult, DataCmdType, TaskReply, TaskResult,
 };
+use super::drain::DrainSignal;
+use super::metrics::ProxyMetrics;
 use super::service::ServerProxyConfig;
 use super::slowlog::{SlowRequestLogger, Slowlog, TaskEvent};
+use super::timeout::CmdTimeoutClass;
+use super::transaction::Transaction;
 use crate::common::batch::TryChunksTimeoutStreamExt;
 use crate::common::cluster::ClusterName;
+use crate::common::response::{ERR_BACKEND_CONNECTION, ERR_TIMEOUT, OK_REPLY};
 use crate::protocol::{
-    new_simple_packet_codec, BinSafeStr, DecodeError, EncodeError, Resp, RespCodec, RespPacket,
-    RespVec,
+    new_simple_packet_codec, Array, BinSafeStr, BulkStr, DecodeError, EncodeError, Resp, RespCodec,
+    RespPacket, RespVec, RespVersion,
 };
-use futures::{future, stream, Future, TryFutureExt};
+use futures::{future, stream, Future, FutureExt, Sink, Stream, TryFutureExt};
 use futures::{SinkExt, StreamExt, TryStreamExt};
 use std::boxed::Box;
 use std::cmp::min;
@@ -21,21 +29,38 @@ use std::convert::TryFrom;
 use std::error::Error;
 use std::fmt;
 use std::io;
+use std::mem;
 use std::num::NonZeroUsize;
 use std::pin::Pin;
 use std::sync;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
-use tokio::net::TcpStream;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::time::{self, timeout, Instant};
 use tokio_util::codec::Decoder;
 
 // CmdReplyReceiver is the fast path without heap allocation.
 pub type CmdReplyFuture<'a> =
     future::Either<CmdReplyReceiver, Pin<Box<dyn Future<Output = TaskResult> + Send + 'a>>>;
 
+pub type PubSubConnFuture =
+    Pin<Box<dyn Future<Output = CreateConnResult<RespPacket>> + Send + 'static>>;
+
 pub trait CmdHandler {
-    fn handle_cmd(&self, cmd: Command) -> CmdReplyFuture;
+    fn handle_cmd(&self, cmd: Command) -> CmdReplyFuture<'_>;
     fn handle_slowlog(&self, request: Box<RespPacket>, slowlog: Slowlog);
+    fn handle_metrics(&self, request: &RespPacket, reply: &RespPacket, slowlog: &Slowlog);
+    // Returns the error reply if the session is not allowed to subscribe.
+    fn create_pubsub_conn(&self) -> Result<PubSubConnFuture, RespVec>;
+    fn get_resp_version(&self) -> RespVersion;
+    // The session will be closed after being idle for this duration.
+    fn get_idle_timeout(&self) -> Option<Duration>;
+    fn handle_idle_timeout(&self);
+    // Called when the reply of a command is sent back to the client.
+    fn handle_reply_done(&self);
+    // The client will get a TIMEOUT error if the reply takes longer than this.
+    fn get_cmd_timeout(&self, cmd: &Command) -> Option<Duration>;
 }
 
 pub trait CmdCtxHandler {
@@ -43,8 +68,128 @@ pub trait CmdCtxHandler {
         &self,
         cmd_ctx: CmdCtx,
         result_receiver: CmdReplyReceiver,
-        session_cluster_name: &sync::RwLock<ClusterName>,
-    ) -> CmdReplyFuture;
+        session_state: &SessionState,
+    ) -> CmdReplyFuture<'_>;
+    fn create_pubsub_conn(&self, session_state: &SessionState)
+        -> Result<PubSubConnFuture, RespVec>;
+    fn get_idle_timeout(&self, session_state: &SessionState) -> Option<Duration>;
+    fn handle_idle_timeout(&self, session_state: &SessionState);
+}
+
+pub struct SessionState {
+    cluster_name: sync::RwLock<ClusterName>,
+    // The cluster this session has passed the password check for.
+    authenticated_cluster: sync::RwLock<Option<ClusterName>>,
+    transaction: sync::Mutex<Transaction>,
+    // Switched by HELLO. The backends always speak RESP2.
+    resp3: AtomicBool,
+    // Switched by READONLY and READWRITE.
+    read_mode: sync::RwLock<ReadMode>,
+    // Counted in the `maxclients` of the cluster it's sending commands to.
+    cluster_client: sync::Mutex<Option<ClusterClientGuard>>,
+    client_info: Arc<ClientInfo>,
+}
+
+impl SessionState {
+    pub fn new(cluster_name: ClusterName, client_info: Arc<ClientInfo>) -> Self {
+        client_info.set_cluster_name(cluster_name.clone());
+        Self {
+            cluster_name: sync::RwLock::new(cluster_name),
+            authenticated_cluster: sync::RwLock::new(None),
+            transaction: sync::Mutex::new(Transaction::default()),
+            resp3: AtomicBool::new(false),
+            read_mode: sync::RwLock::new(ReadMode::Default),
+            cluster_client: sync::Mutex::new(None),
+            client_info,
+        }
+    }
+
+    pub fn get_cluster_name(&self) -> ClusterName {
+        self.cluster_name
+            .read()
+            .expect("SessionState::get_cluster_name")
+            .clone()
+    }
+
+    pub fn set_cluster_name(&self, cluster_name: ClusterName) {
+        self.client_info.set_cluster_name(cluster_name.clone());
+        *self
+            .cluster_name
+            .write()
+            .expect("SessionState::set_cluster_name") = cluster_name;
+    }
+
+    pub fn is_authenticated(&self, cluster_name: &ClusterName) -> bool {
+        self.authenticated_cluster
+            .read()
+            .expect("SessionState::is_authenticated")
+            .as_ref()
+            == Some(cluster_name)
+    }
+
+    pub fn set_authenticated(&self, cluster_name: ClusterName) {
+        *self
+            .authenticated_cluster
+            .write()
+            .expect("SessionState::set_authenticated") = Some(cluster_name);
+    }
+
+    pub fn get_resp_version(&self) -> RespVersion {
+        if self.resp3.load(Ordering::Relaxed) {
+            RespVersion::V3
+        } else {
+            RespVersion::V2
+        }
+    }
+
+    pub fn set_resp_version(&self, version: RespVersion) {
+        self.resp3
+            .store(version == RespVersion::V3, Ordering::Relaxed);
+    }
+
+    pub fn get_read_mode(&self) -> ReadMode {
+        *self.read_mode.read().expect("SessionState::get_read_mode")
+    }
+
+    pub fn set_read_mode(&self, read_mode: ReadMode) {
+        *self.read_mode.write().expect("SessionState::set_read_mode") = read_mode;
+    }
+
+    pub fn get_client_cluster(&self) -> Option<ClusterName> {
+        self.cluster_client
+            .lock()
+            .expect("SessionState::get_client_cluster")
+            .as_ref()
+            .map(|guard| guard.get_cluster_name().clone())
+    }
+
+    // The previous guard is dropped so that it's not counted in the previous cluster anymore.
+    pub fn set_cluster_client(&self, guard: ClusterClientGuard) {
+        *self
+            .cluster_client
+            .lock()
+            .expect("SessionState::set_cluster_client") = Some(guard);
+    }
+
+    pub fn get_client_info(&self) -> &Arc<ClientInfo> {
+        &self.client_info
+    }
+
+    pub fn get_transaction(&self) -> sync::MutexGuard<'_, Transaction> {
+        self.transaction
+            .lock()
+            .expect("SessionState::get_transaction")
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReadMode {
+    // Follow the `read_preference` of the cluster.
+    Default,
+    // Set by READONLY. Read commands could be sent to the replicas.
+    ReadOnly,
+    // Set by READWRITE. All the commands are sent to the masters.
+    ReadWrite,
 }
 
 #[derive(Debug)]
@@ -54,6 +199,7 @@ pub struct CmdCtx {
     slowlog: Slowlog,
     cluster_name: ClusterName,
     redirection_times: Option<usize>,
+    read_mode: ReadMode,
 }
 
 impl CmdCtx {
@@ -71,6 +217,7 @@ impl CmdCtx {
             slowlog,
             cluster_name,
             redirection_times: None,
+            read_mode: ReadMode::Default,
         }
     }
 
@@ -90,6 +237,10 @@ impl CmdCtx {
         self.cmd.change_element(index, data)
     }
 
+    pub fn change_cmd_name(&mut self, cmd_name: Vec<u8>) -> bool {
+        self.cmd.change_command_name(cmd_name)
+    }
+
     // Returns remaining elements
     pub fn extract_inner_cmd(&mut self, removed_num: usize) -> Option<usize> {
         self.cmd.extract_inner_cmd(removed_num)
@@ -99,6 +250,10 @@ impl CmdCtx {
         self.cmd.wrap_cmd(preceding_element)
     }
 
+    pub fn append_cmd_elements(&mut self, following_elements: Vec<BinSafeStr>) -> bool {
+        self.cmd.append_elements(following_elements)
+    }
+
     pub fn get_cmd_type(&self) -> CmdType {
         self.cmd.get_type()
     }
@@ -114,12 +269,26 @@ impl CmdCtx {
     pub fn get_redirection_times(&self) -> Option<usize> {
         self.redirection_times
     }
+
+    pub fn set_read_mode(&mut self, read_mode: ReadMode) {
+        self.read_mode = read_mode
+    }
+
+    pub fn get_read_mode(&self) -> ReadMode {
+        self.read_mode
+    }
+
+    // Returns the original sender so that the reply could be observed before being sent back.
+    pub fn replace_reply_sender(&mut self, reply_sender: CmdReplySender) -> CmdReplySender {
+        mem::replace(&mut self.reply_sender, reply_sender)
+    }
 }
 
 pub struct SessionContext {
     cluster_name: ClusterName,
     session_id: usize,
     slowlog_enabled: bool,
+    read_mode: ReadMode,
 }
 
 impl CmdTask for CmdCtx {
@@ -144,9 +313,11 @@ impl CmdTask for CmdCtx {
         } = self;
         let task_result =
             result.map(|packet| Box::new(TaskReply::new(cmd.into_packet(), packet, slowlog)));
-        let res = reply_sender.send(task_result);
-        if let Err(e) = res {
-            error!("Failed to send result: {:?}", e);
+        match reply_sender.send(task_result) {
+            Ok(()) => (),
+            // The session has timed out the command or has been closed.
+            Err(CommandError::Canceled) => debug!("the reply receiver is dropped"),
+            Err(e) => error!("Failed to send result: {:?}", e),
         }
     }
 
@@ -163,6 +334,7 @@ impl CmdTask for CmdCtx {
             cluster_name: self.cluster_name.clone(),
             session_id: self.get_session_id(),
             slowlog_enabled: self.slowlog.is_enabled(),
+            read_mode: self.read_mode,
         }
     }
 
@@ -176,6 +348,10 @@ impl CmdTask for CmdCtx {
     fn log_event(&mut self, event: TaskEvent) {
         self.slowlog.log_event(event);
     }
+
+    fn is_blocking(&self) -> bool {
+        CmdTimeoutClass::from_cmd(self.get_cmd()) == CmdTimeoutClass::Blocking
+    }
 }
 
 impl ClusterTag for CmdCtx {
@@ -214,8 +390,10 @@ impl CmdTaskFactory for CmdCtxFactory {
             cluster_name,
             session_id,
             slowlog_enabled,
+            read_mode,
         } = context;
-        let cmd_ctx = CmdCtx::new(cluster_name, cmd, reply_sender, session_id, slowlog_enabled);
+        let mut cmd_ctx = CmdCtx::new(cluster_name, cmd, reply_sender, session_id, slowlog_enabled);
+        cmd_ctx.set_read_mode(read_mode);
         let fut = reply_receiver.map_ok(|reply| reply.into_resp_vec());
         (cmd_ctx, Box::pin(fut))
     }
@@ -223,9 +401,10 @@ impl CmdTaskFactory for CmdCtxFactory {
 
 pub struct Session<H: CmdCtxHandler> {
     session_id: usize,
-    cluster_name: sync::Arc<sync::RwLock<ClusterName>>,
+    state: SessionState,
     cmd_ctx_handler: H,
     slow_request_logger: sync::Arc<SlowRequestLogger>,
+    proxy_metrics: Arc<ProxyMetrics>,
     config: Arc<ServerProxyConfig>,
 }
 
@@ -234,27 +413,29 @@ impl<H: CmdCtxHandler> Session<H> {
         session_id: usize,
         cmd_ctx_handler: H,
         slow_request_logger: sync::Arc<SlowRequestLogger>,
+        proxy_metrics: Arc<ProxyMetrics>,
         config: Arc<ServerProxyConfig>,
+        client_info: Arc<ClientInfo>,
     ) -> Self {
         let cluster_name = ClusterName::try_from(DEFAULT_CLUSTER).expect("Session::new");
         Session {
             session_id,
-            cluster_name: sync::Arc::new(sync::RwLock::new(cluster_name)),
+            state: SessionState::new(cluster_name, client_info),
             cmd_ctx_handler,
             slow_request_logger,
+            proxy_metrics,
             config,
         }
     }
 }
 
 impl<H: CmdCtxHandler> CmdHandler for Session<H> {
-    fn handle_cmd(&self, cmd: Command) -> CmdReplyFuture {
+    fn handle_cmd(&self, cmd: Command) -> CmdReplyFuture<'_> {
+        self.state
+            .get_client_info()
+            .record_cmd(cmd.get_command_name());
         let (reply_sender, reply_receiver) = new_command_pair(&cmd);
-        let cluster_name = self
-            .cluster_name
-            .read()
-            .expect("Session::handle_cmd")
-            .clone();
+        let cluster_name = self.state.get_cluster_name();
 
         let slowlog_enabled = self
             .slow_request_logger
@@ -266,29 +447,69 @@ impl<H: CmdCtxHandler> CmdHandler for Session<H> {
             self.session_id,
             slowlog_enabled,
         );
+        cmd_ctx.set_read_mode(self.state.get_read_mode());
         cmd_ctx.log_event(TaskEvent::Created);
         self.cmd_ctx_handler
-            .handle_cmd_ctx(cmd_ctx, reply_receiver, &(*self.cluster_name))
+            .handle_cmd_ctx(cmd_ctx, reply_receiver, &self.state)
     }
 
     fn handle_slowlog(&self, request: Box<RespPacket>, slowlog: Slowlog) {
         self.slow_request_logger.add_slow_log(request, slowlog)
     }
+
+    fn handle_metrics(&self, request: &RespPacket, reply: &RespPacket, slowlog: &Slowlog) {
+        let cluster_name = self.state.get_cluster_name();
+        self.proxy_metrics
+            .record_reply(&cluster_name, request, reply, slowlog)
+    }
+
+    fn create_pubsub_conn(&self) -> Result<PubSubConnFuture, RespVec> {
+        self.cmd_ctx_handler.create_pubsub_conn(&self.state)
+    }
+
+    fn get_resp_version(&self) -> RespVersion {
+        self.state.get_resp_version()
+    }
+
+    fn get_idle_timeout(&self) -> Option<Duration> {
+        self.cmd_ctx_handler.get_idle_timeout(&self.state)
+    }
+
+    fn handle_idle_timeout(&self) {
+        self.cmd_ctx_handler.handle_idle_timeout(&self.state)
+    }
+
+    fn handle_reply_done(&self) {
+        self.state.get_client_info().record_reply()
+    }
+
+    fn get_cmd_timeout(&self, cmd: &Command) -> Option<Duration> {
+        self.config.cmd_timeouts.get_timeout(cmd)
+    }
 }
 
-pub async fn handle_session<H>(
+pub async fn handle_session<H, S>(
     handler: sync::Arc<H>,
-    sock: TcpStream,
+    sock: S,
     _channel_size: usize,
     session_batch_min_time: usize,
     session_batch_max_time: usize,
     session_batch_buf: NonZeroUsize,
+    mut drain_signal: DrainSignal,
 ) -> Result<(), SessionError>
 where
     H: CmdHandler + Send + Sync + 'static,
+    S: AsyncRead + AsyncWrite + Send + 'static,
 {
     let (encoder, decoder) = new_simple_packet_codec::<Box<RespPacket>, Box<RespPacket>>();
-    let (mut writer, reader) = RespCodec::new(encoder, decoder).framed(sock).split();
+    let (writer, reader) = RespCodec::new(encoder, decoder).framed(sock).split();
+    let mut writer = writer.sink_map_err(|err| {
+        error!("writer error: {}", err);
+        match err {
+            EncodeError::Io(err) => SessionError::Io(err),
+            EncodeError::NotReady(_) => SessionError::InvalidState,
+        }
+    });
     let mut reader = reader
         .map_err(|e| match e {
             DecodeError::Io(e) => SessionError::Io(e),
@@ -303,17 +524,68 @@ where
     let mut reply_receiver_list = Vec::with_capacity(session_batch_buf.get());
     let mut replies = Vec::with_capacity(session_batch_buf.get());
     let mut read_buf = VecDeque::with_capacity(session_batch_buf.get());
+    // The dedicated backend connection for the pub/sub mode.
+    let mut pubsub_conn: Option<PubSubConn> = None;
 
     loop {
-        let reqs = if read_buf.is_empty() {
-            match reader.next().await {
-                Some(reqs) => reqs,
-                None => return Ok(()),
-            }
-        } else {
+        let reqs = if !read_buf.is_empty() {
             read_buf
                 .drain(..min(read_buf.len(), session_batch_buf.get()))
                 .collect()
+        } else if let Some((_, pubsub_reader)) = pubsub_conn.as_mut() {
+            let next = future::select(reader.next(), pubsub_reader.next());
+            let pushed = match drain_signal.or_drain(next).await {
+                None => {
+                    debug!("close the pub/sub session for draining");
+                    return Ok(());
+                }
+                Some(future::Either::Left((Some(reqs), _))) => Ok(reqs),
+                Some(future::Either::Left((None, _))) => return Ok(()),
+                Some(future::Either::Right((pushed, _))) => Err(pushed),
+            };
+            match pushed {
+                Ok(reqs) => reqs,
+                Err(Some(Ok(packet))) => {
+                    if is_all_unsubscribed(&packet) {
+                        pubsub_conn = None;
+                    }
+                    let packet = match handler.get_resp_version() {
+                        RespVersion::V2 => packet,
+                        RespVersion::V3 => to_push_packet(packet),
+                    };
+                    writer.send(Box::new(packet)).await?;
+                    continue;
+                }
+                Err(Some(Err(err))) => {
+                    error!("pub/sub connection error {:?}", err);
+                    return Err(SessionError::InvalidState);
+                }
+                Err(None) => {
+                    error!("pub/sub connection is closed");
+                    return Err(SessionError::Io(io::Error::from(io::ErrorKind::BrokenPipe)));
+                }
+            }
+        } else {
+            // All the replies have been sent so the session can be closed for draining.
+            let next = drain_signal.or_drain(reader.next());
+            let next = match handler.get_idle_timeout() {
+                Some(idle_timeout) => match timeout(idle_timeout, next).await {
+                    Ok(next) => next,
+                    Err(_) => {
+                        handler.handle_idle_timeout();
+                        return Ok(());
+                    }
+                },
+                None => next.await,
+            };
+            match next {
+                Some(Some(reqs)) => reqs,
+                Some(None) => return Ok(()),
+                None => {
+                    debug!("close the idle session for draining");
+                    return Ok(());
+                }
+            }
         };
 
         for req in reqs.into_iter() {
@@ -325,67 +597,343 @@ where
                 }
             };
             let cmd = Command::new(packet);
+            let cmd_type = cmd.get_type();
 
-            let fut = handler.handle_cmd(cmd);
-            reply_receiver_list.push(fut);
+            if pubsub_conn.is_none()
+                && cmd_type != CmdType::Subscribe
+                && cmd_type != CmdType::Psubscribe
+            {
+                let cmd_timeout = handler.get_cmd_timeout(&cmd);
+                let fut = handler.handle_cmd(cmd);
+                // The command has been sent to the writing queue here.
+                let deadline = cmd_timeout.map(|t| Instant::now() + t);
+                // HELLO changes the version right in `handle_cmd`,
+                // so this is the version the reply should be encoded in.
+                reply_receiver_list.push((fut, handler.get_resp_version(), deadline));
+                continue;
+            }
+
+            // The replies of the previous commands should go before the pushed messages.
+            let closed = !collect_replies(
+                handler.as_ref(),
+                &mut reply_receiver_list,
+                &mut reader,
+                &mut read_buf,
+                &mut replies,
+            )
+            .await?;
+            write_replies(&mut writer, &mut replies).await?;
+            if closed {
+                return Ok(());
+            }
+
+            let closed =
+                !handle_pubsub_cmd(handler.as_ref(), cmd, &mut pubsub_conn, &mut replies).await?;
+            write_replies(&mut writer, &mut replies).await?;
+            if closed {
+                return Ok(());
+            }
         }
 
-        for reply_receiver in reply_receiver_list.drain(..) {
-            let res = {
-                // reply_fut may block forever for some commands, such as BLPOP, BRPOP, BRPOPLPUSH.
-                // Then even the connection is closed, this future won't exit.
-                // We need to select it with tcp stream read to detect closed connection.
-                let mut reply_fut = Some(reply_receiver);
-                let res = loop {
-                    let fut = reply_fut.take().ok_or_else(|| {
-                        error!("session invalid state: cannot get reply_fut.");
-                        SessionError::InvalidState
-                    })?;
-                    match future::select(fut, reader.next()).await {
-                        future::Either::Left((res, read_fut)) => {
-                            let _ = read_fut; // can be dropped without losing any item.
-                            break res;
-                        }
-                        future::Either::Right((read_result, fut)) => {
-                            reply_fut = Some(fut);
-                            match read_result {
-                                Some(reqs) => read_buf.extend(reqs),
-                                None => return Ok(()),
-                            }
-                            continue;
+        let closed = !collect_replies(
+            handler.as_ref(),
+            &mut reply_receiver_list,
+            &mut reader,
+            &mut read_buf,
+            &mut replies,
+        )
+        .await?;
+        write_replies(&mut writer, &mut replies).await?;
+        if closed {
+            return Ok(());
+        }
+    }
+}
+
+type PubSubConn = (ConnSink<RespPacket>, ConnStream<RespPacket>);
+type RequestChunk = Vec<Result<Box<RespPacket>, SessionError>>;
+
+// Returns false if the connection is closed by the client.
+// The packets are boxed for the session writer.
+#[allow(clippy::vec_box)]
+async fn collect_replies<'a, H, R>(
+    handler: &'a H,
+    reply_receiver_list: &mut Vec<(CmdReplyFuture<'a>, RespVersion, Option<Instant>)>,
+    reader: &mut R,
+    read_buf: &mut VecDeque<Result<Box<RespPacket>, SessionError>>,
+    replies: &mut Vec<Box<RespPacket>>,
+) -> Result<bool, SessionError>
+where
+    H: CmdHandler,
+    R: Stream<Item = RequestChunk> + Unpin,
+{
+    for (reply_receiver, resp_version, deadline) in reply_receiver_list.drain(..) {
+        // The late reply will be dropped along with the receiver.
+        let reply_receiver = match deadline {
+            Some(deadline) => future::Either::Left(time::timeout_at(deadline, reply_receiver)),
+            None => future::Either::Right(reply_receiver.map(Ok)),
+        };
+        let res = {
+            // reply_fut may block forever for some commands, such as BLPOP, BRPOP, BRPOPLPUSH.
+            // Then even the connection is closed, this future won't exit.
+            // We need to select it with tcp stream read to detect closed connection.
+            let mut reply_fut = Some(reply_receiver);
+            let res = loop {
+                let fut = reply_fut.take().ok_or_else(|| {
+                    error!("session invalid state: cannot get reply_fut.");
+                    SessionError::InvalidState
+                })?;
+                match future::select(fut, reader.next()).await {
+                    future::Either::Left((res, read_fut)) => {
+                        let _ = read_fut; // can be dropped without losing any item.
+                        break res;
+                    }
+                    future::Either::Right((read_result, fut)) => {
+                        reply_fut = Some(fut);
+                        match read_result {
+                            Some(reqs) => read_buf.extend(reqs),
+                            None => return Ok(false),
                         }
+                        continue;
                     }
-                };
-                res.map_err(SessionError::CmdErr)
+                }
             };
-
-            let packet = match res {
-                Ok(task_reply) => {
-                    let (request, packet, mut slowlog) = (*task_reply).into_inner();
-                    slowlog.log_event(TaskEvent::WaitDone);
-                    handler.handle_slowlog(request, slowlog);
-                    packet
+            match res {
+                Ok(res) => res.map_err(SessionError::CmdErr),
+                Err(_) => {
+                    debug!("command timed out");
+                    handler.handle_reply_done();
+                    let resp = Resp::Error(ERR_TIMEOUT.to_string().into_bytes());
+                    replies.push(Box::new(RespPacket::from_resp_vec(resp)));
+                    continue;
                 }
-                Err(e) => {
-                    let err_msg = format!("Err cmd error {:?}", e);
-                    error!("{}", err_msg);
-                    let resp = Resp::Error(err_msg.into_bytes());
-                    Box::new(RespPacket::from_resp_vec(resp))
+            }
+        };
+
+        let packet = match res {
+            Ok(task_reply) => {
+                let (request, packet, mut slowlog) = (*task_reply).into_inner();
+                slowlog.log_event(TaskEvent::WaitDone);
+                handler.handle_metrics(&request, &packet, &slowlog);
+                let packet = downgrade_packet(packet, resp_version);
+                let packet = upgrade_packet(&request, packet, resp_version);
+                handler.handle_slowlog(request, slowlog);
+                packet
+            }
+            Err(e) => {
+                let err_msg = format!("Err cmd error {:?}", e);
+                error!("{}", err_msg);
+                let resp = Resp::Error(err_msg.into_bytes());
+                Box::new(RespPacket::from_resp_vec(resp))
+            }
+        };
+
+        handler.handle_reply_done();
+        replies.push(packet);
+    }
+    Ok(true)
+}
+
+#[allow(clippy::vec_box)]
+async fn write_replies<W>(
+    writer: &mut W,
+    replies: &mut Vec<Box<RespPacket>>,
+) -> Result<(), SessionError>
+where
+    W: Sink<Box<RespPacket>, Error = SessionError> + Unpin,
+{
+    if replies.is_empty() {
+        return Ok(());
+    }
+    let mut batch = stream::iter(replies.drain(..)).map(Ok);
+    writer.send_all(&mut batch).await
+}
+
+// Returns false if the session should be closed.
+#[allow(clippy::vec_box)]
+async fn handle_pubsub_cmd<H: CmdHandler>(
+    handler: &H,
+    cmd: Command,
+    pubsub_conn: &mut Option<PubSubConn>,
+    replies: &mut Vec<Box<RespPacket>>,
+) -> Result<bool, SessionError> {
+    let cmd_type = cmd.get_type();
+    match cmd_type {
+        CmdType::Subscribe | CmdType::Psubscribe if pubsub_conn.is_none() => {
+            let conn_fut = match handler.create_pubsub_conn() {
+                Ok(conn_fut) => conn_fut,
+                Err(resp) => {
+                    replies.push(Box::new(RespPacket::from_resp_vec(resp)));
+                    return Ok(true);
                 }
             };
+            match conn_fut.await {
+                Ok(conn) => *pubsub_conn = Some(conn),
+                Err(err) => {
+                    let resp =
+                        Resp::Error(format!("{}: {:?}", ERR_BACKEND_CONNECTION, err).into_bytes());
+                    replies.push(Box::new(RespPacket::from_resp_vec(resp)));
+                    return Ok(true);
+                }
+            }
+        }
+        CmdType::Unsubscribe | CmdType::Punsubscribe if pubsub_conn.is_none() => {
+            let unsubscribe_replies = gen_unsubscribe_replies(&cmd);
+            match handler.get_resp_version() {
+                RespVersion::V2 => replies.extend(unsubscribe_replies),
+                RespVersion::V3 => replies.extend(
+                    unsubscribe_replies
+                        .into_iter()
+                        .map(|packet| Box::new(to_push_packet(*packet))),
+                ),
+            }
+            return Ok(true);
+        }
+        CmdType::Subscribe
+        | CmdType::Psubscribe
+        | CmdType::Unsubscribe
+        | CmdType::Punsubscribe
+        | CmdType::Ping => (),
+        // Close the session after the reply is sent.
+        CmdType::Quit => {
+            let resp = Resp::Simple(OK_REPLY.to_string().into_bytes());
+            replies.push(Box::new(RespPacket::from_resp_vec(resp)));
+            return Ok(false);
+        }
+        _ => {
+            let resp = Resp::Error(
+                b"ERR only (P)SUBSCRIBE / (P)UNSUBSCRIBE / PING / QUIT allowed in this context"
+                    .to_vec(),
+            );
+            replies.push(Box::new(RespPacket::from_resp_vec(resp)));
+            return Ok(true);
+        }
+    }
 
-            replies.push(packet);
+    // The replies will come from the pub/sub connection.
+    if let Some((pubsub_writer, _)) = pubsub_conn.as_mut() {
+        if let Err(err) = pubsub_writer.send(*cmd.into_packet()).await {
+            error!("failed to send to pub/sub connection {:?}", err);
+            return Err(SessionError::InvalidState);
         }
+    }
+    Ok(true)
+}
 
-        let mut batch = stream::iter(replies.drain(..)).map(Ok);
-        if let Err(err) = writer.send_all(&mut batch).await {
-            error!("writer error: {}", err);
-            let err = match err {
-                EncodeError::Io(err) => SessionError::Io(err),
-                EncodeError::NotReady(_) => SessionError::InvalidState,
-            };
-            return Err(err);
+// Redis will still reply when the client is not subscribing to anything.
+#[allow(clippy::vec_box)]
+fn gen_unsubscribe_replies(cmd: &Command) -> Vec<Box<RespPacket>> {
+    let cmd_name = cmd
+        .get_command_name()
+        .map(|s| s.to_lowercase().into_bytes())
+        .unwrap_or_default();
+    let arg_len = cmd.get_command_len().unwrap_or(0);
+    let channels: Vec<BulkStr<Vec<u8>>> = if arg_len <= 1 {
+        vec![BulkStr::Nil]
+    } else {
+        (1..arg_len)
+            .filter_map(|i| cmd.get_command_element(i))
+            .map(|channel| BulkStr::Str(channel.to_vec()))
+            .collect()
+    };
+    channels
+        .into_iter()
+        .map(|channel| {
+            let resp = Resp::Arr(Array::Arr(vec![
+                Resp::Bulk(BulkStr::Str(cmd_name.clone())),
+                Resp::Bulk(channel),
+                Resp::Integer(b"0".to_vec()),
+            ]));
+            Box::new(RespPacket::from_resp_vec(resp))
+        })
+        .collect()
+}
+
+// Only the replies generated by the proxy itself could contain RESP3 types.
+fn downgrade_packet(packet: Box<RespPacket>, resp_version: RespVersion) -> Box<RespPacket> {
+    match (resp_version, packet.as_ref()) {
+        (RespVersion::V2, RespPacket::Data(resp)) if !resp.is_resp2() => {
+            Box::new(RespPacket::Data((*packet).into_resp_vec().into_resp2()))
+        }
+        _ => packet,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Resp3ReplyType {
+    Map,
+    Set,
+    Double,
+}
+
+impl Resp3ReplyType {
+    fn from_cmd_name(cmd_name: &[u8]) -> Option<Self> {
+        let cmd_name = cmd_name.to_ascii_uppercase();
+        match cmd_name.as_slice() {
+            b"HGETALL" => Some(Self::Map),
+            b"SMEMBERS" | b"SINTER" | b"SUNION" | b"SDIFF" => Some(Self::Set),
+            b"ZSCORE" | b"ZINCRBY" => Some(Self::Double),
+            _ => None,
+        }
+    }
+}
+
+// The backends always speak RESP2, so the replies of the commonly used commands
+// whose types differ in RESP3 are converted here like what Redis replies.
+// The others, including those inside EXEC, are still in RESP2 types,
+// which the RESP3 clients could also parse.
+fn upgrade_packet(
+    request: &RespPacket,
+    packet: Box<RespPacket>,
+    resp_version: RespVersion,
+) -> Box<RespPacket> {
+    if resp_version != RespVersion::V3 {
+        return packet;
+    }
+    let reply_type = match request
+        .get_array_element(0)
+        .and_then(Resp3ReplyType::from_cmd_name)
+    {
+        Some(reply_type) => reply_type,
+        None => return packet,
+    };
+
+    let resp = match ((*packet).into_resp_vec(), reply_type) {
+        (Resp::Arr(Array::Arr(resps)), Resp3ReplyType::Map) if resps.len() % 2 == 0 => {
+            let mut pairs = Vec::with_capacity(resps.len() / 2);
+            let mut iter = resps.into_iter();
+            while let (Some(k), Some(v)) = (iter.next(), iter.next()) {
+                pairs.push((k, v));
+            }
+            Resp::Map(pairs)
         }
+        (Resp::Arr(Array::Arr(resps)), Resp3ReplyType::Set) => Resp::Set(resps),
+        (Resp::Bulk(BulkStr::Str(s)), Resp3ReplyType::Double) => Resp::Double(s),
+        (resp, _) => resp,
+    };
+    Box::new(RespPacket::Data(resp))
+}
+
+// RESP3 clients expect the pub/sub messages as out-of-band push types.
+fn to_push_packet(packet: RespPacket) -> RespPacket {
+    match packet.into_resp_vec() {
+        Resp::Arr(Array::Arr(resps)) => RespPacket::Data(Resp::Push(resps)),
+        resp => RespPacket::Data(resp),
+    }
+}
+
+// The client will leave the pub/sub mode after unsubscribing all the channels and patterns.
+fn is_all_unsubscribed(packet: &RespPacket) -> bool {
+    match packet.to_resp_slice() {
+        Resp::Arr(Array::Arr(resps)) => match resps.as_slice() {
+            [Resp::Bulk(BulkStr::Str(kind)), _, Resp::Integer(count)] => {
+                (kind.eq_ignore_ascii_case(b"unsubscribe")
+                    || kind.eq_ignore_ascii_case(b"punsubscribe"))
+                    && *count == b"0"
+            }
+            _ => false,
+        },
+        _ => false,
     }
 }
 
@@ -442,4 +990,239 @@ mod tests {
         };
         assert_matches!(err, CommandError::Dropped);
     }
+
+    #[test]
+    fn test_cmd_ctx_is_blocking() {
+        let gen_cmd_ctx = |args: &[&str]| {
+            let request = RespPacket::Data(Resp::Arr(Array::Arr(
+                args.iter()
+                    .map(|arg| Resp::Bulk(BulkStr::Str(arg.as_bytes().to_vec())))
+                    .collect(),
+            )));
+            let cluster_name = ClusterName::try_from("mycluster").unwrap();
+            let cmd = Command::new(Box::new(request));
+            let (sender, _) = new_command_pair(&cmd);
+            CmdCtx::new(cluster_name, cmd, sender, 7799, true)
+        };
+        assert!(gen_cmd_ctx(&["BLPOP", "a", "0"]).is_blocking());
+        assert!(!gen_cmd_ctx(&["LPOP", "a"]).is_blocking());
+    }
+
+    #[test]
+    fn test_unsubscribe_replies() {
+        let request = RespPacket::Data(Resp::Arr(Array::Arr(vec![
+            Resp::Bulk(BulkStr::Str(b"UNSUBSCRIBE".to_vec())),
+            Resp::Bulk(BulkStr::Str(b"a".to_vec())),
+            Resp::Bulk(BulkStr::Str(b"b".to_vec())),
+        ])));
+        let cmd = Command::new(Box::new(request));
+        let replies = gen_unsubscribe_replies(&cmd);
+        assert_eq!(replies.len(), 2);
+        assert!(is_all_unsubscribed(&replies[0]));
+        assert_eq!(
+            replies[1].to_resp_vec(),
+            Resp::Arr(Array::Arr(vec![
+                Resp::Bulk(BulkStr::Str(b"unsubscribe".to_vec())),
+                Resp::Bulk(BulkStr::Str(b"b".to_vec())),
+                Resp::Integer(b"0".to_vec()),
+            ]))
+        );
+
+        let request = RespPacket::Data(Resp::Arr(Array::Arr(vec![Resp::Bulk(BulkStr::Str(
+            b"PUNSUBSCRIBE".to_vec(),
+        ))])));
+        let cmd = Command::new(Box::new(request));
+        let replies = gen_unsubscribe_replies(&cmd);
+        assert_eq!(replies.len(), 1);
+        assert!(is_all_unsubscribed(&replies[0]));
+    }
+
+    #[test]
+    fn test_is_all_unsubscribed() {
+        let packet = RespPacket::Data(Resp::Arr(Array::Arr(vec![
+            Resp::Bulk(BulkStr::Str(b"unsubscribe".to_vec())),
+            Resp::Bulk(BulkStr::Str(b"a".to_vec())),
+            Resp::Integer(b"1".to_vec()),
+        ])));
+        assert!(!is_all_unsubscribed(&packet));
+        let packet = RespPacket::Data(Resp::Arr(Array::Arr(vec![
+            Resp::Bulk(BulkStr::Str(b"message".to_vec())),
+            Resp::Bulk(BulkStr::Str(b"a".to_vec())),
+            Resp::Bulk(BulkStr::Str(b"0".to_vec())),
+        ])));
+        assert!(!is_all_unsubscribed(&packet));
+    }
+
+    #[test]
+    fn test_downgrade_packet() {
+        let resp = Resp::Map(vec![(
+            Resp::Bulk(BulkStr::Str(b"proto".to_vec())),
+            Resp::Integer(b"3".to_vec()),
+        )]);
+        let packet = Box::new(RespPacket::Data(resp.clone()));
+        let packet = downgrade_packet(packet, RespVersion::V3);
+        assert_eq!(packet.to_resp_vec(), resp);
+
+        let packet = downgrade_packet(packet, RespVersion::V2);
+        assert_eq!(
+            packet.to_resp_vec(),
+            Resp::Arr(Array::Arr(vec![
+                Resp::Bulk(BulkStr::Str(b"proto".to_vec())),
+                Resp::Integer(b"3".to_vec()),
+            ]))
+        );
+    }
+
+    #[test]
+    fn test_upgrade_packet() {
+        let gen_request = |args: &[&str]| {
+            let resps = args
+                .iter()
+                .map(|s| Resp::Bulk(BulkStr::Str(s.as_bytes().to_vec())))
+                .collect();
+            RespPacket::Data(Resp::Arr(Array::Arr(resps)))
+        };
+        let bulk = |s: &str| Resp::Bulk(BulkStr::Str(s.as_bytes().to_vec()));
+
+        let request = gen_request(&["hgetall", "h"]);
+        let reply = Resp::Arr(Array::Arr(vec![bulk("f"), bulk("v")]));
+        let packet = Box::new(RespPacket::Data(reply.clone()));
+        let packet = upgrade_packet(&request, packet, RespVersion::V2);
+        assert_eq!(packet.to_resp_vec(), reply);
+        let packet = upgrade_packet(&request, packet, RespVersion::V3);
+        assert_eq!(
+            packet.to_resp_vec(),
+            Resp::Map(vec![(bulk("f"), bulk("v"))])
+        );
+
+        let request = gen_request(&["SMEMBERS", "s"]);
+        let packet = Box::new(RespPacket::Data(Resp::Arr(Array::Arr(vec![bulk("m")]))));
+        let packet = upgrade_packet(&request, packet, RespVersion::V3);
+        assert_eq!(packet.to_resp_vec(), Resp::Set(vec![bulk("m")]));
+
+        let request = gen_request(&["ZSCORE", "z", "m"]);
+        let packet = Box::new(RespPacket::Data(bulk("1.5")));
+        let packet = upgrade_packet(&request, packet, RespVersion::V3);
+        assert_eq!(packet.to_resp_vec(), Resp::Double(b"1.5".to_vec()));
+
+        // The nil and error replies are not converted.
+        let packet = Box::new(RespPacket::Data(Resp::Bulk(BulkStr::Nil)));
+        let packet = upgrade_packet(&request, packet, RespVersion::V3);
+        assert_eq!(packet.to_resp_vec(), Resp::Bulk(BulkStr::Nil));
+        let request = gen_request(&["HGETALL", "h"]);
+        let error = Resp::Error(b"WRONGTYPE".to_vec());
+        let packet = Box::new(RespPacket::Data(error.clone()));
+        let packet = upgrade_packet(&request, packet, RespVersion::V3);
+        assert_eq!(packet.to_resp_vec(), error);
+
+        let request = gen_request(&["GET", "a"]);
+        let packet = Box::new(RespPacket::Data(bulk("1.5")));
+        let packet = upgrade_packet(&request, packet, RespVersion::V3);
+        assert_eq!(packet.to_resp_vec(), bulk("1.5"));
+    }
+
+    #[test]
+    fn test_to_push_packet() {
+        let elements = vec![
+            Resp::Bulk(BulkStr::Str(b"message".to_vec())),
+            Resp::Bulk(BulkStr::Str(b"a".to_vec())),
+            Resp::Bulk(BulkStr::Str(b"hello".to_vec())),
+        ];
+        let packet = RespPacket::Data(Resp::Arr(Array::Arr(elements.clone())));
+        assert_eq!(to_push_packet(packet).to_resp_vec(), Resp::Push(elements));
+
+        let packet = RespPacket::Data(Resp::Simple(b"PONG".to_vec()));
+        assert_eq!(
+            to_push_packet(packet).to_resp_vec(),
+            Resp::Simple(b"PONG".to_vec())
+        );
+    }
+
+    #[test]
+    fn test_session_resp_version() {
+        let cluster_name = ClusterName::try_from("mycluster").unwrap();
+        let client_info = Arc::new(ClientInfo::new(0, "127.0.0.1:6000".to_string()));
+        let state = SessionState::new(cluster_name, client_info);
+        assert_eq!(state.get_resp_version(), RespVersion::V2);
+        state.set_resp_version(RespVersion::V3);
+        assert_eq!(state.get_resp_version(), RespVersion::V3);
+    }
+
+    struct DummyCmdHandler;
+
+    impl CmdHandler for DummyCmdHandler {
+        fn handle_cmd(&self, _cmd: Command) -> CmdReplyFuture<'_> {
+            unreachable!()
+        }
+        fn handle_slowlog(&self, _request: Box<RespPacket>, _slowlog: Slowlog) {}
+        fn handle_metrics(&self, _request: &RespPacket, _reply: &RespPacket, _slowlog: &Slowlog) {}
+        fn create_pubsub_conn(&self) -> Result<PubSubConnFuture, RespVec> {
+            unreachable!()
+        }
+        fn get_resp_version(&self) -> RespVersion {
+            RespVersion::V2
+        }
+        fn get_idle_timeout(&self) -> Option<Duration> {
+            None
+        }
+        fn handle_idle_timeout(&self) {}
+        fn handle_reply_done(&self) {}
+        fn get_cmd_timeout(&self, _cmd: &Command) -> Option<Duration> {
+            None
+        }
+    }
+
+    fn gen_ping_cmd_ctx() -> (CmdCtx, CmdReplyReceiver) {
+        let request = RespPacket::Data(Resp::Arr(Array::Arr(vec![Resp::Bulk(BulkStr::Str(
+            b"PING".to_vec(),
+        ))])));
+        let cluster_name = ClusterName::try_from("mycluster").unwrap();
+        let cmd = Command::new(Box::new(request));
+        let (sender, receiver) = new_command_pair(&cmd);
+        (CmdCtx::new(cluster_name, cmd, sender, 0, false), receiver)
+    }
+
+    #[tokio::test]
+    async fn test_collect_replies_timeout() {
+        let handler = DummyCmdHandler;
+        let (timed_out_ctx, timed_out_receiver) = gen_ping_cmd_ctx();
+        let (replied_ctx, replied_receiver) = gen_ping_cmd_ctx();
+        replied_ctx.set_resp_result(Ok(Resp::Simple(b"PONG".to_vec())));
+
+        let deadline = Instant::now() + Duration::from_millis(10);
+        let mut reply_receiver_list = vec![
+            (
+                CmdReplyFuture::Left(timed_out_receiver),
+                RespVersion::V2,
+                Some(deadline),
+            ),
+            (
+                CmdReplyFuture::Left(replied_receiver),
+                RespVersion::V2,
+                Some(deadline),
+            ),
+        ];
+        let mut reader = stream::pending::<RequestChunk>();
+        let mut read_buf = VecDeque::new();
+        let mut replies = vec![];
+        let open = collect_replies(
+            &handler,
+            &mut reply_receiver_list,
+            &mut reader,
+            &mut read_buf,
+            &mut replies,
+        )
+        .await
+        .unwrap();
+        assert!(open);
+        assert_eq!(replies.len(), 2);
+        assert_eq!(
+            replies[0].to_resp_vec(),
+            Resp::Error(ERR_TIMEOUT.to_string().into_bytes())
+        );
+        assert_eq!(replies[1].to_resp_vec(), Resp::Simple(b"PONG".to_vec()));
+
+        // The late reply is dropped.
+        timed_out_ctx.set_resp_result(Ok(Resp::Simple(b"PONG".to_vec())));
+    }
 }
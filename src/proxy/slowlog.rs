@@ -1,6 +1,6 @@
 use super::service::ServerProxyConfig;
 use crate::protocol::{Array, BulkStr, Resp, RespPacket, RespVec};
-use arc_swap::ArcSwapOption;
+use arc_swap::{ArcSwap, ArcSwapOption};
 use chrono::{naive, DateTime, Utc};
 use std::cmp::max;
 use std::str;
@@ -103,6 +103,17 @@ impl Slowlog {
     pub fn is_enabled(&self) -> bool {
         self.enabled
     }
+
+    // Returns the nanoseconds from creating the command to getting the reply.
+    pub fn get_latency(&self) -> Option<i64> {
+        if !self.enabled {
+            return None;
+        }
+        match self.event_map.get_used_time(TaskEvent::WaitDone) {
+            0 => None,
+            latency => Some(latency),
+        }
+    }
 }
 
 impl SlowlogRecord {
@@ -164,8 +175,11 @@ impl SlowlogRecord {
     }
 }
 
+type SlowlogSlots = Vec<ArcSwapOption<SlowlogRecord>>;
+
 pub struct SlowRequestLogger {
-    slowlogs: Vec<ArcSwapOption<SlowlogRecord>>,
+    // Replaced when `slowlog_len` is changed.
+    slowlogs: ArcSwap<SlowlogSlots>,
     curr_index: atomic::AtomicUsize,
     rate_limiter: SlowLogRateLimiter,
     config: Arc<ServerProxyConfig>,
@@ -173,12 +187,9 @@ pub struct SlowRequestLogger {
 
 impl SlowRequestLogger {
     pub fn new(config: Arc<ServerProxyConfig>) -> Self {
-        let mut slowlogs = Vec::new();
-        while slowlogs.len() != config.slowlog_len.get() {
-            slowlogs.push(ArcSwapOption::new(None));
-        }
+        let slowlogs = Self::gen_slots(config.get_slowlog_len().get(), &[]);
         Self {
-            slowlogs,
+            slowlogs: ArcSwap::new(Arc::new(slowlogs)),
             curr_index: atomic::AtomicUsize::new(0),
             rate_limiter: SlowLogRateLimiter::default(),
             config,
@@ -196,15 +207,17 @@ impl SlowRequestLogger {
 
     pub fn add(&self, request: Box<RespPacket>, log: Slowlog) {
         let log = SlowlogRecord::from_slow_log(request, log);
-        let index = self.curr_index.fetch_add(1, atomic::Ordering::SeqCst) % self.slowlogs.len();
-        if let Some(log_slot) = self.slowlogs.get(index) {
+        let slowlogs = self.load_slots();
+        let index = self.curr_index.fetch_add(1, atomic::Ordering::SeqCst) % slowlogs.len();
+        if let Some(log_slot) = slowlogs.get(index) {
             log_slot.store(Some(Arc::new(log)))
         }
     }
 
     pub fn get(&self, limit: Option<usize>) -> Vec<Arc<SlowlogRecord>> {
-        let num = limit.unwrap_or_else(|| self.slowlogs.len());
-        self.slowlogs
+        let slowlogs = self.load_slots();
+        let num = limit.unwrap_or_else(|| slowlogs.len());
+        slowlogs
             .iter()
             .filter_map(arc_swap::ArcSwapAny::load)
             .take(num)
@@ -212,11 +225,36 @@ impl SlowRequestLogger {
     }
 
     pub fn reset(&self) {
-        for log_slot in self.slowlogs.iter() {
+        for log_slot in self.load_slots().iter() {
             log_slot.store(None)
         }
     }
 
+    // The existing records are kept as many as possible after resizing.
+    fn load_slots(&self) -> Arc<SlowlogSlots> {
+        let slowlogs = self.slowlogs.load();
+        let slowlog_len = self.config.get_slowlog_len().get();
+        if slowlogs.len() == slowlog_len {
+            return slowlogs;
+        }
+        let new_slowlogs = Arc::new(Self::gen_slots(slowlog_len, &slowlogs));
+        self.slowlogs.store(new_slowlogs.clone());
+        new_slowlogs
+    }
+
+    fn gen_slots(slowlog_len: usize, old_slots: &[ArcSwapOption<SlowlogRecord>]) -> SlowlogSlots {
+        let mut slowlogs: SlowlogSlots = old_slots
+            .iter()
+            .filter_map(arc_swap::ArcSwapAny::load)
+            .take(slowlog_len)
+            .map(|log| ArcSwapOption::new(Some(log)))
+            .collect();
+        while slowlogs.len() != slowlog_len {
+            slowlogs.push(ArcSwapOption::new(None));
+        }
+        slowlogs
+    }
+
     // Returns whether this current log should be enabled.
     pub fn limit_rate(&self, slowlog_sample_rate: u64) -> bool {
         self.rate_limiter.check_current_enabled(slowlog_sample_rate)
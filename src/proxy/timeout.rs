@@ -0,0 +1,162 @@
+use super::command::{CmdType, Command, DataCmdType};
+use std::str::FromStr;
+use std::time::Duration;
+
+const CLASS_NUM: usize = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CmdTimeoutClass {
+    Blocking,
+    Script,
+    Keyspace,
+    MultiKey,
+    Others,
+}
+
+impl CmdTimeoutClass {
+    pub fn from_cmd(cmd: &Command) -> Self {
+        if cmd.get_type() != CmdType::Others {
+            return Self::Others;
+        }
+        match cmd.get_data_cmd_type() {
+            DataCmdType::BLPOP | DataCmdType::BRPOP | DataCmdType::BRPOPLPUSH => Self::Blocking,
+            DataCmdType::EVAL | DataCmdType::EVALSHA => Self::Script,
+            DataCmdType::KEYS
+            | DataCmdType::SCAN
+            | DataCmdType::DBSIZE
+            | DataCmdType::RANDOMKEY
+            | DataCmdType::FLUSHALL
+            | DataCmdType::FLUSHDB => Self::Keyspace,
+            DataCmdType::MGET
+            | DataCmdType::MSET
+            | DataCmdType::MSETNX
+            | DataCmdType::DEL
+            | DataCmdType::EXISTS
+            | DataCmdType::TOUCH
+            | DataCmdType::UNLINK
+            | DataCmdType::SUNION
+            | DataCmdType::SINTER
+            | DataCmdType::SDIFF
+            | DataCmdType::PFCOUNT
+            | DataCmdType::PFMERGE
+            | DataCmdType::BITOP => Self::MultiKey,
+            _ => Self::Others,
+        }
+    }
+
+    fn index(self) -> usize {
+        match self {
+            Self::Blocking => 0,
+            Self::Script => 1,
+            Self::Keyspace => 2,
+            Self::MultiKey => 3,
+            Self::Others => 4,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct InvalidCmdTimeoutsStr;
+
+impl FromStr for CmdTimeoutClass {
+    type Err = InvalidCmdTimeoutsStr;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let lowercase = s.to_lowercase();
+        match lowercase.as_str() {
+            "blocking" => Ok(Self::Blocking),
+            "script" => Ok(Self::Script),
+            "keyspace" => Ok(Self::Keyspace),
+            "multi_key" => Ok(Self::MultiKey),
+            "others" => Ok(Self::Others),
+            _ => Err(InvalidCmdTimeoutsStr),
+        }
+    }
+}
+
+// The max time to wait for the reply of a command after it's forwarded.
+// The blocking commands are not limited by the default timeout
+// unless the `blocking` class is set explicitly.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct CmdTimeouts {
+    timeouts: [Option<Duration>; CLASS_NUM],
+}
+
+impl CmdTimeouts {
+    // `default_timeout` is in milliseconds and 0 disables it.
+    // `class_timeouts` are comma-separated `<class>:<milliseconds>` pairs
+    // such as "script:5000,keyspace:0".
+    pub fn new(default_timeout: u64, class_timeouts: &str) -> Result<Self, InvalidCmdTimeoutsStr> {
+        let mut timeouts = [to_timeout(default_timeout); CLASS_NUM];
+        timeouts[CmdTimeoutClass::Blocking.index()] = None;
+        for pair in class_timeouts
+            .split(',')
+            .map(str::trim)
+            .filter(|p| !p.is_empty())
+        {
+            let mut parts = pair.splitn(2, ':');
+            let (class, timeout) = match (parts.next(), parts.next()) {
+                (Some(class), Some(timeout)) => (class.trim(), timeout.trim()),
+                _ => return Err(InvalidCmdTimeoutsStr),
+            };
+            let class = CmdTimeoutClass::from_str(class)?;
+            let timeout = timeout.parse::<u64>().map_err(|_| InvalidCmdTimeoutsStr)?;
+            timeouts[class.index()] = to_timeout(timeout);
+        }
+        Ok(Self { timeouts })
+    }
+
+    pub fn get_timeout(&self, cmd: &Command) -> Option<Duration> {
+        self.timeouts[CmdTimeoutClass::from_cmd(cmd).index()]
+    }
+}
+
+fn to_timeout(millis: u64) -> Option<Duration> {
+    if millis == 0 {
+        None
+    } else {
+        Some(Duration::from_millis(millis))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::{Array, BulkStr, Resp, RespPacket};
+
+    fn gen_cmd(args: &[&str]) -> Command {
+        let resp = Resp::Arr(Array::Arr(
+            args.iter()
+                .map(|arg| Resp::Bulk(BulkStr::Str(arg.as_bytes().to_vec())))
+                .collect(),
+        ));
+        Command::new(Box::new(RespPacket::Data(resp)))
+    }
+
+    #[test]
+    fn test_cmd_timeouts() {
+        let timeouts = CmdTimeouts::new(100, "script:5000, keyspace:0").unwrap();
+        let get = |args: &[&str]| timeouts.get_timeout(&gen_cmd(args));
+        assert_eq!(get(&["GET", "a"]), Some(Duration::from_millis(100)));
+        assert_eq!(get(&["PING"]), Some(Duration::from_millis(100)));
+        assert_eq!(get(&["MGET", "a", "b"]), Some(Duration::from_millis(100)));
+        assert_eq!(
+            get(&["EVAL", "script", "0"]),
+            Some(Duration::from_millis(5000))
+        );
+        assert_eq!(get(&["KEYS", "*"]), None);
+        assert_eq!(get(&["BLPOP", "a", "0"]), None);
+
+        let timeouts = CmdTimeouts::new(0, "blocking:30000").unwrap();
+        assert_eq!(timeouts.get_timeout(&gen_cmd(&["GET", "a"])), None);
+        assert_eq!(
+            timeouts.get_timeout(&gen_cmd(&["brpop", "a", "0"])),
+            Some(Duration::from_millis(30000))
+        );
+
+        assert!(CmdTimeouts::new(0, "").is_ok());
+        assert!(CmdTimeouts::new(0, "unknown:100").is_err());
+        assert!(CmdTimeouts::new(0, "script").is_err());
+        assert!(CmdTimeouts::new(0, "script:abc").is_err());
+    }
+}
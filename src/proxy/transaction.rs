@@ -0,0 +1,185 @@
+use super::backend::BackendNode;
+use super::command::Command;
+use super::namespace::{get_key_indices, NamespaceError};
+use super::reply::ReplyCommitHandler;
+use crate::common::utils::generate_slot;
+use crate::protocol::RespVec;
+use std::mem;
+
+// The transaction commands rely on the connection states,
+// so they need a connection which will not be shared with other sessions.
+pub type DedicatedBackend = BackendNode<ReplyCommitHandler>;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum TransactionState {
+    Idle,
+    Queuing,
+    Aborted,
+}
+
+pub struct Transaction {
+    state: TransactionState,
+    // All the watched and queued keys should be in this slot.
+    slot: Option<usize>,
+    queued_cmds: Vec<RespVec>,
+    // Created by WATCH and kept until EXEC, DISCARD or UNWATCH.
+    backend: Option<DedicatedBackend>,
+}
+
+impl Default for Transaction {
+    fn default() -> Self {
+        Self {
+            state: TransactionState::Idle,
+            slot: None,
+            queued_cmds: vec![],
+            backend: None,
+        }
+    }
+}
+
+impl Transaction {
+    // Returns false if it's already inside MULTI.
+    pub fn start(&mut self) -> bool {
+        if self.is_queuing() {
+            return false;
+        }
+        self.state = TransactionState::Queuing;
+        true
+    }
+
+    pub fn is_queuing(&self) -> bool {
+        self.state != TransactionState::Idle
+    }
+
+    pub fn is_aborted(&self) -> bool {
+        self.state == TransactionState::Aborted
+    }
+
+    pub fn abort(&mut self) {
+        if self.is_queuing() {
+            self.state = TransactionState::Aborted;
+        }
+    }
+
+    // Returns false and keeps the slot unchanged
+    // if the keys are not in the same slot as the previous keys.
+    pub fn add_keys<'a, It>(&mut self, keys: It) -> bool
+    where
+        It: Iterator<Item = &'a [u8]>,
+    {
+        let mut slot = self.slot;
+        for key in keys {
+            let key_slot = generate_slot(key);
+            match slot {
+                Some(s) if s != key_slot => return false,
+                _ => slot = Some(key_slot),
+            }
+        }
+        self.slot = slot;
+        true
+    }
+
+    pub fn get_slot(&self) -> Option<usize> {
+        self.slot
+    }
+
+    pub fn queue(&mut self, cmd: RespVec) {
+        self.queued_cmds.push(cmd);
+    }
+
+    pub fn get_backend(&self) -> Option<&DedicatedBackend> {
+        self.backend.as_ref()
+    }
+
+    pub fn set_backend(&mut self, backend: DedicatedBackend) {
+        self.backend = Some(backend);
+    }
+
+    // Dropping the dedicated connection will also drop the watched keys.
+    pub fn unwatch(&mut self) {
+        self.backend.take();
+        if !self.is_queuing() {
+            self.slot = None;
+        }
+    }
+
+    // Reset the transaction and return the old one.
+    pub fn take(&mut self) -> Self {
+        mem::take(self)
+    }
+
+    pub fn into_parts(self) -> (Option<usize>, Vec<RespVec>, Option<DedicatedBackend>) {
+        let Self {
+            slot,
+            queued_cmds,
+            backend,
+            ..
+        } = self;
+        (slot, queued_cmds, backend)
+    }
+}
+
+// All the keys should be checked to make sure the transaction is inside one slot.
+// The commands sent to all the nodes such as DBSIZE are rejected.
+pub fn get_command_keys(cmd: &Command) -> Result<Vec<&[u8]>, NamespaceError> {
+    let keys = get_key_indices(cmd)?
+        .into_iter()
+        .filter_map(|i| cmd.get_command_element(i))
+        .collect();
+    Ok(keys)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::{Array, BulkStr, Resp, RespPacket};
+
+    fn gen_cmd(elements: &[&str]) -> Command {
+        let arr = elements
+            .iter()
+            .map(|s| Resp::Bulk(BulkStr::Str(s.as_bytes().to_vec())))
+            .collect();
+        Command::new(Box::new(RespPacket::Data(Resp::Arr(Array::Arr(arr)))))
+    }
+
+    #[test]
+    fn test_get_command_keys() {
+        let check = |args: &[&str], keys: &[&str]| {
+            let keys: Vec<&[u8]> = keys.iter().map(|k| k.as_bytes()).collect();
+            assert_eq!(get_command_keys(&gen_cmd(args)), Ok(keys));
+        };
+        check(&["SET", "a", "1"], &["a"]);
+        check(&["MSET", "a", "1", "b", "2"], &["a", "b"]);
+        check(&["DEL", "a", "b"], &["a", "b"]);
+        check(&["RENAME", "a", "b"], &["a", "b"]);
+        check(&["SMOVE", "a", "b", "m"], &["a", "b"]);
+        check(&["RPOPLPUSH", "a", "b"], &["a", "b"]);
+        check(&["SUNIONSTORE", "d", "a", "b"], &["d", "a", "b"]);
+        check(&["ZUNIONSTORE", "d", "2", "a", "b"], &["d", "a", "b"]);
+        check(&["EVAL", "script", "2", "a", "b", "arg"], &["a", "b"]);
+        check(&["SCRIPT", "FLUSH"], &[]);
+        assert_eq!(
+            get_command_keys(&gen_cmd(&["RANDOMKEY"])),
+            Err(NamespaceError::UnsupportedCmd)
+        );
+    }
+
+    #[test]
+    fn test_add_keys() {
+        let mut txn = Transaction::default();
+        assert!(txn.start());
+        assert!(!txn.start());
+
+        assert!(txn.add_keys(vec![b"{tag}a".as_ref(), b"{tag}b".as_ref()].into_iter()));
+        let slot = txn.get_slot();
+        assert!(slot.is_some());
+        assert!(txn.add_keys(vec![b"{tag}c".as_ref()].into_iter()));
+        assert!(!txn.add_keys(vec![b"a".as_ref()].into_iter()));
+        assert_eq!(txn.get_slot(), slot);
+
+        txn.abort();
+        assert!(txn.is_aborted());
+        let txn = txn.take();
+        assert!(txn.is_aborted());
+    }
+}
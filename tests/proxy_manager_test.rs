@@ -14,7 +14,7 @@ mod tests {
     use std::convert::TryFrom;
     use std::num::NonZeroUsize;
     use std::str;
-    use std::sync::atomic::{AtomicI64, AtomicU64};
+    use std::sync::atomic::{AtomicI64, AtomicU64, AtomicUsize};
     use std::sync::Arc;
     use std::time::Duration;
     use tokio;
@@ -24,45 +24,75 @@ mod tests {
     use undermoon::common::config::ClusterConfig;
     use undermoon::common::proto::ProxyClusterMeta;
     use undermoon::common::response::{
-        ERR_BACKEND_CONNECTION, ERR_CLUSTER_NOT_FOUND, ERR_MOVED, ERR_TOO_MANY_REDIRECTIONS,
-        OK_REPLY,
+        ERR_ATOMIC_CROSS_SLOT, ERR_BACKEND_CONNECTION, ERR_CLUSTER_NOT_FOUND, ERR_CROSS_SLOT,
+        ERR_MOVED, ERR_NOAUTH, ERR_NOPERM, ERR_PFCOUNT_CROSS_SLOT, ERR_RATE_LIMITED,
+        ERR_TOO_MANY_REDIRECTIONS, ERR_WRONG_PASS, OK_REPLY, QUEUED_REPLY,
     };
     use undermoon::common::track::TrackedFutureRegistry;
     use undermoon::common::utils::pretty_print_bytes;
     use undermoon::common::version::UNDERMOON_MIGRATION_VERSION;
     use undermoon::migration::task::{MgrSubCmd, MigrationState, SwitchArg};
     use undermoon::protocol::{Array, BinSafeStr, BulkStr, Resp, RespPacket, RespVec, VFunctor};
-    use undermoon::proxy::command::{new_command_pair, CmdReplyReceiver, Command};
+    use undermoon::proxy::audit::AuditLogger;
+    use undermoon::proxy::client::{ClientInfo, ClientRegistry};
+    use undermoon::proxy::command::{new_command_pair, CmdReplyReceiver, Command, DataCmdType};
+    use undermoon::proxy::drain::DrainController;
+    use undermoon::proxy::executor::SharedForwardHandler;
     use undermoon::proxy::manager::MetaManager;
     use undermoon::proxy::manager::MetaMap;
+    use undermoon::proxy::manager::NodeAddress;
     use undermoon::proxy::service::ServerProxyConfig;
-    use undermoon::proxy::session::CmdCtx;
+    use undermoon::proxy::session::{CmdCtx, CmdCtxHandler, SessionState};
+    use undermoon::proxy::slowlog::SlowRequestLogger;
+    use undermoon::proxy::timeout::CmdTimeouts;
 
     const TEST_CLUSTER: &str = "test_cluster";
     type TestMetaManager = MetaManager<DummyClientFactory, DummyOkConnFactory>;
+    type TestForwardHandler = SharedForwardHandler<DummyClientFactory, DummyOkConnFactory>;
 
     fn gen_config() -> ServerProxyConfig {
         ServerProxyConfig {
             address: "localhost:5299".to_string(),
             announce_address: "localhost:5299".to_string(),
             auto_select_cluster: true,
-            slowlog_len: NonZeroUsize::new(1024).unwrap(),
+            slowlog_len: AtomicUsize::new(1024),
             slowlog_log_slower_than: AtomicI64::new(0),
             slowlog_sample_rate: AtomicU64::new(1),
+            hotkey_sample_rate: AtomicU64::new(1),
+            maxclients: AtomicU64::new(0),
+            idle_timeout: AtomicU64::new(0),
             thread_number: NonZeroUsize::new(2).unwrap(),
             session_channel_size: 1024,
             backend_channel_size: 1024,
             // Should only be 1 so that when `wait_backend_ready` is done,
             // the whole backend is ready.
-            backend_conn_num: NonZeroUsize::new(1).unwrap(),
-            backend_batch_min_time: 10000,
-            backend_batch_max_time: 10000,
-            backend_batch_buf: NonZeroUsize::new(50).unwrap(),
-            session_batch_min_time: 10000,
-            session_batch_max_time: 10000,
-            session_batch_buf: NonZeroUsize::new(50).unwrap(),
+            backend_conn_num: AtomicUsize::new(1),
+            backend_batch_min_time: AtomicUsize::new(10000),
+            backend_batch_max_time: AtomicUsize::new(10000),
+            backend_batch_buf: AtomicUsize::new(50),
+            session_batch_min_time: AtomicUsize::new(10000),
+            session_batch_max_time: AtomicUsize::new(10000),
+            session_batch_buf: AtomicUsize::new(50),
             active_redirection: false,
-            max_redirections: None,
+            max_redirections: AtomicUsize::new(0),
+            tls_cert_path: None,
+            tls_key_path: None,
+            tls_client_ca_path: None,
+            backend_tls_ca_path: None,
+            backend_tls_cert_path: None,
+            backend_tls_key_path: None,
+            backend_tls_server_name: None,
+            metrics_address: None,
+            audit_log_path: None,
+            audit_log_max_size: 0,
+            audit_log_max_files: 0,
+            backend_reply_timeout: 0,
+            breaker_error_rate: 0,
+            breaker_min_requests: 0,
+            breaker_consecutive_timeouts: 0,
+            breaker_open_time: 0,
+            cmd_timeouts: CmdTimeouts::default(),
+            drain_timeout: 0,
         }
     }
 
@@ -85,6 +115,83 @@ mod tests {
         )
     }
 
+    fn gen_testing_handler(
+        handle_func: Arc<dyn Fn(Vec<String>) -> RespVec + Send + Sync + 'static>,
+        config: ServerProxyConfig,
+    ) -> TestForwardHandler {
+        let config = Arc::new(config);
+        let client_factory = Arc::new(DummyClientFactory::new(handle_func.clone()));
+        let conn_factory = Arc::new(DummyOkConnFactory::new(handle_func));
+        let meta_map = Arc::new(ArcSwap::new(Arc::new(MetaMap::empty())));
+        let future_registry = Arc::new(TrackedFutureRegistry::default());
+        SharedForwardHandler::new(
+            config.clone(),
+            ClusterConfig::default(),
+            client_factory,
+            Arc::new(SlowRequestLogger::new(config.clone())),
+            Arc::new(ClientRegistry::default()),
+            Arc::new(AuditLogger::new(&config).unwrap()),
+            Arc::new(DrainController::default()),
+            meta_map,
+            conn_factory,
+            future_registry,
+        )
+    }
+
+    fn gen_session_state() -> SessionState {
+        let cluster_name = ClusterName::try_from(TEST_CLUSTER).unwrap();
+        let client_info = Arc::new(ClientInfo::new(233, "127.0.0.1:6000".to_string()));
+        SessionState::new(cluster_name, client_info)
+    }
+
+    async fn handle_command(
+        handler: &TestForwardHandler,
+        session_state: &SessionState,
+        args: &[&str],
+    ) -> RespVec {
+        let resp = RespPacket::Data(Resp::Arr(Array::Arr(
+            args.iter()
+                .map(|arg| Resp::Bulk(BulkStr::Str(arg.as_bytes().to_vec())))
+                .collect(),
+        )));
+        let command = Command::new(Box::new(resp));
+        let (s, r) = new_command_pair(&command);
+        let cmd_ctx = CmdCtx::new(session_state.get_cluster_name(), command, s, 233, true);
+        let result = handler.handle_cmd_ctx(cmd_ctx, r, session_state).await;
+        let (_, response, _) = result.unwrap().into_inner();
+        response.into_resp_vec()
+    }
+
+    // Retries until the backend connections are ready.
+    async fn handle_data_command(
+        handler: &TestForwardHandler,
+        session_state: &SessionState,
+        args: &[&str],
+    ) -> RespVec {
+        loop {
+            match handle_command(handler, session_state, args).await {
+                Resp::Error(err_str)
+                    if str::from_utf8(err_str.as_slice())
+                        .unwrap()
+                        .starts_with(ERR_BACKEND_CONNECTION) =>
+                {
+                    Delay::new(Duration::from_millis(1)).await;
+                }
+                resp => return resp,
+            }
+        }
+    }
+
+    fn assert_err_reply(resp: &RespVec, err: &str) {
+        match resp {
+            Resp::Error(err_str) => assert!(str::from_utf8(err_str).unwrap().starts_with(err)),
+            other => panic!(
+                "unexpected pattern {:?}",
+                other.clone().map(|b| pretty_print_bytes(b.as_slice()))
+            ),
+        }
+    }
+
     fn gen_set_command(key: BinSafeStr) -> (CmdCtx, CmdReplyReceiver) {
         let cluster_name = ClusterName::try_from(TEST_CLUSTER).unwrap();
         let resp = RespPacket::Data(Resp::Arr(Array::Arr(vec![
@@ -216,6 +323,69 @@ mod tests {
         assert_ok_reply(reply_receiver).await;
     }
 
+    #[tokio::test]
+    async fn test_filter_cmd() {
+        let mut iter = "1 NOFLAGS test_cluster 127.0.0.1:6379 1 0-16383 CONFIG test_cluster denied_commands keys,flushall test_cluster renamed_commands set:myset"
+            .split(' ')
+            .map(|s| s.to_string())
+            .peekable();
+        let (meta, extended_args) = ProxyClusterMeta::parse(&mut iter).unwrap();
+        assert!(extended_args.is_ok());
+        let manager = gen_testing_manager(Arc::new(always_ok), gen_config());
+        manager.set_meta(meta).unwrap();
+
+        let gen_cmd = |args: &[&str]| {
+            let cluster_name = ClusterName::try_from(TEST_CLUSTER).unwrap();
+            let resp = RespPacket::Data(Resp::Arr(Array::Arr(
+                args.iter()
+                    .map(|arg| Resp::Bulk(BulkStr::Str(arg.as_bytes().to_vec())))
+                    .collect(),
+            )));
+            let command = Command::new(Box::new(resp));
+            let (s, _) = new_command_pair(&command);
+            CmdCtx::new(cluster_name, command, s, 233, true)
+        };
+
+        assert!(!manager.filter_cmd(&mut gen_cmd(&["KEYS", "*"])));
+        assert!(!manager.filter_cmd(&mut gen_cmd(&["flushall"])));
+        assert!(!manager.filter_cmd(&mut gen_cmd(&["SET", "key", "value"])));
+
+        let mut cmd_ctx = gen_cmd(&["GET", "key"]);
+        assert!(manager.filter_cmd(&mut cmd_ctx));
+        assert_eq!(cmd_ctx.get_cmd().get_command_name(), Some("GET"));
+
+        let mut cmd_ctx = gen_cmd(&["MYSET", "key", "value"]);
+        assert!(manager.filter_cmd(&mut cmd_ctx));
+        assert_eq!(cmd_ctx.get_cmd().get_command_name(), Some("set"));
+        assert_eq!(cmd_ctx.get_data_cmd_type(), DataCmdType::SET);
+    }
+
+    #[tokio::test]
+    async fn test_get_key_prefix() {
+        let manager = gen_testing_manager(Arc::new(always_ok), gen_config());
+        let cluster_name = ClusterName::try_from(TEST_CLUSTER).unwrap();
+
+        let mut iter = "1 NOFLAGS test_cluster 127.0.0.1:6379 1 0-16383"
+            .split(' ')
+            .map(|s| s.to_string())
+            .peekable();
+        let (meta, _) = ProxyClusterMeta::parse(&mut iter).unwrap();
+        manager.set_meta(meta).unwrap();
+        assert_eq!(manager.get_key_prefix(&cluster_name), None);
+
+        let mut iter = "2 NOFLAGS test_cluster 127.0.0.1:6379 1 0-16383 CONFIG test_cluster key_prefix tenant1:"
+            .split(' ')
+            .map(|s| s.to_string())
+            .peekable();
+        let (meta, extended_args) = ProxyClusterMeta::parse(&mut iter).unwrap();
+        assert!(extended_args.is_ok());
+        manager.set_meta(meta).unwrap();
+        assert_eq!(
+            manager.get_key_prefix(&cluster_name),
+            Some(b"tenant1:".to_vec())
+        );
+    }
+
     fn gen_migration_cluster_meta(is_source_proxy: bool) -> ProxyClusterMeta {
         gen_migration_cluster_meta_helper(is_source_proxy, 233, "127.0.0.1:5299", "127.0.0.1:6000")
     }
@@ -788,4 +958,318 @@ mod tests {
         };
         assert_eq!(s, ERR_TOO_MANY_REDIRECTIONS.as_bytes());
     }
+
+    #[tokio::test]
+    async fn test_get_nodes() {
+        let manager = gen_testing_manager(Arc::new(handle_active_redirection), gen_config());
+        manager
+            .set_meta(gen_active_redirection_proxy1_cluster_meta())
+            .unwrap();
+        let cluster_name = ClusterName::try_from(TEST_CLUSTER).unwrap();
+
+        let nodes = manager.get_nodes(&cluster_name, true);
+        assert_eq!(
+            nodes,
+            vec![
+                NodeAddress::Local("127.0.0.1:7001".to_string()),
+                NodeAddress::Peer("127.0.0.1:6002".to_string()),
+            ]
+        );
+        let nodes = manager.get_nodes(&cluster_name, false);
+        assert_eq!(
+            nodes,
+            vec![NodeAddress::Local("127.0.0.1:7001".to_string())]
+        );
+
+        // The slot of `b` is 3300 and the slot of `a` is 15495.
+        let local_keys: Vec<&[u8]> = vec![b"b", b"{b}c"];
+        assert!(manager.all_slots_local(&cluster_name, local_keys.into_iter()));
+        let keys: Vec<&[u8]> = vec![b"b", b"a"];
+        assert!(!manager.all_slots_local(&cluster_name, keys.into_iter()));
+    }
+
+    #[tokio::test]
+    async fn test_ping_in_transaction() {
+        let handler = gen_testing_handler(Arc::new(always_ok), gen_config());
+        let session_state = gen_session_state();
+        let set_cluster = "UMCTL SETCLUSTER 1 NOFLAGS test_cluster 127.0.0.1:6379 1 0-16383";
+        let args: Vec<&str> = set_cluster.split(' ').collect();
+        let resp = handle_command(&handler, &session_state, &args).await;
+        assert_eq!(resp, Resp::Simple(OK_REPLY.as_bytes().to_vec()));
+
+        let resp = handle_command(&handler, &session_state, &["MULTI"]).await;
+        assert_eq!(resp, Resp::Simple(OK_REPLY.as_bytes().to_vec()));
+        let queued = Resp::Simple(QUEUED_REPLY.as_bytes().to_vec());
+        for args in [&["SET", "a", "1"][..], &["PING", "b"], &["ECHO", "c"]].iter() {
+            let resp = handle_command(&handler, &session_state, args).await;
+            assert_eq!(resp, queued);
+        }
+        assert!(!session_state.get_transaction().is_aborted());
+    }
+
+    #[tokio::test]
+    async fn test_cross_slot_transaction() {
+        let handler = gen_testing_handler(Arc::new(always_ok), gen_config());
+        let session_state = gen_session_state();
+        let set_cluster = "UMCTL SETCLUSTER 1 NOFLAGS test_cluster 127.0.0.1:6379 1 0-16383";
+        let args: Vec<&str> = set_cluster.split(' ').collect();
+        let resp = handle_command(&handler, &session_state, &args).await;
+        assert_eq!(resp, Resp::Simple(OK_REPLY.as_bytes().to_vec()));
+
+        // The slot of `b` is 3300 and the slot of `a` is 15495.
+        for args in [
+            &["RENAME", "a", "b"][..],
+            &["SMOVE", "a", "b", "m"],
+            &["EVAL", "script", "2", "a", "b"],
+        ]
+        .iter()
+        {
+            let resp = handle_command(&handler, &session_state, &["MULTI"]).await;
+            assert_eq!(resp, Resp::Simple(OK_REPLY.as_bytes().to_vec()));
+            let resp = handle_command(&handler, &session_state, args).await;
+            assert_err_reply(&resp, ERR_CROSS_SLOT);
+            assert!(session_state.get_transaction().is_aborted());
+            handle_command(&handler, &session_state, &["DISCARD"]).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_cross_slot_hll_cmd() {
+        let handler = gen_testing_handler(Arc::new(always_ok), gen_config());
+        let session_state = gen_session_state();
+        let set_cluster = "UMCTL SETCLUSTER 1 NOFLAGS test_cluster 127.0.0.1:6379 1 0-16383";
+        let args: Vec<&str> = set_cluster.split(' ').collect();
+        let resp = handle_command(&handler, &session_state, &args).await;
+        assert_eq!(resp, Resp::Simple(OK_REPLY.as_bytes().to_vec()));
+
+        // The slot of `b` is 3300 and the slot of `a` is 15495.
+        let resp = handle_command(&handler, &session_state, &["PFMERGE", "a", "b"]).await;
+        assert_err_reply(&resp, ERR_ATOMIC_CROSS_SLOT);
+        let resp = handle_command(&handler, &session_state, &["PFCOUNT", "a", "b"]).await;
+        assert_err_reply(&resp, ERR_PFCOUNT_CROSS_SLOT);
+        let resp = handle_data_command(&handler, &session_state, &["PFMERGE", "{a}b", "a"]).await;
+        assert_eq!(resp, Resp::Simple(OK_REPLY.as_bytes().to_vec()));
+    }
+
+    #[tokio::test]
+    async fn test_forward_auth() {
+        let handler = gen_testing_handler(Arc::new(always_ok), gen_config());
+        let session_state = gen_session_state();
+        let set_cluster = "UMCTL SETCLUSTER 1 NOFLAGS test_cluster 127.0.0.1:6379 1 0-16383 CONFIG test_cluster password pwd";
+        let args: Vec<&str> = set_cluster.split(' ').collect();
+        let resp = handle_command(&handler, &session_state, &args).await;
+        assert_eq!(resp, Resp::Simple(OK_REPLY.as_bytes().to_vec()));
+
+        let resp = handle_command(&handler, &session_state, &["GET", "key"]).await;
+        assert_err_reply(&resp, ERR_NOAUTH);
+        let resp =
+            handle_command(&handler, &session_state, &["UMFORWARD", "0", "GET", "key"]).await;
+        assert_err_reply(&resp, ERR_NOAUTH);
+        let resp = handle_command(&handler, &session_state, &["UMSYNC", "key"]).await;
+        assert_err_reply(&resp, ERR_NOAUTH);
+        let args = [
+            "UMFORWARD",
+            "0",
+            "AUTH",
+            "test_cluster",
+            "wrong",
+            "GET",
+            "key",
+        ];
+        let resp = handle_command(&handler, &session_state, &args).await;
+        assert_err_reply(&resp, ERR_WRONG_PASS);
+
+        let args = [
+            "UMFORWARD",
+            "0",
+            "AUTH",
+            "test_cluster",
+            "pwd",
+            "GET",
+            "key",
+        ];
+        let resp = handle_data_command(&handler, &session_state, &args).await;
+        assert_eq!(resp, Resp::Simple(OK_REPLY.as_bytes().to_vec()));
+        // The password carried by UMFORWARD does not authenticate the session.
+        let resp = handle_command(&handler, &session_state, &["GET", "key"]).await;
+        assert_err_reply(&resp, ERR_NOAUTH);
+
+        let resp = handle_command(&handler, &session_state, &["AUTH", "test_cluster", "pwd"]).await;
+        assert_eq!(resp, Resp::Simple(OK_REPLY.as_bytes().to_vec()));
+        let resp =
+            handle_command(&handler, &session_state, &["UMFORWARD", "0", "GET", "key"]).await;
+        assert_eq!(resp, Resp::Simple(OK_REPLY.as_bytes().to_vec()));
+    }
+
+    #[tokio::test]
+    async fn test_drain_auth() {
+        let handler = gen_testing_handler(Arc::new(always_ok), gen_config());
+        let session_state = gen_session_state();
+        let set_cluster = "UMCTL SETCLUSTER 1 NOFLAGS test_cluster 127.0.0.1:6379 1 0-16383 CONFIG test_cluster password pwd";
+        let args: Vec<&str> = set_cluster.split(' ').collect();
+        let resp = handle_command(&handler, &session_state, &args).await;
+        assert_eq!(resp, Resp::Simple(OK_REPLY.as_bytes().to_vec()));
+
+        let resp = handle_command(&handler, &session_state, &["UMCTL", "DRAIN"]).await;
+        assert_err_reply(&resp, ERR_NOAUTH);
+
+        let resp = handle_command(&handler, &session_state, &["AUTH", "test_cluster", "pwd"]).await;
+        assert_eq!(resp, Resp::Simple(OK_REPLY.as_bytes().to_vec()));
+        let resp = handle_command(&handler, &session_state, &["UMCTL", "DRAIN"]).await;
+        assert_eq!(resp, Resp::Simple(OK_REPLY.as_bytes().to_vec()));
+    }
+
+    #[tokio::test]
+    async fn test_hotkeys_auth() {
+        let handler = gen_testing_handler(Arc::new(always_ok), gen_config());
+        let session_state = gen_session_state();
+        let other_session_state = gen_session_state();
+        let set_cluster = "UMCTL SETCLUSTER 1 NOFLAGS test_cluster 127.0.0.1:6379 1 0-16383 CONFIG test_cluster password pwd";
+        let args: Vec<&str> = set_cluster.split(' ').collect();
+        let resp = handle_command(&handler, &session_state, &args).await;
+        assert_eq!(resp, Resp::Simple(OK_REPLY.as_bytes().to_vec()));
+
+        let resp = handle_command(&handler, &session_state, &["AUTH", "test_cluster", "pwd"]).await;
+        assert_eq!(resp, Resp::Simple(OK_REPLY.as_bytes().to_vec()));
+        let resp = handle_data_command(&handler, &session_state, &["GET", "key"]).await;
+        assert_eq!(resp, Resp::Simple(OK_REPLY.as_bytes().to_vec()));
+
+        let args = ["UMCTL", "HOTKEYS", "test_cluster"];
+        let resp = handle_command(&handler, &other_session_state, &args).await;
+        assert_err_reply(&resp, ERR_NOAUTH);
+        let resp = handle_command(&handler, &other_session_state, &["UMCTL", "HOTKEYS"]).await;
+        assert_eq!(resp, Resp::Arr(Array::Arr(vec![])));
+        match handle_command(&handler, &session_state, &args).await {
+            Resp::Arr(Array::Arr(hot_keys)) => assert_eq!(hot_keys.len(), 1),
+            other => panic!("unexpected reply {:?}", other),
+        }
+
+        // The hot keys of the removed clusters are dropped.
+        let set_cluster = "UMCTL SETCLUSTER 2 NOFLAGS other_cluster 127.0.0.1:6379 1 0-16383";
+        let args: Vec<&str> = set_cluster.split(' ').collect();
+        let resp = handle_command(&handler, &session_state, &args).await;
+        assert_eq!(resp, Resp::Simple(OK_REPLY.as_bytes().to_vec()));
+        let resp = handle_command(&handler, &session_state, &["UMCTL", "HOTKEYS"]).await;
+        assert_eq!(resp, Resp::Arr(Array::Arr(vec![])));
+    }
+
+    #[tokio::test]
+    async fn test_forward_filter_cmd() {
+        let handler = gen_testing_handler(Arc::new(always_ok), gen_config());
+        let session_state = gen_session_state();
+        let set_cluster = "UMCTL SETCLUSTER 1 NOFLAGS test_cluster 127.0.0.1:6379 1 0-16383 CONFIG test_cluster denied_commands keys,flushall test_cluster renamed_commands set:myset";
+        let args: Vec<&str> = set_cluster.split(' ').collect();
+        let resp = handle_command(&handler, &session_state, &args).await;
+        assert_eq!(resp, Resp::Simple(OK_REPLY.as_bytes().to_vec()));
+
+        let resp = handle_command(&handler, &session_state, &["UMFORWARD", "0", "FLUSHALL"]).await;
+        assert_err_reply(&resp, ERR_NOPERM);
+        let resp = handle_command(&handler, &session_state, &["UMFORWARD", "0", "KEYS", "*"]).await;
+        assert_err_reply(&resp, ERR_NOPERM);
+
+        // The peer proxy sends the original name of the renamed command.
+        let args = ["UMFORWARD", "0", "SET", "key", "value"];
+        let resp = handle_data_command(&handler, &session_state, &args).await;
+        assert_eq!(resp, Resp::Simple(OK_REPLY.as_bytes().to_vec()));
+    }
+
+    #[tokio::test]
+    async fn test_forward_quota() {
+        let handler = gen_testing_handler(Arc::new(always_ok), gen_config());
+        let session_state = gen_session_state();
+        let set_cluster = "UMCTL SETCLUSTER 1 NOFLAGS test_cluster 127.0.0.1:6379 1 0-16383 CONFIG test_cluster max_ops_per_sec 1";
+        let args: Vec<&str> = set_cluster.split(' ').collect();
+        let resp = handle_command(&handler, &session_state, &args).await;
+        assert_eq!(resp, Resp::Simple(OK_REPLY.as_bytes().to_vec()));
+
+        // The forwarded commands have been charged by the peer proxy.
+        let args = ["UMFORWARD", "0", "GET", "key"];
+        for _ in 0..3 {
+            let resp = handle_command(&handler, &session_state, &args).await;
+            assert_ne!(resp, Resp::Error(ERR_RATE_LIMITED.as_bytes().to_vec()));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_transaction_quota() {
+        let handler = gen_testing_handler(Arc::new(always_ok), gen_config());
+        let session_state = gen_session_state();
+        let set_cluster = "UMCTL SETCLUSTER 1 NOFLAGS test_cluster 127.0.0.1:6379 1 0-16383 CONFIG test_cluster max_ops_per_sec 1";
+        let args: Vec<&str> = set_cluster.split(' ').collect();
+        let resp = handle_command(&handler, &session_state, &args).await;
+        assert_eq!(resp, Resp::Simple(OK_REPLY.as_bytes().to_vec()));
+
+        let resp = handle_command(&handler, &session_state, &["MULTI"]).await;
+        assert_eq!(resp, Resp::Simple(OK_REPLY.as_bytes().to_vec()));
+        let resp = handle_command(&handler, &session_state, &["SET", "a", "1"]).await;
+        assert_eq!(resp, Resp::Simple(QUEUED_REPLY.as_bytes().to_vec()));
+        let resp = handle_command(&handler, &session_state, &["SET", "a", "2"]).await;
+        assert_err_reply(&resp, ERR_RATE_LIMITED);
+        assert!(session_state.get_transaction().is_aborted());
+    }
+
+    fn echo_key(cmd: Vec<String>) -> RespVec {
+        Resp::Bulk(BulkStr::Str(cmd[1].as_bytes().to_vec()))
+    }
+
+    #[tokio::test]
+    async fn test_forward_key_prefix() {
+        let handler = gen_testing_handler(Arc::new(echo_key), gen_config());
+        let session_state = gen_session_state();
+        let set_cluster = "UMCTL SETCLUSTER 1 NOFLAGS test_cluster 127.0.0.1:6379 1 0-16383 CONFIG test_cluster key_prefix t1:";
+        let args: Vec<&str> = set_cluster.split(' ').collect();
+        let resp = handle_command(&handler, &session_state, &args).await;
+        assert_eq!(resp, Resp::Simple(OK_REPLY.as_bytes().to_vec()));
+
+        let expected = Resp::Bulk(BulkStr::Str(b"t1:{key}".to_vec()));
+        let resp =
+            handle_data_command(&handler, &session_state, &["UMFORWARD", "0", "GET", "key"]).await;
+        assert_eq!(resp, expected);
+        let resp = handle_data_command(
+            &handler,
+            &session_state,
+            &["UMFORWARD", "0", "GET", "t1:{key}"],
+        )
+        .await;
+        assert_eq!(resp, expected);
+    }
+
+    #[test]
+    fn test_config_reload() {
+        let config = gen_config();
+        let mut new_config = gen_config();
+        new_config.backend_conn_num = AtomicUsize::new(4);
+        new_config.session_batch_buf = AtomicUsize::new(20);
+        new_config.max_redirections = AtomicUsize::new(3);
+        new_config.slowlog_log_slower_than = AtomicI64::new(100);
+        new_config.active_redirection = true;
+        new_config.thread_number = NonZeroUsize::new(4).unwrap();
+
+        let result = config.reload(&new_config);
+        assert_eq!(
+            result.changed,
+            vec!["slowlog_log_slower_than", "max_redirections"]
+        );
+        assert_eq!(
+            result.new_connections_only,
+            vec!["backend_conn_num", "session_batch_buf"]
+        );
+        assert_eq!(
+            result.restart_required,
+            vec!["thread_number", "active_redirection"]
+        );
+        assert!(result.failed.is_empty());
+        assert_eq!(config.get_backend_conn_num().get(), 4);
+        assert_eq!(config.get_session_batch_buf().get(), 20);
+        assert_eq!(config.get_max_redirections(), NonZeroUsize::new(3));
+        assert_eq!(config.get_slowlog_log_slower_than(), 100);
+
+        let result = config.reload(&new_config);
+        assert!(result.changed.is_empty());
+        assert!(result.new_connections_only.is_empty());
+        assert_eq!(
+            result.restart_required,
+            vec!["thread_number", "active_redirection"]
+        );
+    }
 }